@@ -0,0 +1,27 @@
+#![feature(test)]
+
+extern crate test;
+extern crate binary_tree;
+
+use binary_tree::count::CountTree;
+use test::Bencher;
+
+const TOTAL: usize = 65536;
+
+#[bench]
+fn fold_ct(b: &mut Bencher) {
+    let ct: CountTree<_> = (0..TOTAL).collect();
+    b.iter(|| ct.fold(0, |acc, &i| acc ^ i))
+}
+
+#[bench]
+fn iter_fold_ct(b: &mut Bencher) {
+    let ct: CountTree<_> = (0..TOTAL).collect();
+    b.iter(|| (&ct).into_iter().fold(0, |acc, &i| acc ^ i))
+}
+
+#[bench]
+fn iter_fold_vec(b: &mut Bencher) {
+    let v: Vec<_> = (0..TOTAL).collect();
+    b.iter(|| v.iter().fold(0, |acc, &i| acc ^ i))
+}
@@ -3,7 +3,7 @@
 extern crate test;
 extern crate binary_tree;
 
-use binary_tree::count::CountTree;
+use binary_tree::count::{CountTree, CountTreeBuilder};
 use test::Bencher;
 
 use std::collections::LinkedList;
@@ -17,6 +17,15 @@ fn from_iter_ct(b: &mut Bencher) {
     })
 }
 
+#[bench]
+fn from_iter_builder_ct(b: &mut Bencher) {
+    b.iter(|| {
+        let mut builder = CountTreeBuilder::<_>::new();
+        builder.push_run(0..TOTAL);
+        builder.finish()
+    })
+}
+
 #[bench]
 fn from_iter_ll(b: &mut Bencher) {
     b.iter(|| {
@@ -5,6 +5,7 @@ extern crate rand;
 extern crate test;
 
 use binary_tree::count::CountTree;
+use binary_tree::packed::PackedCountTree;
 use rand::{Rand, Rng, StdRng, SeedableRng, thread_rng};
 use std::env;
 use std::str::FromStr;
@@ -38,6 +39,19 @@ pub fn insert_at_random_ct(b: &mut Bencher) {
     });
 }
 
+#[bench]
+pub fn insert_at_random_packed(b: &mut Bencher) {
+    let seed = get_seed();
+    let rng = StdRng::from_seed(&[seed]);
+    b.iter(|| {
+        let mut rng = rng.clone();
+        let mut pt = PackedCountTree::new();
+        for i in 0..TOTAL {
+            pt.insert(rng.gen_range(0, i + 1), i);
+        }
+    });
+}
+
 #[bench]
 pub fn insert_at_random_ll(b: &mut Bencher) {
     let seed = get_seed();
@@ -0,0 +1,41 @@
+#![feature(test)]
+
+extern crate test;
+extern crate binary_tree;
+
+use binary_tree::count::CountTree;
+use test::Bencher;
+
+use std::collections::LinkedList;
+
+const TOTAL: usize = 4096;
+
+#[bench]
+fn push_back_ct(b: &mut Bencher) {
+    b.iter(|| {
+        let mut ct = CountTree::new();
+        for i in 0..TOTAL {
+            ct.push_back(i);
+        }
+    })
+}
+
+#[bench]
+fn push_back_ll(b: &mut Bencher) {
+    b.iter(|| {
+        let mut ll = LinkedList::new();
+        for i in 0..TOTAL {
+            ll.push_back(i);
+        }
+    })
+}
+
+#[bench]
+fn push_back_vec(b: &mut Bencher) {
+    b.iter(|| {
+        let mut v = Vec::new();
+        for i in 0..TOTAL {
+            v.push(i);
+        }
+    })
+}
@@ -0,0 +1,1015 @@
+//! Generic algorithms on binary trees.
+//!
+//! All the algorithms here are iterative, and hence can be used on trees of
+//! any height without the risk of overflowing the stack.
+
+use std::cmp::{self, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::iter::Filter;
+use std::ops::DerefMut;
+
+use Node;
+use NodeMut;
+use WalkAction;
+use iter::{Iter, NodeIter};
+
+/// Returns the number of nodes in the tree rooted at `root`.
+pub fn count_nodes<N: Node>(root: &N) -> usize {
+    let mut count = 0;
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        count += 1;
+        stack.extend(node.left());
+        stack.extend(node.right());
+    }
+    count
+}
+
+/// Returns the height of `root`, i.e. the length of the longest path from it
+/// to a leaf. The height of a leaf is zero.
+pub fn height<N: Node>(root: &N) -> usize {
+    let mut height = 0;
+    let mut stack = vec![(root, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        if depth > height {
+            height = depth;
+        }
+        stack.extend(node.left().map(|n| (n, depth + 1)));
+        stack.extend(node.right().map(|n| (n, depth + 1)));
+    }
+    height
+}
+
+/// Returns the number of leaves (nodes without children) in the tree rooted
+/// at `root`.
+pub fn count_leaves<N: Node>(root: &N) -> usize {
+    let mut count = 0;
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        match (node.left(), node.right()) {
+            (None, None) => count += 1,
+            (left, right) => {
+                stack.extend(left);
+                stack.extend(right);
+            }
+        }
+    }
+    count
+}
+
+/// Iterator over the leaves of a tree, returned by `leaves`.
+pub type Leaves<'a, N> = Filter<NodeIter<'a, N>, fn(&&'a N) -> bool>;
+
+/// Iterator over the internal nodes of a tree, returned by `internal_nodes`.
+pub type InternalNodes<'a, N> = Filter<NodeIter<'a, N>, fn(&&'a N) -> bool>;
+
+/// Returns an iterator over the leaves (nodes without children) of the tree
+/// rooted at `root`, from left to right.
+pub fn leaves<N: Node>(root: &N) -> Leaves<'_, N> {
+    NodeIter::new(Some(root)).filter(is_leaf::<N>)
+}
+
+/// Returns an iterator over the internal nodes (nodes with at least one
+/// child) of the tree rooted at `root`, in-order.
+pub fn internal_nodes<N: Node>(root: &N) -> InternalNodes<'_, N> {
+    NodeIter::new(Some(root)).filter(is_internal::<N>)
+}
+
+fn is_leaf<N: Node>(node: &&N) -> bool {
+    node.left().is_none() && node.right().is_none()
+}
+
+fn is_internal<N: Node>(node: &&N) -> bool {
+    !is_leaf(node)
+}
+
+/// Returns the diameter of the tree rooted at `root`, i.e. the number of
+/// edges on the longest path between any two nodes. Time complexity: O(n)
+pub fn diameter<N: Node>(root: &N) -> usize {
+    // (height, diameter) of each subtree
+    let (_, diameter) = root.fold_tree(|left: Option<(usize, usize)>, _, right| {
+        let lh = left.map_or(0, |(h, _)| h + 1);
+        let rh = right.map_or(0, |(h, _)| h + 1);
+        let ld = left.map_or(0, |(_, d)| d);
+        let rd = right.map_or(0, |(_, d)| d);
+        (cmp::max(lh, rh), cmp::max(lh + rh, cmp::max(ld, rd)))
+    });
+    diameter
+}
+
+/// Returns the diameter of the tree rooted at `root` (see `diameter`), along
+/// with the paths from `root` to the two ends of a longest path. Time
+/// complexity: O(n), with O(n) additional space.
+pub fn diameter_path<N: Node>(root: &N) -> (usize, Vec<WalkAction>, Vec<WalkAction>) {
+    use WalkAction::*;
+
+    struct Info {
+        // height of the subtree, using -1 for no subtree
+        height: isize,
+        left: Option<usize>,
+        right: Option<usize>,
+        parent: Option<(usize, WalkAction)>,
+    }
+
+    // index the nodes in pre-order, so that children come after parents
+    let mut infos: Vec<Info> = Vec::new();
+    let mut stack: Vec<(&N, Option<(usize, WalkAction)>)> = vec![(root, None)];
+    while let Some((node, parent)) = stack.pop() {
+        let id = infos.len();
+        if let Some((pid, action)) = parent {
+            match action {
+                Left => infos[pid].left = Some(id),
+                _ => infos[pid].right = Some(id),
+            }
+        }
+        infos.push(Info {
+            height: 0,
+            left: None,
+            right: None,
+            parent,
+        });
+        stack.extend(node.right().map(|n| (n, Some((id, Right)))));
+        stack.extend(node.left().map(|n| (n, Some((id, Left)))));
+    }
+
+    let height = |infos: &[Info], id: Option<usize>| id.map_or(-1, |id: usize| infos[id].height);
+    let mut best = (0, 0);
+    for id in (0..infos.len()).rev() {
+        let lh = height(&infos, infos[id].left);
+        let rh = height(&infos, infos[id].right);
+        infos[id].height = cmp::max(lh, rh) + 1;
+        let through = (lh + rh + 2) as usize;
+        if through > best.0 {
+            best = (through, id);
+        }
+    }
+
+    let (length, apex) = best;
+    let mut to_apex = Vec::new();
+    let mut id = apex;
+    while let Some((pid, action)) = infos[id].parent {
+        to_apex.push(action);
+        id = pid;
+    }
+    to_apex.reverse();
+    let descend = |first: WalkAction| {
+        let mut path = to_apex.clone();
+        let mut next = match first {
+            Left => infos[apex].left,
+            _ => infos[apex].right,
+        };
+        if next.is_some() {
+            path.push(first);
+        }
+        while let Some(id) = next {
+            let info = &infos[id];
+            if info.left.is_none() && info.right.is_none() {
+                break;
+            }
+            let action = if height(&infos, info.left) >= height(&infos, info.right) {
+                Left
+            } else {
+                Right
+            };
+            path.push(action);
+            next = if action == Left { info.left } else { info.right };
+        }
+        path
+    };
+    (length, descend(Left), descend(Right))
+}
+
+/// Mirrors the tree rooted at `root`, by swapping the left and right children
+/// of every node. Time complexity: O(n)
+///
+/// The children are re-inserted bottom-up, so that implementations which keep
+/// track of subtree statistics in `insert_left`/`insert_right` stay
+/// consistent.
+pub fn invert<N: NodeMut>(root: &mut N) {
+    enum Task<P> {
+        Visit(P),
+        // node with its children detached, and whether it had a left and a
+        // right child respectively
+        Build(P, bool, bool),
+    }
+
+    let (left, right) = root.detach_children();
+    let (has_left, has_right) = (left.is_some(), right.is_some());
+    let mut tasks = Vec::new();
+    let mut done = Vec::new();
+    // the left subtree gets processed (and done) first
+    tasks.extend(right.map(Task::Visit));
+    tasks.extend(left.map(Task::Visit));
+    while let Some(task) = tasks.pop() {
+        match task {
+            Task::Visit(mut node) => {
+                let (left, right) = node.detach_children();
+                tasks.push(Task::Build(node, left.is_some(), right.is_some()));
+                tasks.extend(right.map(Task::Visit));
+                tasks.extend(left.map(Task::Visit));
+            }
+            Task::Build(mut node, has_left, has_right) => {
+                let right = if has_right { done.pop() } else { None };
+                let left = if has_left { done.pop() } else { None };
+                node.graft(right, left);
+                done.push(node);
+            }
+        }
+    }
+    let right = if has_right { done.pop() } else { None };
+    let left = if has_left { done.pop() } else { None };
+    root.graft(right, left);
+}
+
+/// Calls `f` with the depth and a mutable reference to the value of every
+/// node of the tree rooted at `root`, in level-order (breadth-first). The
+/// depth of `root` is zero. Time complexity: O(n), with O(n) additional space.
+///
+/// The nodes are detached while they are visited, and re-inserted bottom-up
+/// afterwards (as in `invert`). See `iter::LevelOrderDepthIter` for a
+/// read-only variant.
+pub fn for_each_level_mut<N, F>(root: &mut N, mut f: F)
+    where N: NodeMut,
+          F: FnMut(usize, &mut N::Value)
+{
+    f(0, root.value_mut());
+    let (left, right) = root.detach_children();
+    let root_children = (left.is_some(), right.is_some());
+    // detached nodes in level-order, along with whether they had a left and a
+    // right child respectively
+    let mut nodes = Vec::new();
+    let mut queue: VecDeque<_> = left.into_iter().chain(right).map(|n| (n, 1)).collect();
+    while let Some((mut node, depth)) = queue.pop_front() {
+        f(depth, node.value_mut());
+        let (left, right) = node.detach_children();
+        nodes.push((node, left.is_some(), right.is_some()));
+        queue.extend(left.map(|n| (n, depth + 1)));
+        queue.extend(right.map(|n| (n, depth + 1)));
+    }
+    // The children of a node are the last (in level-order) among the nodes
+    // which are yet to be re-attached.
+    let mut built = VecDeque::with_capacity(nodes.len());
+    while let Some((mut node, has_left, has_right)) = nodes.pop() {
+        let right = if has_right { built.pop_back() } else { None };
+        let left = if has_left { built.pop_back() } else { None };
+        node.graft(left, right);
+        built.push_front(node);
+    }
+    let right = if root_children.1 { built.pop_back() } else { None };
+    let left = if root_children.0 { built.pop_back() } else { None };
+    root.graft(left, right);
+}
+
+/// Drops the tree rooted at `root` without recursion, and without allocating.
+/// Time complexity: O(n)
+///
+/// Dropping a deep tree through the destructors of its nodes overflows the
+/// stack. Here, the tree is instead rotated into a vine one node at a time,
+/// and each node is dropped after its children have been detached.
+pub fn drop_tree<N: NodeMut>(root: N::NodePtr) {
+    let mut next = Some(root);
+    while let Some(mut node) = next {
+        while node.rotate_right().is_ok() {}
+        next = node.detach_right();
+    }
+}
+
+/// Builds a copy of the tree rooted at `src`, with the same shape, into
+/// another node type. `f` maps the values, and `make` creates a (childless)
+/// target node from a mapped value. Both are called once for each node, in
+/// post-order. Time complexity: O(n)
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate binary_tree;
+/// # use binary_tree::algos::{map_tree, tree_eq};
+/// # use binary_tree::count::CountTree;
+/// # use binary_tree::test::TestNode;
+/// # fn main() {
+/// let mut ct: CountTree<_> = (0..10).collect();
+/// let tn = map_tree(ct.root().unwrap(),
+///                   |v| v * 2,
+///                   |v| Box::new(TestNode::new(v)));
+/// assert!(tree_eq(ct.root().unwrap(), &*map_tree(&*tn, |v| v / 2, |v| Box::new(TestNode::new(v)))));
+/// # }
+/// ```
+pub fn map_tree<S, P, U, F, M>(src: &S, mut f: F, mut make: M) -> P
+    where S: Node,
+          P: DerefMut,
+          P::Target: NodeMut<NodePtr = P>,
+          F: FnMut(&S::Value) -> U,
+          M: FnMut(U) -> P
+{
+    // the flag is set if the children of the node are already on the stack
+    let mut stack = vec![(src, false)];
+    let mut done: Vec<P> = Vec::new();
+    while let Some((node, expanded)) = stack.pop() {
+        if expanded {
+            let right = node.right().map(|_| done.pop().unwrap());
+            let left = node.left().map(|_| done.pop().unwrap());
+            let mut new_node = make(f(node.value()));
+            new_node.graft(left, right);
+            done.push(new_node);
+        } else {
+            stack.push((node, true));
+            stack.extend(node.right().map(|n| (n, false)));
+            stack.extend(node.left().map(|n| (n, false)));
+        }
+    }
+    done.pop().unwrap()
+}
+
+/// Returns the values of the tree rooted at `root` in the array layout of a
+/// binary heap: the children of the node at index `i` are at `2i + 1` and
+/// `2i + 2`, and the positions without a node are `None`. The vector ends at
+/// the last node in level-order. Time complexity: O(n) for a complete tree,
+/// but the length of the vector grows exponentially with the height.
+pub fn to_heap_vec<N>(root: &N) -> Vec<Option<N::Value>>
+    where N: Node,
+          N::Value: Clone
+{
+    let mut heap = Vec::new();
+    let mut queue = VecDeque::new();
+    queue.push_back((root, 0));
+    while let Some((node, index)) = queue.pop_front() {
+        if heap.len() <= index {
+            heap.resize(index + 1, None);
+        }
+        heap[index] = Some(node.value().clone());
+        queue.extend(node.left().map(|n| (n, 2 * index + 1)));
+        queue.extend(node.right().map(|n| (n, 2 * index + 2)));
+    }
+    heap
+}
+
+/// Builds a tree from the array layout of a binary heap (see `to_heap_vec`),
+/// where `make` creates a (childless) node from a value. Values whose parent
+/// position is `None` are dropped. Returns `None` if the root position is
+/// empty. Time complexity: O(heap.len())
+pub fn from_heap_vec<V, P, M>(heap: Vec<Option<V>>, mut make: M) -> Option<P>
+    where P: DerefMut,
+          P::Target: NodeMut<NodePtr = P>,
+          M: FnMut(V) -> P
+{
+    let mut nodes: Vec<Option<P>> = heap.iter().map(|_| None).collect();
+    // build bottom-up, so that the children are ready before their parent
+    for (index, value) in heap.into_iter().enumerate().rev() {
+        if let Some(value) = value {
+            let mut node = make(value);
+            let left = nodes.get_mut(2 * index + 1).and_then(Option::take);
+            let right = nodes.get_mut(2 * index + 2).and_then(Option::take);
+            node.graft(left, right);
+            nodes[index] = Some(node);
+        }
+    }
+    nodes.into_iter().next().and_then(|root| root)
+}
+
+/// Rebalances the tree rooted at `root` to the minimum possible height,
+/// preserving the in-order sequence of values, using the
+/// [Day-Stout-Warren][dsw] algorithm. Time complexity: O(n), with O(1)
+/// additional space.
+///
+/// [dsw]: https://en.wikipedia.org/wiki/Day%E2%80%93Stout%E2%80%93Warren_algorithm
+pub fn rebalance<N: NodeMut>(root: &mut N) {
+    let count = tree_to_vine(root);
+    vine_to_tree(root, count);
+}
+
+/// Rotates the tree rooted at `root` into a "vine", i.e. a tree in which no
+/// node has a left child, and returns the number of nodes in it. Time
+/// complexity: O(n)
+pub fn tree_to_vine<N: NodeMut>(root: &mut N) -> usize {
+    let mut count = 0;
+    let mut node = root;
+    loop {
+        while node.rotate_right().is_ok() {}
+        count += 1;
+        match node.right_mut() {
+            Some(right) => node = right,
+            None => break,
+        }
+    }
+    count
+}
+
+/// Rotates a vine of `count` nodes (as produced by `tree_to_vine`) into a
+/// complete binary tree. Time complexity: O(n)
+pub fn vine_to_tree<N: NodeMut>(root: &mut N, count: usize) {
+    // the number of nodes in the bottom-most level of the result
+    let full = (count + 1).next_power_of_two();
+    let full = if full == count + 1 { full } else { full / 2 };
+    let leaves = count + 1 - full;
+    compress(root, leaves);
+    let mut size = count - leaves;
+    while size > 1 {
+        size /= 2;
+        compress(root, size);
+    }
+}
+
+// Rotates every other node along the right spine to the left, `count` times.
+fn compress<N: NodeMut>(root: &mut N, count: usize) {
+    let mut node = root;
+    for i in 0..count {
+        if i > 0 {
+            node = node.right_mut().unwrap();
+        }
+        node.rotate_left().unwrap();
+    }
+}
+
+/// Returns `true` if both trees have the same shape, irrespective of values.
+pub fn same_shape<A: Node, B: Node>(a: &A, b: &B) -> bool {
+    zip_check(a, b, |_, _| true)
+}
+
+/// Returns `true` if both trees have the same shape, and the values of the
+/// corresponding nodes are equal.
+pub fn tree_eq<A, B>(a: &A, b: &B) -> bool
+    where A: Node,
+          B: Node,
+          A::Value: PartialEq<B::Value>
+{
+    zip_check(a, b, |a, b| a.value() == b.value())
+}
+
+// Walks both trees in lock-step as long as they have the same shape and
+// `check` returns `true` for each pair of corresponding nodes.
+fn zip_check<A, B, F>(a: &A, b: &B, mut check: F) -> bool
+    where A: Node,
+          B: Node,
+          F: FnMut(&A, &B) -> bool
+{
+    let mut stack = vec![(a, b)];
+    while let Some((a, b)) = stack.pop() {
+        if !check(a, b) {
+            return false;
+        }
+        match (a.left(), b.left()) {
+            (Some(al), Some(bl)) => stack.push((al, bl)),
+            (None, None) => (),
+            _ => return false,
+        }
+        match (a.right(), b.right()) {
+            (Some(ar), Some(br)) => stack.push((ar, br)),
+            (None, None) => (),
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Walks both trees in lock-step, calling `f` in pre-order for every position
+/// which exists in at least one of them, with the nodes of either tree at that
+/// position (`None` if absent). Time complexity: O(n + m)
+pub fn zip_walk<'a, 'b, A, B, F>(a: &'a A, b: &'b B, mut f: F)
+    where A: Node,
+          B: Node,
+          F: FnMut(Option<&'a A>, Option<&'b B>)
+{
+    let mut stack = vec![(Some(a), Some(b))];
+    while let Some((a, b)) = stack.pop() {
+        f(a, b);
+        let (al, ar) = a.map_or((None, None), |a| (a.left(), a.right()));
+        let (bl, br) = b.map_or((None, None), |b| (b.left(), b.right()));
+        if ar.is_some() || br.is_some() {
+            stack.push((ar, br));
+        }
+        if al.is_some() || bl.is_some() {
+            stack.push((al, bl));
+        }
+    }
+}
+
+/// Returns `true` if one tree can be transformed into the other by swapping
+/// the left and right children of any number of nodes, irrespective of
+/// values. Time complexity: O(n) expected, with O(n) additional space.
+pub fn isomorphic<A: Node, B: Node>(a: &A, b: &B) -> bool {
+    let mut table = HashMap::new();
+    canonical_id(a, &mut table) == canonical_id(b, &mut table)
+}
+
+// Computes an identifier of the shape of the tree rooted at `root` which is
+// invariant under swapping of children (AHU algorithm). Ids are allocated from
+// `table`, which has to be shared for the ids to be comparable. An absent
+// child has id 0.
+fn canonical_id<N: Node>(root: &N, table: &mut HashMap<(usize, usize), usize>) -> usize {
+    // the flag is set if the children of the node are already on the stack
+    let mut stack = vec![(root, false)];
+    let mut ids = Vec::new();
+    while let Some((node, expanded)) = stack.pop() {
+        if expanded {
+            let rid = node.right().map_or(0, |_| ids.pop().unwrap());
+            let lid = node.left().map_or(0, |_| ids.pop().unwrap());
+            let key = (cmp::min(lid, rid), cmp::max(lid, rid));
+            let next_id = table.len() + 1;
+            ids.push(*table.entry(key).or_insert(next_id));
+        } else {
+            stack.push((node, true));
+            stack.extend(node.right().map(|n| (n, false)));
+            stack.extend(node.left().map(|n| (n, false)));
+        }
+    }
+    ids.pop().unwrap()
+}
+
+/// Returns a digest of the shape and the values of the tree rooted at `root`.
+/// The hash of each node combines its value with the hashes of its children
+/// (Merkle-style), each computed by a fresh hasher from `build`. Trees that
+/// are equal (as in `tree_eq`) have equal digests for a deterministic
+/// `build`, so comparing digests is a cheap pre-check for equality or
+/// changes. Time complexity: O(n)
+pub fn hash_tree<N, B>(root: &N, build: &B) -> u64
+    where N: Node,
+          N::Value: Hash,
+          B: BuildHasher
+{
+    // the flag is set if the children of the node are already on the stack
+    let mut stack = vec![(root, false)];
+    let mut hashes = Vec::new();
+    while let Some((node, expanded)) = stack.pop() {
+        if expanded {
+            let rhash = node.right().map(|_| hashes.pop().unwrap());
+            let lhash = node.left().map(|_| hashes.pop().unwrap());
+            let mut hasher = build.build_hasher();
+            node.value().hash(&mut hasher);
+            lhash.hash(&mut hasher);
+            rhash.hash(&mut hasher);
+            hashes.push(hasher.finish());
+        } else {
+            stack.push((node, true));
+            stack.extend(node.right().map(|n| (n, false)));
+            stack.extend(node.left().map(|n| (n, false)));
+        }
+    }
+    hashes.pop().unwrap()
+}
+
+/// Checks whether the tree rooted at `root` is a search tree under the
+/// ordering `cmp`, i.e. whether its values are sorted in-order. Equal values
+/// are allowed. Returns the first pair of in-order neighbours that is out of
+/// order, if any. Time complexity: O(n)
+pub fn is_search_tree<N, F>(root: &N, mut cmp: F) -> Result<(), (&N::Value, &N::Value)>
+    where N: Node,
+          F: FnMut(&N::Value, &N::Value) -> Ordering
+{
+    let mut iter = Iter::new(Some(root));
+    let mut prev = iter.next().unwrap();
+    for value in iter {
+        if cmp(prev, value) == Ordering::Greater {
+            return Err((prev, value));
+        }
+        prev = value;
+    }
+    Ok(())
+}
+
+/// Returns the lowest common ancestor of the two nodes reached by following
+/// the paths `a` and `b` from `root`, or `None` if the common prefix of the
+/// paths leads out of the tree. A path ends at its first `Stop`, if any.
+pub fn lca<'a, N: Node>(root: &'a N, a: &[WalkAction], b: &[WalkAction]) -> Option<&'a N> {
+    let common = a.iter()
+                  .zip(b)
+                  .take_while(|&(sa, sb)| sa == sb && *sa != WalkAction::Stop)
+                  .count();
+    descend(root, &a[..common])
+}
+
+/// Returns the node reached by following `path` from `root`, or `None` if the
+/// path leads out of the tree. The path ends at its first `Stop`, if any.
+pub fn descend<'a, N: Node>(root: &'a N, path: &[WalkAction]) -> Option<&'a N> {
+    use WalkAction::*;
+
+    let mut node = root;
+    for &action in path {
+        node = match action {
+            Left => node.left()?,
+            Right => node.right()?,
+            Stop => break,
+        };
+    }
+    Some(node)
+}
+
+/// Returns the lowest common ancestor of the first nodes (in pre-order) for
+/// which `pred_a` and `pred_b` return `true` respectively, or `None` if either
+/// of them does not match any node. Time complexity: O(n)
+pub fn lca_by<N, FA, FB>(root: &N, pred_a: FA, pred_b: FB) -> Option<&N>
+    where N: Node,
+          FA: FnMut(&N) -> bool,
+          FB: FnMut(&N) -> bool
+{
+    let path_a = find_path(root, pred_a)?;
+    let path_b = find_path(root, pred_b)?;
+    lca(root, &path_a, &path_b)
+}
+
+/// Searches depth-first for the first node (in pre-order) for which `pred`
+/// returns `true`, and returns the path from `root` to it, or `None` if no
+/// node matches. The path can be resolved back to the node using `descend`.
+/// Time complexity: O(n)
+pub fn find_path<N, F>(root: &N, mut pred: F) -> Option<Vec<WalkAction>>
+    where N: Node,
+          F: FnMut(&N) -> bool
+{
+    use WalkAction::*;
+
+    // (node, depth, the action that led to it)
+    let mut stack = vec![(root, 0, Stop)];
+    let mut path = Vec::new();
+    while let Some((node, depth, action)) = stack.pop() {
+        if depth > 0 {
+            path.truncate(depth - 1);
+            path.push(action);
+        }
+        if pred(node) {
+            return Some(path);
+        }
+        stack.extend(node.right().map(|n| (n, depth + 1, Right)));
+        stack.extend(node.left().map(|n| (n, depth + 1, Left)));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use NodeMut;
+    use test::TestNode;
+    use iter::Iter;
+    use super::{count_nodes, height, count_leaves};
+    use super::{leaves, internal_nodes};
+    use super::{invert, for_each_level_mut};
+    use super::drop_tree;
+    use super::{same_shape, tree_eq, isomorphic, zip_walk};
+    use super::hash_tree;
+    use super::is_search_tree;
+    use super::{lca, lca_by};
+    use super::{find_path, descend};
+    use super::rebalance;
+    use super::map_tree;
+    use super::{to_heap_vec, from_heap_vec};
+    use super::{diameter, diameter_path};
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn stats() {
+        let mut tn = Box::new(TestNode::new(0));
+        assert_eq!((count_nodes(&*tn), height(&*tn), count_leaves(&*tn)), (1, 0, 1));
+        for i in 1..200000 {
+            let mut parent = Box::new(TestNode::new(i));
+            if i % 2 == 0 {
+                parent.insert_right(Some(Box::new(TestNode::new(i))));
+            }
+            parent.insert_left(Some(tn));
+            tn = parent;
+        }
+        assert_eq!(count_nodes(&*tn), 299999);
+        assert_eq!(height(&*tn), 199999);
+        assert_eq!(count_leaves(&*tn), 100000);
+        let _: ::iter::IntoIter<TestNode<_>> = ::iter::IntoIter::new(Some(tn));
+    }
+
+    #[test]
+    fn invert_tree() {
+        let mut tn = TestNode::new(4);
+        let mut left = Box::new(TestNode::new(2));
+        left.insert_left(Some(Box::new(TestNode::new(1))));
+        left.insert_right(Some(Box::new(TestNode::new(3))));
+        tn.insert_left(Some(left));
+        tn.insert_right(Some(Box::new(TestNode::new(5))));
+        invert(&mut tn);
+        assert_eq!(Iter::new(Some(&tn)).cloned().collect::<Vec<_>>(), [5, 4, 3, 2, 1]);
+        invert(&mut tn);
+        assert_eq!(Iter::new(Some(&tn)).cloned().collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        tn.detach_right();
+        invert(&mut tn);
+        assert_eq!(Iter::new(Some(&tn)).cloned().collect::<Vec<_>>(), [4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn equality() {
+        use count::CountTree;
+
+        let mut ct: CountTree<_> = (0..7).collect();
+        let mut tn = TestNode::new(3);
+        let mut left = Box::new(TestNode::new(1));
+        left.insert_left(Some(Box::new(TestNode::new(0))));
+        left.insert_right(Some(Box::new(TestNode::new(2))));
+        let mut right = Box::new(TestNode::new(5));
+        right.insert_left(Some(Box::new(TestNode::new(4))));
+        right.insert_right(Some(Box::new(TestNode::new(6))));
+        tn.insert_left(Some(left));
+        tn.insert_right(Some(right));
+        let ct_root = ct.root().unwrap();
+        assert!(same_shape(ct_root, &tn));
+        assert!(tree_eq(ct_root, &tn));
+        tn.val = 30;
+        assert!(same_shape(ct_root, &tn));
+        assert!(!tree_eq(ct_root, &tn));
+        tn.left_mut().unwrap().detach_right();
+        assert!(!same_shape(ct_root, &tn));
+        assert!(!isomorphic(ct_root, &tn));
+    }
+
+    #[test]
+    fn isomorphism() {
+        // a left-leaning and a right-leaning zig-zag path of the same length
+        let mut a = Box::new(TestNode::new(0));
+        let mut b = Box::new(TestNode::new(0));
+        for i in 1..100 {
+            let mut pa = Box::new(TestNode::new(i));
+            let mut pb = Box::new(TestNode::new(i));
+            if i % 2 == 0 {
+                pa.insert_left(Some(a));
+                pb.insert_right(Some(b));
+            } else {
+                pa.insert_right(Some(a));
+                pb.insert_left(Some(b));
+            }
+            a = pa;
+            b = pb;
+        }
+        assert!(!same_shape(&*a, &*b));
+        assert!(isomorphic(&*a, &*b));
+        b.insert_left(Some(Box::new(TestNode::new(100))));
+        assert!(!isomorphic(&*a, &*b));
+    }
+
+    #[test]
+    fn structural_hash() {
+        use std::collections::hash_map::RandomState;
+        use test::{Shape, gen_tree, parse_tree};
+
+        let build = RandomState::new();
+        let hash = |s: &str| hash_tree(&parse_tree::<u32>(s).unwrap(), &build);
+        assert_eq!(hash("1(2,3(4,))"), hash("1(2,3(4,))"));
+        assert!(hash("1(2,3(4,))") != hash("1(2,3(,4))"));
+        assert!(hash("1(2,3(4,))") != hash("1(2,3(5,))"));
+        assert!(hash("1(2,)") != hash("1(,2)"));
+        assert!(hash("1(2(3,),)") != hash("1(2,3)"));
+
+        let tree = gen_tree(Shape::LeftSpine, 100000).unwrap();
+        let mut ct: ::count::CountTree<_> = (0..100000).collect();
+        assert_eq!(hash_tree(&*tree, &build), hash_tree(&*tree, &build));
+        assert!(hash_tree(&*tree, &build) != hash_tree(ct.root().unwrap(), &build));
+        drop_tree::<TestNode<_>>(tree);
+    }
+
+    #[test]
+    fn lowest_common_ancestor() {
+        use WalkAction::*;
+        use count::CountTree;
+        use Node;
+
+        let mut ct: CountTree<_> = (0..15).collect();
+        let root = ct.root().unwrap();
+        assert_eq!(lca(root, &[Left, Left, Right], &[Left, Right]).map(|n| *n.value()), Some(3));
+        assert_eq!(lca(root, &[Left, Left], &[Left, Left, Left]).map(|n| *n.value()), Some(1));
+        assert_eq!(lca(root, &[Right, Stop, Left], &[Right, Stop]).map(|n| *n.value()), Some(11));
+        assert_eq!(lca(root, &[], &[Right]).map(|n| *n.value()), Some(7));
+        assert!(lca(root, &[Left; 5], &[Left; 5]).is_none());
+
+        assert_eq!(lca_by(root, |n| *n.value() == 8, |n| *n.value() == 10).map(|n| *n.value()),
+                   Some(9));
+        assert_eq!(lca_by(root, |n| *n.value() == 4, |n| *n.value() == 6).map(|n| *n.value()),
+                   Some(5));
+        assert_eq!(lca_by(root, |n| *n.value() == 4, |n| *n.value() == 3).map(|n| *n.value()),
+                   Some(3));
+        assert!(lca_by(root, |n| *n.value() == 4, |n| *n.value() == 15).is_none());
+    }
+
+    #[test]
+    fn dsw() {
+        use test::{compute_level, Level};
+
+        for &n in &[1, 2, 3, 7, 8, 100, 1023, 5000] {
+            // zig-zag spine
+            let mut tn = Box::new(TestNode::new(0));
+            for i in 1..n {
+                let mut parent = Box::new(TestNode::new(i));
+                if i % 2 == 0 {
+                    parent.insert_left(Some(tn));
+                } else {
+                    parent.insert_right(Some(tn));
+                }
+                tn = parent;
+            }
+            let before: Vec<_> = Iter::new(Some(&*tn)).cloned().collect();
+            rebalance(&mut *tn);
+            let after: Vec<_> = Iter::new(Some(&*tn)).cloned().collect();
+            assert_eq!(before, after);
+            assert_eq!(count_nodes(&*tn), n);
+            let min_height = (0..).find(|&h| (1 << (h + 1)) > n).unwrap();
+            assert_eq!(height(&*tn), min_height);
+            assert!(compute_level(&*tn, 1).is_balanced());
+            if n == 1023 {
+                assert_eq!(compute_level(&*tn, 0), Level::Balanced(10));
+            }
+            let _: ::iter::IntoIter<TestNode<_>> = ::iter::IntoIter::new(Some(tn));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn map() {
+        let mut tn = Box::new(TestNode::new(0));
+        for i in 1..100000 {
+            let mut parent = Box::new(TestNode::new(i));
+            parent.insert_right(Some(tn));
+            tn = parent;
+        }
+        let mapped: Box<TestNode<String>> = map_tree(&*tn, |v| v.to_string(), |v| Box::new(TestNode::new(v)));
+        assert!(same_shape(&*tn, &*mapped));
+        assert_eq!(mapped.val, "99999");
+        assert_eq!(mapped.right.as_ref().unwrap().val, "99998");
+        let _: ::iter::IntoIter<TestNode<_>> = ::iter::IntoIter::new(Some(tn));
+        let _: ::iter::IntoIter<TestNode<_>> = ::iter::IntoIter::new(Some(mapped));
+    }
+
+    #[test]
+    fn diameters() {
+        use WalkAction::*;
+
+        let mut tn = TestNode::new(0);
+        assert_eq!(diameter(&tn), 0);
+        assert_eq!(diameter_path(&tn), (0, vec![], vec![]));
+
+        // a left spine of 4 below the root, and a right spine of 3 below the
+        // left child of the root
+        let mut left = Box::new(TestNode::new(1));
+        let mut l = Box::new(TestNode::new(2));
+        for i in 3..6 {
+            let mut p = Box::new(TestNode::new(i));
+            p.insert_left(Some(l));
+            l = p;
+        }
+        let mut r = Box::new(TestNode::new(6));
+        for i in 7..9 {
+            let mut p = Box::new(TestNode::new(i));
+            p.insert_right(Some(r));
+            r = p;
+        }
+        left.insert_left(Some(l));
+        left.insert_right(Some(r));
+        tn.insert_left(Some(left));
+        tn.insert_right(Some(Box::new(TestNode::new(9))));
+
+        assert_eq!(diameter(&tn), 7);
+        let (len, a, b) = diameter_path(&tn);
+        assert_eq!(len, 7);
+        assert_eq!(a, [Left, Left, Left, Left, Left]);
+        assert_eq!(b, [Left, Right, Right, Right]);
+
+        // a single spine: the root is an end
+        let mut spine = TestNode::new(0);
+        spine.insert_right(Some(Box::new(TestNode::new(1))));
+        assert_eq!(diameter_path(&spine), (1, vec![], vec![Right]));
+    }
+
+    #[test]
+    fn paths() {
+        use WalkAction::*;
+        use count::CountTree;
+        use Node;
+
+        let mut ct: CountTree<_> = (0..15).collect();
+        let root = ct.root().unwrap();
+        for i in 0..15 {
+            let path = find_path(root, |n| *n.value() == i).unwrap();
+            assert_eq!(path.len(), 3 - (i as u32 + 1).trailing_zeros() as usize);
+            assert_eq!(descend(root, &path).map(|n| *n.value()), Some(i));
+        }
+        assert_eq!(find_path(root, |n| *n.value() == 10), Some(vec![Right, Left, Right]));
+        assert_eq!(find_path(root, |n| *n.value() == 15), None);
+        assert_eq!(descend(root, &[Right, Right, Stop, Left]).map(|n| *n.value()), Some(13));
+        assert!(descend(root, &[Right, Right, Right, Right]).is_none());
+    }
+
+    #[test]
+    fn search_tree() {
+        use count::CountTree;
+
+        let mut ct: CountTree<_> = (0..15).collect();
+        assert_eq!(is_search_tree(ct.root().unwrap(), Ord::cmp), Ok(()));
+        assert_eq!(is_search_tree(ct.root().unwrap(), |a, b| b.cmp(a)), Err((&0, &1)));
+
+        let mut tn = TestNode::new(4);
+        let mut left = Box::new(TestNode::new(2));
+        left.insert_left(Some(Box::new(TestNode::new(2))));
+        left.insert_right(Some(Box::new(TestNode::new(5))));
+        tn.insert_left(Some(left));
+        tn.insert_right(Some(Box::new(TestNode::new(6))));
+        assert_eq!(is_search_tree(&tn, Ord::cmp), Err((&5, &4)));
+        tn.left_mut().unwrap().right_mut().unwrap().val = 3;
+        assert_eq!(is_search_tree(&tn, Ord::cmp), Ok(()));
+    }
+
+    #[test]
+    fn level_order_mut() {
+        let mut tn = TestNode::new(4);
+        let mut left = Box::new(TestNode::new(2));
+        left.insert_left(Some(Box::new(TestNode::new(1))));
+        left.insert_right(Some(Box::new(TestNode::new(3))));
+        let mut right = Box::new(TestNode::new(5));
+        right.insert_right(Some(Box::new(TestNode::new(6))));
+        tn.insert_left(Some(left));
+        tn.insert_right(Some(right));
+        let before = map_tree(&tn, |v| *v, |v| Box::new(TestNode::new(v)));
+
+        let mut visited = vec![];
+        for_each_level_mut(&mut tn, |depth, value| {
+            visited.push((depth, *value));
+            *value *= 10;
+        });
+        assert_eq!(visited, [(0, 4), (1, 2), (1, 5), (2, 1), (2, 3), (2, 6)]);
+        assert!(same_shape(&tn, &*before));
+        assert_eq!(Iter::new(Some(&tn)).cloned().collect::<Vec<_>>(), [10, 20, 30, 40, 50, 60]);
+
+        let mut leaf = TestNode::new(1);
+        for_each_level_mut(&mut leaf, |depth, value| *value += depth + 1);
+        assert_eq!(leaf.val, 2);
+    }
+
+    #[test]
+    fn leaves_and_internal_nodes() {
+        use Node;
+
+        let mut tn = TestNode::new(4);
+        let mut left = Box::new(TestNode::new(2));
+        left.insert_left(Some(Box::new(TestNode::new(1))));
+        left.insert_right(Some(Box::new(TestNode::new(3))));
+        tn.insert_left(Some(left));
+        tn.insert_right(Some(Box::new(TestNode::new(5))));
+        assert_eq!(leaves(&tn).map(|n| *n.value()).collect::<Vec<_>>(), [1, 3, 5]);
+        assert_eq!(internal_nodes(&tn).map(|n| *n.value()).collect::<Vec<_>>(), [2, 4]);
+
+        let leaf = TestNode::new(1);
+        assert_eq!(leaves(&leaf).count(), 1);
+        assert_eq!(internal_nodes(&leaf).count(), 0);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn drop_deep_tree() {
+        // a zig-zag path, so that both the left and right links are deep
+        let mut tn = Box::new(TestNode::new(0));
+        for i in 1..200000 {
+            let mut parent = Box::new(TestNode::new(i));
+            if i % 2 == 0 {
+                parent.insert_left(Some(tn));
+            } else {
+                parent.insert_right(Some(tn));
+            }
+            tn = parent;
+        }
+        drop_tree::<TestNode<_>>(tn);
+        drop_tree::<TestNode<_>>(Box::new(TestNode::new(0)));
+    }
+
+    #[test]
+    fn zip() {
+        use Node;
+
+        let mut a = TestNode::new(4);
+        let mut left = Box::new(TestNode::new(2));
+        left.insert_left(Some(Box::new(TestNode::new(1))));
+        a.insert_left(Some(left));
+        a.insert_right(Some(Box::new(TestNode::new(5))));
+        let mut b = TestNode::new('d');
+        let mut left = Box::new(TestNode::new('b'));
+        left.insert_right(Some(Box::new(TestNode::new('c'))));
+        b.insert_left(Some(left));
+
+        let mut pairs = vec![];
+        zip_walk(&a, &b, |a, b| pairs.push((a.map(|n| *n.value()), b.map(|n| *n.value()))));
+        assert_eq!(pairs,
+                   [(Some(4), Some('d')),
+                    (Some(2), Some('b')),
+                    (Some(1), None),
+                    (None, Some('c')),
+                    (Some(5), None)]);
+    }
+
+    #[test]
+    fn heap_layout() {
+        use test::{Shape, gen_tree};
+
+        let tree = gen_tree(Shape::PerfectlyBalanced, 7).unwrap();
+        assert_eq!(to_heap_vec(&*tree), [Some(3), Some(1), Some(5), Some(0), Some(2), Some(4), Some(6)]);
+        let tree = gen_tree(Shape::RightSpine, 3).unwrap();
+        let heap = to_heap_vec(&*tree);
+        assert_eq!(heap, [Some(0), None, Some(1), None, None, None, Some(2)]);
+        let rebuilt: Box<TestNode<_>> = from_heap_vec(heap, |v| Box::new(TestNode::new(v))).unwrap();
+        assert!(tree_eq(&*tree, &*rebuilt));
+
+        // the orphaned 4 is dropped
+        let tree: Box<TestNode<_>> = from_heap_vec(vec![Some(1), None, Some(2), Some(4)],
+                                                   |v| Box::new(TestNode::new(v))).unwrap();
+        assert_eq!(Iter::new(Some(&*tree)).cloned().collect::<Vec<_>>(), [1, 2]);
+        assert!(from_heap_vec::<u32, Box<TestNode<_>>, _>(vec![None, Some(1)], |v| Box::new(TestNode::new(v))).is_none());
+        assert!(from_heap_vec::<u32, Box<TestNode<_>>, _>(vec![], |v| Box::new(TestNode::new(v))).is_none());
+    }
+}
@@ -0,0 +1,154 @@
+//! Trees whose nodes live in a custom allocator (requires the `allocator_api`
+//! feature, and hence a nightly compiler).
+//!
+//! `AllocNode<T, A>` links its children with `Box<AllocNode<T, A>, A>`, which
+//! is also its `NodePtr`, so all the generic algorithms and iterators work on
+//! it unchanged. Nodes are created with `AllocNode::new_in`, which allows, for
+//! instance, placing a whole tree in a bump or pool allocator.
+
+use std::alloc::{Allocator, Global};
+use std::mem;
+
+use Node;
+use NodeMut;
+
+/// A minimal `Node` implementation, like `TestNode`, whose nodes are
+/// allocated by `A`.
+#[derive(Debug)]
+pub struct AllocNode<T, A: Allocator = Global> {
+    pub val: T,
+    pub left: Option<Box<AllocNode<T, A>, A>>,
+    pub right: Option<Box<AllocNode<T, A>, A>>,
+}
+
+impl<T, A: Allocator> AllocNode<T, A> {
+    pub fn new(val: T) -> AllocNode<T, A> {
+        AllocNode {
+            val,
+            left: None,
+            right: None,
+        }
+    }
+
+    /// Allocates a childless node holding `val` in `alloc`.
+    pub fn new_in(val: T, alloc: A) -> Box<AllocNode<T, A>, A> {
+        Box::new_in(AllocNode::new(val), alloc)
+    }
+}
+
+impl<T, A: Allocator> Node for AllocNode<T, A> {
+    type Value = T;
+
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    fn value(&self) -> &T {
+        &self.val
+    }
+}
+
+impl<T, A: Allocator> NodeMut for AllocNode<T, A> {
+    type NodePtr = Box<AllocNode<T, A>, A>;
+
+    fn detach_left(&mut self) -> Option<Self::NodePtr> {
+        self.left.take()
+    }
+
+    fn detach_right(&mut self) -> Option<Self::NodePtr> {
+        self.right.take()
+    }
+
+    fn insert_left(&mut self, mut st: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.left, &mut st);
+        st
+    }
+
+    fn insert_right(&mut self, mut st: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.right, &mut st);
+        st
+    }
+
+    fn value_mut(&mut self) -> &mut T {
+        &mut self.val
+    }
+
+    fn into_parts(self) -> (T, Option<Self::NodePtr>, Option<Self::NodePtr>) {
+        (self.val, self.left, self.right)
+    }
+
+    fn left_mut(&mut self) -> Option<&mut Self> {
+        self.left.as_deref_mut()
+    }
+
+    fn right_mut(&mut self) -> Option<&mut Self> {
+        self.right.as_deref_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::{AllocError, Allocator, Global, Layout};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+
+    use NodeMut;
+    use algos;
+    use iter::IntoIter;
+    use unbox::TreePtr;
+    use super::AllocNode;
+
+    /// Counts the live allocations, and forwards to `Global`.
+    #[derive(Clone, Copy)]
+    struct Counting<'a>(&'a Cell<usize>);
+
+    unsafe impl<'a> Allocator for Counting<'a> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.set(self.0.get() - 1);
+            unsafe { Global.deallocate(ptr, layout) }
+        }
+    }
+
+    #[test]
+    fn custom_allocator() {
+        let live = Cell::new(0);
+        let alloc = Counting(&live);
+        let mut root = AllocNode::new_in(1, alloc);
+        root.insert_left(Some(AllocNode::new_in(0, alloc)));
+        root.insert_right(Some(AllocNode::new_in(2, alloc)));
+        root.rotate_left().unwrap();
+        assert_eq!(root.val, 2);
+        assert_eq!(live.get(), 3);
+
+        let mut iter = IntoIter::<AllocNode<_, _>>::new(Some(root));
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(live.get(), 2);
+        drop(iter);
+        assert_eq!(live.get(), 0);
+
+        let mut spine = AllocNode::new_in(0, alloc);
+        for i in 1..100000 {
+            let mut node = AllocNode::new_in(i, alloc);
+            node.insert_left(Some(spine));
+            spine = node;
+        }
+        algos::drop_tree::<AllocNode<_, _>>(spine);
+        assert_eq!(live.get(), 0);
+    }
+
+    #[test]
+    fn global_tree_ptr() {
+        let mut root = <Box<AllocNode<_>> as TreePtr>::new_ptr(AllocNode::new(1));
+        root.insert_right(Some(AllocNode::new_in(2, Global)));
+        assert_eq!(IntoIter::<AllocNode<_>>::new(Some(root)).collect::<Vec<_>>(), [1, 2]);
+    }
+}
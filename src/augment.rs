@@ -0,0 +1,506 @@
+//! Lists maintaining a user-defined summary of every subtree.
+//!
+//! An `AugmentedCountTree<T, A>` is a list like `CountTree`, whose nodes also
+//! cache a summary of the elements of their subtrees, such as their sum or
+//! their minimum. The summaries are computed by the `Augment` implementation
+//! `A`, and kept up to date through all rotations and edits, so that the
+//! summary of any range of elements takes O(log(n)) time to compute. Any
+//! associative way of combining elements (a monoid, or a semigroup) works.
+//!
+//! ```
+//! use binary_tree::augment::{AugmentedCountTree, Sum};
+//!
+//! let mut widths: AugmentedCountTree<u32, Sum> = vec![3, 1, 4, 1, 5].into_iter().collect();
+//! widths.insert(2, 9);
+//! assert_eq!(widths.range_summary(1..4), Some(14));
+//! assert_eq!(widths.summary(), Some(&23));
+//! ```
+
+use std::cmp;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{self, Range};
+
+use BinaryTree;
+use Node;
+use NodeMut;
+use avl::{self, Balanced};
+use iter::{IntoIter, Iter};
+
+/// Computes the summaries of the subtrees of an `AugmentedCountTree`.
+pub trait Augment<T> {
+    type Summary: Clone;
+
+    /// Returns the summary of a subtree given those of its left and right
+    /// subtrees (`None` if missing) and the value of its root. It should be
+    /// associative, i.e. summarize the elements in order regardless of the
+    /// shape of the tree.
+    fn combine(left: Option<&Self::Summary>, value: &T, right: Option<&Self::Summary>) -> Self::Summary;
+}
+
+/// Summarizes a subtree by the sum of its elements.
+pub struct Sum;
+
+impl<T> Augment<T> for Sum
+    where T: ops::Add<Output = T> + Clone
+{
+    type Summary = T;
+
+    fn combine(left: Option<&T>, value: &T, right: Option<&T>) -> T {
+        let sum = match left {
+            Some(left) => left.clone() + value.clone(),
+            None => value.clone(),
+        };
+        match right {
+            Some(right) => sum + right.clone(),
+            None => sum,
+        }
+    }
+}
+
+/// Summarizes a subtree by its least element.
+pub struct Min;
+
+impl<T: Ord + Clone> Augment<T> for Min {
+    type Summary = T;
+
+    fn combine(left: Option<&T>, value: &T, right: Option<&T>) -> T {
+        left.into_iter().chain(Some(value)).chain(right).min().unwrap().clone()
+    }
+}
+
+/// Summarizes a subtree by its greatest element.
+pub struct Max;
+
+impl<T: Ord + Clone> Augment<T> for Max {
+    type Summary = T;
+
+    fn combine(left: Option<&T>, value: &T, right: Option<&T>) -> T {
+        left.into_iter().chain(Some(value)).chain(right).max().unwrap().clone()
+    }
+}
+
+/// Node of an `AugmentedCountTree`.
+pub struct AugNode<T, A: Augment<T>> {
+    val: T,
+    left: Option<Box<AugNode<T, A>>>,
+    right: Option<Box<AugNode<T, A>>>,
+    summary: A::Summary,
+    count: u32,
+    height: u16,
+    _augment: PhantomData<A>,
+}
+
+impl<T, A: Augment<T>> AugNode<T, A> {
+    fn new(val: T) -> AugNode<T, A> {
+        AugNode {
+            summary: A::combine(None, &val, None),
+            val,
+            left: None,
+            right: None,
+            count: 1,
+            height: 0,
+            _augment: PhantomData,
+        }
+    }
+
+    /// Returns the summary of the subtree rooted at this node.
+    pub fn summary(&self) -> &A::Summary {
+        &self.summary
+    }
+
+    fn update_stats(&mut self) {
+        let (count, height) = avl::stats(self.left.as_deref(), self.right.as_deref());
+        self.count = count;
+        self.height = height;
+        self.summary = A::combine(self.left.as_ref().map(|node| &node.summary),
+                                  &self.val,
+                                  self.right.as_ref().map(|node| &node.summary));
+    }
+
+    // Returns the summary of the elements `start..end` of this subtree, which
+    // is a non-empty range. Recursion depth is O(height).
+    fn range_summary(&self, start: u32, end: u32) -> A::Summary {
+        if start == 0 && end == self.count {
+            return self.summary.clone();
+        }
+        let lcount = avl::lcount(self);
+        if end <= lcount {
+            return self.left.as_ref().unwrap().range_summary(start, end);
+        } else if start > lcount {
+            return self.right.as_ref().unwrap().range_summary(start - lcount - 1, end - lcount - 1);
+        }
+        let left = if start < lcount {
+            Some(self.left.as_ref().unwrap().range_summary(start, lcount))
+        } else {
+            None
+        };
+        let right = if end > lcount + 1 {
+            Some(self.right.as_ref().unwrap().range_summary(0, end - lcount - 1))
+        } else {
+            None
+        };
+        A::combine(left.as_ref(), &self.val, right.as_ref())
+    }
+}
+
+impl<T, A: Augment<T>> Balanced for AugNode<T, A> {
+    fn count(&self) -> u32 {
+        self.count
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+impl<T, A: Augment<T>> Node for AugNode<T, A> {
+    type Value = T;
+
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    fn value(&self) -> &T {
+        &self.val
+    }
+}
+
+impl<T, A: Augment<T>> NodeMut for AugNode<T, A> {
+    type NodePtr = Box<AugNode<T, A>>;
+
+    fn detach_left(&mut self) -> Option<Self::NodePtr> {
+        let tree = self.left.take();
+        self.update_stats();
+        tree
+    }
+
+    fn detach_right(&mut self) -> Option<Self::NodePtr> {
+        let tree = self.right.take();
+        self.update_stats();
+        tree
+    }
+
+    fn insert_left(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.left, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn insert_right(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.right, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn value_mut(&mut self) -> &mut T {
+        &mut self.val
+    }
+
+    fn into_parts(self) -> (T, Option<Self::NodePtr>, Option<Self::NodePtr>) {
+        (self.val, self.left, self.right)
+    }
+
+    fn left_mut(&mut self) -> Option<&mut Self> {
+        self.left.as_deref_mut()
+    }
+
+    fn right_mut(&mut self) -> Option<&mut Self> {
+        self.right.as_deref_mut()
+    }
+}
+
+/// A list caching the summaries (computed by `A`) of its subtrees.
+///
+/// Elements can't be borrowed mutably, since the summaries must be updated
+/// after any modification; use `set` or `modify` instead.
+pub struct AugmentedCountTree<T, A: Augment<T>> {
+    root: Option<Box<AugNode<T, A>>>,
+}
+
+impl<T, A: Augment<T>> AugmentedCountTree<T, A> {
+    /// Returns an empty `AugmentedCountTree`.
+    pub fn new() -> AugmentedCountTree<T, A> {
+        AugmentedCountTree { root: None }
+    }
+
+    /// Returns the number elements in the tree. Time complexity: O(1)
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |node| node.count as usize)
+    }
+
+    /// Returns `true` if the tree contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the element at the given index, or `None` if index is out of
+    /// bounds. Time complexity: O(log(n))
+    pub fn get(&self, index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut index = index as u32;
+        let mut node = self.root.as_deref().unwrap();
+        loop {
+            let lcount = avl::lcount(node);
+            match index.cmp(&lcount) {
+                cmp::Ordering::Less => node = node.left.as_deref().unwrap(),
+                cmp::Ordering::Equal => return Some(&node.val),
+                cmp::Ordering::Greater => {
+                    index -= lcount + 1;
+                    node = node.right.as_deref().unwrap();
+                }
+            }
+        }
+    }
+
+    /// Replaces the element at `index`, returning the old one. Time
+    /// complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// if `index >= len()`
+    pub fn set(&mut self, index: usize, value: T) -> T {
+        let mut value = Some(value);
+        self.modify(index, |old| mem::replace(old, value.take().unwrap()))
+    }
+
+    /// Calls `f` on the element at `index`, and updates the summaries.
+    /// Returns the result of `f`. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// if `index >= len()`
+    pub fn modify<F, R>(&mut self, index: usize, f: F) -> R
+        where F: FnOnce(&mut T) -> R
+    {
+        assert!(index < self.len(), "index out of bounds!");
+        let mut index = index as u32;
+        let mut ret = None;
+        self.root.as_mut().unwrap().walk_reshape(|node| avl::index_walker(&mut index, node),
+                                                 |node| {
+                                                     ret = Some(f(&mut node.val));
+                                                     node.update_stats();
+                                                 },
+                                                 |_, _| ());
+        ret.unwrap()
+    }
+
+    /// Inserts an element at `index`, shifting all the elements after it.
+    /// Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// if `index > len()`
+    pub fn insert(&mut self, index: usize, value: T) {
+        avl::insert(&mut self.root, index, Box::new(AugNode::new(value)));
+    }
+
+    /// Removes the element at `index`, shifting all the elements after it.
+    /// Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// if `index >= len()`
+    pub fn remove(&mut self, index: usize) -> T {
+        avl::remove(&mut self.root, index).val
+    }
+
+    /// Prepends an element. Time complexity: O(log(n))
+    pub fn push_front(&mut self, value: T) {
+        self.insert(0, value);
+    }
+
+    /// Appends an element. Time complexity: O(log(n))
+    pub fn push_back(&mut self, value: T) {
+        let len = self.len();
+        self.insert(len, value);
+    }
+
+    /// Removes and returns the first element, or `None` if empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.remove(0))
+        }
+    }
+
+    /// Removes and returns the last element, or `None` if empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            None
+        } else {
+            Some(self.remove(len - 1))
+        }
+    }
+
+    /// Returns the summary of all the elements, or `None` if empty. Time
+    /// complexity: O(1)
+    pub fn summary(&self) -> Option<&A::Summary> {
+        self.root.as_ref().map(|node| &node.summary)
+    }
+
+    /// Returns the summary of the elements in `range`, or `None` if it is
+    /// empty. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// if the range is decreasing or extends past `len()`
+    pub fn range_summary(&self, range: Range<usize>) -> Option<A::Summary> {
+        assert!(range.start <= range.end && range.end <= self.len(),
+                "range {:?} out of bounds for length {}", range, self.len());
+        if range.start < range.end {
+            let root = self.root.as_ref().unwrap();
+            Some(root.range_summary(range.start as u32, range.end as u32))
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over the elements.
+    pub fn iter(&self) -> Iter<'_, AugNode<T, A>> {
+        Iter::with_len(self.root.as_deref(), self.len())
+    }
+}
+
+impl<T, A: Augment<T>> BinaryTree for AugmentedCountTree<T, A> {
+    type Node = AugNode<T, A>;
+
+    fn root(&self) -> Option<&Self::Node> {
+        self.root.as_deref()
+    }
+}
+
+impl<T, A: Augment<T>> Default for AugmentedCountTree<T, A> {
+    fn default() -> AugmentedCountTree<T, A> {
+        AugmentedCountTree::new()
+    }
+}
+
+impl<T, A: Augment<T>> FromIterator<T> for AugmentedCountTree<T, A> {
+    fn from_iter<I>(iterable: I) -> Self
+        where I: IntoIterator<Item = T>
+    {
+        let values: Vec<T> = iterable.into_iter().collect();
+        AugmentedCountTree { root: avl::build_balanced(values.len(), &mut values.into_iter(), &AugNode::new) }
+    }
+}
+
+impl<T, A: Augment<T>> IntoIterator for AugmentedCountTree<T, A> {
+    type Item = T;
+    type IntoIter = IntoIter<AugNode<T, A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.root)
+    }
+}
+
+impl<'a, T, A: Augment<T>> IntoIterator for &'a AugmentedCountTree<T, A> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, AugNode<T, A>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use BinaryTree;
+    use Node;
+    use test::compute_level;
+    use super::{Augment, AugmentedCountTree, AugNode, Max, Min, Sum};
+
+    fn check<T, A>(root: &AugNode<T, A>)
+        where A: Augment<T>,
+              A::Summary: PartialEq + ::std::fmt::Debug
+    {
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            let expected = A::combine(node.left().map(AugNode::summary), node.value(), node.right().map(AugNode::summary));
+            assert_eq!(*node.summary(), expected);
+            stack.extend(node.left());
+            stack.extend(node.right());
+        }
+        assert!(compute_level(root, 1).is_balanced());
+    }
+
+    #[test]
+    fn range_sums() {
+        let mut tree: AugmentedCountTree<i64, Sum> = AugmentedCountTree::new();
+        let mut model = Vec::new();
+        for i in 0..300i64 {
+            let at = (i as usize * 7) % (model.len() + 1);
+            tree.insert(at, i - 150);
+            model.insert(at, i - 150);
+            check(tree.root().unwrap());
+        }
+        for i in 0..100 {
+            let at = (i * 11) % tree.len();
+            assert_eq!(tree.remove(at), model.remove(at));
+            tree.set(at / 2, i as i64);
+            model[at / 2] = i as i64;
+            tree.modify(at / 3, |v| *v *= 2);
+            model[at / 3] *= 2;
+            check(tree.root().unwrap());
+        }
+        for start in (0..model.len()).step_by(13) {
+            for end in (start..model.len() + 1).step_by(17) {
+                let expected = if start < end { Some(model[start..end].iter().sum()) } else { None };
+                assert_eq!(tree.range_summary(start..end), expected);
+            }
+        }
+        assert_eq!(tree.summary(), Some(&model.iter().sum()));
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), model);
+        assert_eq!(tree.pop_front(), Some(model.remove(0)));
+        assert_eq!(tree.pop_back(), model.pop());
+        assert_eq!(tree.get(5), Some(&model[5]));
+        assert_eq!(tree.into_iter().collect::<Vec<_>>(), model);
+    }
+
+    #[test]
+    fn min_max() {
+        let values = [5, 3, 8, 1, 9, 2, 7];
+        let mins: AugmentedCountTree<u32, Min> = values.iter().cloned().collect();
+        let maxs: AugmentedCountTree<u32, Max> = values.iter().cloned().collect();
+        assert_eq!(mins.range_summary(0..3), Some(3));
+        assert_eq!(mins.range_summary(4..7), Some(2));
+        assert_eq!(maxs.range_summary(1..4), Some(8));
+        assert_eq!(maxs.summary(), Some(&9));
+        assert_eq!(maxs.range_summary(2..2), None);
+    }
+
+    // a non-commutative summary: the elements in order, and their count
+    struct Concat;
+
+    impl Augment<char> for Concat {
+        type Summary = (String, usize);
+
+        fn combine(left: Option<&(String, usize)>, value: &char, right: Option<&(String, usize)>) -> (String, usize) {
+            let mut s = left.map_or(String::new(), |l| l.0.clone());
+            s.push(*value);
+            s.push_str(right.map_or("", |r| &r.0));
+            let len = s.len();
+            (s, len)
+        }
+    }
+
+    #[test]
+    fn custom() {
+        let mut tree: AugmentedCountTree<char, Concat> = "binary".chars().collect();
+        tree.push_back('-');
+        tree.push_front('<');
+        tree.insert(4, '_');
+        check(tree.root().unwrap());
+        assert_eq!(tree.summary().map(|s| &*s.0), Some("<bin_ary-"));
+        assert_eq!(tree.range_summary(2..7), Some(("in_ar".to_string(), 5)));
+    }
+}
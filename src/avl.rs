@@ -0,0 +1,137 @@
+//! AVL balancing and positional edits of boxed trees whose nodes cache their
+//! subtree sizes and heights, shared by `LazyTree` and `AugmentedCountTree`.
+
+use std::cmp;
+
+use NodeMut;
+use WalkAction;
+
+/// A node caching the size and the height of its subtree, which it keeps up
+/// to date whenever its children are detached or replaced.
+pub trait Balanced: NodeMut<NodePtr = Box<Self>> {
+    fn count(&self) -> u32;
+
+    fn height(&self) -> u16;
+}
+
+/// Returns the size and the height of a tree with the given subtrees.
+pub fn stats<N: Balanced>(left: Option<&N>, right: Option<&N>) -> (u32, u16) {
+    let count = left.map_or(0, N::count) + right.map_or(0, N::count) + 1;
+    let height = match (left, right) {
+        (None, None) => 0,
+        (left, right) => cmp::max(left.map_or(0, N::height), right.map_or(0, N::height)) + 1,
+    };
+    (count, height)
+}
+
+pub fn lcount<N: Balanced>(node: &N) -> u32 {
+    node.left().map_or(0, N::count)
+}
+
+// generalized version of AVL tree balance factor: h(left) - h(right)
+fn balance_factor<N: Balanced>(node: &N) -> i32 {
+    node.left().map_or(-1, |n| n.height() as i32) - node.right().map_or(-1, |n| n.height() as i32)
+}
+
+/// AVL tree algorithm, as in `CountNode`.
+pub fn rebalance<N: Balanced>(node: &mut N) {
+    if balance_factor(node) > 1 {
+        if let Some(left) = node.left_mut() {
+            if balance_factor(left) < 0 {
+                left.rotate_left().unwrap();
+            }
+        }
+        node.rotate_right().unwrap();
+    } else if balance_factor(node) < -1 {
+        if let Some(right) = node.right_mut() {
+            if balance_factor(right) > 0 {
+                right.rotate_right().unwrap();
+            }
+        }
+        node.rotate_left().unwrap();
+    }
+}
+
+/// Steps towards the node at position `index` of the current subtree.
+pub fn index_walker<N: Balanced>(index: &mut u32, node: &N) -> WalkAction {
+    let lcount = lcount(node);
+    if *index < lcount {
+        WalkAction::Left
+    } else if *index == lcount {
+        WalkAction::Stop
+    } else {
+        *index -= lcount + 1;
+        WalkAction::Right
+    }
+}
+
+/// Inserts the childless `new_node` at `index` of the tree at `root`.
+///
+/// ## Panics
+///
+/// if `index` is greater than the size of the tree
+pub fn insert<N: Balanced>(root: &mut Option<Box<N>>, index: usize, new_node: Box<N>) {
+    let len = root.as_ref().map_or(0, |node| node.count() as usize);
+    assert!(index <= len, "index out of bounds!");
+    let root = match *root {
+        Some(ref mut root) => root,
+        None => {
+            *root = Some(new_node);
+            return;
+        }
+    };
+    let mut index = index as u32;
+    if index as usize == len {
+        root.walk_reshape(|_| WalkAction::Right,
+                          move |node| {
+                              node.insert_right(Some(new_node));
+                          },
+                          |node, _| rebalance(node));
+    } else {
+        root.walk_reshape(|node| index_walker(&mut index, node),
+                          move |node| node.insert_before(new_node, |node, _| rebalance(node)),
+                          |node, _| rebalance(node));
+    }
+}
+
+/// Removes the node at `index` of the tree at `root`, and returns it without
+/// its children.
+///
+/// ## Panics
+///
+/// if `index` is not less than the size of the tree
+pub fn remove<N: Balanced>(root: &mut Option<Box<N>>, index: usize) -> Box<N> {
+    let len = root.as_ref().map_or(0, |node| node.count() as usize);
+    assert!(index < len, "index out of bounds!");
+    if len == 1 {
+        return root.take().unwrap();
+    }
+    let mut index = index as u32;
+    root.as_mut()
+        .unwrap()
+        .walk_extract(|node| index_walker(&mut index, node),
+                      |node, ret| {
+                          *ret = node.try_remove(|node, _| rebalance(node));
+                      },
+                      |node, _| rebalance(node))
+        .unwrap()
+}
+
+/// Builds a perfectly balanced tree out of the next `len` elements of `iter`,
+/// making the nodes with `new_node`. Recursion depth is O(log(len)).
+pub fn build_balanced<N, I, F>(len: usize, iter: &mut I, new_node: &F) -> Option<Box<N>>
+    where N: Balanced,
+          I: Iterator,
+          F: Fn(I::Item) -> N
+{
+    if len == 0 {
+        None
+    } else {
+        let left = build_balanced(len / 2, iter, new_node);
+        let mut node = Box::new(new_node(iter.next().unwrap()));
+        let right = build_balanced(len - len / 2 - 1, iter, new_node);
+        node.insert_left(left);
+        node.insert_right(right);
+        Some(node)
+    }
+}
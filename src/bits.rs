@@ -0,0 +1,690 @@
+//! Dynamic bit vectors supporting rank and select.
+//!
+//! A `BitTree` is a list of bits like a `CountTree<bool>`, but packed 64 to a
+//! word in chunks of up to `CHUNK_BITS` bits, each node of the (balanced)
+//! tree holding one chunk and caching the number of bits and of set bits in
+//! its subtree, like a `PackedCountTree`. Besides reading, inserting and
+//! removing bits by index, it answers `rank1` (the number of ones before a
+//! position) and `select1` (the position of the k-th one) queries in
+//! O(log(n)) time, as needed by compressed indexes, or by text editors to map
+//! between all lines and the visible ones when some are folded.
+//!
+//! ```
+//! use binary_tree::bits::BitTree;
+//!
+//! // lines 2 and 3 are folded
+//! let mut visible: BitTree = vec![true, true, false, false, true].into_iter().collect();
+//! assert_eq!(visible.rank1(4), 2);
+//! assert_eq!(visible.select1(2), Some(4));
+//! visible.insert(1, true);
+//! assert_eq!(visible.select1(2), Some(2));
+//! assert_eq!(visible.count_ones(), 4);
+//! ```
+
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+use std::mem;
+
+use Node;
+use NodeMut;
+use avl::{self, Balanced};
+use iter;
+
+const WORD_BITS: usize = 64;
+const CHUNK_WORDS: usize = 8;
+
+/// Maximum number of bits in a chunk.
+pub const CHUNK_BITS: usize = CHUNK_WORDS * WORD_BITS;
+
+// Up to `CHUNK_BITS` bits, starting from the lowest bit of the first word. The
+// bits past `len` are zero.
+#[derive(Clone, Copy)]
+struct Chunk {
+    words: [u64; CHUNK_WORDS],
+    len: u32,
+}
+
+impl Chunk {
+    fn new() -> Chunk {
+        Chunk {
+            words: [0; CHUNK_WORDS],
+            len: 0,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn get(&self, index: usize) -> bool {
+        self.words[index / WORD_BITS] >> (index % WORD_BITS) & 1 == 1
+    }
+
+    fn set(&mut self, index: usize, bit: bool) -> bool {
+        let old = self.get(index);
+        let mask = 1 << (index % WORD_BITS);
+        if bit {
+            self.words[index / WORD_BITS] |= mask;
+        } else {
+            self.words[index / WORD_BITS] &= !mask;
+        }
+        old
+    }
+
+    fn push(&mut self, bit: bool) {
+        self.len += 1;
+        self.set(self.len() - 1, bit);
+    }
+
+    // The chunk must not be full.
+    fn insert(&mut self, index: usize, bit: bool) {
+        let (word, shift) = (index / WORD_BITS, index % WORD_BITS);
+        let low_mask = (1 << shift) - 1;
+        let old = self.words[word];
+        let mut carry = old >> (WORD_BITS - 1);
+        self.words[word] = old & low_mask | (bit as u64) << shift | (old & !low_mask) << 1;
+        for word in &mut self.words[word + 1..] {
+            let next_carry = *word >> (WORD_BITS - 1);
+            *word = *word << 1 | carry;
+            carry = next_carry;
+        }
+        self.len += 1;
+    }
+
+    fn remove(&mut self, index: usize) -> bool {
+        let (word, shift) = (index / WORD_BITS, index % WORD_BITS);
+        let bit = self.get(index);
+        let mut carry = 0;
+        for word in self.words[word + 1..].iter_mut().rev() {
+            let next_carry = *word & 1;
+            *word = *word >> 1 | carry << (WORD_BITS - 1);
+            carry = next_carry;
+        }
+        let low_mask = (1 << shift) - 1;
+        let old = self.words[word];
+        self.words[word] = old & low_mask | (old >> 1) & !low_mask | carry << (WORD_BITS - 1);
+        self.len -= 1;
+        bit
+    }
+
+    // Moves the upper half of a full chunk into a new one.
+    fn split_half(&mut self) -> Chunk {
+        let mut upper = Chunk::new();
+        upper.words[..CHUNK_WORDS / 2].copy_from_slice(&self.words[CHUNK_WORDS / 2..]);
+        for word in &mut self.words[CHUNK_WORDS / 2..] {
+            *word = 0;
+        }
+        upper.len = self.len - (CHUNK_BITS / 2) as u32;
+        self.len = (CHUNK_BITS / 2) as u32;
+        upper
+    }
+
+    fn append(&mut self, other: &Chunk) {
+        for index in 0..other.len() {
+            self.push(other.get(index));
+        }
+    }
+
+    fn ones(&self) -> u32 {
+        self.words.iter().map(|word| word.count_ones()).sum()
+    }
+
+    // number of ones before `index`
+    fn rank1(&self, index: usize) -> u32 {
+        let (word, shift) = (index / WORD_BITS, index % WORD_BITS);
+        let full: u32 = self.words[..word].iter().map(|word| word.count_ones()).sum();
+        if shift == 0 {
+            full
+        } else {
+            full + (self.words[word] & ((1 << shift) - 1)).count_ones()
+        }
+    }
+
+    // Returns the position of the `k`-th (from 0) bit equal to `bit`, which
+    // must be in the chunk.
+    fn select(&self, mut k: u32, bit: bool) -> usize {
+        for (i, &word) in self.words.iter().enumerate() {
+            let mut word = if bit { word } else { !word };
+            let count = word.count_ones();
+            if k < count {
+                for _ in 0..k {
+                    // clear the lowest set bit
+                    word &= word - 1;
+                }
+                return i * WORD_BITS + word.trailing_zeros() as usize;
+            }
+            k -= count;
+        }
+        unreachable!()
+    }
+}
+
+struct BitNode {
+    chunk: Chunk,
+    left: Option<Box<BitNode>>,
+    right: Option<Box<BitNode>>,
+    // number of nodes in the subtree, used for balancing
+    nodes: u32,
+    // number of bits, and of ones, in the subtree
+    len: usize,
+    ones: usize,
+    height: u16,
+}
+
+impl BitNode {
+    fn new(chunk: Chunk) -> BitNode {
+        BitNode {
+            len: chunk.len(),
+            ones: chunk.ones() as usize,
+            chunk,
+            left: None,
+            right: None,
+            nodes: 1,
+            height: 0,
+        }
+    }
+
+    fn llen(&self) -> usize {
+        self.left.as_ref().map_or(0, |node| node.len)
+    }
+
+    fn lones(&self) -> usize {
+        self.left.as_ref().map_or(0, |node| node.ones)
+    }
+
+    fn update_stats(&mut self) {
+        let (nodes, height) = avl::stats(self.left.as_deref(), self.right.as_deref());
+        self.nodes = nodes;
+        self.height = height;
+        let (rlen, rones) = self.right.as_ref().map_or((0, 0), |node| (node.len, node.ones));
+        self.len = self.llen() + self.chunk.len() + rlen;
+        self.ones = self.lones() + self.chunk.ones() as usize + rones;
+    }
+}
+
+impl Balanced for BitNode {
+    fn count(&self) -> u32 {
+        self.nodes
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+impl Node for BitNode {
+    type Value = Chunk;
+
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    fn value(&self) -> &Chunk {
+        &self.chunk
+    }
+}
+
+impl NodeMut for BitNode {
+    type NodePtr = Box<BitNode>;
+
+    fn detach_left(&mut self) -> Option<Self::NodePtr> {
+        let tree = self.left.take();
+        self.update_stats();
+        tree
+    }
+
+    fn detach_right(&mut self) -> Option<Self::NodePtr> {
+        let tree = self.right.take();
+        self.update_stats();
+        tree
+    }
+
+    fn insert_left(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.left, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn insert_right(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.right, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn value_mut(&mut self) -> &mut Chunk {
+        &mut self.chunk
+    }
+
+    fn into_parts(self) -> (Chunk, Option<Self::NodePtr>, Option<Self::NodePtr>) {
+        (self.chunk, self.left, self.right)
+    }
+
+    fn left_mut(&mut self) -> Option<&mut Self> {
+        self.left.as_deref_mut()
+    }
+
+    fn right_mut(&mut self) -> Option<&mut Self> {
+        self.right.as_deref_mut()
+    }
+}
+
+/// A balanced list of packed bits, see the module documentation.
+#[derive(Default)]
+pub struct BitTree {
+    root: Option<Box<BitNode>>,
+}
+
+impl BitTree {
+    /// Returns an empty `BitTree`.
+    pub fn new() -> BitTree {
+        BitTree { root: None }
+    }
+
+    /// Returns the number of bits. Time complexity: O(1)
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |node| node.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the number of set bits. Time complexity: O(1)
+    pub fn count_ones(&self) -> usize {
+        self.root.as_ref().map_or(0, |node| node.ones)
+    }
+
+    /// Returns the bit at the given index, or `None` if out of bounds. Time
+    /// complexity: O(log(n))
+    pub fn get(&self, mut index: usize) -> Option<bool> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut node = self.root.as_deref().unwrap();
+        loop {
+            let llen = node.llen();
+            if index < llen {
+                node = node.left.as_deref().unwrap();
+            } else if index < llen + node.chunk.len() {
+                return Some(node.chunk.get(index - llen));
+            } else {
+                index -= llen + node.chunk.len();
+                node = node.right.as_deref().unwrap();
+            }
+        }
+    }
+
+    /// Sets the bit at the given index, and returns the old one. Time
+    /// complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is out of bounds.
+    pub fn set(&mut self, index: usize, bit: bool) -> bool {
+        assert!(index < self.len(), "index out of bounds!");
+        let mut old = false;
+        self.walk_to(index, false, |node, offset, _| old = node.chunk.set(offset, bit));
+        old
+    }
+
+    /// Inserts a bit at the given index. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`
+    pub fn insert(&mut self, index: usize, bit: bool) {
+        assert!(index <= self.len(), "index out of bounds!");
+        if self.root.is_none() {
+            let mut chunk = Chunk::new();
+            chunk.push(bit);
+            self.root = Some(Box::new(BitNode::new(chunk)));
+            return;
+        }
+        let mut split = None;
+        self.walk_to(index,
+                     true,
+                     |node, offset, node_index| {
+                         if node.chunk.len() == CHUNK_BITS {
+                             let mut upper = node.chunk.split_half();
+                             if offset > CHUNK_BITS / 2 {
+                                 upper.insert(offset - CHUNK_BITS / 2, bit);
+                             } else {
+                                 node.chunk.insert(offset, bit);
+                             }
+                             split = Some((node_index, upper));
+                         } else {
+                             node.chunk.insert(offset, bit);
+                         }
+                     });
+        if let Some((node_index, upper)) = split {
+            avl::insert(&mut self.root, node_index + 1, Box::new(BitNode::new(upper)));
+        }
+    }
+
+    /// Appends a bit. Time complexity: O(log(n))
+    pub fn push(&mut self, bit: bool) {
+        let len = self.len();
+        self.insert(len, bit);
+    }
+
+    /// Removes the bit at the given index. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> bool {
+        assert!(index < self.len(), "index out of bounds!");
+        let mut removed = (false, 0, 0);
+        self.walk_to(index,
+                     false,
+                     |node, offset, node_index| {
+                         let bit = node.chunk.remove(offset);
+                         removed = (bit, node_index, node.chunk.len());
+                     });
+        let (bit, node_index, chunk_len) = removed;
+        if chunk_len == 0 {
+            avl::remove(&mut self.root, node_index);
+        } else if chunk_len < CHUNK_BITS / 4 && !self.merge_next(node_index) && node_index > 0 {
+            self.merge_next(node_index - 1);
+        }
+        bit
+    }
+
+    /// Returns the number of ones before the given index. Time complexity:
+    /// O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`
+    pub fn rank1(&self, mut index: usize) -> usize {
+        assert!(index <= self.len(), "index out of bounds!");
+        let mut rank = 0;
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            let llen = n.llen();
+            if index < llen {
+                node = n.left.as_deref();
+            } else if index <= llen + n.chunk.len() {
+                return rank + n.lones() + n.chunk.rank1(index - llen) as usize;
+            } else {
+                index -= llen + n.chunk.len();
+                rank += n.lones() + n.chunk.ones() as usize;
+                node = n.right.as_deref();
+            }
+        }
+        rank
+    }
+
+    /// Returns the number of zeros before the given index. Time complexity:
+    /// O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`
+    pub fn rank0(&self, index: usize) -> usize {
+        index - self.rank1(index)
+    }
+
+    /// Returns the index of the `k`-th one (counting from 0), or `None` if
+    /// there are not that many. Time complexity: O(log(n))
+    pub fn select1(&self, k: usize) -> Option<usize> {
+        self.select(k, true)
+    }
+
+    /// Returns the index of the `k`-th zero (counting from 0), or `None` if
+    /// there are not that many. Time complexity: O(log(n))
+    pub fn select0(&self, k: usize) -> Option<usize> {
+        self.select(k, false)
+    }
+
+    /// Returns an iterator over the bits.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            chunks: iter::Iter::new(self.root.as_deref()),
+            chunk: None,
+            index: 0,
+        }
+    }
+
+    fn select(&self, mut k: usize, bit: bool) -> Option<usize> {
+        // number of bits equal to `bit` in a subtree, or in a chunk
+        let count = |len: usize, ones: usize| if bit { ones } else { len - ones };
+        let mut index = 0;
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            let left = n.left.as_ref().map_or(0, |left| count(left.len, left.ones));
+            if k < left {
+                node = n.left.as_deref();
+                continue;
+            }
+            k -= left;
+            index += n.llen();
+            let own = count(n.chunk.len(), n.chunk.ones() as usize);
+            if k < own {
+                return Some(index + n.chunk.select(k as u32, bit));
+            }
+            k -= own;
+            index += n.chunk.len();
+            node = n.right.as_deref();
+        }
+        None
+    }
+
+    // Walks down to the chunk holding the bit at `index` (or, if `at_end`,
+    // the one it can be appended to), calls `f` with the node, the offset of
+    // `index` in its chunk and the index of the node, and updates the stats
+    // on the way up.
+    fn walk_to<F>(&mut self, index: usize, at_end: bool, f: F)
+        where F: FnOnce(&mut BitNode, usize, usize)
+    {
+        use WalkAction::*;
+
+        // the index relative to the current subtree, and the number of nodes
+        // before it
+        let mut state = (index, 0);
+        self.root.as_mut().unwrap().walk_reshape_state(&mut state,
+                                                       |node, &mut (ref mut index, ref mut node_index)| {
+                                                           let llen = node.llen();
+                                                           let end = llen + node.chunk.len();
+                                                           if *index < llen {
+                                                               Left
+                                                           } else if *index < end || at_end && *index == end {
+                                                               Stop
+                                                           } else {
+                                                               *index -= end;
+                                                               *node_index += avl::lcount(node) as usize + 1;
+                                                               Right
+                                                           }
+                                                       },
+                                                       |node, &mut (index, node_index)| {
+                                                           let offset = index - node.llen();
+                                                           let node_index = node_index + avl::lcount(node) as usize;
+                                                           f(node, offset, node_index);
+                                                           node.update_stats();
+                                                       },
+                                                       |_, _, _| ());
+    }
+
+    // Moves the bits of the node after the one at `node_index` into it, if
+    // they fit in one chunk. Returns whether they did.
+    fn merge_next(&mut self, node_index: usize) -> bool {
+        let nodes = self.root.as_ref().map_or(0, |node| node.nodes as usize);
+        if node_index + 1 >= nodes {
+            return false;
+        }
+        if self.node_at(node_index).chunk.len() + self.node_at(node_index + 1).chunk.len() > CHUNK_BITS {
+            return false;
+        }
+        let next = avl::remove(&mut self.root, node_index + 1);
+        let mut index = node_index as u32;
+        self.root.as_mut().unwrap().walk_reshape(|node| avl::index_walker(&mut index, node),
+                                                 |node| {
+                                                     node.chunk.append(&next.chunk);
+                                                     node.update_stats();
+                                                 },
+                                                 |_, _| ());
+        true
+    }
+
+    // Returns the node at `node_index`, in order.
+    fn node_at(&self, node_index: usize) -> &BitNode {
+        use WalkAction::*;
+
+        let mut node_index = node_index as u32;
+        let mut node = self.root.as_deref().unwrap();
+        loop {
+            node = match avl::index_walker(&mut node_index, node) {
+                Left => node.left.as_deref().unwrap(),
+                Right => node.right.as_deref().unwrap(),
+                Stop => return node,
+            };
+        }
+    }
+}
+
+/// Prints the bits as a string of `0`s and `1`s.
+impl Debug for BitTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str("BitTree(")?;
+        for bit in self.iter() {
+            f.write_str(if bit { "1" } else { "0" })?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl FromIterator<bool> for BitTree {
+    /// Fills the chunks to capacity. Time complexity: O(n)
+    fn from_iter<I>(iterable: I) -> Self
+        where I: IntoIterator<Item = bool>
+    {
+        let mut chunks = vec![Chunk::new()];
+        for bit in iterable {
+            if chunks.last().unwrap().len() == CHUNK_BITS {
+                chunks.push(Chunk::new());
+            }
+            chunks.last_mut().unwrap().push(bit);
+        }
+        if chunks[0].len == 0 {
+            chunks.clear();
+        }
+        BitTree { root: avl::build_balanced(chunks.len(), &mut chunks.into_iter(), &BitNode::new) }
+    }
+}
+
+impl<'a> IntoIterator for &'a BitTree {
+    type Item = bool;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the bits of a `BitTree`.
+pub struct Iter<'a> {
+    chunks: iter::Iter<'a, BitNode>,
+    chunk: Option<&'a Chunk>,
+    index: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        loop {
+            if let Some(chunk) = self.chunk {
+                if self.index < chunk.len() {
+                    self.index += 1;
+                    return Some(chunk.get(self.index - 1));
+                }
+            }
+            self.chunk = Some(self.chunks.next()?);
+            self.index = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BitNode, BitTree, CHUNK_BITS};
+
+    // Verifies the cached stats, and that no chunk is empty.
+    fn check(node: &BitNode) -> (usize, usize) {
+        let left = node.left.as_deref().map_or((0, 0), check);
+        let right = node.right.as_deref().map_or((0, 0), check);
+        assert!(node.chunk.len() > 0);
+        let stats = (left.0 + right.0 + node.chunk.len(), left.1 + right.1 + node.chunk.ones() as usize);
+        assert_eq!((node.len, node.ones), stats);
+        stats
+    }
+
+    #[test]
+    fn edits() {
+        let mut bits = BitTree::new();
+        let mut model = Vec::new();
+        let mut state = 777u32;
+        for step in 0..20000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let r = (state >> 8) as usize;
+            // grow first, then shrink
+            if model.is_empty() || (r % 5 < 3) == (step < 12000) {
+                let index = r % (model.len() + 1);
+                let bit = r.is_multiple_of(3);
+                bits.insert(index, bit);
+                model.insert(index, bit);
+            } else if r.is_multiple_of(7) {
+                let index = r % model.len();
+                assert_eq!(bits.set(index, !model[index]), model[index]);
+                model[index] = !model[index];
+            } else {
+                let index = r % model.len();
+                assert_eq!(bits.remove(index), model.remove(index));
+            }
+            if step % 1000 == 0 {
+                assert_eq!(bits.iter().collect::<Vec<_>>(), model);
+                if let Some(root) = bits.root.as_deref() {
+                    check(root);
+                }
+            }
+        }
+        assert_eq!(bits.len(), model.len());
+        assert!(model.len() > CHUNK_BITS);
+        for (index, &bit) in model.iter().enumerate() {
+            assert_eq!(bits.get(index), Some(bit));
+        }
+    }
+
+    #[test]
+    fn rank_select() {
+        let model: Vec<_> = (0..3000).map(|i: usize| i.count_ones().is_multiple_of(3)).collect();
+        let bits: BitTree = model.iter().cloned().collect();
+        let mut ones = Vec::new();
+        let mut zeros = Vec::new();
+        for (index, &bit) in model.iter().enumerate() {
+            assert_eq!(bits.rank1(index), ones.len());
+            assert_eq!(bits.rank0(index), zeros.len());
+            if bit {
+                ones.push(index);
+            } else {
+                zeros.push(index);
+            }
+        }
+        assert_eq!(bits.rank1(3000), ones.len());
+        assert_eq!(bits.count_ones(), ones.len());
+        for (k, &index) in ones.iter().enumerate() {
+            assert_eq!(bits.select1(k), Some(index));
+        }
+        for (k, &index) in zeros.iter().enumerate() {
+            assert_eq!(bits.select0(k), Some(index));
+        }
+        assert_eq!(bits.select1(ones.len()), None);
+        assert_eq!(bits.select0(zeros.len()), None);
+        assert_eq!(format!("{:?}", (0..5).map(|i| i % 2 == 1).collect::<BitTree>()), "BitTree(01010)");
+        assert_eq!(BitTree::new().rank1(0), 0);
+    }
+}
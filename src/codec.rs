@@ -0,0 +1,187 @@
+//! Compact binary encoding of trees.
+//!
+//! A tree is written in pre-order, as one marker byte per position: `1` for
+//! a node, followed by its encoded value, or `0` for a missing child (or an
+//! empty tree). The values are encoded by a user-supplied `ValueCodec`, and
+//! the shape of the tree is preserved exactly. Both encoding and decoding are
+//! iterative, so degenerate trees are fine.
+
+use std::io::{self, Read, Write};
+
+use Node;
+use NodeMut;
+use algos::drop_tree;
+
+const NODE: u8 = 1;
+const NONE: u8 = 0;
+
+/// Encoding and decoding of the values of a tree.
+pub trait ValueCodec<V> {
+    /// Writes `value` to `w`.
+    fn encode<W: Write>(&mut self, value: &V, w: &mut W) -> io::Result<()>;
+
+    /// Reads back a value written by `encode` from `r`.
+    fn decode<R: Read>(&mut self, r: &mut R) -> io::Result<V>;
+}
+
+/// Writes the tree rooted at `root` (if any) to `w`. Time complexity: O(n)
+pub fn encode<N, C, W>(root: Option<&N>, codec: &mut C, w: &mut W) -> io::Result<()>
+    where N: Node,
+          C: ValueCodec<N::Value>,
+          W: Write
+{
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        match node {
+            Some(node) => {
+                w.write_all(&[NODE])?;
+                codec.encode(node.value(), w)?;
+                stack.push(node.right());
+                stack.push(node.left());
+            }
+            None => w.write_all(&[NONE])?,
+        }
+    }
+    Ok(())
+}
+
+/// Reads a tree written by `encode` from `r`. `make` creates a (childless)
+/// node from a decoded value. Returns an error of kind `InvalidData` if a
+/// marker byte is neither `0` nor `1`. Time complexity: O(n)
+pub fn decode<N, C, R, M>(codec: &mut C, r: &mut R, make: M) -> io::Result<Option<N::NodePtr>>
+    where N: NodeMut,
+          C: ValueCodec<N::Value>,
+          R: Read,
+          M: FnMut(N::Value) -> N::NodePtr
+{
+    // nodes whose subtrees are being read; the flag is set if the left one is
+    // already done
+    let mut stack = Vec::new();
+    let res = decode_onto::<N, _, _, _>(&mut stack, codec, r, make);
+    // on error, the nodes read so far may still form a deep tree
+    for (node, _) in stack {
+        drop_tree::<N>(node);
+    }
+    res
+}
+
+fn decode_onto<N, C, R, M>(stack: &mut Vec<(N::NodePtr, bool)>,
+                           codec: &mut C,
+                           r: &mut R,
+                           mut make: M)
+                           -> io::Result<Option<N::NodePtr>>
+    where N: NodeMut,
+          C: ValueCodec<N::Value>,
+          R: Read,
+          M: FnMut(N::Value) -> N::NodePtr
+{
+    loop {
+        let mut marker = [0];
+        r.read_exact(&mut marker)?;
+        let mut done = match marker[0] {
+            NODE => {
+                let value = codec.decode(r)?;
+                stack.push((make(value), false));
+                continue;
+            }
+            NONE => None,
+            m => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                          format!("invalid marker byte: {}", m)));
+            }
+        };
+        // attach the completed subtree to its parent, and so on upwards
+        loop {
+            match stack.pop() {
+                Some((mut node, false)) => {
+                    node.insert_left(done);
+                    stack.push((node, true));
+                    break;
+                }
+                Some((mut node, true)) => {
+                    node.insert_right(done);
+                    done = Some(node);
+                }
+                None => return Ok(done),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+
+    use algos::{drop_tree, tree_eq};
+    use test::{TestNode, Shape, gen_tree};
+    use super::{ValueCodec, encode, decode};
+
+    struct LeCodec;
+
+    impl ValueCodec<usize> for LeCodec {
+        fn encode<W: Write>(&mut self, value: &usize, w: &mut W) -> io::Result<()> {
+            w.write_all(&(*value as u32).to_le_bytes())
+        }
+
+        fn decode<R: Read>(&mut self, r: &mut R) -> io::Result<usize> {
+            let mut buf = [0; 4];
+            r.read_exact(&mut buf)?;
+            Ok(u32::from_le_bytes(buf) as usize)
+        }
+    }
+
+    fn round_trip(tree: Option<&TestNode<usize>>) -> (Vec<u8>, Option<Box<TestNode<usize>>>) {
+        let mut bytes = vec![];
+        encode(tree, &mut LeCodec, &mut bytes).unwrap();
+        let decoded = decode::<TestNode<_>, _, _, _>(&mut LeCodec, &mut &bytes[..],
+                                                     |v| Box::new(TestNode::new(v)));
+        (bytes, decoded.unwrap())
+    }
+
+    #[test]
+    fn codec() {
+        let tree = gen_tree(Shape::RightSpine, 2).unwrap();
+        let (bytes, decoded) = round_trip(Some(&*tree));
+        assert_eq!(bytes, [1, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0]);
+        assert!(tree_eq(&*tree, &*decoded.unwrap()));
+
+        for &shape in &[Shape::LeftSpine, Shape::Random(3), Shape::PerfectlyBalanced] {
+            let tree = gen_tree(shape, 100).unwrap();
+            let (bytes, decoded) = round_trip(Some(&*tree));
+            assert_eq!(bytes.len(), 100 * 5 + 101);
+            assert!(tree_eq(&*tree, &*decoded.unwrap()));
+        }
+
+        let (bytes, decoded) = round_trip(None);
+        assert_eq!(bytes, [0]);
+        assert!(decoded.is_none());
+    }
+
+    #[test]
+    fn invalid() {
+        let decode = |bytes: &[u8]| {
+            decode::<TestNode<_>, _, _, _>(&mut LeCodec, &mut &bytes[..],
+                                           |v| Box::new(TestNode::new(v)))
+                .map(|_| ())
+                .map_err(|e| e.kind())
+        };
+        assert_eq!(decode(&[2]), Err(io::ErrorKind::InvalidData));
+        assert_eq!(decode(&[1, 0, 0, 0, 0, 0]), Err(io::ErrorKind::UnexpectedEof));
+        assert_eq!(decode(&[]), Err(io::ErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn truncated() {
+        // only the right child of the root is missing, so the partially read
+        // tree is a deep left spine
+        let tree = gen_tree(Shape::LeftSpine, 100000).unwrap();
+        let mut bytes = vec![];
+        encode(Some(&*tree), &mut LeCodec, &mut bytes).unwrap();
+        drop_tree::<TestNode<_>>(tree);
+        bytes.pop();
+        let res = decode::<TestNode<_>, _, _, _>(&mut LeCodec, &mut &bytes[..],
+                                                 |v| Box::new(TestNode::new(v)));
+        assert_eq!(res.map(|_| ()).map_err(|e| e.kind()), Err(io::ErrorKind::UnexpectedEof));
+    }
+}
@@ -106,23 +106,227 @@
 //! ```
 //!
 //! then `CountTree` might be a good choice, otherwise you are better off using
-//! `Vec`.
+//! `Vec`, or `PackedCountTree` (see the `packed` module), which stores small
+//! elements in chunks.
 
+use std::cmp;
+use std::collections::{LinkedList, VecDeque};
 use std::mem;
-use std::iter::FromIterator;
-use std::fmt::{self, Debug};
+use std::ops::Deref;
+use std::slice;
+use std::vec;
+use std::iter::{FromIterator, FusedIterator, Rev, Zip};
+use std::ops::{Range, RangeFrom};
+use std::fmt::{self, Debug, Display};
+use std::sync::Arc;
+#[cfg(feature="instrument")]
+use std::cell::Cell;
+#[cfg(feature="instrument")]
+use std::ops;
+#[cfg(feature="instrument")]
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 #[cfg(feature="quickcheck")]
 use quickcheck::{Arbitrary, Gen};
 
 use Node;
 use NodeMut;
-use BinaryTree;
 use WalkAction;
+use algos;
+use cow::{self, ArcCow, RcCow};
+use iter::{Chunks, Cursor, Pairs, StreamingIterMut, Windows};
 use iter::Iter as GenIter;
 use iter::IntoIter as GenIntoIter;
+use unbox::{TreePtr, Unbox};
 
-pub type NodePtr<T> = Box<CountNode<T>>;
+/// The kind of pointers linking the nodes of a `CountTree`.
+pub trait CountKind<T>: Sized {
+    type Ptr: TreePtr + Deref<Target = CountNode<T, Self>>;
+
+    fn clone_ptr(ptr: &Self::Ptr) -> Self::Ptr where T: Clone;
+
+    /// Drops the tree rooted at `ptr` without recursion. Subtrees still
+    /// shared with another tree are released but not descended into.
+    fn drop_tree(ptr: Self::Ptr);
+}
+
+/// Nodes are uniquely owned through `Box`es. This is the default.
+pub enum BoxKind {}
+
+/// Nodes are shared through `RcCow` pointers.
+pub enum RcCowKind {}
+
+/// Nodes are shared through `ArcCow` pointers.
+pub enum ArcCowKind {}
+
+impl<T> CountKind<T> for BoxKind {
+    type Ptr = Box<CountNode<T, BoxKind>>;
+
+    fn clone_ptr(ptr: &Self::Ptr) -> Self::Ptr
+        where T: Clone
+    {
+        on_allocation();
+        Box::new((**ptr).clone())
+    }
+
+    fn drop_tree(ptr: Self::Ptr) {
+        algos::drop_tree::<CountNode<T, BoxKind>>(ptr);
+    }
+}
+
+impl<T: Clone> CountKind<T> for RcCowKind {
+    type Ptr = RcCow<CountNode<T, RcCowKind>>;
+
+    fn clone_ptr(ptr: &Self::Ptr) -> Self::Ptr {
+        ptr.clone()
+    }
+
+    fn drop_tree(ptr: Self::Ptr) {
+        let mut stack = vec![ptr];
+        while let Some(ptr) = stack.pop() {
+            if let Ok(mut node) = RcCow::try_unwrap(ptr) {
+                stack.extend(node.left.take());
+                stack.extend(node.right.take());
+            }
+        }
+    }
+}
+
+impl<T: Clone> CountKind<T> for ArcCowKind {
+    type Ptr = ArcCow<CountNode<T, ArcCowKind>>;
+
+    fn clone_ptr(ptr: &Self::Ptr) -> Self::Ptr {
+        ptr.clone()
+    }
+
+    fn drop_tree(ptr: Self::Ptr) {
+        let mut stack = vec![ptr];
+        while let Some(ptr) = stack.pop() {
+            if let Ok(mut node) = ArcCow::try_unwrap(ptr) {
+                stack.extend(node.left.take());
+                stack.extend(node.right.take());
+            }
+        }
+    }
+}
+
+pub type NodePtr<T, K = BoxKind> = <K as CountKind<T>>::Ptr;
+
+/// A `CountTree` whose nodes are shared through (single-threaded) `RcCow`
+/// pointers, so that `clone` takes O(1) time. Modifying a clone copies only
+/// the nodes along the modified paths. Use `RcCountTree::default()` to create
+/// one.
+pub type RcCountTree<T> = CountTree<T, RcCowKind>;
+
+/// Same as `RcCountTree`, but thread-safe, using `ArcCow` pointers.
+pub type ArcCountTree<T> = CountTree<T, ArcCowKind>;
+
+fn new_node<T, K: CountKind<T>>(val: T) -> NodePtr<T, K> {
+    on_allocation();
+    TreePtr::new_ptr(CountNode::new(val))
+}
+
+/// Counters of the work done by all `CountTree`s (and their nodes) across all
+/// threads, returned by `metrics`, or by a single tree, returned by
+/// `CountTree::metrics` (requires the `instrument` feature).
+#[cfg(feature="instrument")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// Rotations done to rebalance the trees, or to build them.
+    pub rotations: usize,
+    /// Nodes allocated for new elements, or to deep-clone `Box`ed nodes.
+    pub allocations: usize,
+    /// Shared nodes cloned on mutation, see `cow::clone_count`.
+    pub cow_clones: usize,
+}
+
+#[cfg(feature="instrument")]
+impl ops::Add for Metrics {
+    type Output = Metrics;
+
+    fn add(self, other: Metrics) -> Metrics {
+        Metrics {
+            rotations: self.rotations + other.rotations,
+            allocations: self.allocations + other.allocations,
+            cow_clones: self.cow_clones + other.cow_clones,
+        }
+    }
+}
+
+#[cfg(feature="instrument")]
+impl ops::Sub for Metrics {
+    type Output = Metrics;
+
+    fn sub(self, other: Metrics) -> Metrics {
+        Metrics {
+            rotations: self.rotations - other.rotations,
+            allocations: self.allocations - other.allocations,
+            cow_clones: self.cow_clones - other.cow_clones,
+        }
+    }
+}
+
+#[cfg(feature="instrument")]
+static ROTATIONS: AtomicUsize = AtomicUsize::new(0);
+#[cfg(feature="instrument")]
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+// The same counters for the current thread only, from which the work done by
+// each tree is measured.
+#[cfg(feature="instrument")]
+thread_local! {
+    static THREAD_ROTATIONS: Cell<usize> = const { Cell::new(0) };
+    static THREAD_ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Returns the current counters. Note that other threads (such as concurrent
+/// tests) may be counted as well.
+#[cfg(feature="instrument")]
+pub fn metrics() -> Metrics {
+    Metrics {
+        rotations: ROTATIONS.load(Ordering::Relaxed),
+        allocations: ALLOCATIONS.load(Ordering::Relaxed),
+        cow_clones: ::cow::clone_count(),
+    }
+}
+
+/// Resets the counters returned by `metrics` to zero, and returns the old
+/// ones.
+#[cfg(feature="instrument")]
+pub fn reset_metrics() -> Metrics {
+    Metrics {
+        rotations: ROTATIONS.swap(0, Ordering::Relaxed),
+        allocations: ALLOCATIONS.swap(0, Ordering::Relaxed),
+        cow_clones: ::cow::reset_clone_count(),
+    }
+}
+
+#[cfg(feature="instrument")]
+fn thread_metrics() -> Metrics {
+    Metrics {
+        rotations: THREAD_ROTATIONS.with(Cell::get),
+        allocations: THREAD_ALLOCATIONS.with(Cell::get),
+        cow_clones: ::cow::thread_clone_count(),
+    }
+}
+
+// Called whenever a node is rotated.
+fn on_rotation() {
+    #[cfg(feature="instrument")]
+    {
+        ROTATIONS.fetch_add(1, Ordering::Relaxed);
+        THREAD_ROTATIONS.with(|count| count.set(count.get() + 1));
+    }
+}
+
+// Called whenever a node is allocated.
+fn on_allocation() {
+    #[cfg(feature="instrument")]
+    {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        THREAD_ALLOCATIONS.with(|count| count.set(count.get() + 1));
+    }
+}
 
 macro_rules! index_walker {
     ($index:ident, $node:ident, $up_count:ident, $stop:block) => {
@@ -175,33 +379,181 @@ macro_rules! index_walker {
 /// assert_eq!(ct.remove(32), 32);
 /// # }
 /// ```
-pub struct CountTree<T>(Option<NodePtr<T>>);
+///
+/// `push_front` and `push_back` run in amortized O(1) time: pushed elements
+/// are first collected in a buffer at either end, which is merged into the
+/// tree once it grows larger than the height of the tree, or as soon as any
+/// other modifying method is called.
+pub struct CountTree<T, K: CountKind<T> = BoxKind> {
+    root: Option<NodePtr<T, K>>,
+    // elements pushed to the front, in reverse order
+    front: Vec<T>,
+    // elements pushed to the back, in order
+    back: Vec<T>,
+    #[cfg(feature="instrument")]
+    metrics: Metrics,
+}
 
 impl<T> CountTree<T> {
-    fn root_must(&mut self) -> &mut CountNode<T> {
-        &mut **self.0.as_mut().unwrap()
-    }
-
     /// Returns an empty `CountTree`
     pub fn new() -> CountTree<T> {
-        CountTree(None)
+        CountTree::with_root(None)
+    }
+}
+
+impl<T, K: CountKind<T>> Default for CountTree<T, K> {
+    fn default() -> CountTree<T, K> {
+        CountTree::with_root(None)
+    }
+}
+
+impl<T, K: CountKind<T>> CountTree<T, K> {
+    fn with_root(root: Option<NodePtr<T, K>>) -> CountTree<T, K> {
+        CountTree {
+            root,
+            front: Vec::new(),
+            back: Vec::new(),
+            #[cfg(feature="instrument")]
+            metrics: Metrics::default(),
+        }
+    }
+
+    /// Returns a perfectly balanced tree of `len` elements, the element at
+    /// each index `i` being `f(i)`. `f` is called in order of the indices.
+    /// Time complexity: O(n)
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # extern crate binary_tree;
+    /// # use binary_tree::count::CountTree;
+    /// # fn main() {
+    /// let squares: CountTree<_> = CountTree::from_fn(5, |i| i * i);
+    /// assert_eq!(squares.to_vec(), [0, 1, 4, 9, 16]);
+    /// # }
+    /// ```
+    pub fn from_fn<F>(len: usize, mut f: F) -> CountTree<T, K>
+        where F: FnMut(usize) -> T
+    {
+        CountTree::with_root(build_from_fn::<T, K, _>(0, len, &mut f))
+    }
+
+    /// Returns a perfectly balanced tree of `n` clones of `value`. Time
+    /// complexity: O(n), or O(log(n)) for `RcCountTree` and `ArcCountTree`,
+    /// whose equal subtrees are shared (and copied on write). Editing such a
+    /// tree copies the shared nodes along the modified paths, which the
+    /// `cow-strict` feature only allows within `cow::allow_clones`.
+    pub fn repeat(value: T, n: usize) -> CountTree<T, K>
+        where T: Clone
+    {
+        let root = if n == 0 {
+            None
+        } else {
+            Some(repeat_pair::<T, K>(&value, n - 1).1)
+        };
+        CountTree::with_root(root)
+    }
+
+    // Runs `f` on the tree, and adds the work it does (on this thread) to the
+    // metrics of the tree, with the `instrument` feature. `f` may replace the
+    // whole tree.
+    fn tracked<R, F>(&mut self, f: F) -> R
+        where F: FnOnce(&mut Self) -> R
+    {
+        #[cfg(feature="instrument")]
+        let (metrics, before) = (self.metrics, thread_metrics());
+        let ret = f(self);
+        #[cfg(feature="instrument")]
+        {
+            self.metrics = metrics + (thread_metrics() - before);
+        }
+        ret
+    }
+
+    /// Returns the work done so far by the methods modifying this tree (with
+    /// its buffered elements merged by `flush`, and excluding `get_mut` and
+    /// `cursor`), which is included in the counters returned by `metrics`.
+    /// Requires the `instrument` feature.
+    #[cfg(feature="instrument")]
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+
+    /// Resets the counters returned by `CountTree::metrics` to zero, and
+    /// returns the old ones.
+    #[cfg(feature="instrument")]
+    pub fn reset_metrics(&mut self) -> Metrics {
+        mem::take(&mut self.metrics)
+    }
+
+    fn root_must(&mut self) -> &mut CountNode<T, K> {
+        self.root.as_deref_mut().unwrap()
+    }
+
+    fn tree_len(&self) -> usize {
+        self.root.as_deref().map_or(0, |node| node.count() as usize)
+    }
+
+    // maximum number of elements buffered at either end before merging
+    #[cfg(not(feature="compact-nodes"))]
+    fn buffer_limit(&self) -> usize {
+        self.root.as_deref().map_or(1, |node| 2 * (node.height() as usize + 1))
+    }
+
+    // same as above, but with the least height of a tree of that many
+    // elements, as measuring the height takes O(log(n)) time
+    #[cfg(feature="compact-nodes")]
+    fn buffer_limit(&self) -> usize {
+        self.root.as_deref().map_or(1, |node| 2 * (32 - node.count().leading_zeros()) as usize)
     }
 
     /// Returns `true` if the tree contains no elements.
     pub fn is_empty(&self) -> bool {
-        self.0.is_none()
+        self.root.is_none() && self.front.is_empty() && self.back.is_empty()
     }
 
     /// Returns the number elements in the tree. Time complexity: O(1)
     pub fn len(&self) -> usize {
-        self.root().map_or(0, |node| node.count as usize)
+        self.front.len() + self.tree_len() + self.back.len()
     }
 
     /// Clears the tree, dropping all elements iteratively.
     pub fn clear(&mut self) {
-        let mut inner = None;
-        mem::swap(&mut self.0, &mut inner);
-        let _: GenIntoIter<CountNode<T>> = GenIntoIter::new(inner);
+        if let Some(root) = self.root.take() {
+            K::drop_tree(root);
+        }
+        self.front.clear();
+        self.back.clear();
+    }
+
+    /// Merges the elements buffered by `push_front` and `push_back` into the
+    /// tree. Time complexity: O(k + log(n)) for k buffered elements.
+    ///
+    /// This is done implicitly by `root()` and by all other modifying methods.
+    pub fn flush(&mut self) {
+        self.tracked(|ct| {
+            if !ct.front.is_empty() {
+                let mut front = mem::take(&mut ct.front).into_iter();
+                let mid = new_node::<T, K>(front.next().unwrap());
+                let left = build_balanced::<T, K, _>(front.len(), &mut front.rev());
+                let right = ct.root.take();
+                ct.root = Some(join::<T, K>(left, mid, right));
+            }
+            if !ct.back.is_empty() {
+                let mut back = mem::take(&mut ct.back).into_iter();
+                let mid = new_node::<T, K>(back.next().unwrap());
+                let right = build_balanced::<T, K, _>(back.len(), &mut back);
+                let left = ct.root.take();
+                ct.root = Some(join::<T, K>(left, mid, right));
+            }
+        })
+    }
+
+    /// Returns the root of the tree, after merging any buffered elements into
+    /// it (see `flush`).
+    pub fn root(&mut self) -> Option<&CountNode<T, K>> {
+        self.flush();
+        self.root.as_deref()
     }
 
     /// Returns the element at the given index, or `None` if index is out of
@@ -209,12 +561,17 @@ impl<T> CountTree<T> {
     pub fn get(&self, index: usize) -> Option<&T> {
         use WalkAction::*;
 
-        if index >= self.len() {
-            None
+        let flen = self.front.len();
+        let tlen = self.tree_len();
+        if index < flen {
+            self.front.get(flen - index - 1)
+        } else if index >= flen + tlen {
+            self.back.get(index - flen - tlen)
         } else {
+            let index = index - flen;
             let mut val = None;
             let mut up_count = 0;
-            self.root().unwrap().walk(|node| {
+            self.root.as_deref().unwrap().walk(|node| {
                 index_walker!(index, node, up_count, {
                     val = Some(node.value());
                 })
@@ -229,9 +586,14 @@ impl<T> CountTree<T> {
     pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
         use WalkAction::*;
 
-        if index >= self.len() {
-            None
+        let flen = self.front.len();
+        let tlen = self.tree_len();
+        if index < flen {
+            self.front.get_mut(flen - index - 1)
+        } else if index >= flen + tlen {
+            self.back.get_mut(index - flen - tlen)
         } else {
+            let index = index - flen;
             let mut val = None;
             let mut up_count = 0;
             let root = self.root_must();
@@ -242,59 +604,88 @@ impl<T> CountTree<T> {
         }
     }
 
-    /// Inserts an element at the given index. Time complexity: O(log(n))
+    /// Returns the path from the root to the node holding the element at the
+    /// given index, after merging the buffered elements into the tree (see
+    /// `flush`). Time complexity: O(log(n))
+    ///
+    /// The path can be followed with `descend` for as long as the tree is not
+    /// modified, also on clones taken before that: a clone shares its
+    /// structure with the original.
     ///
     /// ## Panics
     ///
-    /// Panics if index is greater than `self.len()`
-    pub fn insert(&mut self, index: usize, value: T) {
+    /// Panics if index is not less than `self.len()`
+    pub fn path_to_index(&mut self, index: usize) -> Vec<WalkAction> {
         use WalkAction::*;
 
-        let len = self.len();
-        if index == 0 {
-            self.push_front(value);
-        } else if index < len {
-            let new_node = Box::new(CountNode::new(value));
-            let mut up_count = 0;
-            let root = self.root_must();
-            root.walk_reshape(|node| index_walker!(index, node, up_count, {}),
-                              move |node| {
-                                  node.insert_before(new_node,
-                                                     |node, _| node.rebalance());
-                              },
-                              |node, _| node.rebalance());
-        } else if index == len {
-            self.push_back(value);
-        } else {
-            panic!("index out of bounds!");
+        assert!(index < self.len(), "index out of bounds!");
+        self.flush();
+        let mut up_count = 0;
+        self.root().unwrap().walk_path(|node| index_walker!(index, node, up_count, {}))
+    }
+
+    /// Follows `path` from the root, and returns the element of the node it
+    /// leads to, or `None` if the tree is empty or the path steps into a
+    /// missing child. A `Stop` ends the path early. Time complexity:
+    /// O(path.len())
+    pub fn descend(&self, path: &[WalkAction]) -> Option<&T> {
+        use WalkAction::*;
+
+        let mut node = self.root.as_deref()?;
+        for action in path {
+            node = match *action {
+                Left => node.left()?,
+                Right => node.right()?,
+                Stop => break,
+            };
         }
+        Some(node.value())
+    }
+
+    /// Mutable version of `descend`. Time complexity: O(path.len())
+    pub fn descend_mut(&mut self, path: &[WalkAction]) -> Option<&mut T> {
+        use WalkAction::*;
+
+        self.descend(path)?;
+        let mut steps = path.iter().cloned();
+        let mut val = None;
+        self.root_must().walk_mut(|_| steps.next().unwrap_or(Stop),
+                                  |node| val = Some(node.value_mut()));
+        val
+    }
+
+    /// Inserts an element at the given index. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.tracked(|ct| {
+            ct.flush();
+            let len = ct.len();
+            if index > len {
+                panic!("index out of bounds!");
+            } else if ct.root.is_none() {
+                ct.root = Some(new_node::<T, K>(value));
+            } else {
+                insert_node::<T, K>(ct.root_must(), index, new_node::<T, K>(value));
+            }
+        })
     }
 
-    /// Prepends an element at the beginning.
+    /// Prepends an element at the beginning. Time complexity: amortized O(1)
     pub fn push_front(&mut self, value: T) {
-        let new_node = Box::new(CountNode::new(value));
-        if self.is_empty() {
-            self.0 = Some(new_node);
-        } else {
-            self.root_must().walk_reshape(|_| WalkAction::Left,
-                                          move |node| {
-                                              node.insert_left(Some(new_node));
-                                          },
-                                          |node, _| node.rebalance());
+        self.front.push(value);
+        if self.front.len() > self.buffer_limit() {
+            self.flush();
         }
     }
 
-    /// Appends an element at the end.
+    /// Appends an element at the end. Time complexity: amortized O(1)
     pub fn push_back(&mut self, value: T) {
-        let new_node = Box::new(CountNode::new(value));
-        if self.is_empty() {
-            self.0 = Some(new_node);
-        } else {
-            self.root_must().walk_reshape(|_| WalkAction::Right,
-                                          move |node| {
-                                              node.insert_right(Some(new_node));
-                                          },
-                                          |node, _| node.rebalance());
+        self.back.push(value);
+        if self.back.len() > self.buffer_limit() {
+            self.flush();
         }
     }
 
@@ -304,155 +695,1026 @@ impl<T> CountTree<T> {
     ///
     /// Panics if index is out of bounds.
     pub fn remove(&mut self, index: usize) -> T {
-        use WalkAction::*;
+        self.tracked(|ct| {
+            ct.flush();
+            if index >= ct.len() {
+                panic!("index out of bounds!");
+            }
+            remove_node::<T, K>(&mut ct.root, index).unbox().into_value()
+        })
+    }
 
-        let len = self.len();
-        if index == 0 {
-            self.pop_front().expect("Tree is empty!")
-        } else if index + 1 < len {
-            let mut up_count = 0;
-            let root = self.root_must();
-            root.walk_extract(|node| index_walker!(index, node, up_count, {}),
-                              |node, ret| {
-                                  *ret = node.try_remove(|node, _| node.rebalance());
-                              },
-                              |node, _| node.rebalance())
-                .unwrap()
-                .into_value()
-        } else if index + 1 == len {
-            self.pop_back().unwrap()
-        } else {
-            panic!("index out of bounds!");
-        }
+    /// Returns the element at the given index counted from the back (the last
+    /// element being at index 0), or `None` if index is out of bounds. Time
+    /// complexity: O(log(n))
+    pub fn get_back(&self, index: usize) -> Option<&T> {
+        self.len().checked_sub(index).and_then(|len| len.checked_sub(1)).and_then(|index| self.get(index))
+    }
+
+    /// Inserts an element at the given index counted from the back, so that
+    /// `index` elements follow it. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`
+    pub fn insert_back(&mut self, index: usize, value: T) {
+        let index = self.len().checked_sub(index).expect("index out of bounds!");
+        self.insert(index, value);
+    }
+
+    /// Removes the element at the given index counted from the back. Time
+    /// complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is out of bounds.
+    pub fn remove_back(&mut self, index: usize) -> T {
+        let index = self.len().checked_sub(index).and_then(|len| len.checked_sub(1)).expect("index out of bounds!");
+        self.remove(index)
     }
 
     /// Removes and returns the first element, or `None` if empty.
     pub fn pop_front(&mut self) -> Option<T> {
-        if self.is_empty() {
-            None
-        } else if self.len() == 1 {
-            Some(self.0.take().unwrap().into_value())
-        } else {
-            let root = self.root_must();
-            Some(root.walk_extract(|_| WalkAction::Left,
-                                   |node, ret| {
-                                       if let Some(mut right) = node.detach_right() {
-                                           mem::swap(&mut *right, node);
-                                           *ret = Some(right);
-                                       }
-                                   },
-                                   |node, _| node.rebalance())
-                     .unwrap()
-                     .into_value())
-        }
+        self.tracked(|ct| {
+            if let Some(value) = ct.front.pop() {
+                return Some(value);
+            }
+            ct.flush();
+            if ct.is_empty() {
+                None
+            } else {
+                Some(remove_node::<T, K>(&mut ct.root, 0).unbox().into_value())
+            }
+        })
     }
 
     /// Removes and returns the last element, or `None` if empty.
     pub fn pop_back(&mut self) -> Option<T> {
-        // FIXME Ewww! Code duplication!
-        if self.is_empty() {
-            None
-        } else if self.len() == 1 {
-            Some(self.0.take().unwrap().into_value())
-        } else {
-            let root = self.root_must();
-            Some(root.walk_extract(|_| WalkAction::Right,
-                                   |node, ret| {
-                                       if let Some(mut left) = node.detach_left() {
-                                           mem::swap(&mut *left, node);
-                                           *ret = Some(left);
-                                       }
-                                   },
-                                   |node, _| node.rebalance())
-                     .unwrap()
-                     .into_value())
+        self.tracked(|ct| {
+            if let Some(value) = ct.back.pop() {
+                return Some(value);
+            }
+            ct.flush();
+            if ct.is_empty() {
+                None
+            } else {
+                let last = ct.len() - 1;
+                Some(remove_node::<T, K>(&mut ct.root, last).unbox().into_value())
+            }
+        })
+    }
+
+    /// Returns the index of the lowest common ancestor of the elements at
+    /// indices `i` and `j`, or `None` if either index is out of bounds or
+    /// refers to an element not yet merged into the tree (see `flush`). Time
+    /// complexity: O(log(n))
+    pub fn lca_of_indices(&self, i: usize, j: usize) -> Option<usize> {
+        use WalkAction::*;
+
+        let flen = self.front.len();
+        let tlen = self.tree_len();
+        if i < flen || j < flen || i >= flen + tlen || j >= flen + tlen {
+            return None;
         }
+        let (i, j) = (i - flen, j - flen);
+        let mut lca = None;
+        let mut up_count = 0;
+        self.root.as_deref().unwrap().walk(|node| {
+            let cur_index = node.lcount() as usize + up_count;
+            if i < cur_index && j < cur_index {
+                Left
+            } else if i > cur_index && j > cur_index {
+                up_count = cur_index + 1;
+                Right
+            } else {
+                lca = Some(cur_index + flen);
+                Stop
+            }
+        });
+        lca
     }
 
-    // TODO ? iter_mut
-    // TODO { O(n) } truncate, append, split_off, retain
-}
+    /// Returns a cursor over the elements, positioned before the first one.
+    /// Buffered elements are merged into the tree first (see `flush`), hence
+    /// the `&mut self`.
+    pub fn cursor(&mut self) -> Cursor<'_, CountNode<T, K>> {
+        self.flush();
+        Cursor::new(self.root())
+    }
+
+    /// Returns a read-only cursor at the element at `index`, which can move
+    /// to the neighbouring elements in amortized O(1) time, and keeps track of
+    /// its index. An `index` equal to the length gives the ghost position (see
+    /// `IndexCursor`). Time complexity: O(log(n))
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn cursor_at(&self, index: usize) -> IndexCursor<'_, T, K> {
+        use WalkAction::*;
 
-impl<T> BinaryTree for CountTree<T> {
-    type Node = CountNode<T>;
+        assert!(index <= self.len(), "index {} out of bounds for length {}", index, self.len());
+        let mut cursor = IndexCursor {
+            tree: self,
+            index,
+            inner: Cursor::new(self.root.as_deref()),
+        };
+        let flen = self.front.len();
+        if index >= flen && index < flen + self.tree_len() {
+            let index = index - flen;
+            let mut up_count = 0;
+            cursor.inner.seek(|node| index_walker!(index, node, up_count, {}));
+        }
+        cursor
+    }
 
-    fn root(&self) -> Option<&Self::Node> {
-        self.0.as_ref().map(|nodeptr| &**nodeptr)
+    /// Returns an iterator over `(index, &element)` pairs, in order.
+    pub fn iter_indexed(&self) -> IndexedIter<'_, T, K> {
+        self.iter_indexed_from(0)
     }
-}
 
-impl<T> Debug for CountTree<T>
-    where T: Debug
-{
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        let mut ds = f.debug_struct("CountTree");
-        if let Some(ref root) = self.0 {
-            ds.field("_count", &root.count);
-            ds.field("_height", &root.height);
-            ds.field("_inner", &DebugPrefix("^", root));
-        } else {
-            ds.field("_count", &0);
-            ds.field("_height", &0);
-            ds.field("_inner", &DebugPrefix("^", &()));
+    /// Same as `iter_indexed`, but starts at the element at `index` (or yields
+    /// nothing if it is out of bounds). The indices are computed from the
+    /// counts cached in the nodes while descending, rather than by counting
+    /// from the first element. Time complexity: O(log(n)) to start.
+    pub fn iter_indexed_from(&self, index: usize) -> IndexedIter<'_, T, K> {
+        use WalkAction::*;
+
+        let flen = self.front.len();
+        let tlen = self.tree_len();
+        let front_start = index.min(flen);
+        let back_start = index.saturating_sub(flen + tlen).min(self.back.len());
+        let mut stack = Vec::new();
+        if let Some(root) = self.root.as_deref() {
+            if index < flen + tlen {
+                let index = index.saturating_sub(flen);
+                let mut up_count = 0;
+                root.walk(|node| {
+                    let cur_index = node.lcount() as usize + up_count;
+                    let action = index_walker!(index, node, up_count, {});
+                    if action != Right {
+                        stack.push((node, flen + cur_index));
+                    }
+                    action
+                });
+            }
+        }
+        IndexedIter {
+            front: (front_start..flen).zip(self.front[..flen - front_start].iter().rev()),
+            stack,
+            back: (flen + tlen + back_start..).zip(self.back[back_start..].iter()),
         }
-        ds.finish()
     }
-}
 
-impl<T> Drop for CountTree<T> {
-    fn drop(&mut self) {
-        self.clear();
+    /// Returns an iterator which copies the elements out in order, in
+    /// buffers of `size` elements each (the last one may be shorter). Panics
+    /// if `size` is 0.
+    pub fn iter_chunks(&self, size: usize) -> Chunks<Iter<'_, T, K>>
+        where T: Clone
+    {
+        Chunks::new(self.into_iter(), size)
     }
-}
 
-fn is_power(v: u32) -> bool {
-    if v == 0 {
-        false
-    } else {
-        v & (v - 1) == 0
+    /// Returns an iterator over the pairs of consecutive elements, by
+    /// reference.
+    pub fn pairs(&self) -> Pairs<Iter<'_, T, K>> {
+        Pairs::new(self.into_iter())
     }
-}
 
-fn exp_floor_log(v: u32) -> u32 {
-    if v == 0 || is_power(v) {
-        v
-    } else {
-        let mut efl = v - 1;
-        efl |= efl >> 1;
-        efl |= efl >> 2;
-        efl |= efl >> 4;
-        efl |= efl >> 8;
-        efl |= efl >> 16;
-        efl += 1;
-        efl >> 1
+    /// Returns an iterator over the overlapping windows of `size` consecutive
+    /// elements, by reference. Panics if `size` is 0.
+    pub fn windows(&self, size: usize) -> Windows<Iter<'_, T, K>> {
+        Windows::new(self.into_iter(), size)
     }
-}
 
-impl<T> FromIterator<T> for CountTree<T> {
-    /// Time complexity: &Theta;(n + log<sup>2</sup>(n))
-    fn from_iter<I>(iterable: I) -> Self
-        where I: IntoIterator<Item = T>
+    /// Returns the index of the minimum element with respect to `compare`
+    /// (the first one, if several are equally minimum), along with the
+    /// element, or `None` if the tree is empty. Time complexity: O(n)
+    pub fn min_by<F>(&self, mut compare: F) -> Option<(usize, &T)>
+        where F: FnMut(&T, &T) -> cmp::Ordering
     {
-        use WalkAction::*;
+        self.iter_indexed().min_by(|a, b| compare(a.1, b.1))
+    }
 
-        let mut iter = iterable.into_iter();
-        if let Some(item) = iter.next() {
-            let mut node = Box::new(CountNode::new(item));
-            let mut count = 1;
-            for item in iter {
-                let mut new_node = Box::new(CountNode::new(item));
-                new_node.insert_left(Some(node));
-                node = new_node;
-                count += 1;
-                let rcount = if is_power(count + 1) {
-                    count >> 1
-                } else {
-                    count
-                };
-                let mut rotate_points = 1;
-                while rcount & rotate_points == rotate_points {
-                    node.rotate_right().unwrap();
-                    rotate_points <<= 1;
+    /// Returns the index of the maximum element with respect to `compare`
+    /// (the last one, if several are equally maximum), along with the
+    /// element, or `None` if the tree is empty. Time complexity: O(n)
+    pub fn max_by<F>(&self, mut compare: F) -> Option<(usize, &T)>
+        where F: FnMut(&T, &T) -> cmp::Ordering
+    {
+        self.iter_indexed().max_by(|a, b| compare(a.1, b.1))
+    }
+
+    /// Returns the index of the element for which `f` returns the minimum key
+    /// (the first one, if several are equally minimum), along with the
+    /// element, or `None` if the tree is empty. Time complexity: O(n)
+    pub fn position_min_by_key<B, F>(&self, mut f: F) -> Option<(usize, &T)>
+        where B: Ord,
+              F: FnMut(&T) -> B
+    {
+        self.iter_indexed().min_by_key(|&(_, item)| f(item))
+    }
+
+    /// Returns the index of the element for which `f` returns the maximum key
+    /// (the last one, if several are equally maximum), along with the
+    /// element, or `None` if the tree is empty. Time complexity: O(n)
+    pub fn position_max_by_key<B, F>(&self, mut f: F) -> Option<(usize, &T)>
+        where B: Ord,
+              F: FnMut(&T) -> B
+    {
+        self.iter_indexed().max_by_key(|&(_, item)| f(item))
+    }
+
+    /// Returns an iterator over the runs of consecutive elements for which
+    /// `pred` holds between each element and the next, as slices of the
+    /// tree. Time complexity: O(n) for the whole iteration.
+    ///
+    /// ```
+    /// use binary_tree::count::CountTree;
+    ///
+    /// let ct: CountTree<_> = vec![1, 1, 2, 3, 3].into_iter().collect();
+    /// let runs: Vec<usize> = ct.chunk_by(|a, b| a == b).map(|run| run.len()).collect();
+    /// assert_eq!(runs, [2, 1, 2]);
+    /// ```
+    pub fn chunk_by<F>(&self, pred: F) -> ChunkBy<'_, T, K, F>
+        where F: FnMut(&T, &T) -> bool
+    {
+        let mut iter = self.into_iter();
+        let next = iter.next();
+        ChunkBy {
+            tree: self,
+            iter,
+            start: 0,
+            next,
+            pred,
+        }
+    }
+
+    /// Calls `f` with a mutable reference to each element, in order. Time
+    /// complexity: O(n)
+    pub fn for_each_mut<F>(&mut self, mut f: F)
+        where F: FnMut(&mut T)
+    {
+        self.front.iter_mut().rev().for_each(&mut f);
+        if let Some(ref mut root) = self.root {
+            let mut iter = StreamingIterMut::new(&mut **root);
+            while iter.next_with(&mut f).is_some() {}
+        }
+        self.back.iter_mut().for_each(f);
+    }
+
+    /// Folds the elements in order, like `Iterator::fold`, but walks the tree
+    /// directly instead of keeping the stack of an iterator. Time complexity:
+    /// O(n)
+    pub fn fold<B, F>(&self, init: B, mut f: F) -> B
+        where F: FnMut(B, &T) -> B
+    {
+        let acc = self.front.iter().rev().fold(init, &mut f);
+        let acc = match self.root.as_deref() {
+            Some(root) => fold_node(root, acc, &mut f),
+            None => acc,
+        };
+        self.back.iter().fold(acc, f)
+    }
+
+    /// Calls `f` with a reference to each element, in order (see `fold`).
+    /// Time complexity: O(n)
+    pub fn for_each<F>(&self, mut f: F)
+        where F: FnMut(&T)
+    {
+        self.fold((), |(), item| f(item))
+    }
+
+    /// Returns a view of the elements in `range`, which supports indexing
+    /// and iteration like the tree itself, without copying.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> CountTreeSlice<'_, T, K> {
+        assert!(range.start <= range.end && range.end <= self.len(),
+                "range {:?} out of bounds for length {}", range, self.len());
+        CountTreeSlice {
+            tree: self,
+            start: range.start,
+            len: range.len(),
+        }
+    }
+
+    /// Consumes the tree, and returns two trees holding the elements before
+    /// `at` and the rest, respectively. Time complexity: O(log(n)), after
+    /// merging the buffered elements (see `flush`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `at > len`.
+    pub fn split_at(mut self, at: usize) -> (CountTree<T, K>, CountTree<T, K>) {
+        assert!(at <= self.len(), "index {} out of bounds for length {}", at, self.len());
+        self.flush();
+        let (left, right) = split::<T, K>(self.root.take(), at);
+        (CountTree::with_root(left), CountTree::with_root(right))
+    }
+
+    /// Removes the first `k` elements (or all of them, if fewer), and returns
+    /// them as a tree. Time complexity: O(log(n)), after merging the buffered
+    /// elements (see `flush`).
+    pub fn pop_front_n(&mut self, k: usize) -> CountTree<T, K> {
+        self.tracked(|ct| {
+            let at = k.min(ct.len());
+            let (front, rest) = mem::take(ct).split_at(at);
+            *ct = rest;
+            front
+        })
+    }
+
+    /// Removes the last `k` elements (or all of them, if fewer), and returns
+    /// them as a tree. Time complexity: O(log(n)), after merging the buffered
+    /// elements (see `flush`).
+    pub fn pop_back_n(&mut self, k: usize) -> CountTree<T, K> {
+        self.tracked(|ct| {
+            let at = ct.len() - k.min(ct.len());
+            let (rest, back) = mem::take(ct).split_at(at);
+            *ct = rest;
+            back
+        })
+    }
+
+    /// Consumes the tree, and returns an iterator over trees of `size`
+    /// elements each (the last one may be shorter), in order. The pieces are
+    /// split off without copying the elements, so that they can be sent to
+    /// worker threads. Each piece takes O(log(n)) time, after merging the
+    /// buffered elements (see `flush`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is 0.
+    pub fn chunks(self, size: usize) -> TreeChunks<T, K> {
+        assert!(size != 0, "chunk size must be non-zero");
+        TreeChunks { rest: self, size }
+    }
+
+    /// Consumes the tree, and returns an immutable snapshot of it, which can
+    /// be cloned and shared between threads cheaply. Time complexity: O(1),
+    /// after merging the buffered elements (see `flush`).
+    pub fn freeze(mut self) -> FrozenCountTree<T, K> {
+        self.flush();
+        FrozenCountTree { tree: Arc::new(self) }
+    }
+
+    /// Runs `f` on a `Txn` through which it edits the tree, and returns its
+    /// result. If `f` returns `Err` or panics, the tree is rolled back to its
+    /// state before the transaction, otherwise the edits are kept.
+    ///
+    /// The tree is kept as a snapshot (a clone) for the rollback, which takes
+    /// O(1) time with shared nodes (as in `RcCountTree` and `ArcCountTree`),
+    /// whose edits then copy only the nodes along the modified paths, and
+    /// O(n) time otherwise.
+    ///
+    /// ```
+    /// use binary_tree::count::ArcCountTree;
+    ///
+    /// let mut ct: ArcCountTree<_> = (0..5).collect();
+    /// let ret = ct.transaction(|txn| {
+    ///     txn.remove(0);
+    ///     txn.push_back(5);
+    ///     if txn.len() > 4 { Err("too long") } else { Ok(()) }
+    /// });
+    /// assert_eq!(ret, Err("too long"));
+    /// assert_eq!(ct.to_vec(), [0, 1, 2, 3, 4]);
+    /// ```
+    pub fn transaction<R, E, F>(&mut self, f: F) -> Result<R, E>
+        where T: Clone,
+              F: FnOnce(&mut Txn<'_, T, K>) -> Result<R, E>
+    {
+        self.flush();
+        let snapshot = self.clone();
+        let mut txn = Txn {
+            tree: self,
+            snapshot: Some(snapshot),
+        };
+        // copying the nodes shared with the snapshot is intended
+        let ret = cow::allow_clones(|| f(&mut txn));
+        if ret.is_ok() {
+            txn.snapshot = None;
+        }
+        ret
+    }
+
+    /// Inserts all the elements of `other` at `index`, shifting the elements
+    /// after it. Time complexity: O(log(n) + log(m)), after merging the
+    /// buffered elements of both trees (see `flush`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index > len`.
+    pub fn insert_tree(&mut self, index: usize, mut other: CountTree<T, K>) {
+        self.tracked(|ct| {
+            assert!(index <= ct.len(), "index {} out of bounds for length {}", index, ct.len());
+            ct.flush();
+            other.flush();
+            let (left, right) = split::<T, K>(ct.root.take(), index);
+            ct.root = concat::<T, K>(left, concat::<T, K>(other.root.take(), right));
+        })
+    }
+
+    /// Retains only the elements for which `f` returns `true`, in order. `f`
+    /// may also modify the elements it visits. The tree is rebuilt from the
+    /// retained elements. If `f` panics, the elements not visited yet (and
+    /// the one it panicked on) are kept. Time complexity: O(n)
+    pub fn retain_mut<F>(&mut self, mut f: F)
+        where F: FnMut(&mut T) -> bool
+    {
+        self.tracked(|ct| {
+            let mut guard = RetainGuard {
+                kept: Vec::with_capacity(ct.len()),
+                rest: mem::take(ct).into_iter(),
+                tree: ct,
+            };
+            for item in guard.rest.by_ref() {
+                guard.kept.push(item);
+                if !f(guard.kept.last_mut().unwrap()) {
+                    guard.kept.pop();
+                }
+            }
+        })
+    }
+
+    /// Retains only the elements for which `f` returns `true`, in order. Time
+    /// complexity: O(n)
+    pub fn retain<F>(&mut self, mut f: F)
+        where F: FnMut(&T) -> bool
+    {
+        self.retain_mut(|item| f(item))
+    }
+
+    /// Returns a new tree holding a copy of the elements in `range`. With
+    /// shared nodes (as in `RcCountTree` and `ArcCountTree`), only the nodes
+    /// along the boundaries of the range are copied, and the rest are shared
+    /// with `self`. Time complexity: O(log(n)) with shared nodes (plus the
+    /// number of buffered elements), O(n) otherwise.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn clone_range(&self, range: Range<usize>) -> CountTree<T, K>
+        where T: Clone
+    {
+        assert!(range.start <= range.end && range.end <= self.len(),
+                "range {:?} out of bounds for length {}", range, self.len());
+        // copying the boundaries of shared nodes is intended
+        cow::allow_clones(|| {
+            let (_, rest) = self.clone().split_at(range.start);
+            rest.split_at(range.len()).0
+        })
+    }
+
+    /// Returns a copy of the elements in a `Vec`. Time complexity: O(n)
+    pub fn to_vec(&self) -> Vec<T>
+        where T: Clone
+    {
+        let mut vec = Vec::with_capacity(self.len());
+        vec.extend(self.into_iter().cloned());
+        vec
+    }
+
+    /// Copies the elements in `range` into `dst`. Panics if the range is out
+    /// of bounds, or if its length differs from that of `dst`. Time
+    /// complexity: O(log(n) + range.len())
+    pub fn copy_range_to_slice(&self, range: Range<usize>, dst: &mut [T])
+        where T: Copy
+    {
+        assert!(range.start <= range.end && range.end <= self.len(),
+                "range {:?} out of bounds for length {}", range, self.len());
+        assert_eq!(range.len(), dst.len(), "range and destination lengths differ");
+        for (slot, (_, item)) in dst.iter_mut().zip(self.iter_indexed_from(range.start)) {
+            *slot = *item;
+        }
+    }
+
+    /// Reverses the order of the elements in place. Time complexity: O(n)
+    pub fn reverse(&mut self) {
+        // `front` is stored in reverse order, and `back` in order
+        mem::swap(&mut self.front, &mut self.back);
+        if let Some(ref mut root) = self.root {
+            algos::invert(&mut **root);
+        }
+    }
+
+    // TODO ? iter_mut
+    // TODO { O(n) } truncate, append, split_off
+}
+
+impl<T: Clone> CountTree<T, RcCowKind> {
+    /// Converts into a thread-safe `ArcCountTree` holding the same elements,
+    /// e.g. to hand a tree built on one thread over to others. Elements still
+    /// shared with clones of this tree are cloned, the others are moved. Time
+    /// complexity: O(n)
+    pub fn into_shared(self) -> ArcCountTree<T> {
+        self.into_iter().collect()
+    }
+}
+
+impl<T: Clone> CountTree<T, ArcCowKind> {
+    /// Converts into a single-threaded `RcCountTree` holding the same
+    /// elements. This is the inverse of `RcCountTree::into_shared`. Time
+    /// complexity: O(n)
+    pub fn into_local(self) -> RcCountTree<T> {
+        self.into_iter().collect()
+    }
+}
+
+/// Displays the elements as a list, like `[a, b, c]`. The formatting options
+/// are applied to each element.
+impl<T, K: CountKind<T>> Display for CountTree<T, K>
+    where T: Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.write_str("[")?;
+        for (i, item) in self.into_iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            item.fmt(f)?;
+        }
+        f.write_str("]")
+    }
+}
+
+impl<T, K: CountKind<T>> Debug for CountTree<T, K>
+    where T: Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let mut ds = f.debug_struct("CountTree");
+        if !self.front.is_empty() {
+            ds.field("_front", &self.front);
+        }
+        if !self.back.is_empty() {
+            ds.field("_back", &self.back);
+        }
+        if let Some(ref root) = self.root {
+            ds.field("_count", &root.count());
+            ds.field("_height", &root.height());
+            ds.field("_inner", &DebugPrefix("^", &**root));
+        } else {
+            ds.field("_count", &0);
+            ds.field("_height", &0);
+            ds.field("_inner", &DebugPrefix("^", &()));
+        }
+        ds.finish()
+    }
+}
+
+impl<T, K: CountKind<T>> Drop for CountTree<T, K> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+#[cfg(not(feature="compact-nodes"))]
+fn is_power(v: u32) -> bool {
+    if v == 0 {
+        false
+    } else {
+        v & (v - 1) == 0
+    }
+}
+
+#[cfg(not(feature="compact-nodes"))]
+fn exp_floor_log(v: u32) -> u32 {
+    if v == 0 || is_power(v) {
+        v
+    } else {
+        let mut efl = v - 1;
+        efl |= efl >> 1;
+        efl |= efl >> 2;
+        efl |= efl >> 4;
+        efl |= efl >> 8;
+        efl |= efl >> 16;
+        efl += 1;
+        efl >> 1
+    }
+}
+
+// Builds a perfectly balanced tree out of the next `len` elements of `iter`.
+// Recursion depth is O(log(len)).
+fn build_balanced<T, K, I>(len: usize, iter: &mut I) -> Option<NodePtr<T, K>>
+    where K: CountKind<T>,
+          I: Iterator<Item = T>
+{
+    if len == 0 {
+        None
+    } else {
+        let left = build_balanced::<T, K, _>(len / 2, iter);
+        let mut node = new_node::<T, K>(iter.next().unwrap());
+        let right = build_balanced::<T, K, _>(len - len / 2 - 1, iter);
+        node.graft(left, right);
+        Some(node)
+    }
+}
+
+// Builds a perfectly balanced tree of `f(start)`, ..., `f(start + len - 1)`,
+// calling `f` in that order. Recursion depth is O(log(len)).
+fn build_from_fn<T, K, F>(start: usize, len: usize, f: &mut F) -> Option<NodePtr<T, K>>
+    where K: CountKind<T>,
+          F: FnMut(usize) -> T
+{
+    if len == 0 {
+        None
+    } else {
+        let mid = start + len / 2;
+        let left = build_from_fn::<T, K, _>(start, len / 2, f);
+        let mut node = new_node::<T, K>(f(mid));
+        let right = build_from_fn::<T, K, _>(mid + 1, len - len / 2 - 1, f);
+        node.graft(left, right);
+        Some(node)
+    }
+}
+
+// Returns the trees of `n` and of `n + 1` copies of `value`, shaped like those
+// made by `build_balanced`. Equal subtrees are copied with `K::clone_ptr`, so
+// only O(log(n)) nodes are made when it shares them. Recursion depth is
+// O(log(n)).
+fn repeat_pair<T, K>(value: &T, n: usize) -> (Option<NodePtr<T, K>>, NodePtr<T, K>)
+    where T: Clone,
+          K: CountKind<T>
+{
+    let node = |left, right| {
+        let mut node = new_node::<T, K>(value.clone());
+        node.graft(left, right);
+        node
+    };
+    if n == 0 {
+        return (None, node(None, None));
+    }
+    // the subtrees of both trees have either `m` or `m + 1` elements
+    let (small, large) = repeat_pair::<T, K>(value, (n - 1) / 2);
+    let copy = |tree: &Option<NodePtr<T, K>>| tree.as_ref().map(K::clone_ptr);
+    if n % 2 == 1 {
+        let tree = node(copy(&small), copy(&small));
+        (Some(tree), node(Some(large), small))
+    } else {
+        let tree = node(Some(K::clone_ptr(&large)), small);
+        (Some(tree), node(Some(K::clone_ptr(&large)), Some(large)))
+    }
+}
+
+fn height_of<T, K: CountKind<T>>(tree: Option<&CountNode<T, K>>) -> i32 {
+    tree.map_or(-1, |node| node.height() as i32)
+}
+
+// Returns the height of a perfect tree of `count` nodes.
+fn perfect_height(count: u32) -> i32 {
+    (count + 1).trailing_zeros() as i32 - 1
+}
+
+// Inserts the single node `new` at `index` of the subtree at `root`,
+// rebalancing on the way up. Time complexity: O(log(n))
+#[cfg(not(feature="compact-nodes"))]
+fn insert_node<T, K>(root: &mut CountNode<T, K>, index: usize, new: NodePtr<T, K>)
+    where K: CountKind<T>
+{
+    use WalkAction::*;
+
+    if index == 0 {
+        root.walk_reshape(|_| Left,
+                          move |node| {
+                              node.insert_left(Some(new));
+                          },
+                          |node, _| node.rebalance());
+    } else if index == root.count() as usize {
+        root.walk_reshape(|_| Right,
+                          move |node| {
+                              node.insert_right(Some(new));
+                          },
+                          |node, _| node.rebalance());
+    } else {
+        let mut up_count = 0;
+        root.walk_reshape(|node| index_walker!(index, node, up_count, {}),
+                          move |node| {
+                              node.insert_before(new, |node, _| node.rebalance());
+                          },
+                          |node, _| node.rebalance());
+    }
+}
+
+// Inserts the single node `new` at `index` of the subtree at `root`, and
+// returns by how much the subtree grew. Recursion depth is O(log(n)).
+#[cfg(feature="compact-nodes")]
+fn insert_node<T, K>(root: &mut CountNode<T, K>, index: usize, new: NodePtr<T, K>) -> i32
+    where K: CountKind<T>
+{
+    let lcount = root.lcount() as usize;
+    let to_left = index <= lcount;
+    let (child, index) = if to_left {
+        (&mut root.left, index)
+    } else {
+        (&mut root.right, index - lcount - 1)
+    };
+    let grown = match *child {
+        Some(ref mut child) => insert_node::<T, K>(child, index, new),
+        None => {
+            *child = Some(new);
+            1
+        }
+    };
+    root.retrace(to_left, grown)
+}
+
+// Removes the node at `index` of the tree at `root`, rebalancing on the way
+// up, and returns it detached. Time complexity: O(log(n))
+#[cfg(not(feature="compact-nodes"))]
+fn remove_node<T, K>(root: &mut Option<NodePtr<T, K>>, index: usize) -> NodePtr<T, K>
+    where K: CountKind<T>
+{
+    use WalkAction::*;
+
+    let count = root.as_ref().unwrap().count() as usize;
+    if count == 1 {
+        return root.take().unwrap();
+    }
+    let root = &mut **root.as_mut().unwrap();
+    if index == 0 {
+        root.walk_extract(|_| Left,
+                          |node, ret| {
+                              if let Some(mut right) = node.detach_right() {
+                                  mem::swap(&mut *right, node);
+                                  *ret = Some(right);
+                              }
+                          },
+                          |node, _| node.rebalance())
+    } else if index + 1 == count {
+        root.walk_extract(|_| Right,
+                          |node, ret| {
+                              if let Some(mut left) = node.detach_left() {
+                                  mem::swap(&mut *left, node);
+                                  *ret = Some(left);
+                              }
+                          },
+                          |node, _| node.rebalance())
+    } else {
+        let mut up_count = 0;
+        root.walk_extract(|node| index_walker!(index, node, up_count, {}),
+                          |node, ret| {
+                              *ret = node.try_remove(|node, _| node.rebalance());
+                          },
+                          |node, _| node.rebalance())
+    }
+    .unwrap()
+}
+
+// Removes the node at `index` of the tree at `root`, and returns it detached.
+// Time complexity: O(log(n))
+#[cfg(feature="compact-nodes")]
+fn remove_node<T, K>(root: &mut Option<NodePtr<T, K>>, index: usize) -> NodePtr<T, K>
+    where K: CountKind<T>
+{
+    remove_at::<T, K>(root, index).0
+}
+
+// Removes the node at `index` of the subtree at `slot`, and returns it
+// detached, along with by how much the subtree grew (0 or -1). Recursion depth
+// is O(log(n)).
+#[cfg(feature="compact-nodes")]
+fn remove_at<T, K>(slot: &mut Option<NodePtr<T, K>>, index: usize) -> (NodePtr<T, K>, i32)
+    where K: CountKind<T>
+{
+    let node = slot.as_mut().unwrap();
+    let lcount = node.lcount() as usize;
+    let (removed, from_left, grown) = if index < lcount {
+        let (removed, grown) = remove_at::<T, K>(&mut node.left, index);
+        (removed, true, grown)
+    } else if index > lcount {
+        let (removed, grown) = remove_at::<T, K>(&mut node.right, index - lcount - 1);
+        (removed, false, grown)
+    } else if node.left.is_some() && node.right.is_some() {
+        // the previous node takes the place of this one
+        let (mut prev, grown) = remove_at::<T, K>(&mut node.left, index - 1);
+        mem::swap(&mut prev.val, &mut node.val);
+        (prev, true, grown)
+    } else {
+        let child = node.left.take().or_else(|| node.right.take());
+        let mut removed = mem::replace(slot, child).unwrap();
+        removed.stats = 1;
+        return (removed, -1);
+    };
+    let grown = node.retrace(from_left, grown);
+    (removed, grown)
+}
+
+// Folds the elements of the subtree at `node` in order, recursing only into
+// left children. Recursion depth is O(height).
+fn fold_node<T, K, B, F>(mut node: &CountNode<T, K>, mut acc: B, f: &mut F) -> B
+    where K: CountKind<T>,
+          F: FnMut(B, &T) -> B
+{
+    loop {
+        if let Some(left) = node.left() {
+            acc = fold_node(left, acc, f);
+        }
+        acc = f(acc, &node.val);
+        match node.right() {
+            Some(right) => node = right,
+            None => return acc,
+        }
+    }
+}
+
+// Joins two balanced trees with `mid` (a single node) placed in between them.
+// Time complexity: O(|h(left) - h(right)| + 1)
+#[cfg(not(feature="compact-nodes"))]
+fn join<T, K>(left: Option<NodePtr<T, K>>, mut mid: NodePtr<T, K>, right: Option<NodePtr<T, K>>) -> NodePtr<T, K>
+    where K: CountKind<T>
+{
+    use WalkAction::*;
+
+    debug_assert!(mid.count() == 1);
+    let (lh, rh) = (height_of(left.as_deref()), height_of(right.as_deref()));
+    if lh > rh + 1 {
+        let mut left = left.unwrap();
+        left.walk_reshape(|node| {
+                              if node.height() as i32 > rh + 1 {
+                                  Right
+                              } else {
+                                  Stop
+                              }
+                          },
+                          move |node| {
+                              if node.height() as i32 > rh + 1 {
+                                  // stopped at a missing child, which is only
+                                  // possible if `right` is empty
+                                  node.insert_right(Some(mid));
+                                  return;
+                              }
+                              mem::swap(&mut *mid, node);
+                              node.insert_left(Some(mid));
+                              node.insert_right(right);
+                          },
+                          |node, _| node.rebalance());
+        left
+    } else if rh > lh + 1 {
+        let mut right = right.unwrap();
+        right.walk_reshape(|node| {
+                               if node.height() as i32 > lh + 1 {
+                                   Left
+                               } else {
+                                   Stop
+                               }
+                           },
+                           move |node| {
+                               if node.height() as i32 > lh + 1 {
+                                   // stopped at a missing child, which is only
+                                   // possible if `left` is empty
+                                   node.insert_left(Some(mid));
+                                   return;
+                               }
+                               mem::swap(&mut *mid, node);
+                               node.insert_right(Some(mid));
+                               node.insert_left(left);
+                           },
+                           |node, _| node.rebalance());
+        right
+    } else {
+        mid.graft(left, right);
+        mid
+    }
+}
+
+// Joins two balanced trees with `mid` (a single node) placed in between them.
+// Time complexity: O(log(n)), for measuring the heights
+#[cfg(feature="compact-nodes")]
+fn join<T, K>(left: Option<NodePtr<T, K>>, mid: NodePtr<T, K>, right: Option<NodePtr<T, K>>) -> NodePtr<T, K>
+    where K: CountKind<T>
+{
+    let (lh, rh) = (height_of(left.as_deref()), height_of(right.as_deref()));
+    join_h::<T, K>(left, lh, mid, right, rh).0
+}
+
+// Same as `join`, given the heights of `left` and `right`, and also returns
+// the height of the result.
+#[cfg(not(feature="compact-nodes"))]
+fn join_h<T, K>(left: Option<NodePtr<T, K>>, _lh: i32, mid: NodePtr<T, K>, right: Option<NodePtr<T, K>>, _rh: i32) -> (NodePtr<T, K>, i32)
+    where K: CountKind<T>
+{
+    let tree = join::<T, K>(left, mid, right);
+    let height = tree.height() as i32;
+    (tree, height)
+}
+
+// Joins two balanced trees of heights `lh` and `rh` with `mid` (a single node)
+// placed in between them, and returns the tree and its height. Recursion depth
+// is O(|lh - rh| + 1).
+#[cfg(feature="compact-nodes")]
+fn join_h<T, K>(left: Option<NodePtr<T, K>>, lh: i32, mut mid: NodePtr<T, K>, right: Option<NodePtr<T, K>>, rh: i32) -> (NodePtr<T, K>, i32)
+    where K: CountKind<T>
+{
+    debug_assert!(mid.count() == 1);
+    if lh > rh + 1 {
+        let mut left = left.unwrap();
+        let (_, lrh) = left.child_heights(lh);
+        let (tree, height) = join_h::<T, K>(left.right.take(), lrh, mid, right, rh);
+        left.right = Some(tree);
+        let grown = left.retrace(false, height - lrh);
+        (left, lh + grown)
+    } else if rh > lh + 1 {
+        let mut right = right.unwrap();
+        let (rlh, _) = right.child_heights(rh);
+        let (tree, height) = join_h::<T, K>(left, lh, mid, right.left.take(), rlh);
+        right.left = Some(tree);
+        let grown = right.retrace(true, height - rlh);
+        (right, rh + grown)
+    } else {
+        mid.left = left;
+        mid.right = right;
+        mid.update_count();
+        mid.set_balance(lh - rh);
+        (mid, cmp::max(lh, rh) + 1)
+    }
+}
+
+type Halves<T, K> = (Option<NodePtr<T, K>>, Option<NodePtr<T, K>>);
+
+// Splits a balanced tree into two balanced trees, holding the first `at`
+// elements and the rest. Time complexity: O(log(n)), as the costs of the
+// joins telescope
+fn split<T, K>(root: Option<NodePtr<T, K>>, at: usize) -> Halves<T, K>
+    where K: CountKind<T>
+{
+    // the nodes along the path, detached from their children, with their
+    // other subtree and its height; the flag is set if the split point is to
+    // the left of the node
+    let mut path = Vec::new();
+    let mut height = height_of(root.as_deref());
+    let mut subtree = root;
+    let mut at = at;
+    while let Some(mut node) = subtree {
+        let lcount = node.lcount() as usize;
+        let (lh, rh) = node.child_heights(height);
+        let (left, right) = node.take_children();
+        if at <= lcount {
+            subtree = left;
+            height = lh;
+            path.push((node, right, rh, true));
+        } else {
+            at -= lcount + 1;
+            subtree = right;
+            height = rh;
+            path.push((node, left, lh, false));
+        }
+    }
+    let (mut left, mut lh, mut right, mut rh) = (None, -1, None, -1);
+    while let Some((node, other, oh, to_left)) = path.pop() {
+        if to_left {
+            let (tree, height) = join_h::<T, K>(right, rh, node, other, oh);
+            right = Some(tree);
+            rh = height;
+        } else {
+            let (tree, height) = join_h::<T, K>(other, oh, node, left, lh);
+            left = Some(tree);
+            lh = height;
+        }
+    }
+    (left, right)
+}
+
+// Joins two balanced trees, using the first node of `right` as the middle
+// one. Time complexity: O(log(n))
+fn concat<T, K>(left: Option<NodePtr<T, K>>, mut right: Option<NodePtr<T, K>>) -> Option<NodePtr<T, K>>
+    where K: CountKind<T>
+{
+    if right.is_none() {
+        return left;
+    }
+    let mid = remove_node::<T, K>(&mut right, 0);
+    Some(join::<T, K>(left, mid, right))
+}
+
+impl<T, K: CountKind<T>> FromIterator<T> for CountTree<T, K> {
+    /// Time complexity: &Theta;(n + log<sup>2</sup>(n))
+    #[cfg(not(feature="compact-nodes"))]
+    fn from_iter<I>(iterable: I) -> Self
+        where I: IntoIterator<Item = T>
+    {
+        use WalkAction::*;
+
+        let mut iter = iterable.into_iter();
+        if let Some(item) = iter.next() {
+            let mut node = new_node::<T, K>(item);
+            let mut count = 1;
+            for item in iter {
+                let mut new_node = new_node::<T, K>(item);
+                new_node.insert_left(Some(node));
+                node = new_node;
+                count += 1;
+                let rcount = if is_power(count + 1) {
+                    count >> 1
+                } else {
+                    count
+                };
+                let mut rotate_points = 1;
+                while rcount & rotate_points == rotate_points {
+                    node.rotate_right().unwrap();
+                    on_rotation();
+                    rotate_points <<= 1;
                     rotate_points |= 1;
                 }
             }
@@ -460,12 +1722,14 @@ impl<T> FromIterator<T> for CountTree<T> {
             count = node.lcount() + 1; // not needed
             while count > balanced_till {
                 node.rotate_right().unwrap();
+                on_rotation();
                 node.right
                     .as_mut()
                     .unwrap()
                     .walk_reshape(|node| {
                                       if node.balance_factor() > 1 {
                                           node.rotate_right().unwrap();
+                                          on_rotation();
                                           Right
                                       } else {
                                           Stop
@@ -475,38 +1739,282 @@ impl<T> FromIterator<T> for CountTree<T> {
                                   |_, _| ());
                 count = node.lcount() + 1;
             }
-            CountTree(Some(node))
+            CountTree::with_root(Some(node))
         } else {
-            CountTree::new()
+            CountTree::default()
         }
     }
+
+    /// Time complexity: O(n)
+    #[cfg(feature="compact-nodes")]
+    fn from_iter<I>(iterable: I) -> Self
+        where I: IntoIterator<Item = T>
+    {
+        // the rotations above may leave the top of the tree off the AVL
+        // bounds, which the balance factors of compact nodes cannot record
+        let mut builder = CountTreeBuilder::new();
+        builder.extend(iterable);
+        builder.finish()
+    }
+}
+
+impl<T, K: CountKind<T>> From<VecDeque<T>> for CountTree<T, K> {
+    /// Time complexity: O(n)
+    fn from(deque: VecDeque<T>) -> Self {
+        deque.into_iter().collect()
+    }
+}
+
+impl<T, K: CountKind<T>> From<LinkedList<T>> for CountTree<T, K> {
+    /// Time complexity: O(n)
+    fn from(list: LinkedList<T>) -> Self {
+        list.into_iter().collect()
+    }
 }
 
-impl<'a, T> IntoIterator for &'a CountTree<T> {
+impl<T, K: CountKind<T>> From<CountTree<T, K>> for VecDeque<T> {
+    /// Time complexity: O(n)
+    fn from(tree: CountTree<T, K>) -> Self {
+        let mut deque = VecDeque::with_capacity(tree.len());
+        deque.extend(tree);
+        deque
+    }
+}
+
+impl<'a, T, K: CountKind<T>> IntoIterator for &'a CountTree<T, K> {
     type Item = &'a T;
-    type IntoIter = Iter<'a, T>;
+    type IntoIter = Iter<'a, T, K>;
 
     fn into_iter(self) -> Self::IntoIter {
         Iter {
-            inner: GenIter::new(self.root()),
-            remaining: self.len(),
+            front: self.front.iter().rev(),
+            inner: GenIter::with_len(self.root.as_deref(), self.tree_len()),
+            back: self.back.iter(),
+        }
+    }
+}
+
+pub struct Iter<'a, T: 'a, K: CountKind<T> + 'a = BoxKind> {
+    front: Rev<slice::Iter<'a, T>>,
+    inner: GenIter<'a, CountNode<T, K>>,
+    back: slice::Iter<'a, T>,
+}
+
+impl<'a, T, K: CountKind<T>> Iterator for Iter<'a, T, K> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        self.front.next().or_else(|| self.inner.next()).or_else(|| self.back.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.front.len() + self.inner.size_hint().0 + self.back.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, T, K: CountKind<T>> ExactSizeIterator for Iter<'a, T, K> {}
+
+impl<'a, T, K: CountKind<T>> FusedIterator for Iter<'a, T, K> {}
+
+/// Iterator returned by `CountTree::iter_indexed`.
+pub struct IndexedIter<'a, T: 'a, K: CountKind<T> + 'a = BoxKind> {
+    front: Zip<Range<usize>, Rev<slice::Iter<'a, T>>>,
+    // nodes whose left subtrees are already done, with their indices
+    stack: Vec<(&'a CountNode<T, K>, usize)>,
+    back: Zip<RangeFrom<usize>, slice::Iter<'a, T>>,
+}
+
+impl<'a, T, K: CountKind<T>> IndexedIter<'a, T, K> {
+    fn next_in_tree(&mut self) -> Option<(usize, &'a T)> {
+        let (node, index) = self.stack.pop()?;
+        if let Some(mut child) = node.right() {
+            let mut child_index = index + child.lcount() as usize + 1;
+            self.stack.push((child, child_index));
+            while let Some(left) = child.left() {
+                child_index -= left.rcount() as usize + 1;
+                child = left;
+                self.stack.push((child, child_index));
+            }
         }
+        Some((index, &node.val))
+    }
+}
+
+impl<'a, T, K: CountKind<T>> Iterator for IndexedIter<'a, T, K> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<(usize, &'a T)> {
+        self.front.next().or_else(|| self.next_in_tree()).or_else(|| self.back.next())
     }
 }
 
-pub struct Iter<'a, T: 'a> {
-    inner: GenIter<'a, CountNode<T>>,
+impl<'a, T, K: CountKind<T>> FusedIterator for IndexedIter<'a, T, K> {}
+
+/// A read-only cursor over the elements of a `CountTree`, returned by
+/// `CountTree::cursor_at`.
+///
+/// Like `iter::Cursor`, it has a ghost position between the last and the
+/// first element, whose index is taken to be the length of the tree. Moving
+/// past either end leads to it, and moving on from there wraps around.
+pub struct IndexCursor<'a, T: 'a, K: CountKind<T> + 'a = BoxKind> {
+    tree: &'a CountTree<T, K>,
+    index: usize,
+    // positioned only while `index` is within the tree proper
+    inner: Cursor<'a, CountNode<T, K>>,
+}
+
+impl<'a, T, K: CountKind<T>> IndexCursor<'a, T, K> {
+    /// Returns the index of the cursor, or `None` at the ghost position.
+    pub fn index(&self) -> Option<usize> {
+        if self.index < self.tree.len() {
+            Some(self.index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the element at the cursor, or `None` at the ghost position.
+    pub fn current(&self) -> Option<&'a T> {
+        let tree = self.tree;
+        let (flen, tlen) = (tree.front.len(), tree.tree_len());
+        if self.index < flen {
+            tree.front.get(flen - self.index - 1)
+        } else if self.index < flen + tlen {
+            self.inner.peek()
+        } else {
+            tree.back.get(self.index - flen - tlen)
+        }
+    }
+
+    /// Moves the cursor to the next element, and returns it. Time complexity:
+    /// amortized O(1)
+    pub fn move_next(&mut self) -> Option<&'a T> {
+        let (flen, tlen) = (self.tree.front.len(), self.tree.tree_len());
+        self.index = if self.index == self.tree.len() { 0 } else { self.index + 1 };
+        if self.index == flen && tlen > 0 {
+            self.inner.seek_to_start();
+        } else if self.index > flen && self.index < flen + tlen {
+            self.inner.next();
+        }
+        self.current()
+    }
+
+    /// Moves the cursor to the previous element, and returns it. Time
+    /// complexity: amortized O(1)
+    pub fn move_prev(&mut self) -> Option<&'a T> {
+        let (flen, tlen) = (self.tree.front.len(), self.tree.tree_len());
+        self.index = if self.index == 0 { self.tree.len() } else { self.index - 1 };
+        if self.index + 1 == flen + tlen && tlen > 0 {
+            self.inner.seek_to_end();
+        } else if self.index >= flen && self.index + 1 < flen + tlen {
+            self.inner.prev();
+        }
+        self.current()
+    }
+}
+
+impl<'a, T, K: CountKind<T>> Clone for IndexCursor<'a, T, K> {
+    fn clone(&self) -> Self {
+        IndexCursor {
+            tree: self.tree,
+            index: self.index,
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// An immutable view of a contiguous range of the elements of a `CountTree`,
+/// returned by `CountTree::slice`.
+pub struct CountTreeSlice<'a, T: 'a, K: CountKind<T> + 'a = BoxKind> {
+    tree: &'a CountTree<T, K>,
+    start: usize,
+    len: usize,
+}
+
+impl<'a, T, K: CountKind<T>> CountTreeSlice<'a, T, K> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the element at the given index (relative to the start of the
+    /// slice), or `None` if it is out of bounds. Time complexity: O(log(n))
+    pub fn get(&self, index: usize) -> Option<&'a T> {
+        if index < self.len {
+            self.tree.get(self.start + index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns an iterator over the elements of the slice. Time complexity:
+    /// O(log(n)) to start.
+    pub fn iter(&self) -> SliceIter<'a, T, K> {
+        SliceIter {
+            inner: self.tree.iter_indexed_from(self.start),
+            remaining: self.len,
+        }
+    }
+
+    /// Returns a view of the elements in `range` (relative to the start of
+    /// this slice).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> CountTreeSlice<'a, T, K> {
+        assert!(range.start <= range.end && range.end <= self.len,
+                "range {:?} out of bounds for length {}", range, self.len);
+        CountTreeSlice {
+            tree: self.tree,
+            start: self.start + range.start,
+            len: range.len(),
+        }
+    }
+}
+
+impl<'a, T, K: CountKind<T>> Clone for CountTreeSlice<'a, T, K> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T, K: CountKind<T>> Copy for CountTreeSlice<'a, T, K> {}
+
+impl<'a, T: Debug, K: CountKind<T>> Debug for CountTreeSlice<'a, T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'a, T, K: CountKind<T>> IntoIterator for CountTreeSlice<'a, T, K> {
+    type Item = &'a T;
+    type IntoIter = SliceIter<'a, T, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator returned by `CountTreeSlice::iter`.
+pub struct SliceIter<'a, T: 'a, K: CountKind<T> + 'a = BoxKind> {
+    inner: IndexedIter<'a, T, K>,
     remaining: usize,
 }
 
-impl<'a, T> Iterator for Iter<'a, T> {
+impl<'a, T, K: CountKind<T>> Iterator for SliceIter<'a, T, K> {
     type Item = &'a T;
 
     fn next(&mut self) -> Option<&'a T> {
-        if self.remaining > 0 {
-            self.remaining -= 1;
+        if self.remaining == 0 {
+            return None;
         }
-        self.inner.next()
+        self.remaining -= 1;
+        self.inner.next().map(|(_, item)| item)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -514,103 +2022,564 @@ impl<'a, T> Iterator for Iter<'a, T> {
     }
 }
 
-impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+impl<'a, T, K: CountKind<T>> ExactSizeIterator for SliceIter<'a, T, K> {}
+
+impl<'a, T, K: CountKind<T>> FusedIterator for SliceIter<'a, T, K> {}
+
+/// Iterator over the runs of consecutive elements of a `CountTree` which
+/// satisfy a predicate pairwise, returned by `CountTree::chunk_by`.
+pub struct ChunkBy<'a, T: 'a, K: CountKind<T> + 'a, F> {
+    tree: &'a CountTree<T, K>,
+    iter: Iter<'a, T, K>,
+    start: usize,
+    // first element of the next run
+    next: Option<&'a T>,
+    pred: F,
+}
+
+impl<'a, T, K, F> Iterator for ChunkBy<'a, T, K, F>
+    where K: CountKind<T>,
+          F: FnMut(&T, &T) -> bool
+{
+    type Item = CountTreeSlice<'a, T, K>;
+
+    fn next(&mut self) -> Option<CountTreeSlice<'a, T, K>> {
+        let mut prev = self.next?;
+        let mut len = 1;
+        loop {
+            match self.iter.next() {
+                Some(item) if (self.pred)(prev, item) => {
+                    prev = item;
+                    len += 1;
+                }
+                next => {
+                    self.next = next;
+                    break;
+                }
+            }
+        }
+        let run = CountTreeSlice {
+            tree: self.tree,
+            start: self.start,
+            len,
+        };
+        self.start += len;
+        Some(run)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.next.is_some() {
+            (1, Some(self.iter.len() + 1))
+        } else {
+            (0, Some(0))
+        }
+    }
+}
+
+impl<'a, T, K, F> FusedIterator for ChunkBy<'a, T, K, F>
+    where K: CountKind<T>,
+          F: FnMut(&T, &T) -> bool
+{
+}
+
+/// Iterator over pieces of a `CountTree` split off its ends, returned by
+/// `CountTree::chunks`.
+pub struct TreeChunks<T, K: CountKind<T> = BoxKind> {
+    rest: CountTree<T, K>,
+    size: usize,
+}
+
+impl<T, K: CountKind<T>> Iterator for TreeChunks<T, K> {
+    type Item = CountTree<T, K>;
+
+    fn next(&mut self) -> Option<CountTree<T, K>> {
+        if self.rest.is_empty() {
+            None
+        } else {
+            Some(self.rest.pop_front_n(self.size))
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = self.rest.len().div_ceil(self.size);
+        (n, Some(n))
+    }
+}
+
+impl<T, K: CountKind<T>> DoubleEndedIterator for TreeChunks<T, K> {
+    fn next_back(&mut self) -> Option<CountTree<T, K>> {
+        match self.rest.len() % self.size {
+            _ if self.rest.is_empty() => None,
+            0 => Some(self.rest.pop_back_n(self.size)),
+            last => Some(self.rest.pop_back_n(last)),
+        }
+    }
+}
+
+impl<T, K: CountKind<T>> ExactSizeIterator for TreeChunks<T, K> {}
+
+impl<T, K: CountKind<T>> FusedIterator for TreeChunks<T, K> {}
+
+/// An immutable snapshot of a `CountTree`, returned by `CountTree::freeze`.
+///
+/// Cloning a `FrozenCountTree` is O(1), and it is `Send` and `Sync` whenever
+/// `T` is (for `BoxKind` and `ArcCowKind` nodes), so the same snapshot can be
+/// published to any number of reader threads. Only reading is possible.
+pub struct FrozenCountTree<T, K: CountKind<T> = BoxKind> {
+    tree: Arc<CountTree<T, K>>,
+}
+
+impl<T, K: CountKind<T>> FrozenCountTree<T, K> {
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// Returns the element at the given index, or `None` if index is out of
+    /// bounds. Time complexity: O(log(n))
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.tree.get(index)
+    }
+
+    /// Returns an iterator over the elements.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        (&*self.tree).into_iter()
+    }
+
+    /// Returns a view of the elements in `range`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn range(&self, range: Range<usize>) -> CountTreeSlice<'_, T, K> {
+        self.tree.slice(range)
+    }
+}
+
+impl<T, K: CountKind<T>> Clone for FrozenCountTree<T, K> {
+    fn clone(&self) -> Self {
+        FrozenCountTree { tree: self.tree.clone() }
+    }
+}
+
+impl<T: Debug, K: CountKind<T>> Debug for FrozenCountTree<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
 
-impl<T> IntoIterator for CountTree<T> {
+impl<'a, T, K: CountKind<T>> IntoIterator for &'a FrozenCountTree<T, K> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// The edits of a transaction, see `CountTree::transaction`.
+///
+/// Reading goes through `Deref` to the tree being edited.
+pub struct Txn<'a, T: 'a, K: CountKind<T> + 'a = BoxKind> {
+    tree: &'a mut CountTree<T, K>,
+    // the tree before the transaction, restored on drop unless committed
+    snapshot: Option<CountTree<T, K>>,
+}
+
+impl<'a, T, K: CountKind<T>> Txn<'a, T, K> {
+    /// See `CountTree::get_mut`.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.tree.get_mut(index)
+    }
+
+    /// See `CountTree::insert`.
+    pub fn insert(&mut self, index: usize, value: T) {
+        self.tree.insert(index, value);
+    }
+
+    /// See `CountTree::remove`.
+    pub fn remove(&mut self, index: usize) -> T {
+        self.tree.remove(index)
+    }
+
+    /// See `CountTree::push_front`.
+    pub fn push_front(&mut self, value: T) {
+        self.tree.push_front(value);
+    }
+
+    /// See `CountTree::push_back`.
+    pub fn push_back(&mut self, value: T) {
+        self.tree.push_back(value);
+    }
+
+    /// See `CountTree::pop_front`.
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.tree.pop_front()
+    }
+
+    /// See `CountTree::pop_back`.
+    pub fn pop_back(&mut self) -> Option<T> {
+        self.tree.pop_back()
+    }
+
+    /// See `CountTree::clear`.
+    pub fn clear(&mut self) {
+        self.tree.clear();
+    }
+}
+
+impl<'a, T, K: CountKind<T>> Deref for Txn<'a, T, K> {
+    type Target = CountTree<T, K>;
+
+    fn deref(&self) -> &CountTree<T, K> {
+        self.tree
+    }
+}
+
+impl<'a, T, K: CountKind<T>> Drop for Txn<'a, T, K> {
+    fn drop(&mut self) {
+        if let Some(snapshot) = self.snapshot.take() {
+            *self.tree = snapshot;
+        }
+    }
+}
+
+/// Builds a balanced `CountTree` in one pass over elements of unknown number.
+///
+/// The elements are linked into perfect subtrees as they arrive, like the
+/// digits of a binary counter, along a spine of nodes waiting for their right
+/// subtrees. `finish` joins the spine in O(log(n)) time, so that building a
+/// tree of n elements takes O(n) time without any rotations.
+///
+/// ```
+/// use binary_tree::count::CountTreeBuilder;
+///
+/// let mut builder = CountTreeBuilder::<_>::new();
+/// builder.push(0);
+/// builder.push_run(1..10);
+/// let ct = builder.finish();
+/// assert_eq!(ct.to_vec(), (0..10).collect::<Vec<_>>());
+/// ```
+pub struct CountTreeBuilder<T, K: CountKind<T> = BoxKind> {
+    // nodes with perfect left subtrees, waiting for right subtrees of the same
+    // height; the heights are decreasing
+    spine: Vec<NodePtr<T, K>>,
+    // a perfect subtree waiting for its parent
+    pending: Option<NodePtr<T, K>>,
+    len: usize,
+}
+
+impl<T, K: CountKind<T>> CountTreeBuilder<T, K> {
+    pub fn new() -> CountTreeBuilder<T, K> {
+        CountTreeBuilder {
+            spine: Vec::new(),
+            pending: None,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of elements pushed so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends an element. Time complexity: amortized O(1)
+    pub fn push(&mut self, value: T) {
+        let mut node = new_node::<T, K>(value);
+        self.len += 1;
+        if let Some(left) = self.pending.take() {
+            node.insert_left(Some(left));
+            self.spine.push(node);
+            return;
+        }
+        // complete the parents waiting for a subtree of this height
+        while self.spine.last().is_some_and(|parent| parent.lcount() == node.count()) {
+            let mut parent = self.spine.pop().unwrap();
+            parent.insert_right(Some(node));
+            node = parent;
+        }
+        self.pending = Some(node);
+    }
+
+    /// Appends all the elements of `iter`.
+    pub fn push_run<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = T>
+    {
+        for value in iter {
+            self.push(value);
+        }
+    }
+
+    /// Returns the tree of all the elements pushed, in order. Time
+    /// complexity: O(log(n))
+    pub fn finish(self) -> CountTree<T, K> {
+        // all the subtrees are perfect, so their heights follow from the counts
+        let height = |tree: &Option<NodePtr<T, K>>| perfect_height(tree.as_ref().map_or(0, |node| node.count()));
+        let mut root = self.pending;
+        let mut rh = height(&root);
+        for mut node in self.spine.into_iter().rev() {
+            let left = node.detach_left();
+            let lh = height(&left);
+            let (tree, height) = join_h::<T, K>(left, lh, node, root, rh);
+            root = Some(tree);
+            rh = height;
+        }
+        CountTree::with_root(root)
+    }
+}
+
+impl<T, K: CountKind<T>> Default for CountTreeBuilder<T, K> {
+    fn default() -> CountTreeBuilder<T, K> {
+        CountTreeBuilder::new()
+    }
+}
+
+impl<T, K: CountKind<T>> Extend<T> for CountTreeBuilder<T, K> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = T>
+    {
+        self.push_run(iter);
+    }
+}
+
+impl<T, K: CountKind<T>> IntoIterator for CountTree<T, K> {
     type Item = T;
-    type IntoIter = IntoIter<T>;
+    type IntoIter = IntoIter<T, K>;
 
     fn into_iter(mut self) -> Self::IntoIter {
         let len = self.len();
         let mut inner = None;
-        mem::swap(&mut self.0, &mut inner);
+        mem::swap(&mut self.root, &mut inner);
         IntoIter {
+            front: mem::take(&mut self.front).into_iter().rev(),
             inner: GenIntoIter::new(inner),
+            back: mem::take(&mut self.back).into_iter(),
             remaining: len,
         }
     }
 }
 
-pub struct IntoIter<T> {
-    inner: GenIntoIter<CountNode<T>>,
+pub struct IntoIter<T, K: CountKind<T> = BoxKind> {
+    front: Rev<vec::IntoIter<T>>,
+    inner: GenIntoIter<CountNode<T, K>>,
+    back: vec::IntoIter<T>,
     remaining: usize,
 }
 
-impl<T> Iterator for IntoIter<T> {
+impl<T, K: CountKind<T>> Iterator for IntoIter<T, K> {
     type Item = T;
 
     fn next(&mut self) -> Option<T> {
         if self.remaining > 0 {
             self.remaining -= 1;
         }
-        self.inner.next()
+        self.front.next().or_else(|| self.inner.next()).or_else(|| self.back.next())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
     }
+}
+
+impl<T, K: CountKind<T>> DoubleEndedIterator for IntoIter<T, K> {
+    fn next_back(&mut self) -> Option<T> {
+        if self.remaining > 0 {
+            self.remaining -= 1;
+        }
+        self.back.next_back().or_else(|| self.inner.next_back()).or_else(|| self.front.next_back())
+    }
+}
+
+impl<T, K: CountKind<T>> ExactSizeIterator for IntoIter<T, K> {}
+
+// The state of `retain_mut`, which puts the retained elements and the ones not
+// visited yet back into the tree when dropped, even if `f` panicked.
+struct RetainGuard<'a, T: 'a, K: CountKind<T> + 'a> {
+    tree: &'a mut CountTree<T, K>,
+    kept: Vec<T>,
+    rest: IntoIter<T, K>,
+}
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.remaining, Some(self.remaining))
+impl<'a, T, K: CountKind<T>> Drop for RetainGuard<'a, T, K> {
+    fn drop(&mut self) {
+        let len = self.kept.len() + self.rest.len();
+        let mut items = mem::take(&mut self.kept).into_iter().chain(&mut self.rest);
+        self.tree.root = build_balanced::<T, K, _>(len, &mut items);
     }
 }
 
-impl<T> ExactSizeIterator for IntoIter<T> {}
-
 /// Node of a `CountTree`.
 ///
 /// The only way of getting your hands on a `CountNode` is through
 /// [`CountTree::root()`](struct.CountTree.html#method.root) method which
 /// returns a shared reference to its root.  Thus `NodeMut` methods are not
 /// accessible to users.
-pub struct CountNode<T> {
+pub struct CountNode<T, K: CountKind<T> = BoxKind> {
     val: T,
-    left: Option<NodePtr<T>>,
-    right: Option<NodePtr<T>>,
+    left: Option<NodePtr<T, K>>,
+    right: Option<NodePtr<T, K>>,
+    #[cfg(not(feature="compact-nodes"))]
     count: u32,
+    #[cfg(not(feature="compact-nodes"))]
     height: u16,
+    // the count in the lower `COUNT_BITS` bits, and which subtree is taller
+    // (`LEFT_TALLER` or `RIGHT_TALLER`, or neither) in the upper ones
+    #[cfg(feature="compact-nodes")]
+    stats: u32,
+}
+
+#[cfg(feature="compact-nodes")]
+const COUNT_BITS: u32 = 30;
+#[cfg(feature="compact-nodes")]
+const LEFT_TALLER: u32 = 1;
+#[cfg(feature="compact-nodes")]
+const RIGHT_TALLER: u32 = 2;
+
+// Returns which of two subtrees with the given heights is taller, as stored in
+// `CountNode::stats`.
+#[cfg(feature="compact-nodes")]
+fn taller(lh: i32, rh: i32) -> u32 {
+    use std::cmp::Ordering::*;
+
+    match lh.cmp(&rh) {
+        Greater => LEFT_TALLER,
+        Less => RIGHT_TALLER,
+        Equal => 0,
+    }
 }
 
-impl<T> CountNode<T> {
-    fn new(val: T) -> CountNode<T> {
+impl<T, K: CountKind<T>> CountNode<T, K> {
+    fn new(val: T) -> CountNode<T, K> {
         CountNode {
             val: val,
             left: None,
             right: None,
+            #[cfg(not(feature="compact-nodes"))]
             count: 1,
+            #[cfg(not(feature="compact-nodes"))]
             height: 0,
+            #[cfg(feature="compact-nodes")]
+            stats: 1,
+        }
+    }
+
+    #[cfg(not(feature="compact-nodes"))]
+    fn count(&self) -> u32 {
+        self.count
+    }
+
+    #[cfg(feature="compact-nodes")]
+    fn count(&self) -> u32 {
+        self.stats & ((1 << COUNT_BITS) - 1)
+    }
+
+    #[cfg(not(feature="compact-nodes"))]
+    fn height(&self) -> u16 {
+        self.height
+    }
+
+    // Follows the taller subtrees down to a leaf. Time complexity: O(height)
+    #[cfg(feature="compact-nodes")]
+    fn height(&self) -> u16 {
+        let mut height = 0;
+        let mut node = self;
+        loop {
+            let next = if node.stats >> COUNT_BITS == RIGHT_TALLER {
+                node.right()
+            } else {
+                node.left()
+            };
+            match next {
+                Some(child) => node = child,
+                None => return height,
+            }
+            height += 1;
         }
     }
 
     fn lcount(&self) -> u32 {
-        self.left.as_ref().map_or(0, |tree| tree.count)
+        self.left.as_ref().map_or(0, |tree| tree.count())
     }
 
     fn rcount(&self) -> u32 {
-        self.right.as_ref().map_or(0, |tree| tree.count)
+        self.right.as_ref().map_or(0, |tree| tree.count())
     }
 
     // generalized version of AVL tree balance factor: h(left) - h(right)
+    #[cfg(any(not(feature="compact-nodes"), test))]
     fn balance_factor(&self) -> i32 {
-        self.left.as_ref().map_or(-1, |node| node.height as i32) -
-            self.right.as_ref().map_or(-1, |node| node.height as i32)
+        height_of(self.left()) - height_of(self.right())
+    }
+
+    // the balance factor as stored in `stats`. Time complexity: O(1)
+    #[cfg(feature="compact-nodes")]
+    fn balance(&self) -> i32 {
+        match self.stats >> COUNT_BITS {
+            LEFT_TALLER => 1,
+            RIGHT_TALLER => -1,
+            _ => 0,
+        }
+    }
+
+    // Returns the heights of the subtrees, given that of this node.
+    #[cfg(not(feature="compact-nodes"))]
+    fn child_heights(&self, _height: i32) -> (i32, i32) {
+        (height_of(self.left()), height_of(self.right()))
     }
 
-    // AVL tree algorithm
+    #[cfg(feature="compact-nodes")]
+    fn child_heights(&self, height: i32) -> (i32, i32) {
+        match self.balance() {
+            1 => (height - 1, height - 2),
+            -1 => (height - 2, height - 1),
+            _ => (height - 1, height - 1),
+        }
+    }
+
+    // Detaches both subtrees. Time complexity: O(1)
+    #[cfg(not(feature="compact-nodes"))]
+    fn take_children(&mut self) -> Halves<T, K> {
+        self.detach_children()
+    }
+
+    #[cfg(feature="compact-nodes")]
+    fn take_children(&mut self) -> Halves<T, K> {
+        self.stats = 1;
+        (self.left.take(), self.right.take())
+    }
+
+    // AVL tree algorithm (the edits of compact nodes use `rebalance_to`)
+    #[cfg(any(not(feature="compact-nodes"), test))]
     fn rebalance(&mut self) {
         if self.balance_factor() > 1 {
             self.left.as_mut().map(|node| {
                 if node.balance_factor() < 0 {
                     node.rotate_left().unwrap();
+                    on_rotation();
                 }
             });
             self.rotate_right().unwrap();
+            on_rotation();
         } else if self.balance_factor() < -1 {
             self.right.as_mut().map(|node| {
                 if node.balance_factor() > 0 {
                     node.rotate_right().unwrap();
+                    on_rotation();
                 }
             });
             self.rotate_left().unwrap();
+            on_rotation();
         }
     }
 
+    #[cfg(not(feature="compact-nodes"))]
     fn update_stats(&mut self) {
         use std::cmp::max;
         self.count = self.lcount() + self.rcount() + 1;
@@ -621,13 +2590,132 @@ impl<T> CountNode<T> {
         }
     }
 
+    // Measures the heights of the subtrees, which takes O(log(n)) time. This is
+    // only done by the generic `NodeMut` edits, as when building or reversing
+    // a tree (O(n) time in total); the edits of `CountTree` keep the balance
+    // up to date as AVL trees do, see `retrace`.
+    #[cfg(feature="compact-nodes")]
+    fn update_stats(&mut self) {
+        let count = self.lcount() + self.rcount() + 1;
+        debug_assert!(count < 1 << COUNT_BITS, "too many elements");
+        self.stats = count | taller(height_of(self.left()), height_of(self.right())) << COUNT_BITS;
+    }
+
+    // Updates the count, keeping the balance factor as it is.
+    #[cfg(feature="compact-nodes")]
+    fn update_count(&mut self) {
+        let count = self.lcount() + self.rcount() + 1;
+        debug_assert!(count < 1 << COUNT_BITS, "too many elements");
+        self.stats = (self.stats >> COUNT_BITS) << COUNT_BITS | count;
+    }
+
+    #[cfg(feature="compact-nodes")]
+    fn set_balance(&mut self, balance: i32) {
+        debug_assert!(balance.abs() <= 1, "balance = {}", balance);
+        self.stats = self.count() | taller(balance, 0) << COUNT_BITS;
+    }
+
+    // Same as `rotate_right`, but only the counts are updated; the callers
+    // set the balance factors.
+    #[cfg(feature="compact-nodes")]
+    fn turn_right(&mut self) {
+        let mut pivot = self.left.take().unwrap();
+        self.left = pivot.right.take();
+        self.update_count();
+        mem::swap(self, &mut *pivot);
+        self.right = Some(pivot);
+        self.update_count();
+        on_rotation();
+    }
+
+    // Same as `rotate_left`, see `turn_right`.
+    #[cfg(feature="compact-nodes")]
+    fn turn_left(&mut self) {
+        let mut pivot = self.right.take().unwrap();
+        self.right = pivot.left.take();
+        self.update_count();
+        mem::swap(self, &mut *pivot);
+        self.left = Some(pivot);
+        self.update_count();
+        on_rotation();
+    }
+
+    // Updates the count and the balance factor after the left (or right)
+    // subtree grew by `grown` levels (-1, 0 or 1), and rebalances. Returns by
+    // how much this subtree grew. Time complexity: O(1)
+    #[cfg(feature="compact-nodes")]
+    fn retrace(&mut self, left: bool, grown: i32) -> i32 {
+        self.update_count();
+        // the heights relative to the old one of the changed subtree
+        let balance = self.balance();
+        let (lh, rh) = if left { (0, -balance) } else { (balance, 0) };
+        let (new_lh, new_rh) = if left { (grown, rh) } else { (lh, grown) };
+        cmp::max(new_lh, new_rh) - self.rebalance_to(new_lh - new_rh) - cmp::max(lh, rh)
+    }
+
+    // Sets the balance factor, which may be off the AVL bounds by one, and
+    // rotates as needed. Returns by how much the rotations lowered the
+    // subtree.
+    #[cfg(feature="compact-nodes")]
+    fn rebalance_to(&mut self, balance: i32) -> i32 {
+        // the balance factors after the rotations follow from those before
+        let inner = if balance > 1 {
+            let lb = self.left().unwrap().balance();
+            if lb >= 0 {
+                self.turn_right();
+                self.right.as_mut().unwrap().set_balance(1 - lb);
+                self.set_balance(lb - 1);
+                return lb;
+            }
+            let inner = self.left().unwrap().right().unwrap().balance();
+            self.left.as_mut().unwrap().turn_left();
+            self.turn_right();
+            inner
+        } else if balance < -1 {
+            let rb = self.right().unwrap().balance();
+            if rb <= 0 {
+                self.turn_left();
+                self.left.as_mut().unwrap().set_balance(-1 - rb);
+                self.set_balance(rb + 1);
+                return -rb;
+            }
+            let inner = self.right().unwrap().left().unwrap().balance();
+            self.right.as_mut().unwrap().turn_right();
+            self.turn_left();
+            inner
+        } else {
+            self.set_balance(balance);
+            return 0;
+        };
+        // a double rotation moved the inner grandchild up here
+        self.left.as_mut().unwrap().set_balance(cmp::max(-inner, 0));
+        self.right.as_mut().unwrap().set_balance(cmp::min(-inner, 0));
+        self.set_balance(0);
+        1
+    }
+
+    #[cfg(not(feature="compact-nodes"))]
+    fn heights_valid(&self) -> bool {
+        let child_height = match (self.left(), self.right()) {
+            (None, None) => None,
+            (left, right) => Some(left.map_or(0, |n| n.height).max(right.map_or(0, |n| n.height))),
+        };
+        self.height == child_height.map_or(0, |h| h + 1)
+    }
+
+    #[cfg(feature="compact-nodes")]
+    fn heights_valid(&self) -> bool {
+        let actual = |node: Option<&Self>| node.map_or(-1, |n| algos::height(n) as i32);
+        self.stats >> COUNT_BITS == taller(actual(self.left()), actual(self.right()))
+    }
+
     fn into_value(self) -> T {
-        debug_assert!(self.count == 1, "count = {}", self.count);
+        debug_assert!(self.count() == 1, "count = {}", self.count());
         self.val
     }
 }
 
-impl<T> Node for CountNode<T> {
+impl<T, K: CountKind<T>> Node for CountNode<T, K> {
     type Value = T;
 
     fn left(&self) -> Option<&Self> {
@@ -643,8 +2731,8 @@ impl<T> Node for CountNode<T> {
     }
 }
 
-impl<T> NodeMut for CountNode<T> {
-    type NodePtr = NodePtr<T>;
+impl<T, K: CountKind<T>> NodeMut for CountNode<T, K> {
+    type NodePtr = NodePtr<T, K>;
 
     fn detach_left(&mut self) -> Option<Self::NodePtr> {
         let tree = self.left.take();
@@ -698,22 +2786,67 @@ impl<'a, 'b, T> Debug for DebugPrefix<'a, 'b, T>
     }
 }
 
-impl<T> Debug for CountNode<T>
+impl<T, K: CountKind<T>> Debug for CountNode<T, K>
     where T: Debug
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         let mut dt = f.debug_tuple("");
         dt.field(&self.val);
         if let Some(ref left) = self.left {
-            dt.field(&DebugPrefix("L", left));
+            dt.field(&DebugPrefix("L", &**left));
         }
         if let Some(ref right) = self.right {
-            dt.field(&DebugPrefix("R", right));
+            dt.field(&DebugPrefix("R", &**right));
         }
         dt.finish()
     }
 }
 
+/// Verifies the `count` cached in each node of the tree rooted at `root`,
+/// bottom-up. Returns the path from `root` to the first (deepest) node whose
+/// count does not match those of its children. Time complexity: O(n)
+pub fn check_counts<T, K: CountKind<T>>(root: &CountNode<T, K>) -> Result<(), Vec<WalkAction>> {
+    check_nodes(root, |node| node.count() == node.lcount() + node.rcount() + 1)
+}
+
+/// Verifies the `height` cached in each node of the tree rooted at `root`,
+/// bottom-up. Returns the path from `root` to the first (deepest) node whose
+/// height does not match those of its children. Time complexity: O(n)
+///
+/// With the `compact-nodes` feature, which of the subtrees of each node is
+/// taller is checked instead. Time complexity: O(n log(n))
+pub fn check_heights<T, K: CountKind<T>>(root: &CountNode<T, K>) -> Result<(), Vec<WalkAction>> {
+    check_nodes(root, CountNode::heights_valid)
+}
+
+// checks `valid` for each node in post-order, keeping track of the path to it
+fn check_nodes<T, K, F>(root: &CountNode<T, K>, mut valid: F) -> Result<(), Vec<WalkAction>>
+    where K: CountKind<T>,
+          F: FnMut(&CountNode<T, K>) -> bool
+{
+    use WalkAction::*;
+
+    let mut path = Vec::new();
+    // the flag is set if the children of the node are already on the stack
+    let mut stack = vec![(root, Stop, false)];
+    while let Some((node, action, expanded)) = stack.pop() {
+        if expanded {
+            if !valid(node) {
+                return Err(path);
+            }
+            path.pop();
+        } else {
+            if action != Stop {
+                path.push(action);
+            }
+            stack.push((node, action, true));
+            stack.extend(node.right().map(|n| (n, Right, false)));
+            stack.extend(node.left().map(|n| (n, Left, false)));
+        }
+    }
+    Ok(())
+}
+
 #[cfg(feature="quickcheck")]
 impl Arbitrary for CountTree<usize> {
     fn arbitrary<G: Gen>(g: &mut G) -> CountTree<usize> {
@@ -730,26 +2863,36 @@ impl Arbitrary for CountTree<usize> {
     }
 }
 
-#[cfg(feature="quickcheck")]
-impl<T> Clone for CountTree<T>
+impl<T, K: CountKind<T>> Clone for CountTree<T, K>
     where T: Clone
 {
+    /// Time complexity: O(n), or O(1) if the nodes are shared (as in
+    /// `RcCountTree`).
     fn clone(&self) -> Self {
-        CountTree(self.0.clone())
+        CountTree {
+            root: self.root.as_ref().map(K::clone_ptr),
+            front: self.front.clone(),
+            back: self.back.clone(),
+            #[cfg(feature="instrument")]
+            metrics: Metrics::default(),
+        }
     }
 }
 
-#[cfg(feature="quickcheck")]
-impl<T> Clone for CountNode<T>
+impl<T, K: CountKind<T>> Clone for CountNode<T, K>
     where T: Clone
 {
     fn clone(&self) -> Self {
         CountNode {
             val: self.val.clone(),
-            left: self.left.clone(),
-            right: self.right.clone(),
+            left: self.left.as_ref().map(K::clone_ptr),
+            right: self.right.as_ref().map(K::clone_ptr),
+            #[cfg(not(feature="compact-nodes"))]
             count: self.count,
+            #[cfg(not(feature="compact-nodes"))]
             height: self.height,
+            #[cfg(feature="compact-nodes")]
+            stats: self.stats,
         }
     }
 }
@@ -757,7 +2900,6 @@ impl<T> Clone for CountNode<T>
 #[cfg(feature="quickcheck")]
 pub mod quickcheck {
     use super::CountTree;
-    use BinaryTree;
 
     #[derive(Clone, Copy)]
     enum ShrinkerState {
@@ -774,8 +2916,10 @@ pub mod quickcheck {
 
     impl Shrinker {
         pub fn new(inner: &CountTree<usize>) -> Shrinker {
+            let mut inner = inner.clone();
+            inner.flush();
             Shrinker {
-                inner: inner.clone(),
+                inner: inner,
                 state: ShrinkerState::Value,
             }
         }
@@ -785,7 +2929,7 @@ pub mod quickcheck {
         type Item = CountTree<usize>;
 
         fn next(&mut self) -> Option<Self::Item> {
-            if self.inner.0.is_none() {
+            if self.inner.root.is_none() {
                 None
             } else {
                 use self::ShrinkerState::*;
@@ -793,7 +2937,7 @@ pub mod quickcheck {
                 match self.state {
                     Value => {
                         let mut ct = CountTree::new();
-                        if root.count > 1 {
+                        if root.count() > 1 {
                             ct.push_back(root.val);
                             self.state = Left;
                         } else {
@@ -803,11 +2947,11 @@ pub mod quickcheck {
                     }
                     Left => {
                         self.state = Right;
-                        Some(CountTree(root.left.clone()))
+                        Some(CountTree::with_root(root.left.clone()))
                     }
                     Right => {
                         self.state = End;
-                        Some(CountTree(root.right.clone()))
+                        Some(CountTree::with_root(root.right.clone()))
                     }
                     End => {
                         None
@@ -820,10 +2964,12 @@ pub mod quickcheck {
 
 #[cfg(test)]
 mod tests {
-    use BinaryTree;
     use NodeMut;
-    use super::CountNode;
+    use super::{BoxKind, CountNode};
     use super::CountTree;
+    use super::{check_counts, check_heights};
+    #[cfg(feature="compact-nodes")]
+    use super::{COUNT_BITS, LEFT_TALLER, RIGHT_TALLER};
     use test::compute_level;
     use test::Level;
 
@@ -837,7 +2983,7 @@ mod tests {
 
     #[test]
     fn custom() {
-        let ct = CountTree(Some(test_nodes()));
+        let ct: CountTree<_> = CountTree::with_root(Some(test_nodes()));
         assert_eq!(ct.get(0), Some(&8));
         assert_eq!(ct.get(1), Some(&12));
         assert_eq!(ct.get(2), Some(&7));
@@ -848,13 +2994,40 @@ mod tests {
         assert_eq!(ct.get(3), Some(&100));
     }
 
+    #[test]
+    fn descend() {
+        use WalkAction::*;
+        use super::RcCountTree;
+
+        let mut ct: CountTree<_> = CountTree::with_root(Some(test_nodes()));
+        assert_eq!(ct.path_to_index(1), [Left, Right]);
+        assert_eq!(ct.descend(&[Left, Right]), Some(&12));
+        assert_eq!(ct.descend(&[]), Some(&7));
+        assert_eq!(ct.descend(&[Right, Stop, Left]), Some(&5));
+        assert_eq!(ct.descend(&[Right, Left]), None);
+        *ct.descend_mut(&[Left]).unwrap() = 9;
+        assert_eq!(ct.get(0), Some(&9));
+        assert_eq!(ct.descend_mut(&[Left, Left]), None);
+        assert_eq!(CountTree::<u32>::new().descend(&[]), None);
+
+        let mut ct: RcCountTree<_> = (0..100).collect();
+        ct.push_front(-1);
+        ct.push_back(100);
+        let paths: Vec<_> = (0..102).map(|i| ct.path_to_index(i)).collect();
+        let snapshot = ct.clone();
+        ct.clear();
+        for (i, path) in paths.iter().enumerate() {
+            assert_eq!(snapshot.descend(path), Some(&(i as i32 - 1)));
+        }
+    }
+
     #[test]
     fn counting() {
         let cn = test_nodes();
         assert_eq!(cn.lcount(), 2);
         assert_eq!(cn.rcount(), 1);
-        assert_eq!(cn.count, 4);
-        assert_eq!(cn.height, 2);
+        assert_eq!(cn.count(), 4);
+        assert_eq!(cn.height(), 2);
     }
 
     #[test]
@@ -865,7 +3038,7 @@ mod tests {
         cn.rebalance();
         assert_eq!(cn.balance_factor(), 0);
         assert_eq!(compute_level(&*cn, 1), Level::Balanced(2));
-        let ct = CountTree(Some(cn));
+        let ct: CountTree<_> = CountTree::with_root(Some(cn));
         assert_eq!(ct.get(0), Some(&8));
         assert_eq!(ct.get(1), Some(&12));
         assert_eq!(ct.get(2), Some(&7));
@@ -887,25 +3060,30 @@ mod tests {
         ct.insert(0, 7);
         assert_eq!(ct.get(4), Some(&3));
         assert_eq!(ct.get(5), Some(&2));
-        assert_eq!(ct.root().unwrap().height, 2);
+        assert_eq!(ct.root().unwrap().height(), 2);
         assert_eq!(compute_level(ct.root().unwrap(), 1), Level::Balanced(3));
         ct.insert(6, 1);
         assert_eq!(ct.get(6), Some(&1));
-        assert_eq!(ct.root().unwrap().height, 3);
+        assert_eq!(ct.root().unwrap().height(), 3);
         assert_eq!(compute_level(ct.root().unwrap(), 1), Level::Balanced(4));
     }
 
     #[test]
     fn from_iter() {
-        let ct: CountTree<_> = (0..63).collect();
+        let mut ct: CountTree<_> = (0..63).collect();
         let root = ct.root().unwrap();
-        assert_eq!(root.height, 5);
+        assert_eq!(root.height(), 5);
         assert_eq!(compute_level(root, 0), Level::Balanced(6));
 
-        let ct: CountTree<_> = (0..94).collect();
+        let mut ct: CountTree<_> = (0..94).collect();
         let root = ct.root().unwrap();
+        // compact nodes are collected with `CountTreeBuilder`, which leans
+        // the other way
+        #[cfg(not(feature="compact-nodes"))]
         assert_eq!(root.balance_factor(), -1);
-        assert_eq!(root.height, 6);
+        #[cfg(feature="compact-nodes")]
+        assert_eq!(root.balance_factor(), 1);
+        assert_eq!(root.height(), 6);
         assert_eq!(compute_level(root, 1), Level::Balanced(7));
     }
 
@@ -917,4 +3095,835 @@ mod tests {
             assert!(compute_level(ct.root().unwrap(), 1).is_balanced());
         }
     }
+
+    #[test]
+    fn push() {
+        let mut ct: CountTree<_> = (10..20).collect();
+        for i in 0..10 {
+            ct.push_front(9 - i);
+            ct.push_back(20 + i);
+        }
+        assert_eq!(ct.len(), 30);
+        for i in 0..30 {
+            assert_eq!(ct.get(i), Some(&i));
+        }
+        assert_eq!(ct.get(30), None);
+        assert_eq!((&ct).into_iter().cloned().collect::<Vec<_>>(), (0..30).collect::<Vec<_>>());
+        ct.flush();
+        assert_eq!(ct.root().unwrap().count(), 30);
+        assert!(compute_level(ct.root().unwrap(), 1).is_balanced());
+        assert_eq!(ct.into_iter().collect::<Vec<_>>(), (0..30).collect::<Vec<_>>());
+
+        let mut ct = CountTree::new();
+        for i in 0..1000 {
+            ct.push_back(i);
+        }
+        assert_eq!(ct.pop_front(), Some(0));
+        assert_eq!(ct.pop_back(), Some(999));
+        assert!(compute_level(ct.root().unwrap(), 1).is_balanced());
+        assert_eq!(ct.remove(500), 501);
+        assert_eq!(ct.len(), 997);
+    }
+
+    #[test]
+    fn into_iter_rev() {
+        let make = || {
+            let mut ct: CountTree<_> = (10..20).collect();
+            for i in 0..10 {
+                ct.push_front(9 - i);
+                ct.push_back(20 + i);
+            }
+            ct
+        };
+        let mut iter = make().into_iter();
+        assert_eq!(iter.next_back(), Some(29));
+        assert_eq!(iter.next(), Some(0));
+        assert_eq!(iter.len(), 28);
+        assert_eq!(iter.rev().collect::<Vec<_>>(), (1..29).rev().collect::<Vec<_>>());
+        assert_eq!(make().into_iter().rev().collect::<Vec<_>>(), (0..30).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn reverse() {
+        let mut ct: CountTree<_> = (0..40).collect();
+        ct.push_front(-1);
+        ct.push_back(40);
+        ct.reverse();
+        assert_eq!(ct.len(), 42);
+        for i in 0..42 {
+            assert_eq!(ct.get(i), Some(&(40 - i as i32)));
+        }
+        ct.flush();
+        assert!(compute_level(ct.root().unwrap(), 1).is_balanced());
+        ct.insert(21, 100);
+        assert_eq!(ct.get(22), Some(&19));
+    }
+
+    #[test]
+    fn cursor() {
+        let mut ct: CountTree<_> = (0..10).collect();
+        ct.push_front(-1);
+        ct.push_back(10);
+        let mut cursor = ct.cursor();
+        assert_eq!(cursor.by_ref().take(3).last(), Some(&1));
+        assert_eq!(cursor.prev(), Some(&0));
+        cursor.seek_to_end();
+        assert_eq!((cursor.peek(), cursor.prev()), (Some(&10), Some(&9)));
+    }
+
+    #[test]
+    fn pairs_and_windows() {
+        let mut ct: CountTree<_> = (1..5).collect();
+        ct.push_front(0);
+        let pairs: Vec<_> = ct.pairs().map(|(a, b)| (*a, *b)).collect();
+        assert_eq!(pairs, [(0, 1), (1, 2), (2, 3), (3, 4)]);
+        assert_eq!(ct.pairs().len(), 4);
+        let windows: Vec<Vec<_>> = ct.windows(3).collect();
+        assert_eq!(windows, [[&0, &1, &2], [&1, &2, &3], [&2, &3, &4]]);
+        assert_eq!(ct.windows(3).len(), 3);
+        assert_eq!(ct.windows(1).count(), 5);
+        assert_eq!((ct.windows(5).count(), ct.windows(6).count(), ct.windows(6).len()), (1, 0, 0));
+
+        let single: CountTree<_> = Some(0).into_iter().collect();
+        assert_eq!((single.pairs().count(), single.pairs().len()), (0, 0));
+        assert_eq!(CountTree::<u8>::new().pairs().len(), 0);
+    }
+
+    #[test]
+    fn chunk_by() {
+        let mut ct: CountTree<_> = vec![1, 1, 2, 3, 3, 3, 5, 4].into_iter().collect();
+        ct.push_front(1);
+        ct.push_back(4);
+        let runs: Vec<Vec<_>> = ct.chunk_by(|a, b| a == b)
+            .map(|run| run.iter().cloned().collect())
+            .collect();
+        assert_eq!(runs, [vec![1, 1, 1], vec![2], vec![3, 3, 3], vec![5], vec![4, 4]]);
+        let ascending: Vec<_> = ct.chunk_by(|a, b| a <= b).map(|run| run.len()).collect();
+        assert_eq!(ascending, [8, 2]);
+        assert_eq!(ct.chunk_by(|_, _| false).count(), 10);
+        assert_eq!(CountTree::<u8>::new().chunk_by(|_, _| true).count(), 0);
+    }
+
+    #[test]
+    fn iter_chunks() {
+        let mut ct: CountTree<_> = (0..10).collect();
+        ct.push_front(-1);
+        ct.push_back(10);
+        let chunks: Vec<_> = ct.iter_chunks(5).collect();
+        assert_eq!(chunks, [vec![-1, 0, 1, 2, 3], vec![4, 5, 6, 7, 8], vec![9, 10]]);
+        assert_eq!(ct.iter_chunks(4).len(), 3);
+    }
+
+    #[test]
+    fn iter_indexed() {
+        let mut ct: CountTree<_> = (0..20).collect();
+        for i in 1..4 {
+            ct.push_front(-i);
+            ct.push_back(19 + i);
+        }
+        let expected: Vec<_> = (-3..23).enumerate().collect();
+        let pairs: Vec<_> = ct.iter_indexed().map(|(i, &v)| (i, v)).collect();
+        assert_eq!(pairs, expected);
+        for start in 0..27 {
+            let pairs: Vec<_> = ct.iter_indexed_from(start).map(|(i, &v)| (i, v)).collect();
+            assert_eq!(pairs, &expected[start.min(26)..]);
+        }
+    }
+
+    #[test]
+    fn check_stats() {
+        use WalkAction::*;
+
+        let mut cn = test_nodes();
+        assert_eq!((check_counts(&*cn), check_heights(&*cn)), (Ok(()), Ok(())));
+        let mut ct: CountTree<_> = (0..100).collect();
+        assert_eq!((check_counts(ct.root().unwrap()), check_heights(ct.root().unwrap())), (Ok(()), Ok(())));
+
+        #[cfg(not(feature="compact-nodes"))]
+        {
+            cn.left.as_mut().unwrap().count = 3;
+        }
+        #[cfg(feature="compact-nodes")]
+        {
+            cn.left.as_mut().unwrap().stats = 3 | RIGHT_TALLER << COUNT_BITS;
+        }
+        assert_eq!(check_counts(&*cn), Err(vec![Left]));
+        assert_eq!(check_heights(&*cn), Ok(()));
+        #[cfg(not(feature="compact-nodes"))]
+        {
+            cn.left.as_mut().unwrap().right.as_mut().unwrap().height = 1;
+        }
+        #[cfg(feature="compact-nodes")]
+        {
+            cn.left.as_mut().unwrap().right.as_mut().unwrap().stats |= LEFT_TALLER << COUNT_BITS;
+        }
+        assert_eq!(check_heights(&*cn), Err(vec![Left, Right]));
+    }
+
+    #[test]
+    #[cfg(feature="compact-nodes")]
+    fn compact_nodes() {
+        use std::mem;
+        use algos;
+
+        assert_eq!(mem::size_of::<CountNode<u32>>(), 24);
+        let mut ct: CountTree<_> = (0..1000).collect();
+        for i in 0..500 {
+            ct.remove(i);
+            ct.insert(2 * i, i);
+        }
+        assert_eq!(ct.root().unwrap().height() as usize, algos::height(ct.root().unwrap()));
+        assert_eq!((check_counts(ct.root().unwrap()), check_heights(ct.root().unwrap())), (Ok(()), Ok(())));
+
+        let (mut left, right) = ct.split_at(333);
+        for i in 0..100 {
+            left.push_front(i);
+        }
+        left.insert_tree(50, right);
+        left.flush();
+        let root = left.root().unwrap();
+        assert_eq!((check_counts(root), check_heights(root)), (Ok(()), Ok(())));
+        assert!(compute_level(root, 1).is_balanced());
+    }
+
+    #[test]
+    fn conversions() {
+        use std::collections::{LinkedList, VecDeque};
+
+        let deque: VecDeque<_> = (0..50).collect();
+        let mut ct: CountTree<_> = CountTree::from(deque.clone());
+        assert_eq!(ct.len(), 50);
+        ct.push_front(-1);
+        let back: VecDeque<_> = ct.into();
+        assert_eq!(back, (-1..50).collect::<VecDeque<_>>());
+        let list: LinkedList<_> = (0..5).collect();
+        let ct: CountTree<_> = list.into();
+        assert_eq!((&ct).into_iter().cloned().collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn display() {
+        let mut ct: CountTree<_> = (1..4).map(|i| i as f64 / 2.0).collect();
+        ct.push_front(0.0);
+        assert_eq!(ct.to_string(), "[0, 0.5, 1, 1.5]");
+        assert_eq!(format!("{:.2}", ct), "[0.00, 0.50, 1.00, 1.50]");
+        assert_eq!(CountTree::<u8>::new().to_string(), "[]");
+    }
+
+    #[test]
+    fn to_vec() {
+        let mut ct: CountTree<_> = (0..20).collect();
+        ct.push_front(-1);
+        ct.push_back(20);
+        assert_eq!(ct.to_vec(), (-1..21).collect::<Vec<_>>());
+        let mut buf = [0; 5];
+        ct.copy_range_to_slice(9..14, &mut buf);
+        assert_eq!(buf, [8, 9, 10, 11, 12]);
+        ct.copy_range_to_slice(17..22, &mut buf);
+        assert_eq!(buf, [16, 17, 18, 19, 20]);
+        ct.copy_range_to_slice(3..3, &mut []);
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_range_out_of_bounds() {
+        let ct: CountTree<_> = (0..5).collect();
+        ct.copy_range_to_slice(3..6, &mut [0; 3]);
+    }
+
+    #[test]
+    #[cfg(feature="instrument")]
+    fn metrics() {
+        use super::{metrics, RcCountTree};
+
+        // other tests may run concurrently, so only lower bounds are exact
+        let before = metrics();
+        let mut ct: CountTree<_> = (0..3).collect();
+        for i in 0..4 {
+            ct.insert(0, i);
+        }
+        let _ = ct.clone();
+        let mut rc: RcCountTree<_> = (0..7).collect();
+        let rc2 = rc.clone();
+        ::cow::allow_clones(|| rc.insert(0, 7));
+        let after = metrics();
+        // 7 nodes for each of `ct`, its deep clone and `rc`, and 1 more for
+        // the inserted element
+        assert!(after.allocations >= before.allocations + 22);
+        assert!(after.rotations >= before.rotations + 2);
+        assert!(after.cow_clones >= before.cow_clones + 3);
+        assert_eq!(rc2.len(), 7);
+    }
+
+    #[test]
+    #[cfg(feature="instrument")]
+    fn tree_metrics() {
+        use super::{Metrics, RcCountTree};
+
+        let mut appended = CountTree::new();
+        let mut inserted = CountTree::new();
+        for i in 0..1000 {
+            appended.push_back(i);
+            inserted.insert(i * 7 % (i + 1), i);
+        }
+        appended.flush();
+        assert_eq!(appended.metrics().allocations, 1000);
+        assert_eq!(inserted.metrics().allocations, 1000);
+        assert!(appended.metrics().rotations < inserted.metrics().rotations);
+        assert_eq!(appended.metrics().cow_clones, 0);
+
+        let old = inserted.reset_metrics();
+        assert_eq!(inserted.metrics(), Metrics::default());
+        assert!(old.rotations > 0);
+        // splitting reuses the nodes
+        inserted.pop_front_n(500);
+        assert_eq!(inserted.metrics().allocations, 0);
+
+        let mut rc: RcCountTree<_> = (0..100).collect();
+        let rc2 = rc.clone();
+        ::cow::allow_clones(|| rc.remove(50));
+        assert!(rc.metrics().cow_clones >= 5);
+        assert_eq!(rc2.metrics(), Metrics::default());
+    }
+
+    #[test]
+    fn slices() {
+        let mut ct: CountTree<_> = (1..20).collect();
+        ct.push_front(0);
+        ct.push_back(20);
+        let all = ct.slice(0..21);
+        assert_eq!(all.iter().cloned().collect::<Vec<_>>(), (0..21).collect::<Vec<_>>());
+
+        let slice = ct.slice(5..15);
+        assert_eq!((slice.len(), slice.get(0), slice.get(9), slice.get(10)), (10, Some(&5), Some(&14), None));
+        assert_eq!(slice.iter().len(), 10);
+        let nested = slice.slice(2..4);
+        assert_eq!(nested.into_iter().collect::<Vec<_>>(), [&7, &8]);
+        assert_eq!(format!("{:?}", nested), "[7, 8]");
+        assert!(slice.slice(10..10).is_empty());
+        assert_eq!(ct.slice(19..21).iter().cloned().collect::<Vec<_>>(), [19, 20]);
+        assert_eq!(ct.slice(0..1).iter().cloned().collect::<Vec<_>>(), [0]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn slice_out_of_bounds() {
+        let ct: CountTree<_> = (0..10).collect();
+        ct.slice(2..8).slice(3..7);
+    }
+
+    #[test]
+    fn split_at() {
+        use super::RcCountTree;
+
+        for n in 0..40 {
+            for at in 0..n + 1 {
+                let mut ct: CountTree<_> = (1..n).collect();
+                if n > 0 {
+                    ct.push_front(0);
+                }
+                let (left, right) = ct.split_at(at);
+                assert_eq!(left.to_vec(), (0..at).collect::<Vec<_>>());
+                assert_eq!(right.to_vec(), (at..n).collect::<Vec<_>>());
+                for half in &mut [left, right] {
+                    if let Some(root) = half.root() {
+                        assert!(compute_level(root, 1).is_balanced());
+                        assert_eq!(check_counts(root), Ok(()));
+                        assert_eq!(check_heights(root), Ok(()));
+                    }
+                }
+            }
+        }
+
+        // the nodes along the split path are copied, the rest is shared
+        let rc: RcCountTree<_> = (0..100).collect();
+        let (left, right) = ::cow::allow_clones(|| rc.clone().split_at(30));
+        assert_eq!((left.len(), right.get(0), rc.len()), (30, Some(&30), 100));
+    }
+
+    #[test]
+    fn cursor_at() {
+        let mut ct: CountTree<_> = (2..20).collect();
+        ct.push_front(1);
+        ct.push_front(0);
+        ct.push_back(20);
+        let len = ct.len();
+        for start in 0..len + 1 {
+            let mut cursor = ct.cursor_at(start);
+            assert_eq!(cursor.index(), if start < len { Some(start) } else { None });
+            assert_eq!(cursor.current(), ct.get(start));
+            // two full rounds in each direction, through the ghost position
+            let mut index = start;
+            for _ in 0..2 * (len + 1) {
+                index = (index + 1) % (len + 1);
+                assert_eq!(cursor.move_next(), ct.get(index));
+                assert_eq!(cursor.index(), if index < len { Some(index) } else { None });
+            }
+            let mut back = cursor.clone();
+            for _ in 0..2 * (len + 1) {
+                index = (index + len) % (len + 1);
+                assert_eq!(back.move_prev(), ct.get(index));
+                assert_eq!(back.index(), if index < len { Some(index) } else { None });
+            }
+        }
+
+        let empty = CountTree::<u32>::new();
+        let mut cursor = empty.cursor_at(0);
+        assert_eq!((cursor.move_next(), cursor.move_prev(), cursor.index()), (None, None, None));
+    }
+
+    #[test]
+    fn pop_n() {
+        let mut ct: CountTree<_> = (1..30).collect();
+        ct.push_front(0);
+        ct.push_back(30);
+        assert_eq!(ct.pop_front_n(5).to_vec(), [0, 1, 2, 3, 4]);
+        assert_eq!(ct.pop_back_n(3).to_vec(), [28, 29, 30]);
+        assert_eq!(ct.pop_front_n(0).len(), 0);
+        assert_eq!(ct.to_vec(), (5..28).collect::<Vec<_>>());
+        let root = ct.root().unwrap();
+        assert!(compute_level(root, 1).is_balanced());
+        assert_eq!(check_counts(root), Ok(()));
+
+        assert_eq!(ct.pop_back_n(100).len(), 23);
+        assert!(ct.is_empty());
+        assert!(ct.pop_front_n(1).is_empty());
+    }
+
+    #[test]
+    fn chunks() {
+        use std::thread;
+        use super::ArcCountTree;
+
+        let mut ct: CountTree<_> = (1..100).collect();
+        ct.push_front(0);
+        let mut chunks: Vec<_> = ct.chunks(30).collect();
+        assert_eq!(chunks.iter().map(|c| c.len()).collect::<Vec<_>>(), [30, 30, 30, 10]);
+        for (i, chunk) in chunks.iter_mut().enumerate() {
+            assert_eq!(chunk.to_vec(), (i * 30..100.min(i * 30 + 30)).collect::<Vec<_>>());
+            let root = chunk.root().unwrap();
+            assert!(compute_level(root, 1).is_balanced());
+            assert_eq!(check_counts(root), Ok(()));
+        }
+
+        let ct: CountTree<_> = (0..10).collect();
+        let mut chunks = ct.chunks(4);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks.next_back().map(|c| c.to_vec()), Some(vec![8, 9]));
+        assert_eq!(chunks.next_back().map(|c| c.to_vec()), Some(vec![4, 5, 6, 7]));
+        assert_eq!(chunks.next().map(|c| c.to_vec()), Some(vec![0, 1, 2, 3]));
+        assert!(chunks.next().is_none() && chunks.next_back().is_none());
+        assert_eq!(CountTree::<u8>::new().chunks(3).count(), 0);
+
+        let act: ArcCountTree<u64> = (0..1000).collect();
+        let workers: Vec<_> = act.chunks(100)
+            .map(|chunk| thread::spawn(move || chunk.into_iter().sum::<u64>()))
+            .collect();
+        let sums: Vec<u64> = workers.into_iter().map(|w| w.join().unwrap()).collect();
+        assert_eq!(sums.len(), 10);
+        assert_eq!(sums.iter().sum::<u64>(), 999 * 1000 / 2);
+    }
+
+    #[test]
+    fn freeze() {
+        use std::thread;
+        use super::{ArcCountTree, FrozenCountTree};
+
+        fn is_send_sync<T: Send + Sync>(_: &T) {}
+
+        let mut ct: CountTree<_> = (1..100).collect();
+        ct.push_front(0);
+        let frozen = ct.freeze();
+        is_send_sync(&frozen);
+        assert_eq!((frozen.len(), frozen.get(42), frozen.get(100)), (100, Some(&42), None));
+        assert_eq!(frozen.range(10..13).iter().collect::<Vec<_>>(), [&10, &11, &12]);
+        let readers: Vec<_> = (0..4)
+            .map(|i| {
+                let snapshot = frozen.clone();
+                thread::spawn(move || snapshot.iter().skip(i * 25).take(25).sum::<i32>())
+            })
+            .collect();
+        let sum: i32 = readers.into_iter().map(|r| r.join().unwrap()).sum();
+        assert_eq!(sum, 99 * 100 / 2);
+
+        let act: ArcCountTree<_> = (0..3).collect();
+        let frozen: FrozenCountTree<_, _> = act.freeze();
+        is_send_sync(&frozen);
+        assert_eq!(format!("{:?}", frozen), "[0, 1, 2]");
+        assert!(CountTree::<u8>::new().freeze().is_empty());
+    }
+
+    #[test]
+    fn builder() {
+        use super::CountTreeBuilder;
+
+        for n in 0..130 {
+            let mut builder = CountTreeBuilder::new();
+            builder.push_run(0..n / 2);
+            builder.extend(n / 2..n);
+            assert_eq!(builder.len(), n);
+            let mut ct: CountTree<_> = builder.finish();
+            assert_eq!(ct.to_vec(), (0..n).collect::<Vec<_>>());
+            if let Some(root) = ct.root() {
+                assert!(compute_level(root, 1).is_balanced(), "n = {}", n);
+                assert_eq!(check_counts(root), Ok(()));
+                assert_eq!(check_heights(root), Ok(()));
+            }
+        }
+        let mut builder = CountTreeBuilder::new();
+        for i in 0..100000 {
+            builder.push(i);
+        }
+        let mut ct: CountTree<_> = builder.finish();
+        assert_eq!(ct.root().unwrap().height(), 16);
+        assert_eq!(ct.get(54321), Some(&54321));
+    }
+
+    #[test]
+    fn from_fn() {
+        for n in 0..70 {
+            let mut calls = Vec::new();
+            let mut ct: CountTree<_> = CountTree::from_fn(n, |i| {
+                calls.push(i);
+                i * 2
+            });
+            assert_eq!(calls, (0..n).collect::<Vec<_>>());
+            assert_eq!(ct.to_vec(), (0..n).map(|i| i * 2).collect::<Vec<_>>());
+            if let Some(root) = ct.root() {
+                assert!(compute_level(root, 1).is_balanced(), "n = {}", n);
+                assert_eq!(check_counts(root), Ok(()));
+                assert_eq!(check_heights(root), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn fold() {
+        let mut ct: CountTree<_> = (0..100).collect();
+        ct.push_front(-1);
+        ct.push_back(100);
+        assert_eq!(ct.fold(Vec::new(), |mut v, &i| {
+                       v.push(i);
+                       v
+                   }),
+                   (-1..101).collect::<Vec<_>>());
+        let mut sum = 0;
+        ct.for_each(|&i| sum += i);
+        assert_eq!(sum, 5050 - 1);
+        assert_eq!(CountTree::<i32>::new().fold(7, |acc, &i| acc + i), 7);
+    }
+
+    #[test]
+    fn min_max() {
+        let mut ct: CountTree<i32> = vec![3, 1, 4, 1, 5, 9, 2, 6, 5].into_iter().collect();
+        ct.push_front(9);
+        assert_eq!(ct.min_by(|a, b| a.cmp(b)), Some((2, &1)));
+        assert_eq!(ct.max_by(|a, b| a.cmp(b)), Some((6, &9)));
+        assert_eq!(ct.position_min_by_key(|&v| (v - 5).abs()), Some((5, &5)));
+        assert_eq!(ct.position_max_by_key(|&v| v % 5), Some((6, &9)));
+        let empty: CountTree<i32> = CountTree::new();
+        assert_eq!(empty.min_by(|a, b| a.cmp(b)), None);
+        assert_eq!(empty.position_max_by_key(|&v| v), None);
+    }
+
+    #[test]
+    fn index_from_back() {
+        let mut ct: CountTree<_> = (0..10).collect();
+        assert_eq!(ct.get_back(0), Some(&9));
+        assert_eq!(ct.get_back(9), Some(&0));
+        assert_eq!(ct.get_back(10), None);
+        assert_eq!(ct.get_back(usize::MAX), None);
+        ct.insert_back(0, 10);
+        ct.insert_back(11, -1);
+        ct.insert_back(3, 100);
+        assert_eq!(ct.to_vec(), [-1, 0, 1, 2, 3, 4, 5, 6, 7, 100, 8, 9, 10]);
+        assert_eq!(ct.remove_back(3), 100);
+        assert_eq!(ct.remove_back(0), 10);
+        assert_eq!(ct.remove_back(10), -1);
+        assert_eq!(ct.to_vec(), (0..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic]
+    fn insert_back_out_of_bounds() {
+        let mut ct: CountTree<_> = (0..3).collect();
+        ct.insert_back(4, 0);
+    }
+
+    #[test]
+    fn repeat() {
+        use super::RcCountTree;
+        use cow::allow_clones;
+
+        for n in 0..70 {
+            let ct: CountTree<_> = CountTree::repeat('a', n);
+            let mut rct: RcCountTree<_> = CountTree::repeat('a', n);
+            assert_eq!(ct.to_vec(), vec!['a'; n]);
+            assert_eq!(rct.to_vec(), vec!['a'; n]);
+            if let Some(root) = rct.root() {
+                assert!(compute_level(root, 1).is_balanced(), "n = {}", n);
+                assert_eq!(check_counts(root), Ok(()));
+                assert_eq!(check_heights(root), Ok(()));
+                let shape = CountTree::<_>::from_fn(n, |_| ()).root().unwrap().height();
+                assert_eq!(root.height(), shape);
+            }
+            if n > 0 {
+                allow_clones(|| *rct.get_mut(n / 3).unwrap() = 'b');
+                assert_eq!(rct.to_vec().iter().filter(|&&c| c == 'a').count(), n - 1);
+                assert_eq!(rct.get(n / 3), Some(&'b'));
+            }
+        }
+        let mut rct: RcCountTree<_> = CountTree::repeat(0u8, 1 << 29);
+        assert_eq!(rct.len(), 1 << 29);
+        allow_clones(|| rct.insert(12345, 1));
+        assert_eq!(rct.get(12345), Some(&1));
+        assert_eq!(rct.get(12346), Some(&0));
+    }
+
+    #[test]
+    fn clone_range() {
+        use super::ArcCountTree;
+
+        let mut act: ArcCountTree<_> = (1..100).collect();
+        act.push_front(0);
+        let mut range = act.clone_range(20..70);
+        assert_eq!(range.to_vec(), (20..70).collect::<Vec<_>>());
+        assert_eq!(act.to_vec(), (0..100).collect::<Vec<_>>());
+        let root = range.root().unwrap();
+        assert!(compute_level(root, 1).is_balanced());
+        assert_eq!(check_counts(root), Ok(()));
+        assert!(act.clone_range(100..100).is_empty());
+
+        let ct: CountTree<_> = (0..10).collect();
+        assert_eq!(ct.clone_range(3..5).to_vec(), [3, 4]);
+    }
+
+    #[test]
+    fn insert_tree() {
+        let buffered = |range: ::std::ops::Range<usize>| {
+            let mut ct: CountTree<_> = range.clone().skip(1).collect();
+            if let Some(first) = range.clone().next() {
+                ct.push_front(first);
+            }
+            ct
+        };
+        for n in 0..20 {
+            for m in 0..20 {
+                for index in 0..n + 1 {
+                    let mut ct = buffered(0..n);
+                    ct.insert_tree(index, buffered(100..100 + m));
+                    let expected: Vec<_> = (0..index).chain(100..100 + m).chain(index..n).collect();
+                    assert_eq!(ct.to_vec(), expected);
+                    if let Some(root) = ct.root() {
+                        assert!(compute_level(root, 1).is_balanced());
+                        assert_eq!(check_counts(root), Ok(()));
+                        assert_eq!(check_heights(root), Ok(()));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn retain() {
+        use std::panic::{self, AssertUnwindSafe};
+
+        // (value, remaining ttl)
+        let mut ct: CountTree<_> = (0..50).map(|i| (i, i % 3)).collect();
+        ct.push_front((-1, 1));
+        ct.retain_mut(|entry| {
+            if entry.1 == 0 {
+                return false;
+            }
+            entry.1 -= 1;
+            true
+        });
+        let expected: Vec<_> = (-1..50).filter(|i| i % 3 != 0).map(|i| (i, if i == -1 { 0 } else { i % 3 - 1 })).collect();
+        assert_eq!(ct.to_vec(), expected);
+        let root = ct.root().unwrap();
+        assert!(compute_level(root, 1).is_balanced());
+        assert_eq!(check_counts(root), Ok(()));
+
+        ct.retain(|&(i, _)| i > 40);
+        assert_eq!((&ct).into_iter().map(|e| e.0).collect::<Vec<_>>(), [41, 43, 44, 46, 47, 49]);
+        ct.retain(|_| false);
+        assert!(ct.is_empty() && ct.root().is_none());
+
+        // a panic keeps the elements not removed yet
+        let mut ct: CountTree<_> = (0..10).collect();
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+            ct.retain_mut(|i| {
+                assert!(*i < 6);
+                *i % 2 == 0
+            })
+        }));
+        assert!(ret.is_err());
+        assert_eq!(ct.to_vec(), [0, 2, 4, 6, 7, 8, 9]);
+        assert_eq!(check_counts(ct.root().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn for_each_mut() {
+        let mut ct: CountTree<_> = (1..20).collect();
+        ct.push_front(0);
+        ct.push_back(20);
+        let mut i = 0;
+        ct.for_each_mut(|v| {
+            assert_eq!(*v, i);
+            *v *= 2;
+            i += 1;
+        });
+        assert_eq!(ct.to_vec(), (0..21).map(|v| 2 * v).collect::<Vec<_>>());
+        // the buffered elements are visited without being merged
+        assert_eq!(ct.root.as_deref().unwrap().count(), 19);
+        assert_eq!(check_counts(ct.root().unwrap()), Ok(()));
+    }
+
+    #[test]
+    fn lca_of_indices() {
+        let mut ct: CountTree<_> = (0..15).collect();
+        assert_eq!(ct.lca_of_indices(8, 10), Some(9));
+        assert_eq!(ct.lca_of_indices(4, 10), Some(7));
+        assert_eq!(ct.lca_of_indices(3, 2), Some(3));
+        assert_eq!(ct.lca_of_indices(6, 6), Some(6));
+        assert_eq!(ct.lca_of_indices(0, 15), None);
+        ct.push_front(-1);
+        assert_eq!(ct.lca_of_indices(9, 11), Some(10));
+        assert_eq!(ct.lca_of_indices(0, 11), None);
+    }
+
+    #[test]
+    fn join() {
+        for &(llen, rlen) in &[(0, 0), (0, 1), (1, 0), (3, 40), (40, 3), (100, 90)] {
+            let left = super::build_balanced::<_, BoxKind, _>(llen, &mut (0..llen));
+            let mid = Box::new(CountNode::new(llen));
+            let right = super::build_balanced::<_, BoxKind, _>(rlen, &mut (llen + 1..llen + rlen + 1));
+            let root = super::join::<_, BoxKind>(left, mid, right);
+            assert!(compute_level(&*root, 1).is_balanced());
+            let ct: CountTree<_> = CountTree::with_root(Some(root));
+            assert_eq!(ct.len(), llen + rlen + 1);
+            for i in 0..ct.len() {
+                assert_eq!(ct.get(i), Some(&i));
+            }
+        }
+    }
+
+    #[test]
+    fn flush_onto_missing_child() {
+        // after the removal, the outermost node below the root on the side of
+        // the push has only an inner child
+        let mut ct: CountTree<_> = (0..7).collect();
+        ct.remove(0);
+        ct.push_front(-1);
+        assert!(compute_level(ct.root().unwrap(), 1).is_balanced());
+        assert_eq!(ct.into_iter().collect::<Vec<_>>(), [-1, 1, 2, 3, 4, 5, 6]);
+
+        let mut ct: CountTree<_> = (0..7).collect();
+        ct.remove(6);
+        ct.push_back(7);
+        assert!(compute_level(ct.root().unwrap(), 1).is_balanced());
+        assert_eq!(ct.into_iter().collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5, 7]);
+    }
+
+    #[test]
+    fn transaction() {
+        use std::panic::{self, AssertUnwindSafe};
+        use super::ArcCountTree;
+
+        let mut ct: ArcCountTree<_> = (0..10).collect();
+        ct.push_back(10);
+        let ret: Result<_, ()> = ct.transaction(|txn| {
+            txn.insert(0, -1);
+            *txn.get_mut(5).unwrap() = 40;
+            Ok(txn.pop_back())
+        });
+        assert_eq!(ret, Ok(Some(10)));
+        assert_eq!(ct.to_vec(), [-1, 0, 1, 2, 3, 40, 5, 6, 7, 8, 9]);
+
+        let before = ct.to_vec();
+        let ret = ct.transaction(|txn| {
+            txn.clear();
+            txn.push_front(1);
+            assert_eq!(txn.to_vec(), [1]);
+            Err::<(), _>("abort")
+        });
+        assert_eq!(ret, Err("abort"));
+        assert_eq!(ct.to_vec(), before);
+
+        let ret = panic::catch_unwind(AssertUnwindSafe(|| {
+            ct.transaction(|txn| -> Result<(), ()> {
+                txn.remove(0);
+                txn.remove(100);
+                Ok(())
+            })
+        }));
+        assert!(ret.is_err());
+        assert_eq!(ct.to_vec(), before);
+
+        // also with unshared nodes
+        let mut ct: CountTree<_> = (0..10).collect();
+        let _ = ct.transaction(|txn| -> Result<(), ()> {
+            txn.remove(3);
+            Err(())
+        });
+        assert_eq!(ct.len(), 10);
+    }
+
+    #[test]
+    fn shared() {
+        use super::{RcCountTree, ArcCountTree};
+        use cow::allow_clones;
+
+        let mut ct: RcCountTree<_> = (0..100).collect();
+        let snapshot = ct.clone();
+        allow_clones(|| for i in 0..10 {
+            ct.push_back(100 + i);
+            ct.remove(i);
+            ct.insert(i, 1000 + i);
+        });
+        assert_eq!((&snapshot).into_iter().cloned().collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+        assert_eq!(ct.len(), 110);
+        assert_eq!(ct.get(9), Some(&1009));
+        assert_eq!(snapshot.len(), 100);
+        assert_eq!(allow_clones(|| snapshot.into_iter().next_back()), Some(99));
+
+        let mut ct = ArcCountTree::default();
+        ct.push_back(1);
+        let snapshot = ct.clone();
+        allow_clones(|| *ct.get_mut(0).unwrap() = 2);
+        assert_eq!((snapshot.get(0), ct.get(0)), (Some(&1), Some(&2)));
+    }
+
+    #[test]
+    fn into_shared() {
+        use std::thread;
+        use super::RcCountTree;
+        use cow::allow_clones;
+
+        let mut ct: RcCountTree<_> = (0..100).collect();
+        let snapshot = ct.clone();
+        allow_clones(|| ct.push_back(100));
+        let act = allow_clones(|| ct.into_shared());
+        let act = thread::spawn(move || {
+            let mut act = act;
+            act.remove(0);
+            act
+        }).join().unwrap();
+        let ct = act.into_local();
+        assert_eq!(ct.into_iter().collect::<Vec<_>>(), (1..101).collect::<Vec<_>>());
+        assert_eq!(snapshot.len(), 100);
+    }
+
+    #[test]
+    fn drop_shared() {
+        use super::RcCountTree;
+
+        // dropping a tree must not copy, or descend into, the nodes it still
+        // shares with another tree
+        let ct: RcCountTree<_> = (0..1000).collect();
+        let snapshot = ct.clone();
+        drop(ct);
+        assert_eq!(snapshot.len(), 1000);
+        assert!((&snapshot).into_iter().cloned().eq(0..1000));
+    }
 }
@@ -2,11 +2,85 @@
 //!
 //! Thin wrappers around the standard library ref-counted pointers that clones
 //! on `DerefMut` if reference count is greater than 1.
+//!
+//! With the `cow-metrics` feature, the number of such clones is counted, see
+//! `clone_count`. With the `cow-strict` feature, debug builds panic on such a
+//! clone, unless it happens within `allow_clones`. This catches lingering
+//! references (which make every mutation of a tree O(n)) early.
 
+use std::cell::Cell;
 use std::ops::Deref;
 use std::ops::DerefMut;
-use std::rc::Rc;
-use std::sync::Arc;
+use std::rc::{self, Rc};
+use std::sync::{self, Arc};
+#[cfg(feature="cow-metrics")]
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use unbox::Unbox;
+
+thread_local! {
+    static CLONES_ALLOWED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Runs `f`, within which `RcCow` and `ArcCow` are expected to clone shared
+/// values on `DerefMut`. This is only relevant with the `cow-strict` feature.
+pub fn allow_clones<R, F: FnOnce() -> R>(f: F) -> R {
+    struct Reset(bool);
+
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            let old = self.0;
+            CLONES_ALLOWED.with(|allowed| allowed.set(old));
+        }
+    }
+
+    let _reset = Reset(CLONES_ALLOWED.with(|allowed| allowed.replace(true)));
+    f()
+}
+
+// Called before a shared value is cloned.
+fn on_clone() {
+    #[cfg(feature="cow-metrics")]
+    {
+        CLONES.fetch_add(1, Ordering::Relaxed);
+        THREAD_CLONES.with(|count| count.set(count.get() + 1));
+    }
+    #[cfg(all(feature="cow-strict", debug_assertions))]
+    {
+        if !CLONES_ALLOWED.with(|allowed| allowed.get()) {
+            panic!("shared value cloned on mutation (see `cow::allow_clones`)");
+        }
+    }
+}
+
+#[cfg(feature="cow-metrics")]
+static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature="cow-metrics")]
+thread_local! {
+    static THREAD_CLONES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Returns the number of times a value was cloned on `DerefMut` (or
+/// `make_mut`) by `RcCow` or `ArcCow` pointers, across all threads.
+#[cfg(feature="cow-metrics")]
+pub fn clone_count() -> usize {
+    CLONES.load(Ordering::Relaxed)
+}
+
+/// Returns the number of the clones counted by `clone_count` which happened
+/// on the current thread. It is never reset.
+#[cfg(feature="cow-metrics")]
+pub fn thread_clone_count() -> usize {
+    THREAD_CLONES.with(Cell::get)
+}
+
+/// Resets the count returned by `clone_count` to zero, and returns the old
+/// count.
+#[cfg(feature="cow-metrics")]
+pub fn reset_clone_count() -> usize {
+    CLONES.swap(0, Ordering::Relaxed)
+}
 
 pub struct RcCow<T>(pub Rc<T>);
 
@@ -14,6 +88,54 @@ impl<T: Clone> RcCow<T> {
     pub fn new(value: T) -> RcCow<T> {
         RcCow(Rc::new(value))
     }
+
+    /// Converts into a thread-safe `ArcCow`, cloning the value if it is shared.
+    ///
+    /// This converts only the pointer itself. Since the type of a node depends
+    /// on the type of the pointers to its children, a whole tree has to be
+    /// converted node by node, e.g. using `algos::map_tree`, or for a
+    /// `CountTree`, using `RcCountTree::into_shared` and
+    /// `ArcCountTree::into_local`.
+    pub fn into_shared(self) -> ArcCow<T> {
+        ArcCow::new(self.unbox())
+    }
+
+    /// Returns a mutable reference to the value, cloning it first if it is
+    /// shared. This is what `DerefMut` does.
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        if Rc::strong_count(&this.0) > 1 {
+            on_clone();
+        }
+        Rc::make_mut(&mut this.0)
+    }
+}
+
+impl<T> RcCow<T> {
+    /// Returns a mutable reference to the value if it is not shared, without
+    /// ever cloning it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        Rc::get_mut(&mut this.0)
+    }
+
+    /// Returns the value if it is not shared, or `this` otherwise.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        Rc::try_unwrap(this.0).map_err(RcCow)
+    }
+
+    /// Returns the number of pointers sharing the value.
+    pub fn strong_count(this: &Self) -> usize {
+        Rc::strong_count(&this.0)
+    }
+
+    /// Returns `true` if both the pointers share the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Rc::ptr_eq(&this.0, &other.0)
+    }
+
+    /// Creates a weak pointer to the value.
+    pub fn downgrade(this: &Self) -> RcCowWeak<T> {
+        RcCowWeak(Rc::downgrade(&this.0))
+    }
 }
 
 impl<T> Clone for RcCow<T> {
@@ -32,7 +154,38 @@ impl<T> Deref for RcCow<T> {
 
 impl<T: Clone> DerefMut for RcCow<T> {
     fn deref_mut(&mut self) -> &mut T {
-        Rc::make_mut(&mut self.0)
+        RcCow::make_mut(self)
+    }
+}
+
+/// Weak counterpart of `RcCow`, which does not keep the value alive.
+///
+/// Note that mutating the value through `DerefMut` moves it to a new
+/// allocation whenever there are other pointers to it, strong or weak, so the
+/// weak pointers never observe such mutations.
+pub struct RcCowWeak<T>(pub rc::Weak<T>);
+
+impl<T> RcCowWeak<T> {
+    /// Creates a weak pointer which never upgrades.
+    pub fn new() -> RcCowWeak<T> {
+        RcCowWeak(rc::Weak::new())
+    }
+
+    /// Returns a strong pointer to the value, if it is still alive.
+    pub fn upgrade(&self) -> Option<RcCow<T>> {
+        self.0.upgrade().map(RcCow)
+    }
+}
+
+impl<T> Default for RcCowWeak<T> {
+    fn default() -> RcCowWeak<T> {
+        RcCowWeak::new()
+    }
+}
+
+impl<T> Clone for RcCowWeak<T> {
+    fn clone(&self) -> RcCowWeak<T> {
+        RcCowWeak(self.0.clone())
     }
 }
 
@@ -42,6 +195,54 @@ impl<T: Clone> ArcCow<T> {
     pub fn new(value: T) -> ArcCow<T> {
         ArcCow(Arc::new(value))
     }
+
+    /// Converts into a single-threaded `RcCow`, cloning the value if it is shared.
+    ///
+    /// This converts only the pointer itself. Since the type of a node depends
+    /// on the type of the pointers to its children, a whole tree has to be
+    /// converted node by node, e.g. using `algos::map_tree`, or for a
+    /// `CountTree`, using `RcCountTree::into_shared` and
+    /// `ArcCountTree::into_local`.
+    pub fn into_local(self) -> RcCow<T> {
+        RcCow::new(self.unbox())
+    }
+
+    /// Returns a mutable reference to the value, cloning it first if it is
+    /// shared. This is what `DerefMut` does.
+    pub fn make_mut(this: &mut Self) -> &mut T {
+        if Arc::strong_count(&this.0) > 1 {
+            on_clone();
+        }
+        Arc::make_mut(&mut this.0)
+    }
+}
+
+impl<T> ArcCow<T> {
+    /// Returns a mutable reference to the value if it is not shared, without
+    /// ever cloning it.
+    pub fn get_mut(this: &mut Self) -> Option<&mut T> {
+        Arc::get_mut(&mut this.0)
+    }
+
+    /// Returns the value if it is not shared, or `this` otherwise.
+    pub fn try_unwrap(this: Self) -> Result<T, Self> {
+        Arc::try_unwrap(this.0).map_err(ArcCow)
+    }
+
+    /// Returns the number of pointers sharing the value.
+    pub fn strong_count(this: &Self) -> usize {
+        Arc::strong_count(&this.0)
+    }
+
+    /// Returns `true` if both the pointers share the same value.
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        Arc::ptr_eq(&this.0, &other.0)
+    }
+
+    /// Creates a weak pointer to the value.
+    pub fn downgrade(this: &Self) -> ArcCowWeak<T> {
+        ArcCowWeak(Arc::downgrade(&this.0))
+    }
 }
 
 impl<T> Clone for ArcCow<T> {
@@ -60,6 +261,123 @@ impl<T> Deref for ArcCow<T> {
 
 impl<T: Clone> DerefMut for ArcCow<T> {
     fn deref_mut(&mut self) -> &mut T {
-        Arc::make_mut(&mut self.0)
+        ArcCow::make_mut(self)
+    }
+}
+
+/// Weak counterpart of `ArcCow`, which does not keep the value alive.
+///
+/// Note that mutating the value through `DerefMut` moves it to a new
+/// allocation whenever there are other pointers to it, strong or weak, so the
+/// weak pointers never observe such mutations.
+pub struct ArcCowWeak<T>(pub sync::Weak<T>);
+
+impl<T> ArcCowWeak<T> {
+    /// Creates a weak pointer which never upgrades.
+    pub fn new() -> ArcCowWeak<T> {
+        ArcCowWeak(sync::Weak::new())
+    }
+
+    /// Returns a strong pointer to the value, if it is still alive.
+    pub fn upgrade(&self) -> Option<ArcCow<T>> {
+        self.0.upgrade().map(ArcCow)
+    }
+}
+
+impl<T> Default for ArcCowWeak<T> {
+    fn default() -> ArcCowWeak<T> {
+        ArcCowWeak::new()
+    }
+}
+
+impl<T> Clone for ArcCowWeak<T> {
+    fn clone(&self) -> ArcCowWeak<T> {
+        ArcCowWeak(self.0.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RcCow, ArcCow, ArcCowWeak, allow_clones};
+
+    #[test]
+    fn sharing() {
+        let mut a = RcCow::new(vec![1]);
+        assert_eq!(RcCow::get_mut(&mut a).map(|v| v.len()), Some(1));
+        let mut b = a.clone();
+        assert_eq!(RcCow::strong_count(&a), 2);
+        assert!(RcCow::ptr_eq(&a, &b));
+        assert!(RcCow::get_mut(&mut a).is_none());
+        allow_clones(|| RcCow::make_mut(&mut b).push(2));
+        assert!(!RcCow::ptr_eq(&a, &b));
+        assert_eq!(RcCow::strong_count(&a), 1);
+        assert_eq!(RcCow::try_unwrap(b).ok(), Some(vec![1, 2]));
+
+        let a = ArcCow::new(1);
+        let b = a.clone();
+        let a = ArcCow::try_unwrap(a).unwrap_err();
+        drop(b);
+        assert_eq!(ArcCow::try_unwrap(a).ok(), Some(1));
+    }
+
+    #[test]
+    fn weak() {
+        let mut a = RcCow::new(1);
+        let weak = RcCow::downgrade(&a);
+        let b = weak.upgrade().unwrap();
+        assert!(RcCow::ptr_eq(&a, &b));
+        allow_clones(|| *a = 2);
+        // `a` got a copy of its own, and the weak pointer stayed with `b`
+        assert!(RcCow::ptr_eq(&weak.upgrade().unwrap(), &b));
+        assert_eq!(weak.upgrade().map(|b| *b), Some(1));
+        drop(b);
+        assert!(weak.upgrade().is_none());
+
+        let a = ArcCow::new(1);
+        let weak = ArcCow::downgrade(&a);
+        drop(a);
+        assert!(weak.upgrade().is_none());
+        assert!(ArcCowWeak::<u32>::new().upgrade().is_none());
+    }
+
+    #[test]
+    #[cfg(feature="cow-metrics")]
+    fn metrics() {
+        use super::clone_count;
+
+        // other tests may clone concurrently, so only a lower bound is exact
+        let before = clone_count();
+        let mut a = RcCow::new(1);
+        *a = 2;
+        let b = a.clone();
+        allow_clones(|| *a = 3);
+        let mut c = ArcCow::new(1);
+        let d = c.clone();
+        allow_clones(|| *c = 2);
+        assert!(clone_count() >= before + 2);
+        assert_eq!((*b, *d), (2, 1));
+    }
+
+    #[test]
+    fn conversion() {
+        let a = RcCow::new(vec![1]);
+        let b = a.clone();
+        let mut c = a.into_shared();
+        c.push(2);
+        assert_eq!(*b, [1]);
+        assert_eq!(*c.into_local(), [1, 2]);
+    }
+
+    #[test]
+    #[cfg(all(feature="cow-strict", debug_assertions))]
+    fn strict() {
+        use std::panic;
+
+        let mut a = ArcCow::new(1);
+        let b = a.clone();
+        assert!(panic::catch_unwind(panic::AssertUnwindSafe(|| *a = 2)).is_err());
+        allow_clones(|| *a = 2);
+        assert_eq!((*a, *b), (2, 1));
+        *a = 3;
     }
 }
@@ -0,0 +1,566 @@
+//! Lists whose elements can be tracked through edits.
+//!
+//! `HandleTree` is a list like `CountTree`, whose insertion methods return a
+//! `Handle` to the new element. A handle stays valid until its element is
+//! removed, however the tree is rebalanced in the meantime, and `index_of`
+//! finds the current position of its element in O(log(n)) time. This is what
+//! a text editor needs for marks (cursors, bookmarks, diagnostics) that follow
+//! the lines they were put on.
+//!
+//! The nodes are kept in an arena (a `Vec`), and link to their parents as
+//! well as their children through indices into it, so that the position of a
+//! node can be found by walking up to the root. A handle is the index of a
+//! node in the arena, with a generation number which detects handles to
+//! removed elements once the slot is reused.
+//!
+//! ```
+//! use binary_tree::handle::HandleTree;
+//!
+//! let mut lines = HandleTree::new();
+//! lines.push_back("fn main() {");
+//! let mark = lines.push_back("}");
+//! lines.insert(1, "    println!(\"hello\");");
+//! assert_eq!(lines.index_of(mark), Some(2));
+//! lines.remove(0);
+//! assert_eq!(lines.index_of(mark), Some(1));
+//! assert_eq!(lines.get_by_handle(mark), Some(&"}"));
+//! ```
+
+use std::cmp;
+use std::fmt::{self, Debug};
+use std::iter::FromIterator;
+
+/// Refers to an element of a `HandleTree`, for as long as it is not removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    slot: usize,
+    generation: u32,
+}
+
+struct Slot<T> {
+    // `None` if the slot is free
+    val: Option<T>,
+    // incremented whenever the slot is freed
+    generation: u32,
+    parent: Option<usize>,
+    left: Option<usize>,
+    right: Option<usize>,
+    count: u32,
+    height: u16,
+}
+
+/// A balanced list (AVL tree, as `CountTree`) handing out stable `Handle`s to
+/// its elements.
+pub struct HandleTree<T> {
+    slots: Vec<Slot<T>>,
+    // indices of the free slots
+    free: Vec<usize>,
+    root: Option<usize>,
+}
+
+impl<T> HandleTree<T> {
+    /// Returns an empty `HandleTree`.
+    pub fn new() -> HandleTree<T> {
+        HandleTree {
+            slots: Vec::new(),
+            free: Vec::new(),
+            root: None,
+        }
+    }
+
+    /// Returns the number of elements in the tree. Time complexity: O(1)
+    pub fn len(&self) -> usize {
+        self.count(self.root) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the element at the given index, or `None` if index is out of
+    /// bounds. Time complexity: O(log(n))
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.find(index).and_then(|i| self.slots[i].val.as_ref())
+    }
+
+    /// Returns a mutable reference to the element at the given index, or
+    /// `None` if out of bounds. Time complexity: O(log(n))
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        match self.find(index) {
+            Some(i) => self.slots[i].val.as_mut(),
+            None => None,
+        }
+    }
+
+    /// Returns the handle of the element at the given index, or `None` if
+    /// index is out of bounds. Time complexity: O(log(n))
+    pub fn handle_at(&self, index: usize) -> Option<Handle> {
+        self.find(index).map(|i| self.handle(i))
+    }
+
+    /// Returns the current index of the element referred to by `handle`, or
+    /// `None` if it has been removed. Time complexity: O(log(n))
+    pub fn index_of(&self, handle: Handle) -> Option<usize> {
+        let mut node = self.resolve(handle)?;
+        let mut index = self.count(self.slots[node].left);
+        while let Some(parent) = self.slots[node].parent {
+            if self.slots[parent].right == Some(node) {
+                index += self.count(self.slots[parent].left) + 1;
+            }
+            node = parent;
+        }
+        Some(index as usize)
+    }
+
+    /// Returns the element referred to by `handle`, or `None` if it has been
+    /// removed. Time complexity: O(1)
+    pub fn get_by_handle(&self, handle: Handle) -> Option<&T> {
+        self.resolve(handle).and_then(|i| self.slots[i].val.as_ref())
+    }
+
+    /// Mutable version of `get_by_handle`. Time complexity: O(1)
+    pub fn get_by_handle_mut(&mut self, handle: Handle) -> Option<&mut T> {
+        match self.resolve(handle) {
+            Some(i) => self.slots[i].val.as_mut(),
+            None => None,
+        }
+    }
+
+    /// Inserts an element at the given index, and returns its handle. Time
+    /// complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`
+    pub fn insert(&mut self, index: usize, value: T) -> Handle {
+        assert!(index <= self.len(), "index out of bounds!");
+        let new = self.alloc(value);
+        let mut index = index as u32;
+        let mut node = match self.root {
+            Some(root) => root,
+            None => {
+                self.root = Some(new);
+                return self.handle(new);
+            }
+        };
+        loop {
+            let lcount = self.count(self.slots[node].left);
+            if index <= lcount {
+                match self.slots[node].left {
+                    Some(left) => node = left,
+                    None => {
+                        self.set_left(node, Some(new));
+                        break;
+                    }
+                }
+            } else {
+                index -= lcount + 1;
+                match self.slots[node].right {
+                    Some(right) => node = right,
+                    None => {
+                        self.set_right(node, Some(new));
+                        break;
+                    }
+                }
+            }
+        }
+        self.retrace(Some(node));
+        self.handle(new)
+    }
+
+    /// Prepends an element, and returns its handle. Time complexity:
+    /// O(log(n))
+    pub fn push_front(&mut self, value: T) -> Handle {
+        self.insert(0, value)
+    }
+
+    /// Appends an element, and returns its handle. Time complexity: O(log(n))
+    pub fn push_back(&mut self, value: T) -> Handle {
+        let len = self.len();
+        self.insert(len, value)
+    }
+
+    /// Removes the element at the given index, invalidating its handle. Time
+    /// complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        let node = self.find(index).expect("index out of bounds!");
+        self.unlink(node)
+    }
+
+    /// Removes the element referred to by `handle`, or returns `None` if it
+    /// has already been removed. Time complexity: O(log(n))
+    pub fn remove_by_handle(&mut self, handle: Handle) -> Option<T> {
+        self.resolve(handle).map(|node| self.unlink(node))
+    }
+
+    /// Returns an iterator over the elements in order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        let mut next = self.root;
+        while let Some(left) = next.and_then(|i| self.slots[i].left) {
+            next = Some(left);
+        }
+        Iter {
+            tree: self,
+            next,
+            remaining: self.len(),
+        }
+    }
+
+    fn handle(&self, node: usize) -> Handle {
+        Handle {
+            slot: node,
+            generation: self.slots[node].generation,
+        }
+    }
+
+    fn resolve(&self, handle: Handle) -> Option<usize> {
+        match self.slots.get(handle.slot) {
+            Some(slot) if slot.val.is_some() && slot.generation == handle.generation => Some(handle.slot),
+            _ => None,
+        }
+    }
+
+    fn count(&self, node: Option<usize>) -> u32 {
+        node.map_or(0, |i| self.slots[i].count)
+    }
+
+    fn height(&self, node: Option<usize>) -> i32 {
+        node.map_or(-1, |i| self.slots[i].height as i32)
+    }
+
+    fn find(&self, index: usize) -> Option<usize> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut index = index as u32;
+        let mut node = self.root?;
+        loop {
+            let lcount = self.count(self.slots[node].left);
+            if index < lcount {
+                node = self.slots[node].left?;
+            } else if index == lcount {
+                return Some(node);
+            } else {
+                index -= lcount + 1;
+                node = self.slots[node].right?;
+            }
+        }
+    }
+
+    // Returns a free slot holding `value`, without links.
+    fn alloc(&mut self, value: T) -> usize {
+        match self.free.pop() {
+            Some(i) => {
+                let slot = &mut self.slots[i];
+                slot.val = Some(value);
+                slot.count = 1;
+                slot.height = 0;
+                i
+            }
+            None => {
+                self.slots.push(Slot {
+                    val: Some(value),
+                    generation: 0,
+                    parent: None,
+                    left: None,
+                    right: None,
+                    count: 1,
+                    height: 0,
+                });
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    // Detaches `node` from the tree, frees its slot and returns its value.
+    fn unlink(&mut self, node: usize) -> T {
+        let Slot { parent, left, right, .. } = self.slots[node];
+        let (replacement, retrace_from) = match (left, right) {
+            (None, child) | (child, None) => (child, parent),
+            (Some(left), Some(right)) => {
+                // the successor takes the place of `node`
+                let mut succ = right;
+                while let Some(next) = self.slots[succ].left {
+                    succ = next;
+                }
+                let retrace_from = if succ == right {
+                    succ
+                } else {
+                    let succ_parent = self.slots[succ].parent.unwrap();
+                    let succ_right = self.slots[succ].right;
+                    self.set_left(succ_parent, succ_right);
+                    self.set_right(succ, Some(right));
+                    succ_parent
+                };
+                self.set_left(succ, Some(left));
+                (Some(succ), Some(retrace_from))
+            }
+        };
+        self.replace_child(parent, node, replacement);
+        self.retrace(retrace_from);
+
+        let slot = &mut self.slots[node];
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.parent = None;
+        slot.left = None;
+        slot.right = None;
+        self.free.push(node);
+        slot.val.take().unwrap()
+    }
+
+    fn set_left(&mut self, node: usize, child: Option<usize>) {
+        self.slots[node].left = child;
+        if let Some(child) = child {
+            self.slots[child].parent = Some(node);
+        }
+    }
+
+    fn set_right(&mut self, node: usize, child: Option<usize>) {
+        self.slots[node].right = child;
+        if let Some(child) = child {
+            self.slots[child].parent = Some(node);
+        }
+    }
+
+    // Puts `new` in place of `old`, the child of `parent` (or the root).
+    fn replace_child(&mut self, parent: Option<usize>, old: usize, new: Option<usize>) {
+        match parent {
+            Some(parent) if self.slots[parent].left == Some(old) => self.set_left(parent, new),
+            Some(parent) => self.set_right(parent, new),
+            None => {
+                self.root = new;
+                if let Some(new) = new {
+                    self.slots[new].parent = None;
+                }
+            }
+        }
+    }
+
+    fn update_stats(&mut self, node: usize) {
+        let (left, right) = (self.slots[node].left, self.slots[node].right);
+        let count = self.count(left) + self.count(right) + 1;
+        let height = cmp::max(self.height(left), self.height(right)) + 1;
+        let slot = &mut self.slots[node];
+        slot.count = count;
+        slot.height = height as u16;
+    }
+
+    // generalized version of AVL tree balance factor: h(left) - h(right)
+    fn balance_factor(&self, node: usize) -> i32 {
+        self.height(self.slots[node].left) - self.height(self.slots[node].right)
+    }
+
+    // Returns the node taking the place of `node`.
+    fn rotate_left(&mut self, node: usize) -> usize {
+        let parent = self.slots[node].parent;
+        let right = self.slots[node].right.unwrap();
+        let inner = self.slots[right].left;
+        self.set_right(node, inner);
+        self.set_left(right, Some(node));
+        self.replace_child(parent, node, Some(right));
+        self.update_stats(node);
+        self.update_stats(right);
+        right
+    }
+
+    // Returns the node taking the place of `node`.
+    fn rotate_right(&mut self, node: usize) -> usize {
+        let parent = self.slots[node].parent;
+        let left = self.slots[node].left.unwrap();
+        let inner = self.slots[left].right;
+        self.set_left(node, inner);
+        self.set_right(left, Some(node));
+        self.replace_child(parent, node, Some(left));
+        self.update_stats(node);
+        self.update_stats(left);
+        left
+    }
+
+    // AVL tree algorithm, as in `CountNode`. Returns the node taking the place
+    // of `node`.
+    fn rebalance(&mut self, node: usize) -> usize {
+        self.update_stats(node);
+        let balance = self.balance_factor(node);
+        if balance > 1 {
+            let left = self.slots[node].left.unwrap();
+            if self.balance_factor(left) < 0 {
+                self.rotate_left(left);
+            }
+            self.rotate_right(node)
+        } else if balance < -1 {
+            let right = self.slots[node].right.unwrap();
+            if self.balance_factor(right) > 0 {
+                self.rotate_right(right);
+            }
+            self.rotate_left(node)
+        } else {
+            node
+        }
+    }
+
+    // Rebalances `node` and all its ancestors.
+    fn retrace(&mut self, mut node: Option<usize>) {
+        while let Some(i) = node {
+            let i = self.rebalance(i);
+            node = self.slots[i].parent;
+        }
+    }
+}
+
+impl<T> Default for HandleTree<T> {
+    fn default() -> HandleTree<T> {
+        HandleTree::new()
+    }
+}
+
+impl<T: Debug> Debug for HandleTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for HandleTree<T> {
+    /// Time complexity: O(n log(n))
+    fn from_iter<I>(iterable: I) -> Self
+        where I: IntoIterator<Item = T>
+    {
+        let mut tree = HandleTree::new();
+        for value in iterable {
+            tree.push_back(value);
+        }
+        tree
+    }
+}
+
+impl<'a, T> IntoIterator for &'a HandleTree<T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+/// In-order iterator over the elements of a `HandleTree`, following the
+/// parent links.
+pub struct Iter<'a, T: 'a> {
+    tree: &'a HandleTree<T>,
+    next: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let slots = &self.tree.slots;
+        let node = self.next?;
+        self.next = match slots[node].right {
+            Some(mut next) => {
+                while let Some(left) = slots[next].left {
+                    next = left;
+                }
+                Some(next)
+            }
+            None => {
+                // up to the first ancestor of which `node` is in the left
+                let mut child = node;
+                loop {
+                    match slots[child].parent {
+                        Some(parent) if slots[parent].right == Some(child) => child = parent,
+                        parent => break parent,
+                    }
+                }
+            }
+        };
+        self.remaining -= 1;
+        slots[node].val.as_ref()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+#[cfg(test)]
+mod tests {
+    use super::HandleTree;
+
+    // Verifies the links, counts and heights, and AVL balance.
+    fn check<T>(tree: &HandleTree<T>) {
+        fn check_node<T>(tree: &HandleTree<T>, node: usize, parent: Option<usize>) -> (u32, i32) {
+            let slot = &tree.slots[node];
+            assert_eq!(slot.parent, parent);
+            assert!(slot.val.is_some());
+            let (lcount, lheight) = slot.left.map_or((0, -1), |left| check_node(tree, left, Some(node)));
+            let (rcount, rheight) = slot.right.map_or((0, -1), |right| check_node(tree, right, Some(node)));
+            assert!((lheight - rheight).abs() <= 1);
+            assert_eq!(slot.count, lcount + rcount + 1);
+            assert_eq!(slot.height as i32, lheight.max(rheight) + 1);
+            (slot.count, slot.height as i32)
+        }
+        if let Some(root) = tree.root {
+            check_node(tree, root, None);
+        }
+    }
+
+    #[test]
+    fn handles() {
+        let mut tree = HandleTree::new();
+        let mut model = Vec::new();
+        let mut state = 12345u32;
+        for step in 0..2000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let r = (state >> 8) as usize;
+            if model.is_empty() || !r.is_multiple_of(3) {
+                let index = r % (model.len() + 1);
+                let handle = tree.insert(index, step);
+                model.insert(index, (handle, step));
+            } else {
+                let index = r % model.len();
+                let (handle, value) = model.remove(index);
+                if r.is_multiple_of(2) {
+                    assert_eq!(tree.remove(index), value);
+                } else {
+                    assert_eq!(tree.remove_by_handle(handle), Some(value));
+                }
+                assert_eq!(tree.index_of(handle), None);
+                assert_eq!(tree.get_by_handle(handle), None);
+            }
+            if step % 100 == 0 {
+                check(&tree);
+                for (index, &(handle, value)) in model.iter().enumerate() {
+                    assert_eq!(tree.index_of(handle), Some(index));
+                    assert_eq!(tree.get_by_handle(handle), Some(&value));
+                    assert_eq!(tree.handle_at(index), Some(handle));
+                }
+            }
+        }
+        check(&tree);
+        let values: Vec<_> = model.iter().map(|&(_, value)| value).collect();
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), values);
+        assert_eq!(tree.iter().len(), values.len());
+    }
+
+    #[test]
+    fn stale_handles() {
+        let mut tree: HandleTree<_> = (0..10).collect();
+        let handle = tree.handle_at(3).unwrap();
+        *tree.get_by_handle_mut(handle).unwrap() = 30;
+        assert_eq!(tree.get(3), Some(&30));
+        assert_eq!(tree.remove_by_handle(handle), Some(30));
+        assert_eq!(tree.remove_by_handle(handle), None);
+        // the slot is reused, but the old handle stays invalid
+        let new = tree.push_front(-1);
+        assert_eq!(new.slot, handle.slot);
+        assert_eq!(tree.get_by_handle(handle), None);
+        assert_eq!(tree.index_of(new), Some(0));
+        assert_eq!(format!("{:?}", tree), "[-1, 0, 1, 2, 4, 5, 6, 7, 8, 9]");
+    }
+}
@@ -2,9 +2,16 @@
 //!
 //! This module is not meant for the end-user.
 
+use std::collections::VecDeque;
+use std::iter::{ExactSizeIterator, FusedIterator};
+use std::mem;
+use std::ptr;
+
 use Node;
 use NodeMut;
+use WalkAction;
 use unbox::Unbox;
+use algos::drop_tree;
 
 #[derive(PartialEq)]
 enum IterAction {
@@ -15,14 +22,28 @@ enum IterAction {
 pub struct Iter<'a, T>
     where T: Node + 'a
 {
-    stack: Vec<(&'a T, IterAction)>,
+    inner: NodeIter<'a, T>,
+    // number of values yet to be yielded, if known
+    remaining: Option<usize>,
 }
 
 impl<'a, T> Iter<'a, T>
     where T: Node + 'a
 {
     pub fn new(root: Option<&'a T>) -> Iter<'a, T> {
-        Iter { stack: root.map_or(vec![], |node| vec![(node, IterAction::Left)]) }
+        Iter {
+            inner: NodeIter::new(root),
+            remaining: None,
+        }
+    }
+
+    /// Creates an iterator over a tree of `len` nodes, which gives an exact
+    /// `size_hint`.
+    pub fn with_len(root: Option<&'a T>, len: usize) -> Iter<'a, T> {
+        Iter {
+            inner: NodeIter::new(root),
+            remaining: Some(len),
+        }
     }
 }
 
@@ -32,6 +53,45 @@ impl<'a, T> Iterator for Iter<'a, T>
     type Item = &'a T::Value;
 
     fn next(&mut self) -> Option<&'a T::Value> {
+        let node = self.inner.next()?;
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= 1;
+        }
+        Some(node.value())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self.remaining {
+            Some(remaining) => (remaining, Some(remaining)),
+            None if self.inner.stack.is_empty() => (0, Some(0)),
+            None => (self.inner.stack.len(), None),
+        }
+    }
+}
+
+impl<'a, T> FusedIterator for Iter<'a, T> where T: Node + 'a {}
+
+/// In-order iterator over the nodes themselves (rather than their values).
+pub struct NodeIter<'a, T>
+    where T: Node + 'a
+{
+    stack: Vec<(&'a T, IterAction)>,
+}
+
+impl<'a, T> NodeIter<'a, T>
+    where T: Node + 'a
+{
+    pub fn new(root: Option<&'a T>) -> NodeIter<'a, T> {
+        NodeIter { stack: root.map_or(vec![], |node| vec![(node, IterAction::Left)]) }
+    }
+}
+
+impl<'a, T> Iterator for NodeIter<'a, T>
+    where T: Node + 'a
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
         if let Some((mut subtree, action)) = self.stack.pop() {
             if action == IterAction::Left {
                 while let Some(st) = subtree.left() {
@@ -42,18 +102,20 @@ impl<'a, T> Iterator for Iter<'a, T>
             if let Some(st) = subtree.right() {
                 self.stack.push((&*st, IterAction::Left));
             }
-            Some(subtree.value())
+            Some(subtree)
         } else {
             None
         }
     }
 }
 
+impl<'a, T> FusedIterator for NodeIter<'a, T> where T: Node + 'a {}
+
 pub struct IntoIter<T>
     where T: NodeMut,
           T::NodePtr: Unbox<Target=T>
 {
-    stack: Vec<(T::NodePtr, IterAction)>,
+    inner: NodeIntoIter<T>,
 }
 
 impl<T> IntoIter<T>
@@ -61,7 +123,7 @@ impl<T> IntoIter<T>
           T::NodePtr: Unbox<Target=T>
 {
     pub fn new(root: Option<T::NodePtr>) -> IntoIter<T> {
-        IntoIter { stack: root.map_or(vec![], |node| vec![(node, IterAction::Left)]) }
+        IntoIter { inner: NodeIntoIter::new(root) }
     }
 }
 
@@ -72,55 +134,1250 @@ impl<T> Iterator for IntoIter<T>
     type Item = T::Value;
 
     fn next(&mut self) -> Option<T::Value> {
-        if let Some((mut subtree, action)) = self.stack.pop() {
-            if action == IterAction::Left {
-                while let Some(left) = subtree.detach_left() {
-                    self.stack.push((subtree, IterAction::Right));
-                    subtree = left;
+        self.inner.next().map(|node| node.unbox().into_parts().0)
+    }
+}
+
+impl<T> DoubleEndedIterator for IntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    fn next_back(&mut self) -> Option<T::Value> {
+        self.inner.next_back().map(|node| node.unbox().into_parts().0)
+    }
+}
+
+/// Consumes a tree in-order, yielding its nodes with their children detached.
+/// Unlike `IntoIter`, this does not need to unbox the node pointers, so it
+/// works with any `NodePtr` type; the values can be moved out of the yielded
+/// nodes with `NodeMut::value_mut`.
+pub struct NodeIntoIter<T>
+    where T: NodeMut
+{
+    // the remaining subtrees in-order; the flag is set if the children of the
+    // node have been detached (and are elsewhere in the deque)
+    deque: VecDeque<(T::NodePtr, bool)>,
+}
+
+impl<T> NodeIntoIter<T>
+    where T: NodeMut
+{
+    pub fn new(root: Option<T::NodePtr>) -> NodeIntoIter<T> {
+        NodeIntoIter { deque: root.map(|node| (node, false)).into_iter().collect() }
+    }
+}
+
+impl<T> Iterator for NodeIntoIter<T>
+    where T: NodeMut
+{
+    type Item = T::NodePtr;
+
+    fn next(&mut self) -> Option<T::NodePtr> {
+        let (mut subtree, bare) = self.deque.pop_front()?;
+        if !bare {
+            loop {
+                let (left, right) = subtree.detach_children();
+                if let Some(st) = right {
+                    self.deque.push_front((st, false));
+                }
+                match left {
+                    Some(st) => {
+                        self.deque.push_front((subtree, true));
+                        subtree = st;
+                    }
+                    None => break,
                 }
             }
-            let (value, _, right) = subtree.unbox().into_parts();
-            if let Some(st) = right {
-                self.stack.push((st, IterAction::Left));
+        }
+        Some(subtree)
+    }
+}
+
+impl<T> DoubleEndedIterator for NodeIntoIter<T>
+    where T: NodeMut
+{
+    fn next_back(&mut self) -> Option<T::NodePtr> {
+        let (mut subtree, bare) = self.deque.pop_back()?;
+        if !bare {
+            loop {
+                let (left, right) = subtree.detach_children();
+                if let Some(st) = left {
+                    self.deque.push_back((st, false));
+                }
+                match right {
+                    Some(st) => {
+                        self.deque.push_back((subtree, true));
+                        subtree = st;
+                    }
+                    None => break,
+                }
             }
-            Some(value)
-        } else {
+        }
+        Some(subtree)
+    }
+}
+
+impl<T> Drop for NodeIntoIter<T>
+    where T: NodeMut
+{
+    fn drop(&mut self) {
+        for (node, _) in self.deque.drain(..) {
+            drop_tree::<T>(node);
+        }
+    }
+}
+
+/// Copies consecutive values out of an iterator of references into buffers of
+/// `size` values each. Every buffer is full, except possibly the last one.
+pub struct Chunks<I> {
+    inner: I,
+    size: usize,
+}
+
+impl<I> Chunks<I> {
+    /// Panics if `size` is 0.
+    pub fn new(inner: I, size: usize) -> Chunks<I> {
+        assert!(size != 0, "chunk size must be non-zero");
+        Chunks { inner, size }
+    }
+}
+
+impl<'a, I, V> Iterator for Chunks<I>
+    where I: Iterator<Item = &'a V>,
+          V: Clone + 'a
+{
+    type Item = Vec<V>;
+
+    fn next(&mut self) -> Option<Vec<V>> {
+        let mut chunk = Vec::with_capacity(self.size);
+        chunk.extend(self.inner.by_ref().take(self.size).cloned());
+        if chunk.is_empty() {
             None
+        } else {
+            Some(chunk)
         }
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.inner.size_hint();
+        (lower.div_ceil(self.size), upper.map(|n| n.div_ceil(self.size)))
+    }
 }
 
-impl<T> Drop for IntoIter<T>
-    where T: NodeMut,
+impl<'a, I, V> ExactSizeIterator for Chunks<I>
+    where I: ExactSizeIterator<Item = &'a V>,
+          V: Clone + 'a
+{
+}
+
+impl<'a, I, V> FusedIterator for Chunks<I>
+    where I: FusedIterator<Item = &'a V>,
+          V: Clone + 'a
+{
+}
+
+/// Yields the overlapping pairs of consecutive items of an iterator, i.e. one
+/// pair fewer than there are items.
+pub struct Pairs<I: Iterator> {
+    inner: I,
+    prev: Option<I::Item>,
+}
+
+impl<I: Iterator> Pairs<I> {
+    pub fn new(mut inner: I) -> Pairs<I> {
+        let prev = inner.next();
+        Pairs { inner, prev }
+    }
+}
+
+impl<I> Iterator for Pairs<I>
+    where I: Iterator,
+          I::Item: Clone
+{
+    type Item = (I::Item, I::Item);
+
+    fn next(&mut self) -> Option<(I::Item, I::Item)> {
+        let next = self.inner.next()?;
+        let prev = self.prev.replace(next.clone())?;
+        Some((prev, next))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.prev.is_some() {
+            self.inner.size_hint()
+        } else {
+            (0, Some(0))
+        }
+    }
+}
+
+impl<I> ExactSizeIterator for Pairs<I>
+    where I: ExactSizeIterator,
+          I::Item: Clone
+{
+}
+
+impl<I> FusedIterator for Pairs<I>
+    where I: FusedIterator,
+          I::Item: Clone
+{
+}
+
+/// Yields the overlapping windows of `size` consecutive items of an iterator,
+/// as vectors of the (cloned) items. There are no windows if there are fewer
+/// than `size` items.
+pub struct Windows<I: Iterator> {
+    inner: I,
+    window: VecDeque<I::Item>,
+    size: usize,
+}
+
+impl<I: Iterator> Windows<I> {
+    /// Panics if `size` is 0.
+    pub fn new(mut inner: I, size: usize) -> Windows<I> {
+        assert!(size != 0, "window size must be non-zero");
+        let window = inner.by_ref().take(size - 1).collect();
+        Windows { inner, window, size }
+    }
+}
+
+impl<I> Iterator for Windows<I>
+    where I: Iterator,
+          I::Item: Clone
+{
+    type Item = Vec<I::Item>;
+
+    fn next(&mut self) -> Option<Vec<I::Item>> {
+        if self.window.len() + 1 < self.size {
+            return None;
+        }
+        let item = self.inner.next()?;
+        if self.window.len() == self.size {
+            self.window.pop_front();
+        }
+        self.window.push_back(item);
+        Some(self.window.iter().cloned().collect())
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.window.len() + 1 < self.size {
+            (0, Some(0))
+        } else {
+            self.inner.size_hint()
+        }
+    }
+}
+
+impl<I> ExactSizeIterator for Windows<I>
+    where I: ExactSizeIterator,
+          I::Item: Clone
+{
+}
+
+impl<I> FusedIterator for Windows<I>
+    where I: FusedIterator,
+          I::Item: Clone
+{
+}
+
+/// Removes values from a tree in-order, from either end. When dropped, the
+/// values which were not yielded are put back into the tree.
+///
+/// The shape of the tree put back is not balanced in any way.
+pub struct Drain<'a, T>
+    where T: NodeMut + 'a,
+          T::NodePtr: Unbox<Target=T>
+{
+    root: &'a mut Option<T::NodePtr>,
+    inner: IntoIter<T>,
+}
+
+impl<'a, T> Drain<'a, T>
+    where T: NodeMut + 'a,
+          T::NodePtr: Unbox<Target=T>
+{
+    pub fn new(root: &'a mut Option<T::NodePtr>) -> Drain<'a, T> {
+        let inner = IntoIter::new(root.take());
+        Drain { root, inner }
+    }
+}
+
+impl<'a, T> Iterator for Drain<'a, T>
+    where T: NodeMut + 'a,
+          T::NodePtr: Unbox<Target=T>
+{
+    type Item = T::Value;
+
+    fn next(&mut self) -> Option<T::Value> {
+        self.inner.next()
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Drain<'a, T>
+    where T: NodeMut + 'a,
+          T::NodePtr: Unbox<Target=T>
+{
+    fn next_back(&mut self) -> Option<T::Value> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, T> Drop for Drain<'a, T>
+    where T: NodeMut + 'a,
           T::NodePtr: Unbox<Target=T>
 {
     fn drop(&mut self) {
-        for _ in self {}
+        // join the remaining subtrees back together, in-order
+        let mut tree: Option<T::NodePtr> = None;
+        for (mut node, bare) in self.inner.inner.deque.drain(..) {
+            tree = match tree {
+                None => Some(node),
+                Some(left) if bare => {
+                    node.insert_left(Some(left));
+                    Some(node)
+                }
+                Some(mut left) => {
+                    left.walk_reshape(|_| WalkAction::Right,
+                                      |rightmost| {
+                                          rightmost.insert_right(Some(node));
+                                      },
+                                      |_, _| ());
+                    Some(left)
+                }
+            };
+        }
+        *self.root = tree;
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use NodeMut;
-    use test::TestNode;
-    use super::Iter;
-    use super::IntoIter;
+/// Lending in-order traversal which gives mutable access to one value at a
+/// time, through `next_with`.
+///
+/// The nodes along the path from the root to the current one are detached
+/// from their parents while the traversal is in progress, and attached back
+/// when moving up (or when it is dropped), so no `unsafe` is needed, and the
+/// statistics kept by `insert_left`/`insert_right` stay consistent.
+pub struct StreamingIterMut<'a, T>
+    where T: NodeMut + 'a
+{
+    root: &'a mut T,
+    // the side of `root` from which the first node of `path` is detached
+    root_side: WalkAction,
+    // the nodes below `root` down to the current one, each along with the side
+    // from which the next one is detached (`Stop` for the current node)
+    path: Vec<(T::NodePtr, WalkAction)>,
+    // `None` before the first value, and `Some(false)` after the last one
+    active: Option<bool>,
+}
 
-    #[test]
-    fn iteration() {
-        let mut ct = Box::new(TestNode::new(7));
-        let mut ct_l = Box::new(TestNode::new(8));
-        ct_l.insert_right(Some(Box::new(TestNode::new(12))));
-        ct.insert_left(Some(ct_l));
-        ct.insert_right(Some(Box::new(TestNode::new(5))));
+impl<'a, T> StreamingIterMut<'a, T>
+    where T: NodeMut + 'a
+{
+    pub fn new(root: &'a mut T) -> StreamingIterMut<'a, T> {
+        StreamingIterMut {
+            root,
+            root_side: WalkAction::Stop,
+            path: Vec::new(),
+            active: None,
+        }
+    }
 
-        {
-            let vals: Vec<_> = Iter::new(Some(&*ct)).collect();
-            assert_eq!(vals, [&8, &12, &7, &5]);
+    /// Moves to the next value in-order, and calls `f` with a mutable
+    /// reference to it. Returns `None` once all values have been visited.
+    /// Time complexity: amortized O(1) over a full pass.
+    pub fn next_with<F, R>(&mut self, f: F) -> Option<R>
+        where F: FnOnce(&mut T::Value) -> R
+    {
+        match self.active {
+            None => {
+                self.descend_left();
+                self.active = Some(true);
+            }
+            Some(true) => {
+                if let Some(right) = self.current().detach_right() {
+                    *self.side() = WalkAction::Right;
+                    self.path.push((right, WalkAction::Stop));
+                    self.descend_left();
+                } else if !self.climb() {
+                    self.active = Some(false);
+                }
+            }
+            Some(false) => (),
+        }
+        if self.active == Some(true) {
+            Some(f(self.current().value_mut()))
+        } else {
+            None
         }
+    }
 
-        let node_mi: IntoIter<TestNode<_>> = IntoIter::new(Some(ct));
-        let vals: Vec<_> = node_mi.collect();
-        assert_eq!(vals, [8, 12, 7, 5]);
+    fn current(&mut self) -> &mut T {
+        match self.path.last_mut() {
+            Some(&mut (ref mut node, _)) => node,
+            None => self.root,
+        }
+    }
+
+    fn side(&mut self) -> &mut WalkAction {
+        match self.path.last_mut() {
+            Some(&mut (_, ref mut side)) => side,
+            None => &mut self.root_side,
+        }
+    }
+
+    fn descend_left(&mut self) {
+        while let Some(left) = self.current().detach_left() {
+            *self.side() = WalkAction::Left;
+            self.path.push((left, WalkAction::Stop));
+        }
+    }
+
+    // attaches the current node back to its parent, and so on upwards until
+    // a parent from which a left subtree was detached, which is the next
+    // node in-order; returns `false` if there is no such node
+    fn climb(&mut self) -> bool {
+        while let Some((child, _)) = self.path.pop() {
+            let side = mem::replace(self.side(), WalkAction::Stop);
+            match side {
+                WalkAction::Left => {
+                    self.current().insert_left(Some(child));
+                    return true;
+                }
+                WalkAction::Right => {
+                    self.current().insert_right(Some(child));
+                }
+                WalkAction::Stop => unreachable!(),
+            }
+        }
+        false
+    }
+}
+
+impl<'a, T> Drop for StreamingIterMut<'a, T>
+    where T: NodeMut + 'a
+{
+    fn drop(&mut self) {
+        while self.climb() {}
+    }
+}
+
+/// A cursor which moves over the values of a tree in-order, in either
+/// direction.
+///
+/// Besides the nodes, the cursor has a "ghost" position between the last and
+/// the first node, where it starts. Moving forward from the last node, or
+/// backward from the first one, leads to the ghost position, and moving on
+/// from there wraps around to the other end.
+pub struct Cursor<'a, T>
+    where T: Node + 'a
+{
+    root: Option<&'a T>,
+    // the path from the root to the current node; empty at the ghost position
+    stack: Vec<&'a T>,
+}
+
+impl<'a, T> Clone for Cursor<'a, T>
+    where T: Node + 'a
+{
+    fn clone(&self) -> Self {
+        Cursor {
+            root: self.root,
+            stack: self.stack.clone(),
+        }
+    }
+}
+
+impl<'a, T> Cursor<'a, T>
+    where T: Node + 'a
+{
+    /// Creates a cursor at the ghost position.
+    pub fn new(root: Option<&'a T>) -> Cursor<'a, T> {
+        Cursor {
+            root,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Returns the value at the cursor, or `None` at the ghost position.
+    pub fn peek(&self) -> Option<&'a T::Value> {
+        self.stack.last().map(|node| node.value())
+    }
+
+    /// Returns the node at the cursor, or `None` at the ghost position.
+    pub fn peek_node(&self) -> Option<&'a T> {
+        self.stack.last().cloned()
+    }
+
+    /// Moves the cursor to the ghost position.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+    }
+
+    /// Moves the cursor to the first value. Time complexity: O(log(n)) for a
+    /// balanced tree.
+    pub fn seek_to_start(&mut self) {
+        self.stack.clear();
+        self.descend(self.root, T::left);
+    }
+
+    /// Moves the cursor to the last value. Time complexity: O(log(n)) for a
+    /// balanced tree.
+    pub fn seek_to_end(&mut self) {
+        self.stack.clear();
+        self.descend(self.root, T::right);
+    }
+
+    /// Walks down from the root as guided by `step_in`, and moves the cursor
+    /// to the node for which it returns `Stop`. If the walk leads to a
+    /// missing child, the cursor moves to the ghost position instead. Returns
+    /// the value at the new position. Time complexity: O(log(n)) for a
+    /// balanced tree.
+    pub fn seek<F>(&mut self, mut step_in: F) -> Option<&'a T::Value>
+        where F: FnMut(&'a T) -> WalkAction
+    {
+        self.stack.clear();
+        let mut node = self.root;
+        while let Some(n) = node {
+            self.stack.push(n);
+            node = match step_in(n) {
+                WalkAction::Left => n.left(),
+                WalkAction::Right => n.right(),
+                WalkAction::Stop => return self.peek(),
+            };
+        }
+        self.stack.clear();
+        None
+    }
+
+    /// Moves the cursor backward, and returns the value it moved to. Moving
+    /// backward from the ghost position leads to the last value. Time
+    /// complexity: amortized O(1) over a full pass.
+    pub fn prev(&mut self) -> Option<&'a T::Value> {
+        self.step(T::right, T::left)
+    }
+
+    // pushes `node` and then its descendants along `toward`
+    fn descend(&mut self, mut node: Option<&'a T>, toward: fn(&'a T) -> Option<&'a T>) {
+        while let Some(n) = node {
+            self.stack.push(n);
+            node = toward(n);
+        }
+    }
+
+    // moves to the in-order neighbour in the direction of `forward`
+    fn step(&mut self,
+            backward: fn(&'a T) -> Option<&'a T>,
+            forward: fn(&'a T) -> Option<&'a T>)
+            -> Option<&'a T::Value> {
+        let current = match self.stack.last() {
+            Some(&node) => node,
+            None => {
+                self.descend(self.root, backward);
+                return self.peek();
+            }
+        };
+        if let Some(next) = forward(current) {
+            self.descend(Some(next), backward);
+            return self.peek();
+        }
+        // climb up until we leave a subtree which lies behind its parent
+        let mut child = self.stack.pop().unwrap();
+        while let Some(&parent) = self.stack.last() {
+            if backward(parent).is_some_and(|node| ptr::eq(node, child)) {
+                return self.peek();
+            }
+            child = self.stack.pop().unwrap();
+        }
+        None
+    }
+}
+
+impl<'a, T> Iterator for Cursor<'a, T>
+    where T: Node + 'a
+{
+    type Item = &'a T::Value;
+
+    /// Moves the cursor forward, and returns the value it moved to. Moving
+    /// forward from the ghost position leads to the first value. Time
+    /// complexity: amortized O(1) over a full pass.
+    fn next(&mut self) -> Option<&'a T::Value> {
+        self.step(T::left, T::right)
+    }
+}
+
+pub struct PreOrderIter<'a, T>
+    where T: Node + 'a
+{
+    stack: Vec<&'a T>,
+}
+
+impl<'a, T> PreOrderIter<'a, T>
+    where T: Node + 'a
+{
+    pub fn new(root: Option<&'a T>) -> PreOrderIter<'a, T> {
+        PreOrderIter { stack: root.into_iter().collect() }
+    }
+}
+
+impl<'a, T> Iterator for PreOrderIter<'a, T>
+    where T: Node + 'a
+{
+    type Item = &'a T::Value;
+
+    fn next(&mut self) -> Option<&'a T::Value> {
+        self.stack.pop().map(|node| {
+            self.stack.extend(node.right());
+            self.stack.extend(node.left());
+            node.value()
+        })
+    }
+}
+
+pub struct PostOrderIter<'a, T>
+    where T: Node + 'a
+{
+    // the flag is set if the children of the node are already on the stack
+    stack: Vec<(&'a T, bool)>,
+}
+
+impl<'a, T> PostOrderIter<'a, T>
+    where T: Node + 'a
+{
+    pub fn new(root: Option<&'a T>) -> PostOrderIter<'a, T> {
+        PostOrderIter { stack: root.map_or(vec![], |node| vec![(node, false)]) }
+    }
+}
+
+impl<'a, T> Iterator for PostOrderIter<'a, T>
+    where T: Node + 'a
+{
+    type Item = &'a T::Value;
+
+    fn next(&mut self) -> Option<&'a T::Value> {
+        while let Some((node, expanded)) = self.stack.pop() {
+            if expanded {
+                return Some(node.value());
+            }
+            self.stack.push((node, true));
+            if let Some(right) = node.right() {
+                self.stack.push((right, false));
+            }
+            if let Some(left) = node.left() {
+                self.stack.push((left, false));
+            }
+        }
+        None
+    }
+}
+
+pub struct LevelOrderIter<'a, T>
+    where T: Node + 'a
+{
+    queue: VecDeque<&'a T>,
+}
+
+impl<'a, T> LevelOrderIter<'a, T>
+    where T: Node + 'a
+{
+    pub fn new(root: Option<&'a T>) -> LevelOrderIter<'a, T> {
+        LevelOrderIter { queue: root.into_iter().collect() }
+    }
+}
+
+impl<'a, T> Iterator for LevelOrderIter<'a, T>
+    where T: Node + 'a
+{
+    type Item = &'a T::Value;
+
+    fn next(&mut self) -> Option<&'a T::Value> {
+        self.queue.pop_front().map(|node| {
+            self.queue.extend(node.left());
+            self.queue.extend(node.right());
+            node.value()
+        })
+    }
+}
+
+pub struct LevelOrderDepthIter<'a, T>
+    where T: Node + 'a
+{
+    queue: VecDeque<(&'a T, usize)>,
+}
+
+impl<'a, T> LevelOrderDepthIter<'a, T>
+    where T: Node + 'a
+{
+    pub fn new(root: Option<&'a T>) -> LevelOrderDepthIter<'a, T> {
+        LevelOrderDepthIter { queue: root.map(|node| (node, 0)).into_iter().collect() }
+    }
+}
+
+impl<'a, T> Iterator for LevelOrderDepthIter<'a, T>
+    where T: Node + 'a
+{
+    type Item = (usize, &'a T::Value);
+
+    fn next(&mut self) -> Option<(usize, &'a T::Value)> {
+        self.queue.pop_front().map(|(node, depth)| {
+            self.queue.extend(node.left().map(|n| (n, depth + 1)));
+            self.queue.extend(node.right().map(|n| (n, depth + 1)));
+            (depth, node.value())
+        })
+    }
+}
+
+pub struct PreOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    stack: Vec<T::NodePtr>,
+}
+
+impl<T> PreOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    pub fn new(root: Option<T::NodePtr>) -> PreOrderIntoIter<T> {
+        PreOrderIntoIter { stack: root.into_iter().collect() }
+    }
+}
+
+impl<T> Iterator for PreOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    type Item = T::Value;
+
+    fn next(&mut self) -> Option<T::Value> {
+        self.stack.pop().map(|node| {
+            let (value, left, right) = node.unbox().into_parts();
+            self.stack.extend(right);
+            self.stack.extend(left);
+            value
+        })
+    }
+}
+
+impl<T> Drop for PreOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    fn drop(&mut self) {
+        for node in self.stack.drain(..) {
+            drop_tree::<T>(node);
+        }
+    }
+}
+
+/// Consumes a tree in post-order. Children are always yielded before their
+/// parent, and so are they dropped, even if the iterator is dropped early.
+pub struct PostOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    stack: Vec<T::NodePtr>,
+}
+
+impl<T> PostOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    pub fn new(root: Option<T::NodePtr>) -> PostOrderIntoIter<T> {
+        PostOrderIntoIter { stack: root.into_iter().collect() }
+    }
+}
+
+impl<T> Iterator for PostOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    type Item = T::Value;
+
+    fn next(&mut self) -> Option<T::Value> {
+        while let Some(mut node) = self.stack.pop() {
+            let (left, right) = node.detach_children();
+            if left.is_none() && right.is_none() {
+                let (value, _, _) = node.unbox().into_parts();
+                return Some(value);
+            }
+            // revisit the node once its children are gone
+            self.stack.push(node);
+            self.stack.extend(right);
+            self.stack.extend(left);
+        }
+        None
+    }
+}
+
+impl<T> Drop for PostOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    fn drop(&mut self) {
+        // unlike `drop_tree`, this keeps the children-first drop order
+        for _ in self.by_ref() {}
+    }
+}
+
+pub struct LevelOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    queue: VecDeque<T::NodePtr>,
+}
+
+impl<T> LevelOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    pub fn new(root: Option<T::NodePtr>) -> LevelOrderIntoIter<T> {
+        LevelOrderIntoIter { queue: root.into_iter().collect() }
+    }
+}
+
+impl<T> Iterator for LevelOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    type Item = T::Value;
+
+    fn next(&mut self) -> Option<T::Value> {
+        self.queue.pop_front().map(|node| {
+            let (value, left, right) = node.unbox().into_parts();
+            self.queue.extend(left);
+            self.queue.extend(right);
+            value
+        })
+    }
+}
+
+impl<T> Drop for LevelOrderIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    fn drop(&mut self) {
+        for node in self.queue.drain(..) {
+            drop_tree::<T>(node);
+        }
+    }
+}
+
+/// Same as `LevelOrderIntoIter`, but also yields the depth of each value (the
+/// root being at depth 0), so that consumers can tell where each level starts.
+pub struct LevelOrderDepthIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    queue: VecDeque<(T::NodePtr, usize)>,
+}
+
+impl<T> LevelOrderDepthIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    pub fn new(root: Option<T::NodePtr>) -> LevelOrderDepthIntoIter<T> {
+        LevelOrderDepthIntoIter { queue: root.map(|node| (node, 0)).into_iter().collect() }
+    }
+}
+
+impl<T> Iterator for LevelOrderDepthIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    type Item = (usize, T::Value);
+
+    fn next(&mut self) -> Option<(usize, T::Value)> {
+        self.queue.pop_front().map(|(node, depth)| {
+            let (value, left, right) = node.unbox().into_parts();
+            self.queue.extend(left.map(|n| (n, depth + 1)));
+            self.queue.extend(right.map(|n| (n, depth + 1)));
+            (depth, value)
+        })
+    }
+}
+
+impl<T> Drop for LevelOrderDepthIntoIter<T>
+    where T: NodeMut,
+          T::NodePtr: Unbox<Target=T>
+{
+    fn drop(&mut self) {
+        for (node, _) in self.queue.drain(..) {
+            drop_tree::<T>(node);
+        }
+    }
+}
+
+/// Event emitted by `Traverse`.
+pub enum TraverseEvent<'a, T: 'a> {
+    /// The node is entered, before any of its descendants.
+    Enter(&'a T),
+    /// The node is left, after all of its descendants.
+    Leave(&'a T),
+}
+
+/// Depth-first traversal which emits an event when entering and leaving each
+/// node. The left subtree is traversed before the right one.
+pub struct Traverse<'a, T>
+    where T: Node + 'a
+{
+    // the flag is set if the node has been entered
+    stack: Vec<(&'a T, bool)>,
+}
+
+impl<'a, T> Traverse<'a, T>
+    where T: Node + 'a
+{
+    pub fn new(root: Option<&'a T>) -> Traverse<'a, T> {
+        Traverse { stack: root.map_or(vec![], |node| vec![(node, false)]) }
+    }
+}
+
+impl<'a, T> Iterator for Traverse<'a, T>
+    where T: Node + 'a
+{
+    type Item = TraverseEvent<'a, T>;
+
+    fn next(&mut self) -> Option<TraverseEvent<'a, T>> {
+        self.stack.pop().map(|(node, entered)| {
+            if entered {
+                TraverseEvent::Leave(node)
+            } else {
+                self.stack.push((node, true));
+                if let Some(right) = node.right() {
+                    self.stack.push((right, false));
+                }
+                if let Some(left) = node.left() {
+                    self.stack.push((left, false));
+                }
+                TraverseEvent::Enter(node)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Node;
+    use NodeMut;
+    use test::TestNode;
+    use super::{Iter, NodeIter};
+    use super::{IntoIter, NodeIntoIter, Drain};
+    use super::Cursor;
+    use super::StreamingIterMut;
+    use super::Chunks;
+    use super::{PreOrderIter, PostOrderIter, LevelOrderIter, LevelOrderDepthIter};
+    use super::{PreOrderIntoIter, PostOrderIntoIter, LevelOrderIntoIter, LevelOrderDepthIntoIter};
+    use super::{Traverse, TraverseEvent};
+
+    #[test]
+    fn iteration() {
+        let mut ct = Box::new(TestNode::new(7));
+        let mut ct_l = Box::new(TestNode::new(8));
+        ct_l.insert_right(Some(Box::new(TestNode::new(12))));
+        ct.insert_left(Some(ct_l));
+        ct.insert_right(Some(Box::new(TestNode::new(5))));
+
+        {
+            let vals: Vec<_> = Iter::new(Some(&*ct)).collect();
+            assert_eq!(vals, [&8, &12, &7, &5]);
+        }
+
+        let node_mi: IntoIter<TestNode<_>> = IntoIter::new(Some(ct));
+        let vals: Vec<_> = node_mi.collect();
+        assert_eq!(vals, [8, 12, 7, 5]);
+
+        let node_mi: IntoIter<TestNode<_>> = IntoIter::new(Some(test_tree()));
+        assert_eq!(node_mi.rev().collect::<Vec<_>>(), [9, 5, 7, 12, 8, 3]);
+        let mut node_mi: IntoIter<TestNode<_>> = IntoIter::new(Some(test_tree()));
+        assert_eq!(node_mi.next_back(), Some(9));
+        assert_eq!(node_mi.next(), Some(3));
+        assert_eq!(node_mi.next_back(), Some(5));
+        assert_eq!(node_mi.next(), Some(8));
+        assert_eq!(node_mi.next(), Some(12));
+        assert_eq!(node_mi.next_back(), Some(7));
+        assert_eq!(node_mi.next_back(), None);
+        assert_eq!(node_mi.next(), None);
+    }
+
+    //         7
+    //       /   \
+    //      8     5
+    //     / \     \
+    //    3  12    9
+    fn test_tree() -> Box<TestNode<u32>> {
+        let mut ct = Box::new(TestNode::new(7));
+        let mut ct_l = Box::new(TestNode::new(8));
+        ct_l.insert_left(Some(Box::new(TestNode::new(3))));
+        ct_l.insert_right(Some(Box::new(TestNode::new(12))));
+        let mut ct_r = Box::new(TestNode::new(5));
+        ct_r.insert_right(Some(Box::new(TestNode::new(9))));
+        ct.insert_left(Some(ct_l));
+        ct.insert_right(Some(ct_r));
+        ct
+    }
+
+    #[test]
+    fn drain() {
+        let mut root = Some(test_tree());
+        {
+            let mut drain: Drain<TestNode<_>> = Drain::new(&mut root);
+            assert_eq!(drain.next(), Some(3));
+            assert_eq!(drain.next_back(), Some(9));
+            assert_eq!(drain.next(), Some(8));
+        }
+        assert_eq!(Iter::new(root.as_deref()).cloned().collect::<Vec<_>>(), [12, 7, 5]);
+        {
+            let mut drain: Drain<TestNode<_>> = Drain::new(&mut root);
+            assert_eq!(drain.next_back(), Some(5));
+        }
+        assert_eq!(Iter::new(root.as_deref()).cloned().collect::<Vec<_>>(), [12, 7]);
+        let drain: Drain<TestNode<_>> = Drain::new(&mut root);
+        assert_eq!(drain.collect::<Vec<_>>(), [12, 7]);
+        assert!(root.is_none());
+    }
+
+    #[test]
+    fn other_orders() {
+        let ct = test_tree();
+        let vals: Vec<_> = PreOrderIter::new(Some(&*ct)).cloned().collect();
+        assert_eq!(vals, [7, 8, 3, 12, 5, 9]);
+        let vals: Vec<_> = PostOrderIter::new(Some(&*ct)).cloned().collect();
+        assert_eq!(vals, [3, 12, 8, 9, 5, 7]);
+        let vals: Vec<_> = LevelOrderIter::new(Some(&*ct)).cloned().collect();
+        assert_eq!(vals, [7, 8, 5, 3, 12, 9]);
+        let vals: Vec<_> = LevelOrderDepthIter::new(Some(&*ct)).map(|(d, &v)| (d, v)).collect();
+        assert_eq!(vals, [(0, 7), (1, 8), (1, 5), (2, 3), (2, 12), (2, 9)]);
+        assert_eq!(PreOrderIter::<TestNode<u32>>::new(None).next(), None);
+
+        let iter: PreOrderIntoIter<TestNode<_>> = PreOrderIntoIter::new(Some(test_tree()));
+        assert_eq!(iter.collect::<Vec<_>>(), [7, 8, 3, 12, 5, 9]);
+        let iter: PostOrderIntoIter<TestNode<_>> = PostOrderIntoIter::new(Some(test_tree()));
+        assert_eq!(iter.collect::<Vec<_>>(), [3, 12, 8, 9, 5, 7]);
+        let iter: LevelOrderIntoIter<TestNode<_>> = LevelOrderIntoIter::new(Some(test_tree()));
+        assert_eq!(iter.collect::<Vec<_>>(), [7, 8, 5, 3, 12, 9]);
+    }
+
+    #[test]
+    fn level_order_depth_into_iter() {
+        type DepthIntoIter = LevelOrderDepthIntoIter<TestNode<u32>>;
+
+        let iter = DepthIntoIter::new(Some(test_tree()));
+        assert_eq!(iter.collect::<Vec<_>>(), [(0, 7), (1, 8), (1, 5), (2, 3), (2, 12), (2, 9)]);
+
+        // the unvisited nodes are dropped along with the iterator
+        let mut iter = DepthIntoIter::new(Some(test_tree()));
+        assert_eq!(iter.nth(2), Some((1, 5)));
+        assert_eq!(iter.next(), Some((2, 3)));
+        drop(iter);
+
+        // a right spine has one node per level
+        let mut spine = Box::new(TestNode::new(0));
+        for i in 1..1000 {
+            let mut node = Box::new(TestNode::new(i));
+            node.insert_right(Some(spine));
+            spine = node;
+        }
+        let iter = DepthIntoIter::new(Some(spine));
+        assert!(iter.enumerate().all(|(i, (depth, val))| depth == i && val as usize == 999 - i));
+
+        let mut iter = DepthIntoIter::new(None);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn node_into_iter() {
+        let mut nodes: NodeIntoIter<TestNode<_>> = NodeIntoIter::new(Some(test_tree()));
+        let last = nodes.next_back().unwrap();
+        assert!(last.left().is_none() && last.right().is_none());
+        let vals: Vec<_> = nodes.map(|mut node| ::std::mem::take(node.value_mut())).collect();
+        assert_eq!((vals, last.val), (vec![3, 8, 12, 7, 5], 9));
+
+        // every node is yielded detached from its children
+        let mut nodes: NodeIntoIter<TestNode<_>> = NodeIntoIter::new(Some(test_tree()));
+        assert!(nodes.all(|node| node.left().is_none() && node.right().is_none()));
+
+        // dropping a partially consumed iterator drops the remaining nodes
+        let mut nodes: NodeIntoIter<TestNode<_>> = NodeIntoIter::new(Some(test_tree()));
+        assert_eq!(nodes.next().map(|node| node.val), Some(3));
+        assert_eq!(nodes.next_back().map(|node| node.val), Some(9));
+        drop(nodes);
+
+        let mut nodes: NodeIntoIter<TestNode<u32>> = NodeIntoIter::new(None);
+        assert!(nodes.next().is_none());
+        assert!(nodes.next_back().is_none());
+    }
+
+    #[test]
+    fn size_hint() {
+        let ct = test_tree();
+        let mut iter = Iter::with_len(Some(&*ct), 6);
+        for remaining in (0..7).rev() {
+            assert_eq!(iter.size_hint(), (remaining, Some(remaining)));
+            iter.next();
+        }
+        assert_eq!(iter.next(), None);
+
+        // without a length, only the subtrees yet to be visited are counted
+        let mut iter = Iter::new(Some(&*ct));
+        for remaining in (1..7).rev() {
+            let (lower, upper) = iter.size_hint();
+            assert!((1..=remaining).contains(&lower));
+            assert_eq!(upper, None);
+            iter.next();
+        }
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+        assert_eq!(iter.next(), None);
+
+        assert_eq!(Iter::<TestNode<u32>>::new(None).size_hint(), (0, Some(0)));
+        assert_eq!(Iter::<TestNode<u32>>::with_len(None, 0).size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn node_iter() {
+        let ct = test_tree();
+        let shape: Vec<_> = NodeIter::new(Some(&*ct))
+                                .map(|node| (node.val, node.left().is_none(),
+                                             node.right().is_none()))
+                                .collect();
+        assert_eq!(shape, [(3, true, true), (8, false, false), (12, true, true),
+                           (7, false, false), (5, true, false), (9, true, true)]);
+
+        let leaf = TestNode::new(1);
+        let mut iter = NodeIter::new(Some(&leaf));
+        assert_eq!(iter.next().map(|node| node.val), Some(1));
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+
+        let mut iter = NodeIter::<TestNode<u32>>::new(None);
+        assert!(iter.next().is_none());
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn traverse() {
+        let ct = test_tree();
+        let mut depth = 0;
+        let mut max_depth = 0;
+        let mut rendered = String::new();
+        for event in Traverse::new(Some(&*ct)) {
+            match event {
+                TraverseEvent::Enter(node) => {
+                    depth += 1;
+                    max_depth = ::std::cmp::max(depth, max_depth);
+                    rendered.push_str(&format!("({}", node.val));
+                }
+                TraverseEvent::Leave(_) => {
+                    depth -= 1;
+                    rendered.push(')');
+                }
+            }
+        }
+        assert_eq!(depth, 0);
+        assert_eq!(max_depth, 3);
+        assert_eq!(rendered, "(7(8(3)(12))(5(9)))");
+    }
+
+    #[test]
+    fn cursor() {
+        let mut ct = Box::new(TestNode::new(7));
+        let mut ct_l = Box::new(TestNode::new(8));
+        ct_l.insert_right(Some(Box::new(TestNode::new(12))));
+        ct.insert_left(Some(ct_l));
+        ct.insert_right(Some(Box::new(TestNode::new(5))));
+
+        let mut cursor = Cursor::new(Some(&*ct));
+        assert_eq!(cursor.peek(), None);
+        assert_eq!(cursor.clone().collect::<Vec<_>>(), [&8, &12, &7, &5]);
+        assert_eq!(cursor.prev(), Some(&5));
+        assert_eq!(cursor.prev(), Some(&7));
+        assert_eq!(cursor.prev(), Some(&12));
+        assert_eq!(cursor.next(), Some(&7));
+        assert_eq!(cursor.peek_node().map(|node| node.left().is_some()), Some(true));
+        cursor.seek_to_start();
+        assert_eq!(cursor.peek(), Some(&8));
+        assert_eq!(cursor.prev(), None);
+        assert_eq!(cursor.prev(), Some(&5));
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.next(), Some(&8));
+        cursor.seek_to_end();
+        assert_eq!(cursor.peek(), Some(&5));
+        cursor.reset();
+        assert_eq!(cursor.peek(), None);
+
+        let mut cursor = Cursor::<TestNode<i32>>::new(None);
+        cursor.seek_to_start();
+        assert_eq!((cursor.next(), cursor.prev(), cursor.peek()), (None, None, None));
+    }
+
+    #[test]
+    fn chunks() {
+        let ct = test_tree();
+        let mut chunks = Chunks::new(Iter::with_len(Some(&*ct), 6), 4);
+        assert_eq!(chunks.size_hint(), (2, Some(2)));
+        assert_eq!(chunks.next(), Some(vec![3, 8, 12, 7]));
+        assert_eq!(chunks.next(), Some(vec![5, 9]));
+        assert_eq!(chunks.next(), None);
+        let chunks = Chunks::new(Iter::new(Some(&*ct)), 3);
+        assert_eq!(chunks.collect::<Vec<_>>(), [[3, 8, 12], [7, 5, 9]]);
+        assert_eq!(Chunks::new(Iter::<TestNode<u32>>::new(None), 2).next(), None);
+    }
+
+    #[test]
+    fn post_order_drop() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        struct Noisy(u32, Rc<RefCell<Vec<u32>>>);
+
+        impl Drop for Noisy {
+            fn drop(&mut self) {
+                self.1.borrow_mut().push(self.0);
+            }
+        }
+
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let noisy = |val| Box::new(TestNode::new(Noisy(val, log.clone())));
+        let mut ct = noisy(7);
+        let mut ct_l = noisy(8);
+        ct_l.insert_left(Some(noisy(3)));
+        ct_l.insert_right(Some(noisy(12)));
+        ct.insert_left(Some(ct_l));
+        ct.insert_right(Some(noisy(5)));
+
+        let mut iter: PostOrderIntoIter<TestNode<_>> = PostOrderIntoIter::new(Some(ct));
+        assert_eq!(iter.next().map(|noisy| noisy.0), Some(3));
+        drop(iter);
+        assert_eq!(*log.borrow(), [3, 12, 8, 5, 7]);
+    }
+
+    #[test]
+    fn streaming_iter_mut() {
+        let mut ct = test_tree();
+        {
+            let mut iter = StreamingIterMut::new(&mut *ct);
+            let mut i = 0;
+            let mut olds = vec![];
+            while let Some(old) = iter.next_with(|v| {
+                i += 1;
+                ::std::mem::replace(v, i)
+            }) {
+                olds.push(old);
+            }
+            assert_eq!(olds, [3, 8, 12, 7, 5, 9]);
+            assert_eq!(iter.next_with(|_| ()), None);
+        }
+        assert_eq!(PreOrderIter::new(Some(&*ct)).cloned().collect::<Vec<_>>(), [4, 2, 1, 3, 5, 6]);
+        {
+            // stopping early puts the tree back together
+            let mut iter = StreamingIterMut::new(&mut *ct);
+            for _ in 0..3 {
+                iter.next_with(|v| *v *= 10);
+            }
+        }
+        assert_eq!(PreOrderIter::new(Some(&*ct)).cloned().collect::<Vec<_>>(), [4, 20, 10, 30, 5, 6]);
+        let mut leaf = TestNode::new(1);
+        let mut iter = StreamingIterMut::new(&mut leaf);
+        assert_eq!((iter.next_with(|v| *v), iter.next_with(|v| *v)), (Some(1), None));
     }
 }
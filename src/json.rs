@@ -0,0 +1,71 @@
+//! Nested JSON import and export (requires the `json` feature).
+//!
+//! Each node is represented as an object with the keys `value`, `left` and
+//! `right`, where a missing child is `null`:
+//!
+//! ```json
+//! {"value": 20, "left": {"value": 10, "left": null, "right": null}, "right": null}
+//! ```
+//!
+//! Note that both directions recurse once per level of the tree, and that
+//! `serde_json` refuses to parse documents nested deeper than 128 levels.
+
+use serde::de::DeserializeOwned;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use serde_json;
+
+use Node;
+use test::TestNode;
+
+/// Wrapper which serializes the tree rooted at a `Node` as nested maps with
+/// the keys `value`, `left` and `right`, in any serde format.
+pub struct SerializeTree<'a, N: 'a>(pub &'a N);
+
+impl<'a, N> Serialize for SerializeTree<'a, N>
+    where N: Node,
+          N::Value: Serialize
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut node = serializer.serialize_struct("Node", 3)?;
+        node.serialize_field("value", self.0.value())?;
+        node.serialize_field("left", &self.0.left().map(SerializeTree))?;
+        node.serialize_field("right", &self.0.right().map(SerializeTree))?;
+        node.end()
+    }
+}
+
+/// Dumps the tree rooted at `root` as nested JSON.
+pub fn to_json<N>(root: &N) -> serde_json::Result<String>
+    where N: Node,
+          N::Value: Serialize
+{
+    serde_json::to_string(&SerializeTree(root))
+}
+
+/// Parses nested JSON (as written by `to_json`) into a `TestNode` tree.
+pub fn from_json<T: DeserializeOwned>(json: &str) -> serde_json::Result<TestNode<T>> {
+    serde_json::from_str(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use algos::tree_eq;
+    use count::CountTree;
+    use test::TestNode;
+    use super::{to_json, from_json};
+
+    #[test]
+    fn round_trip() {
+        let mut ct: CountTree<_> = (0..3).collect();
+        let json = to_json(ct.root().unwrap()).unwrap();
+        assert_eq!(json, concat!(r#"{"value":1,"left":{"value":0,"left":null,"right":null},"#,
+                                 r#""right":{"value":2,"left":null,"right":null}}"#));
+        let tn: TestNode<i32> = from_json(&json).unwrap();
+        assert!(tree_eq(ct.root().unwrap(), &tn));
+        assert_eq!(to_json(&tn).unwrap(), json);
+
+        let tn: TestNode<String> = from_json(r#"{"value":"a","right":{"value":"b","left":null,"right":null},"left":null}"#).unwrap();
+        assert_eq!((tn.val.as_str(), tn.left.is_none(), tn.right.unwrap().val), ("a", true, "b".to_string()));
+        assert!(from_json::<u8>(r#"{"value":300,"left":null,"right":null}"#).is_err());
+    }
+}
@@ -0,0 +1,507 @@
+//! Lists supporting updates of whole ranges in O(log(n)) time.
+//!
+//! `LazyTree` is a list like `CountTree`, in which each node also holds a
+//! pending update, or "lazy tag", to be applied to all of its descendants.
+//! `apply_range` tags O(log(n)) subtrees covering the range instead of
+//! visiting every element, and the tags are pushed down to the children
+//! whenever a node is accessed or restructured. Adding a delta to the offsets
+//! of a whole suffix of lines (as in a text editor) is thus as cheap as a
+//! single lookup.
+//!
+//! Updates are user-defined through the `Update` trait; `Add` is provided for
+//! the common case of shifting numbers.
+//!
+//! ```
+//! use binary_tree::lazy::{Add, LazyTree};
+//!
+//! let mut offsets: LazyTree<u32, Add<u32>> = vec![0, 10, 25, 40].into_iter().collect();
+//! // five characters inserted on the second line
+//! offsets.apply_range(2..4, Add(5));
+//! assert_eq!(offsets.iter().cloned().collect::<Vec<_>>(), [0, 10, 30, 45]);
+//! ```
+
+use std::cmp;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::{AddAssign, Range};
+
+use Node;
+use NodeMut;
+use avl::{self, Balanced};
+use iter::{IntoIter, Iter};
+
+/// An update which can be applied to values of type `T`, and composed with
+/// other updates of the same type.
+pub trait Update<T>: Clone {
+    /// Applies the update to `value`.
+    fn apply(&self, value: &mut T);
+
+    /// Composes `later` into `self`, so that applying `self` afterwards has
+    /// the same effect as applying the old `self` followed by `later`.
+    fn compose(&mut self, later: &Self);
+}
+
+/// Update adding a constant to each value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Add<T>(pub T);
+
+impl<T> Update<T> for Add<T>
+    where T: AddAssign + Clone
+{
+    fn apply(&self, value: &mut T) {
+        *value += self.0.clone();
+    }
+
+    fn compose(&mut self, later: &Self) {
+        self.0 += later.0.clone();
+    }
+}
+
+/// Node of a `LazyTree`.
+///
+/// The value of a node is always up to date, but those of its descendants
+/// are not until the pending update has been pushed down, which happens when
+/// its children are detached or replaced. Hence `LazyTree` does not expose
+/// its nodes.
+pub struct LazyNode<T, U: Update<T>> {
+    val: T,
+    left: Option<Box<LazyNode<T, U>>>,
+    right: Option<Box<LazyNode<T, U>>>,
+    pending: Option<U>,
+    count: u32,
+    height: u16,
+}
+
+impl<T, U: Update<T>> LazyNode<T, U> {
+    fn new(val: T) -> LazyNode<T, U> {
+        LazyNode {
+            val,
+            left: None,
+            right: None,
+            pending: None,
+            count: 1,
+            height: 0,
+        }
+    }
+
+    // Applies `update` to the whole subtree, lazily.
+    fn tag(&mut self, update: &U) {
+        update.apply(&mut self.val);
+        if self.count > 1 {
+            match self.pending {
+                Some(ref mut pending) => pending.compose(update),
+                None => self.pending = Some(update.clone()),
+            }
+        }
+    }
+
+    fn push_down(&mut self) {
+        if let Some(pending) = self.pending.take() {
+            if let Some(ref mut left) = self.left {
+                left.tag(&pending);
+            }
+            if let Some(ref mut right) = self.right {
+                right.tag(&pending);
+            }
+        }
+    }
+
+    // Applies `update` to the elements `start..end` of this subtree, which is
+    // a non-empty range. Recursion depth is O(height).
+    fn apply_range(&mut self, start: u32, end: u32, update: &U) {
+        if start == 0 && end == self.count {
+            self.tag(update);
+            return;
+        }
+        self.push_down();
+        let lcount = avl::lcount(self);
+        if start < lcount {
+            self.left.as_mut().unwrap().apply_range(start, cmp::min(end, lcount), update);
+        }
+        if start <= lcount && lcount < end {
+            update.apply(&mut self.val);
+        }
+        if end > lcount + 1 {
+            let rstart = start.saturating_sub(lcount + 1);
+            self.right.as_mut().unwrap().apply_range(rstart, end - lcount - 1, update);
+        }
+    }
+
+    fn update_stats(&mut self) {
+        let (count, height) = avl::stats(self.left.as_deref(), self.right.as_deref());
+        self.count = count;
+        self.height = height;
+    }
+}
+
+impl<T, U: Update<T>> Balanced for LazyNode<T, U> {
+    fn count(&self) -> u32 {
+        self.count
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+impl<T, U: Update<T>> Node for LazyNode<T, U> {
+    type Value = T;
+
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    fn value(&self) -> &T {
+        &self.val
+    }
+}
+
+impl<T, U: Update<T>> NodeMut for LazyNode<T, U> {
+    type NodePtr = Box<LazyNode<T, U>>;
+
+    fn detach_left(&mut self) -> Option<Self::NodePtr> {
+        self.push_down();
+        let tree = self.left.take();
+        self.update_stats();
+        tree
+    }
+
+    fn detach_right(&mut self) -> Option<Self::NodePtr> {
+        self.push_down();
+        let tree = self.right.take();
+        self.update_stats();
+        tree
+    }
+
+    fn insert_left(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        self.push_down();
+        mem::swap(&mut self.left, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn insert_right(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        self.push_down();
+        mem::swap(&mut self.right, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn value_mut(&mut self) -> &mut T {
+        &mut self.val
+    }
+
+    fn into_parts(mut self) -> (T, Option<Self::NodePtr>, Option<Self::NodePtr>) {
+        self.push_down();
+        (self.val, self.left, self.right)
+    }
+
+    fn left_mut(&mut self) -> Option<&mut Self> {
+        self.push_down();
+        self.left.as_deref_mut()
+    }
+
+    fn right_mut(&mut self) -> Option<&mut Self> {
+        self.push_down();
+        self.right.as_deref_mut()
+    }
+}
+
+/// A list supporting O(log(n)) updates of a range of elements, with updates
+/// of type `U`.
+///
+/// Reading the elements pushes pending updates down the tree, so even the
+/// read-only accessors take `&mut self`.
+pub struct LazyTree<T, U: Update<T>> {
+    root: Option<Box<LazyNode<T, U>>>,
+}
+
+impl<T, U: Update<T>> LazyTree<T, U> {
+    /// Returns an empty `LazyTree`.
+    pub fn new() -> LazyTree<T, U> {
+        LazyTree { root: None }
+    }
+
+    /// Returns the number elements in the tree. Time complexity: O(1)
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |node| node.count as usize)
+    }
+
+    /// Returns `true` if the tree contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    // Returns the node holding the element at `index`, after pushing down
+    // the updates pending above it.
+    fn node_at(&mut self, mut index: usize) -> Option<&mut LazyNode<T, U>> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut node = self.root.as_deref_mut().unwrap();
+        loop {
+            node.push_down();
+            let lcount = avl::lcount(node) as usize;
+            if index < lcount {
+                node = node.left.as_deref_mut().unwrap();
+            } else if index == lcount {
+                return Some(node);
+            } else {
+                index -= lcount + 1;
+                node = node.right.as_deref_mut().unwrap();
+            }
+        }
+    }
+
+    /// Returns a reference to the element at `index`, or `None` if out of
+    /// bounds. Time complexity: O(log(n))
+    pub fn get(&mut self, index: usize) -> Option<&T> {
+        self.node_at(index).map(|node| &node.val)
+    }
+
+    /// Returns a mutable reference to the element at `index`, or `None` if
+    /// out of bounds. Time complexity: O(log(n))
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.node_at(index).map(|node| &mut node.val)
+    }
+
+    /// Inserts an element at `index`, shifting all the elements after it.
+    /// Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// if `index > len()`
+    pub fn insert(&mut self, index: usize, value: T) {
+        avl::insert(&mut self.root, index, Box::new(LazyNode::new(value)));
+    }
+
+    /// Removes the element at `index`, shifting all the elements after it.
+    /// Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// if `index >= len()`
+    pub fn remove(&mut self, index: usize) -> T {
+        avl::remove(&mut self.root, index).val
+    }
+
+    /// Prepends an element. Time complexity: O(log(n))
+    pub fn push_front(&mut self, value: T) {
+        self.insert(0, value);
+    }
+
+    /// Appends an element. Time complexity: O(log(n))
+    pub fn push_back(&mut self, value: T) {
+        let len = self.len();
+        self.insert(len, value);
+    }
+
+    /// Removes and returns the first element, or `None` if empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.remove(0))
+        }
+    }
+
+    /// Removes and returns the last element, or `None` if empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            None
+        } else {
+            Some(self.remove(len - 1))
+        }
+    }
+
+    /// Applies `update` to every element in `range`. Time complexity:
+    /// O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// if the range is decreasing or extends past `len()`
+    pub fn apply_range(&mut self, range: Range<usize>, update: U) {
+        assert!(range.start <= range.end && range.end <= self.len(),
+                "range {:?} out of bounds for length {}", range, self.len());
+        if range.start < range.end {
+            let root = self.root.as_mut().unwrap();
+            root.apply_range(range.start as u32, range.end as u32, &update);
+        }
+    }
+
+    /// Returns an iterator over the elements, after pushing down all the
+    /// pending updates. Time complexity: O(n) to create the iterator.
+    pub fn iter(&mut self) -> Iter<'_, LazyNode<T, U>> {
+        let mut stack: Vec<&mut LazyNode<T, U>> = self.root.as_deref_mut().into_iter().collect();
+        while let Some(node) = stack.pop() {
+            node.push_down();
+            let LazyNode { ref mut left, ref mut right, .. } = *node;
+            stack.extend(left.as_deref_mut());
+            stack.extend(right.as_deref_mut());
+        }
+        Iter::with_len(self.root.as_deref(), self.len())
+    }
+}
+
+impl<T, U: Update<T>> Default for LazyTree<T, U> {
+    fn default() -> LazyTree<T, U> {
+        LazyTree::new()
+    }
+}
+
+impl<T, U: Update<T>> FromIterator<T> for LazyTree<T, U> {
+    fn from_iter<I>(iterable: I) -> Self
+        where I: IntoIterator<Item = T>
+    {
+        let values: Vec<T> = iterable.into_iter().collect();
+        LazyTree { root: avl::build_balanced(values.len(), &mut values.into_iter(), &LazyNode::new) }
+    }
+}
+
+impl<T, U: Update<T>> IntoIterator for LazyTree<T, U> {
+    type Item = T;
+    type IntoIter = IntoIter<LazyNode<T, U>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.root)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Node;
+    use test::compute_level;
+    use super::{Add, LazyNode, LazyTree, Update};
+
+    fn check<T, U: Update<T>>(root: &LazyNode<T, U>) {
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            let lcount = node.left().map_or(0, |n| n.count);
+            let rcount = node.right().map_or(0, |n| n.count);
+            assert_eq!(node.count, lcount + rcount + 1);
+            stack.extend(node.left());
+            stack.extend(node.right());
+        }
+        assert!(compute_level(root, 1).is_balanced());
+    }
+
+    fn contents(tree: &mut LazyTree<i64, Add<i64>>) -> Vec<i64> {
+        tree.iter().cloned().collect()
+    }
+
+    #[test]
+    fn apply_range() {
+        let mut tree: LazyTree<i64, Add<i64>> = (0..100).collect();
+        let mut model: Vec<i64> = (0..100).collect();
+        for i in 0..100 {
+            let (start, end) = ((i * 37) % 101, (i * 53) % 101);
+            let range = cmp_range(start, end);
+            tree.apply_range(range.clone(), Add(i as i64));
+            for v in &mut model[range] {
+                *v += i as i64;
+            }
+            if i % 10 == 0 {
+                assert_eq!(tree.get(i), Some(&model[i]));
+            }
+        }
+        assert_eq!(contents(&mut tree), model);
+        tree.apply_range(5..5, Add(1000));
+        assert_eq!(contents(&mut tree), model);
+    }
+
+    fn cmp_range(a: usize, b: usize) -> ::std::ops::Range<usize> {
+        if a <= b { a..b } else { b..a }
+    }
+
+    #[test]
+    fn edits_after_updates() {
+        // line offsets of an editor buffer, shifted on every insertion
+        let mut offsets: LazyTree<i64, Add<i64>> = LazyTree::new();
+        let mut model = Vec::new();
+        for line in 0..200usize {
+            let at = (line * 7) % (line + 1);
+            let offset = model.get(at).cloned().unwrap_or(0);
+            offsets.insert(at, offset);
+            model.insert(at, offset);
+            offsets.apply_range(at + 1..line + 1, Add(10));
+            for v in &mut model[at + 1..] {
+                *v += 10;
+            }
+            check(offsets.root.as_ref().unwrap());
+        }
+        assert_eq!(contents(&mut offsets), model);
+        for i in 0..150 {
+            let at = (i * 13) % offsets.len();
+            offsets.apply_range(at..offsets.len(), Add(-1));
+            for v in &mut model[at..] {
+                *v -= 1;
+            }
+            assert_eq!(offsets.remove(at), model.remove(at));
+            check(offsets.root.as_ref().unwrap());
+        }
+        *offsets.get_mut(3).unwrap() = 0;
+        model[3] = 0;
+        assert_eq!(offsets.pop_front(), Some(model.remove(0)));
+        assert_eq!(offsets.pop_back(), model.pop());
+        assert_eq!(offsets.into_iter().collect::<Vec<_>>(), model);
+    }
+
+    // updates which don't commute with `Add`, to check the order of
+    // composition
+    #[derive(Clone, Debug)]
+    struct Affine(i64, i64);
+
+    impl Update<i64> for Affine {
+        fn apply(&self, value: &mut i64) {
+            *value = *value * self.0 + self.1;
+        }
+
+        fn compose(&mut self, later: &Self) {
+            *self = Affine(self.0 * later.0, self.1 * later.0 + later.1);
+        }
+    }
+
+    // toggles the flags if `true`
+    #[derive(Clone, Debug)]
+    struct Toggle(bool);
+
+    impl Update<bool> for Toggle {
+        fn apply(&self, value: &mut bool) {
+            *value ^= self.0;
+        }
+
+        fn compose(&mut self, later: &Self) {
+            self.0 ^= later.0;
+        }
+    }
+
+    #[test]
+    fn composition() {
+        let mut tree: LazyTree<i64, Affine> = (0..50).collect();
+        let mut model: Vec<i64> = (0..50).collect();
+        let updates = [(0..50, Affine(2, 1)), (10..40, Affine(1, -3)),
+                       (20..30, Affine(-1, 0)), (0..25, Affine(3, 2))];
+        for (range, update) in &updates {
+            tree.apply_range(range.clone(), update.clone());
+            for v in &mut model[range.clone()] {
+                update.apply(v);
+            }
+        }
+        tree.insert(17, 0);
+        model.insert(17, 0);
+        assert_eq!(tree.remove(33), model.remove(33));
+        assert_eq!(tree.iter().cloned().collect::<Vec<_>>(), model);
+
+        let mut flags: LazyTree<bool, Toggle> = (0..8).map(|_| false).collect();
+        flags.apply_range(0..6, Toggle(true));
+        flags.apply_range(2..8, Toggle(true));
+        flags.apply_range(3..5, Toggle(true));
+        assert_eq!(flags.iter().cloned().collect::<Vec<_>>(),
+                   [true, true, false, true, true, false, true, true]);
+    }
+}
@@ -6,14 +6,51 @@
 //! * Height of a node is the length of the longest path to _its_ leaves. Thus
 //!   all leaf nodes have zero height.
 
+#![cfg_attr(feature="allocator_api", feature(allocator_api))]
+
 #[cfg(feature="quickcheck")]
 extern crate quickcheck;
-
+#[cfg(feature="serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(feature="serde_json")]
+extern crate serde_json;
+#[cfg(feature="rayon")]
+extern crate rayon;
+#[cfg(any(feature="rand", all(test, feature="quickcheck")))]
+extern crate rand;
+
+pub mod algos;
+#[cfg(feature="allocator_api")]
+pub mod allocator;
+pub mod augment;
+pub mod bits;
+mod avl;
+pub mod codec;
 pub mod cow;
 pub mod count;
+pub mod handle;
 pub mod iter;
+#[cfg(feature="json")]
+pub mod json;
+pub mod lazy;
+#[cfg(feature="model-tests")]
+pub mod model_test;
+pub mod multiset;
+pub mod newick;
+pub mod ops;
+pub mod packed;
+#[cfg(feature="rayon")]
+pub mod par;
+pub mod rope;
+pub mod shared;
+pub mod succinct;
+pub mod svg;
 pub mod test;
 pub mod unbox;
+pub mod versioned;
+pub mod walker;
+pub mod weighted;
 
 use std::mem;
 use std::ops::DerefMut;
@@ -24,14 +61,6 @@ pub trait BinaryTree {
     fn root(&self) -> Option<&Self::Node>;
 }
 
-unsafe fn borrow<'a, T, U>(raw: *const T, _: &'a U) -> &'a T {
-    &*raw
-}
-
-unsafe fn borrow_mut<'a, T, U>(raw: *mut T, _: &'a U) -> &'a mut T {
-    &mut *raw
-}
-
 /// Generic methods for traversing a binary tree.
 pub trait Node {
     type Value;
@@ -61,6 +90,103 @@ pub trait Node {
             };
         }
     }
+
+    /// Walk down the tree like `walk`, but with a fallible `step_in`. The walk
+    /// is aborted on the first error, which is returned.
+    fn try_walk<'a, F, E>(&'a self, mut step_in: F) -> Result<(), E>
+        where F: FnMut(&'a Self) -> Result<WalkAction, E>
+    {
+        use WalkAction::*;
+
+        let mut subtree = Some(self);
+        while let Some(st) = subtree {
+            subtree = match step_in(st)? {
+                Left => st.left(),
+                Right => st.right(),
+                Stop => break,
+            };
+        }
+        Ok(())
+    }
+
+    /// Walk down the tree, possibly into both children of a node. `step_in` is
+    /// called for each visited node in pre-order (left subtree before right),
+    /// and decides which of its children are visited next. This can be used to
+    /// implement pruning searches, like range queries.
+    fn walk_full<'a, F>(&'a self, mut step_in: F)
+        where F: FnMut(&'a Self) -> Descend
+    {
+        use Descend::*;
+
+        let mut stack = vec![self];
+        while let Some(st) = stack.pop() {
+            let (left, right) = match step_in(st) {
+                Both => (st.left(), st.right()),
+                Left => (st.left(), None),
+                Right => (None, st.right()),
+                Skip => (None, None),
+            };
+            stack.extend(right);
+            stack.extend(left);
+        }
+    }
+
+    /// Walk down the tree like `walk`, and return the sequence of actions
+    /// that were taken. Only the steps into an existing child are recorded, so
+    /// following the returned path from `self` leads to the node where the
+    /// walk ended.
+    fn walk_path<'a, F>(&'a self, mut step_in: F) -> Vec<WalkAction>
+        where F: FnMut(&'a Self) -> WalkAction
+    {
+        use WalkAction::*;
+
+        let mut path = Vec::new();
+        let mut subtree = Some(self);
+        while let Some(st) = subtree {
+            let action = step_in(st);
+            subtree = match action {
+                Left => st.left(),
+                Right => st.right(),
+                Stop => break,
+            };
+            if subtree.is_some() {
+                path.push(action);
+            }
+        }
+        path
+    }
+
+    /// Fold the values of the tree in-order.
+    fn fold<B, F>(&self, init: B, f: F) -> B
+        where F: FnMut(B, &Self::Value) -> B,
+              Self: Sized
+    {
+        iter::Iter::new(Some(self)).fold(init, f)
+    }
+
+    /// Fold the tree bottom-up: `f` is called for each node with the results
+    /// of its left and right subtrees (`None` for a missing child) and its
+    /// value, and the result for the root is returned. The traversal is
+    /// iterative.
+    fn fold_tree<B, F>(&self, mut f: F) -> B
+        where F: FnMut(Option<B>, &Self::Value, Option<B>) -> B
+    {
+        // the flag is set if the children of the node are already on the stack
+        let mut stack = vec![(self, false)];
+        let mut results = Vec::new();
+        while let Some((node, expanded)) = stack.pop() {
+            if expanded {
+                let right = node.right().map(|_| results.pop().unwrap());
+                let left = node.left().map(|_| results.pop().unwrap());
+                results.push(f(left, node.value(), right));
+            } else {
+                stack.push((node, true));
+                stack.extend(node.right().map(|n| (n, false)));
+                stack.extend(node.left().map(|n| (n, false)));
+            }
+        }
+        results.pop().unwrap()
+    }
 }
 
 /// Mutating methods on a Binary Tree node.
@@ -79,6 +205,23 @@ pub trait NodeMut: Node + Sized {
     /// Replace the right subtree with `tree` and return the old one.
     fn insert_right(&mut self, tree: Option<Self::NodePtr>) -> Option<Self::NodePtr>;
 
+    /// Detach both the subtrees, and return them as (left, right).
+    fn detach_children(&mut self) -> (Option<Self::NodePtr>, Option<Self::NodePtr>) {
+        let left = self.detach_left();
+        let right = self.detach_right();
+        (left, right)
+    }
+
+    /// Replace both the subtrees, and return the old ones as (left, right).
+    fn graft(&mut self,
+             left: Option<Self::NodePtr>,
+             right: Option<Self::NodePtr>)
+             -> (Option<Self::NodePtr>, Option<Self::NodePtr>) {
+        let left = self.insert_left(left);
+        let right = self.insert_right(right);
+        (left, right)
+    }
+
     /// Returns a mutable reference to the value of the current node.
     fn value_mut(&mut self) -> &mut Self::Value;
 
@@ -129,24 +272,25 @@ pub trait NodeMut: Node + Sized {
     {
         use WalkAction::*;
 
-        let mut node: *mut _ = self;
+        let mut node = self;
         loop {
-            let action = {
-                let pin = ();
-                step_in(unsafe { borrow(node, &pin) })
+            let action = step_in(node);
+            // check first, so that the mutable borrow below is unconditional
+            let has_next = match action {
+                Left => node.left().is_some(),
+                Right => node.right().is_some(),
+                Stop => false,
             };
-            let next = match action {
-                Left => unsafe { borrow_mut(node, self) }.left_mut(),
-                Right => unsafe { borrow_mut(node, self) }.right_mut(),
-                Stop => break,
-            };
-            if let Some(st) = next {
-                node = st;
-            } else {
+            if !has_next {
                 break;
             }
+            node = match action {
+                Left => node.left_mut(),
+                Right => node.right_mut(),
+                Stop => unreachable!(),
+            }.unwrap();
         }
-        stop(unsafe { borrow_mut(node, self) });
+        stop(node);
     }
 
     /// Walks down the tree by detaching subtrees, then up reattaching them
@@ -158,11 +302,24 @@ pub trait NodeMut: Node + Sized {
         where FI: FnMut(&mut Self) -> WalkAction,
               FS: FnOnce(&mut Self),
               FO: FnMut(&mut Self, WalkAction)
+    {
+        self.walk_reshape_state(&mut (),
+                                |node, _| step_in(node),
+                                |node, _| stop(node),
+                                |node, action, _| step_out(node, action));
+    }
+
+    /// Same as `walk_reshape`, but `state` is passed on to all the closures,
+    /// so that they can share it without resorting to a `RefCell`.
+    fn walk_reshape_state<S, FI, FS, FO>(&mut self, state: &mut S, mut step_in: FI, stop: FS, mut step_out: FO)
+        where FI: FnMut(&mut Self, &mut S) -> WalkAction,
+              FS: FnOnce(&mut Self, &mut S),
+              FO: FnMut(&mut Self, WalkAction, &mut S)
     {
         use WalkAction::*;
 
         let mut stack = Vec::with_capacity(8);
-        let root_action = step_in(self);
+        let root_action = step_in(self, state);
         let mut subtree = match root_action {
             Left => self.detach_left(),
             Right => self.detach_right(),
@@ -171,7 +328,7 @@ pub trait NodeMut: Node + Sized {
         let mut action = root_action;
         while action != Stop {
             if let Some(mut st) = subtree {
-                action = step_in(&mut st);
+                action = step_in(&mut st, state);
                 subtree = match action {
                     Left => st.detach_left(),
                     Right => st.detach_right(),
@@ -184,14 +341,14 @@ pub trait NodeMut: Node + Sized {
         }
         if let Some((mut sst, _)) = stack.pop() {
             //               -^- the final action is irrelevant
-            stop(&mut sst);
+            stop(&mut sst, state);
             while let Some((mut st, action)) = stack.pop() {
                 match action {
                     Left => st.insert_left(Some(sst)),
                     Right => st.insert_right(Some(sst)),
                     Stop => unreachable!(),
                 };
-                step_out(&mut st, action);
+                step_out(&mut st, action, state);
                 sst = st;
             }
             match root_action {
@@ -199,9 +356,41 @@ pub trait NodeMut: Node + Sized {
                 Right => self.insert_right(Some(sst)),
                 Stop => unreachable!(),
             };
-            step_out(self, root_action);
+            step_out(self, root_action, state);
         } else {
-            stop(self)
+            stop(self, state)
+        }
+    }
+
+    /// Same as `walk_reshape`, but with a fallible `step_in`. If it returns an
+    /// error, the subtrees detached so far are attached back (without calling
+    /// `stop` or `step_out`), and the error is returned.
+    fn try_walk_reshape<FI, FS, FO, E>(&mut self, mut step_in: FI, stop: FS, mut step_out: FO) -> Result<(), E>
+        where FI: FnMut(&mut Self) -> Result<WalkAction, E>,
+              FS: FnOnce(&mut Self),
+              FO: FnMut(&mut Self, WalkAction)
+    {
+        let mut error = None;
+        self.walk_reshape_state(&mut error,
+                                |node, error| {
+                                    step_in(node).unwrap_or_else(|e| {
+                                        *error = Some(e);
+                                        WalkAction::Stop
+                                    })
+                                },
+                                |node, error| {
+                                    if error.is_none() {
+                                        stop(node);
+                                    }
+                                },
+                                |node, action, error| {
+                                    if error.is_none() {
+                                        step_out(node, action);
+                                    }
+                                });
+        match error {
+            Some(e) => Err(e),
+            None => Ok(()),
         }
     }
 
@@ -238,28 +427,29 @@ pub trait NodeMut: Node + Sized {
     /// finally visited node (that for which `extract` was called).
     ///
     /// See the source of `CountTree::remove` for an example use.
-    fn walk_extract<FI, FE, FO>(&mut self, step_in: FI, extract: FE, mut step_out: FO) -> Option<Self::NodePtr>
+    fn walk_extract<FI, FE, FO>(&mut self, mut step_in: FI, extract: FE, mut step_out: FO) -> Option<Self::NodePtr>
         where FI: FnMut(&mut Self) -> WalkAction,
               FE: FnOnce(&mut Self, &mut Option<Self::NodePtr>),
               FO: FnMut(&mut Self, WalkAction)
     {
         use WalkAction::*;
 
-        let ret = std::cell::RefCell::new(None);
-        self.walk_reshape(step_in,
-                          |node| extract(node, &mut *ret.borrow_mut()),
-                          |node, action| {
-                              if ret.borrow().is_none() {
-                                  // take out the last visited node if `extract` was unable to
-                                  *ret.borrow_mut() = match action {
-                                      Left => node.detach_left(),
-                                      Right => node.detach_right(),
-                                      Stop => unreachable!(),
-                                  };
-                              }
-                              step_out(node, action);
-                          });
-        ret.into_inner()
+        let mut ret = None;
+        self.walk_reshape_state(&mut ret,
+                                |node, _| step_in(node),
+                                extract,
+                                |node, action, ret| {
+                                    if ret.is_none() {
+                                        // take out the last visited node if `extract` was unable to
+                                        *ret = match action {
+                                            Left => node.detach_left(),
+                                            Right => node.detach_right(),
+                                            Stop => unreachable!(),
+                                        };
+                                    }
+                                    step_out(node, action);
+                                });
+        ret
     }
 
     /// Replace this node with one of its descendant, returns `None` if it has
@@ -269,7 +459,7 @@ pub trait NodeMut: Node + Sized {
     {
         use WalkAction::*;
 
-        match (self.detach_left(), self.detach_right()) {
+        match self.detach_children() {
             (Some(mut left), right @ Some(_)) => {
                 // fetch the rightmost descendant of left into pio (previous-in-order of self)
                 let mut pio = left.walk_extract(|_| Right,
@@ -308,7 +498,7 @@ pub trait NodeMut: Node + Sized {
     }
 }
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 /// List of actions during a `Node::walk` or `NodeMut::walk_*`.
 pub enum WalkAction {
     /// Enter(ed) the left child
@@ -318,3 +508,16 @@ pub enum WalkAction {
     /// Stop walking
     Stop,
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// List of choices during a `Node::walk_full`.
+pub enum Descend {
+    /// Enter both the children
+    Both,
+    /// Enter only the left child
+    Left,
+    /// Enter only the right child
+    Right,
+    /// Do not enter any child
+    Skip,
+}
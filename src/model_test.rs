@@ -0,0 +1,190 @@
+//! Model-based testing of `CountTree` (requires the `model-tests` feature).
+//!
+//! Sequences of operations (see `ops`) are applied to both a `CountTree` and
+//! a `Vec`, which serves as the reference model. After every step, the
+//! results of the operation, the contents of both, and the invariants of the
+//! tree (cached counts and heights, and AVL balance) are compared.
+//! `random_ops` generates reproducible sequences from a seed, so that a
+//! failure can be replayed (and shrunk by hand) with `run`.
+
+use std::fmt;
+use std::mem;
+
+use count::{self, CountTree};
+use ops::{self, Op};
+use test::compute_level;
+
+/// A mismatch between the tree and the model, or a broken invariant.
+#[derive(Debug)]
+pub struct Failure {
+    /// Index of the failed operation.
+    pub step: usize,
+    pub op: Op<u32>,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "step {} ({:?}): {}", self.step, self.op, self.message)
+    }
+}
+
+/// Returns `n` random operations generated from `seed`. Insertions and
+/// pushes are weighted so that the sequence tends to grow.
+pub fn random_ops(seed: u64, n: usize) -> Vec<Op<u32>> {
+    use self::Op::*;
+
+    // xorshift64*, the state must not be zero
+    let mut state = seed | 1;
+    let mut next = move || {
+        state ^= state >> 12;
+        state ^= state << 25;
+        state ^= state >> 27;
+        state.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    };
+    (0..n)
+        .map(|_| {
+            let index = next() as usize;
+            let value = next() as u32;
+            match next() % 22 {
+                0..=3 => Insert(index, value),
+                4..=5 => Remove(index),
+                6..=7 => Get(index),
+                8 => Set(index, value),
+                9..=11 => PushFront(value),
+                12..=14 => PushBack(value),
+                15 => PopFront,
+                16 => PopBack,
+                17 => Reverse,
+                18 => SplitAt(index, next() as usize),
+                19 => PopFrontN(index % 4),
+                20 => PopBackN(index % 4),
+                _ => Flush,
+            }
+        })
+        .collect()
+}
+
+/// Applies `ops` to an empty `CountTree` and an empty `Vec`, checking them
+/// against each other after every step.
+pub fn run(ops: &[Op<u32>]) -> Result<(), Failure> {
+    let mut tree = CountTree::new();
+    let mut model = Vec::new();
+    for (step, &op) in ops.iter().enumerate() {
+        apply(&mut tree, &mut model, op)
+            .and_then(|_| check(&tree, &model))
+            .map_err(|message| {
+                Failure {
+                    step,
+                    op,
+                    message,
+                }
+            })?;
+    }
+    Ok(())
+}
+
+/// Runs `n` random operations generated from `seed`.
+pub fn run_random(seed: u64, n: usize) -> Result<(), Failure> {
+    run(&random_ops(seed, n))
+}
+
+fn apply(tree: &mut CountTree<u32>, model: &mut Vec<u32>, op: Op<u32>) -> Result<(), String> {
+    use ops::Op::*;
+
+    let len = model.len();
+    let expected = match op {
+        Insert(index, value) => {
+            model.insert(index % (len + 1), value);
+            None
+        }
+        Remove(_) | Get(_) | Set(_, _) if len == 0 => None,
+        Remove(index) => Some(model.remove(index % len)),
+        Get(index) => Some(model[index % len]),
+        Set(index, value) => Some(mem::replace(&mut model[index % len], value)),
+        PushFront(value) => {
+            model.insert(0, value);
+            None
+        }
+        PushBack(value) => {
+            model.push(value);
+            None
+        }
+        PopFront if len == 0 => None,
+        PopFront => Some(model.remove(0)),
+        PopBack => model.pop(),
+        Reverse => {
+            model.reverse();
+            None
+        }
+        Flush => None,
+        SplitAt(at, index) => {
+            let mut rest = model.split_off(at % (len + 1));
+            let tail = rest.split_off(index % (rest.len() + 1));
+            rest.append(model);
+            rest.extend(tail);
+            *model = rest;
+            None
+        }
+        PopFrontN(n) => {
+            model.drain(..n.min(len));
+            None
+        }
+        PopBackN(n) => {
+            model.truncate(len - n.min(len));
+            None
+        }
+    };
+    let result = ops::apply_op(tree, &op);
+    if result == expected {
+        Ok(())
+    } else {
+        Err(format!("tree returned {:?}, model {:?}", result, expected))
+    }
+}
+
+fn check(tree: &CountTree<u32>, model: &[u32]) -> Result<(), String> {
+    if tree.len() != model.len() {
+        return Err(format!("length is {}, expected {}", tree.len(), model.len()));
+    }
+    if tree.to_vec() != model {
+        return Err(format!("contents are {:?}, expected {:?}", tree, model));
+    }
+    // the structure is checked on a flushed copy, so that the buffers of
+    // `tree` itself carry over to the next step
+    let mut flushed = tree.clone();
+    if let Some(root) = flushed.root() {
+        count::check_counts(root).map_err(|path| format!("wrong count at {:?}", path))?;
+        count::check_heights(root).map_err(|path| format!("wrong height at {:?}", path))?;
+        if !compute_level(root, 1).is_balanced() {
+            return Err("tree is not balanced".to_string());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use ops::Op;
+    use super::{random_ops, run, run_random};
+
+    #[test]
+    fn random_sequences() {
+        for seed in 0..20 {
+            if let Err(failure) = run_random(seed, 1000) {
+                panic!("seed {}: {}", seed, failure);
+            }
+        }
+    }
+
+    #[test]
+    fn replay() {
+        use ops::Op::*;
+
+        assert_eq!(random_ops(42, 100), random_ops(42, 100));
+        let ops = [PushBack(1), PushFront(0), Insert(7, 2), Remove(4), PopFront, Get(0), Reverse, Flush,
+                   SplitAt(1, 1), PushFront(3), PopBackN(2), PopFrontN(5)];
+        assert!(run(&ops).is_ok());
+        assert!(run(&[Op::PopBack, Op::Remove(3)]).is_ok());
+    }
+}
@@ -0,0 +1,561 @@
+//! Ordered multiset implementation.
+//!
+//! ## When should you use `Multiset`?
+//!
+//! - You want to maintain a sorted bag of possibly repeated values.
+//! - You want to count occurrences, and answer rank queries ("how many
+//!   elements are smaller than `x`?", "which is the k-th smallest element?")
+//!   in O(log(n)) time, where n is the number of _distinct_ elements.
+//! - You have less than 4.29 billion (`u32::MAX`) elements in total!
+
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::mem;
+
+use Node;
+use NodeMut;
+use BinaryTree;
+use WalkAction;
+use algos;
+
+pub type NodePtr<T> = Box<MultisetNode<T>>;
+
+/// Ordered multiset.
+///
+/// A balanced binary search tree in which equal elements are collapsed into a
+/// single node that carries their number of occurrences. Each node also keeps
+/// track of the total number of elements in its subtree, which is used to
+/// answer rank queries. The balancing algorithm is the same as that of
+/// [`CountTree`](../count/struct.CountTree.html).
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate binary_tree;
+/// # use binary_tree::multiset::Multiset;
+/// # fn main() {
+/// let mut ms = Multiset::new();
+/// ms.insert(20);
+/// ms.insert(10);
+/// ms.insert(20);
+/// assert_eq!(ms.len(), 3);
+/// assert_eq!(ms.count(&20), 2);
+/// assert_eq!(ms.rank(&20), 1);
+/// assert_eq!(ms.nth(2), Some(&20));
+/// # }
+/// ```
+pub struct Multiset<T: Ord> {
+    root: Option<NodePtr<T>>,
+}
+
+impl<T: Ord> Multiset<T> {
+    fn root_must(&mut self) -> &mut MultisetNode<T> {
+        self.root.as_deref_mut().unwrap()
+    }
+
+    /// Returns an empty `Multiset`
+    pub fn new() -> Multiset<T> {
+        Multiset { root: None }
+    }
+
+    /// Returns `true` if the multiset contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the number of elements in the multiset, counting repetitions.
+    /// Time complexity: O(1)
+    pub fn len(&self) -> usize {
+        self.root().map_or(0, |node| node.total as usize)
+    }
+
+    /// Clears the multiset, dropping all elements iteratively.
+    pub fn clear(&mut self) {
+        if let Some(root) = self.root.take() {
+            algos::drop_tree::<MultisetNode<T>>(root);
+        }
+    }
+
+    /// Returns the number of occurrences of `value`. Time complexity:
+    /// O(log(n))
+    pub fn count(&self, value: &T) -> usize {
+        let mut count = 0;
+        if let Some(root) = self.root() {
+            root.walk(|node| {
+                let action = search_walker(value, node);
+                if action == WalkAction::Stop {
+                    count = node.mult as usize;
+                }
+                action
+            });
+        }
+        count
+    }
+
+    /// Returns `true` if `value` occurs at least once.
+    pub fn contains(&self, value: &T) -> bool {
+        self.count(value) > 0
+    }
+
+    /// Returns the number of elements strictly smaller than `value`. Time
+    /// complexity: O(log(n))
+    pub fn rank(&self, value: &T) -> usize {
+        use WalkAction::*;
+
+        let mut rank = 0;
+        if let Some(root) = self.root() {
+            root.walk(|node| {
+                match value.cmp(node.value()) {
+                    Ordering::Less => Left,
+                    Ordering::Equal => {
+                        rank += node.ltotal() as usize;
+                        Stop
+                    }
+                    Ordering::Greater => {
+                        rank += (node.ltotal() + node.mult) as usize;
+                        Right
+                    }
+                }
+            });
+        }
+        rank
+    }
+
+    /// Returns the element at the given sorted position (counting
+    /// repetitions), or `None` if out of bounds. Time complexity: O(log(n))
+    pub fn nth(&self, index: usize) -> Option<&T> {
+        use WalkAction::*;
+
+        if index >= self.len() {
+            None
+        } else {
+            let mut val = None;
+            let mut up_count = 0;
+            self.root().unwrap().walk(|node| {
+                let start = node.ltotal() as usize + up_count;
+                if index < start {
+                    Left
+                } else if index < start + node.mult as usize {
+                    val = Some(node.value());
+                    Stop
+                } else {
+                    up_count = start + node.mult as usize;
+                    Right
+                }
+            });
+            debug_assert!(val.is_some());
+            val
+        }
+    }
+
+    /// Inserts one occurrence of `value`. Time complexity: O(log(n))
+    pub fn insert(&mut self, value: T) {
+        if self.is_empty() {
+            self.root = Some(Box::new(MultisetNode::new(value)));
+        } else {
+            // `step_in` only needs to borrow `value`, which `stop` moves
+            let mut value = Some(value);
+            self.root_must().walk_reshape_state(&mut value,
+                                                |node, value| {
+                                                    search_walker(value.as_ref().unwrap(), node)
+                                                },
+                                                |node, value| {
+                                                    let value = value.take().unwrap();
+                                                    match value.cmp(&node.val) {
+                                                        Ordering::Less => {
+                                                            let new_node = MultisetNode::new(value);
+                                                            node.insert_left(Some(Box::new(new_node)));
+                                                        }
+                                                        Ordering::Equal => {
+                                                            node.mult += 1;
+                                                            node.update_stats();
+                                                        }
+                                                        Ordering::Greater => {
+                                                            let new_node = MultisetNode::new(value);
+                                                            node.insert_right(Some(Box::new(new_node)));
+                                                        }
+                                                    }
+                                                },
+                                                |node, _, _| node.rebalance());
+        }
+    }
+
+    /// Removes one occurrence of `value`. Returns `false` if `value` was not
+    /// present. Time complexity: O(log(n))
+    pub fn remove_one(&mut self, value: &T) -> bool {
+        match self.count(value) {
+            0 => false,
+            1 => {
+                self.remove_node(value);
+                true
+            }
+            _ => {
+                self.root_must().walk_reshape(|node| search_walker(value, node),
+                                              |node| {
+                                                  node.mult -= 1;
+                                                  node.update_stats();
+                                              },
+                                              |node, _| node.rebalance());
+                true
+            }
+        }
+    }
+
+    /// Removes all occurrences of `value`, and returns how many were removed.
+    /// Time complexity: O(log(n))
+    pub fn remove_all(&mut self, value: &T) -> usize {
+        let count = self.count(value);
+        if count > 0 {
+            self.remove_node(value);
+        }
+        count
+    }
+
+    // `value` must be present in the tree
+    fn remove_node(&mut self, value: &T) {
+        let root_is_leaf = {
+            let root = self.root().unwrap();
+            root.left.is_none() && root.right.is_none()
+        };
+        if root_is_leaf {
+            self.root = None;
+        } else {
+            let root = self.root_must();
+            let node = root.walk_extract(|node| search_walker(value, node),
+                                         |node, ret| {
+                                             *ret = node.try_remove(|node, _| node.rebalance());
+                                         },
+                                         |node, _| node.rebalance());
+            debug_assert!(node.is_some_and(|node| node.val == *value));
+        }
+    }
+
+    /// Builds a perfectly balanced multiset out of elements given in ascending
+    /// order (repetitions allowed). The order is only checked in debug builds;
+    /// otherwise the result of unsorted input is unspecified. Time complexity:
+    /// O(n)
+    pub fn from_sorted_iter<I>(iterable: I) -> Multiset<T>
+        where I: IntoIterator<Item = T>
+    {
+        // the distinct elements, with their number of occurrences
+        let mut runs: Vec<(T, u32)> = Vec::new();
+        for item in iterable {
+            match runs.last_mut() {
+                Some(&mut (ref last, ref mut mult)) if *last == item => {
+                    *mult += 1;
+                    continue;
+                }
+                Some(&mut (ref last, _)) => {
+                    debug_assert!(*last < item, "from_sorted_iter: elements are not sorted");
+                }
+                None => (),
+            }
+            runs.push((item, 1));
+        }
+        let len = runs.len();
+        Multiset { root: build_balanced(len, &mut runs.into_iter()) }
+    }
+
+    /// Returns an iterator over the distinct elements in ascending order, along
+    /// with their number of occurrences.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { stack: self.root().map_or(vec![], |node| vec![(node, false)]) }
+    }
+}
+
+fn search_walker<T: Ord>(value: &T, node: &MultisetNode<T>) -> WalkAction {
+    match value.cmp(&node.val) {
+        Ordering::Less => WalkAction::Left,
+        Ordering::Equal => WalkAction::Stop,
+        Ordering::Greater => WalkAction::Right,
+    }
+}
+
+// Builds a perfectly balanced tree out of the next `len` runs of `iter`.
+// Recursion depth is O(log(len)).
+fn build_balanced<T, I>(len: usize, iter: &mut I) -> Option<NodePtr<T>>
+    where I: Iterator<Item = (T, u32)>
+{
+    if len == 0 {
+        None
+    } else {
+        let left = build_balanced(len / 2, iter);
+        let (val, mult) = iter.next().unwrap();
+        let mut node = Box::new(MultisetNode::new(val));
+        node.mult = mult;
+        let right = build_balanced(len - len / 2 - 1, iter);
+        node.graft(left, right);
+        Some(node)
+    }
+}
+
+impl<T: Ord> Default for Multiset<T> {
+    fn default() -> Multiset<T> {
+        Multiset::new()
+    }
+}
+
+impl<T: Ord> BinaryTree for Multiset<T> {
+    type Node = MultisetNode<T>;
+
+    fn root(&self) -> Option<&Self::Node> {
+        self.root.as_deref()
+    }
+}
+
+impl<T: Ord> Debug for Multiset<T>
+    where T: Debug
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<T: Ord> Drop for Multiset<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Iterator over the distinct elements of a `Multiset` and their counts.
+pub struct Iter<'a, T: 'a> {
+    // the flag is set if the left subtree of the node has been visited
+    stack: Vec<(&'a MultisetNode<T>, bool)>,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (&'a T, usize);
+
+    fn next(&mut self) -> Option<(&'a T, usize)> {
+        if let Some((mut subtree, left_done)) = self.stack.pop() {
+            if !left_done {
+                while let Some(st) = subtree.left() {
+                    self.stack.push((subtree, true));
+                    subtree = st;
+                }
+            }
+            if let Some(st) = subtree.right() {
+                self.stack.push((st, false));
+            }
+            Some((subtree.value(), subtree.mult as usize))
+        } else {
+            None
+        }
+    }
+}
+
+/// Node of a `Multiset`.
+pub struct MultisetNode<T> {
+    val: T,
+    left: Option<NodePtr<T>>,
+    right: Option<NodePtr<T>>,
+    // number of occurrences of `val`
+    mult: u32,
+    // number of elements in this subtree, counting repetitions
+    total: u32,
+    height: u16,
+}
+
+impl<T> MultisetNode<T> {
+    fn new(val: T) -> MultisetNode<T> {
+        MultisetNode {
+            val,
+            left: None,
+            right: None,
+            mult: 1,
+            total: 1,
+            height: 0,
+        }
+    }
+
+    /// Returns the number of occurrences of the value of this node.
+    pub fn multiplicity(&self) -> usize {
+        self.mult as usize
+    }
+
+    fn ltotal(&self) -> u32 {
+        self.left.as_ref().map_or(0, |tree| tree.total)
+    }
+
+    fn rtotal(&self) -> u32 {
+        self.right.as_ref().map_or(0, |tree| tree.total)
+    }
+
+    // generalized version of AVL tree balance factor: h(left) - h(right)
+    fn balance_factor(&self) -> i32 {
+        self.left.as_ref().map_or(-1, |node| node.height as i32) -
+            self.right.as_ref().map_or(-1, |node| node.height as i32)
+    }
+
+    // AVL tree algorithm
+    fn rebalance(&mut self) {
+        if self.balance_factor() > 1 {
+            if let Some(ref mut node) = self.left {
+                if node.balance_factor() < 0 {
+                    node.rotate_left().unwrap();
+                }
+            }
+            self.rotate_right().unwrap();
+        } else if self.balance_factor() < -1 {
+            if let Some(ref mut node) = self.right {
+                if node.balance_factor() > 0 {
+                    node.rotate_right().unwrap();
+                }
+            }
+            self.rotate_left().unwrap();
+        }
+    }
+
+    fn update_stats(&mut self) {
+        use std::cmp::max;
+        self.total = self.ltotal() + self.mult + self.rtotal();
+        self.height = match (&self.left, &self.right) {
+            (&None, &None) => 0,
+            _ => max(self.left.as_ref().map_or(0, |tree| tree.height),
+                     self.right.as_ref().map_or(0, |tree| tree.height)) + 1,
+        };
+    }
+}
+
+impl<T> Node for MultisetNode<T> {
+    type Value = T;
+
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    fn value(&self) -> &T {
+        &self.val
+    }
+}
+
+impl<T> NodeMut for MultisetNode<T> {
+    type NodePtr = NodePtr<T>;
+
+    fn detach_left(&mut self) -> Option<Self::NodePtr> {
+        let tree = self.left.take();
+        self.update_stats();
+        tree
+    }
+
+    fn detach_right(&mut self) -> Option<Self::NodePtr> {
+        let tree = self.right.take();
+        self.update_stats();
+        tree
+    }
+
+    fn insert_left(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.left, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn insert_right(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.right, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn value_mut(&mut self) -> &mut T {
+        &mut self.val
+    }
+
+    fn into_parts(self) -> (T, Option<Self::NodePtr>, Option<Self::NodePtr>) {
+        (self.val, self.left, self.right)
+    }
+
+    fn left_mut(&mut self) -> Option<&mut Self> {
+        self.left.as_deref_mut()
+    }
+
+    fn right_mut(&mut self) -> Option<&mut Self> {
+        self.right.as_deref_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use BinaryTree;
+    use super::Multiset;
+    use test::{compute_level, Level};
+
+    #[test]
+    fn insert() {
+        let mut ms = Multiset::new();
+        for i in 0..100 {
+            ms.insert(i % 10);
+            ms.insert(99 - i);
+        }
+        assert_eq!(ms.len(), 200);
+        assert_eq!(ms.count(&3), 11);
+        assert_eq!(ms.count(&50), 1);
+        assert_eq!(ms.count(&100), 0);
+        assert!(compute_level(ms.root().unwrap(), 1).is_balanced());
+        let items: Vec<_> = ms.iter().take(3).collect();
+        assert_eq!(items, [(&0, 11), (&1, 11), (&2, 11)]);
+    }
+
+    #[test]
+    fn rank() {
+        let ms: Multiset<_> = {
+            let mut ms = Multiset::new();
+            for &v in &[5, 1, 3, 3, 9, 3, 7] {
+                ms.insert(v);
+            }
+            ms
+        };
+        assert_eq!(ms.rank(&0), 0);
+        assert_eq!(ms.rank(&3), 1);
+        assert_eq!(ms.rank(&4), 4);
+        assert_eq!(ms.rank(&10), 7);
+        let sorted: Vec<_> = (0..ms.len()).map(|i| *ms.nth(i).unwrap()).collect();
+        assert_eq!(sorted, [1, 3, 3, 3, 5, 7, 9]);
+        assert_eq!(ms.nth(7), None);
+    }
+
+    #[test]
+    fn remove() {
+        let mut ms = Multiset::new();
+        for i in 0..64 {
+            ms.insert(i / 2);
+        }
+        assert!(!ms.remove_one(&32));
+        assert!(ms.remove_one(&10));
+        assert_eq!(ms.count(&10), 1);
+        assert_eq!(ms.remove_all(&10), 1);
+        assert!(!ms.contains(&10));
+        for i in 0..16 {
+            assert_eq!(ms.remove_all(&i), if i == 10 { 0 } else { 2 });
+            assert!(compute_level(ms.root().unwrap(), 1).is_balanced());
+        }
+        assert_eq!(ms.len(), 32);
+        assert_eq!(ms.rank(&20), 8);
+        for i in 16..32 {
+            assert_eq!(ms.remove_all(&i), 2);
+        }
+        assert!(ms.is_empty());
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        let ms = Multiset::from_sorted_iter((0..100).map(|i| i / 3));
+        assert_eq!(ms.len(), 100);
+        assert_eq!((ms.count(&0), ms.count(&33), ms.count(&34)), (3, 1, 0));
+        assert_eq!(ms.rank(&20), 60);
+        let root = ms.root().unwrap();
+        assert_eq!(compute_level(root, 1), Level::Balanced(6));
+        assert_eq!(root.multiplicity(), 3);
+        assert!(Multiset::<u8>::from_sorted_iter(vec![]).is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(debug_assertions)]
+    fn from_unsorted_iter() {
+        Multiset::from_sorted_iter(vec![1, 3, 2]);
+    }
+}
@@ -0,0 +1,258 @@
+//! Reading and writing trees in the Newick format of phylogenetics tools.
+//!
+//! A leaf is written as its label, and an internal node as the list of its
+//! subtrees in parentheses followed by its label, e.g. `((a,b)c,d)e;`. As
+//! the format doesn't distinguish left and right, a node with only a right
+//! child is written with an empty left subtree, as in `(,b)c`; conversely,
+//! an empty (and unquoted) label in the place of a subtree is read as a
+//! missing child, and an only child is read as the left one.
+//!
+//! Labels containing whitespace or any of `()[]':;,` (and empty labels) are
+//! written in single quotes, with quotes doubled. When reading, branch lengths
+//! (`:0.5`) and comments (`[...]`) are skipped, and the final `;` is optional.
+//! Both directions are iterative.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use Node;
+use test::{ParseError, TestNode};
+
+const SPECIAL: &[u8] = b"()[]':;,";
+
+/// Writes the tree rooted at `root` in Newick format, with the values as
+/// labels. Time complexity: O(n)
+pub fn to_newick<N>(root: &N) -> String
+    where N: Node,
+          N::Value: Display
+{
+    enum Item<'a, N: 'a> {
+        Node(&'a N),
+        Label(&'a N),
+        Text(&'static str),
+    }
+
+    let mut out = String::new();
+    let mut stack = vec![Item::Node(root)];
+    while let Some(item) = stack.pop() {
+        match item {
+            Item::Node(node) => {
+                stack.push(Item::Label(node));
+                match (node.left(), node.right()) {
+                    (None, None) => continue,
+                    (Some(left), None) => {
+                        stack.push(Item::Text(")"));
+                        stack.push(Item::Node(left));
+                    }
+                    (left, Some(right)) => {
+                        stack.push(Item::Text(")"));
+                        stack.push(Item::Node(right));
+                        stack.push(Item::Text(","));
+                        stack.extend(left.map(Item::Node));
+                    }
+                }
+                out.push('(');
+            }
+            Item::Label(node) => write_label(&mut out, &node.value().to_string()),
+            Item::Text(text) => out.push_str(text),
+        }
+    }
+    out.push(';');
+    out
+}
+
+fn write_label(out: &mut String, label: &str) {
+    let plain = !label.is_empty() &&
+                label.bytes().all(|b| !SPECIAL.contains(&b) && !(b as char).is_whitespace());
+    if plain {
+        out.push_str(label);
+    } else {
+        out.push('\'');
+        out.push_str(&label.replace('\'', "''"));
+        out.push('\'');
+    }
+}
+
+/// Reads a tree in Newick format, parsing the labels as values. Errors are
+/// reported as by `test::parse_tree`; a node with more than two children is
+/// a syntax error. Time complexity: O(n)
+pub fn from_newick<T: FromStr>(text: &str) -> Result<TestNode<T>, ParseError<T::Err>> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+    };
+    // the subtrees parsed so far of the nodes whose lists are open
+    let mut stack: Vec<Vec<Option<Box<TestNode<T>>>>> = Vec::new();
+    let mut done: Option<Box<TestNode<T>>>;
+    'subtree: loop {
+        parser.skip_space()?;
+        if parser.peek() == Some(b'(') {
+            parser.pos += 1;
+            stack.push(Vec::new());
+            continue;
+        }
+        done = match parser.label()? {
+            Some(label) => Some(Box::new(TestNode::new(label.parse().map_err(ParseError::Value)?))),
+            None => None,
+        };
+        parser.length()?;
+        // attach the completed subtree to its parent, and so on upwards
+        loop {
+            parser.skip_space()?;
+            let mut subtrees = match stack.pop() {
+                Some(subtrees) => subtrees,
+                None => break 'subtree,
+            };
+            if subtrees.len() == 2 {
+                return Err(ParseError::Syntax(parser.pos));
+            }
+            subtrees.push(done);
+            match parser.peek() {
+                Some(b',') => {
+                    parser.pos += 1;
+                    stack.push(subtrees);
+                    continue 'subtree;
+                }
+                Some(b')') => parser.pos += 1,
+                _ => return Err(ParseError::Syntax(parser.pos)),
+            }
+            parser.skip_space()?;
+            let label = parser.label()?.unwrap_or_default();
+            let mut node = TestNode::new(label.parse().map_err(ParseError::Value)?);
+            let mut subtrees = subtrees.into_iter();
+            node.left = subtrees.next().unwrap();
+            node.right = subtrees.next().and_then(|st| st);
+            parser.length()?;
+            done = Some(Box::new(node));
+        }
+    }
+    if parser.peek() == Some(b';') {
+        parser.pos += 1;
+        parser.skip_space()?;
+    }
+    match done {
+        Some(root) if parser.pos == text.len() => Ok(*root),
+        _ => Err(ParseError::Syntax(parser.pos)),
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).cloned()
+    }
+
+    // Skips whitespace and comments.
+    fn skip_space<E>(&mut self) -> Result<(), ParseError<E>> {
+        loop {
+            match self.peek() {
+                Some(b) if (b as char).is_whitespace() => self.pos += 1,
+                Some(b'[') => {
+                    match self.text[self.pos..].find(']') {
+                        Some(len) => self.pos += len + 1,
+                        None => return Err(ParseError::Syntax(self.text.len())),
+                    }
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    // Reads a label, which is `None` if it is empty and unquoted.
+    fn label<E>(&mut self) -> Result<Option<String>, ParseError<E>> {
+        let bytes = self.text.as_bytes();
+        if self.peek() != Some(b'\'') {
+            let start = self.pos;
+            while self.pos < bytes.len() && !SPECIAL.contains(&bytes[self.pos]) &&
+                  !(bytes[self.pos] as char).is_whitespace() {
+                self.pos += 1;
+            }
+            let label = &self.text[start..self.pos];
+            return Ok(if label.is_empty() { None } else { Some(label.to_string()) });
+        }
+        let mut label = String::new();
+        loop {
+            self.pos += 1;
+            match self.text[self.pos..].find('\'') {
+                Some(len) => {
+                    label.push_str(&self.text[self.pos..self.pos + len]);
+                    self.pos += len + 1;
+                }
+                None => return Err(ParseError::Syntax(self.text.len())),
+            }
+            if self.peek() != Some(b'\'') {
+                return Ok(Some(label));
+            }
+            label.push('\'');
+        }
+    }
+
+    // Skips the branch length, if any.
+    fn length<E>(&mut self) -> Result<(), ParseError<E>> {
+        self.skip_space()?;
+        if self.peek() != Some(b':') {
+            return Ok(());
+        }
+        self.pos += 1;
+        self.skip_space()?;
+        let start = self.pos;
+        match self.label()? {
+            Some(ref length) if length.parse::<f64>().is_ok() => Ok(()),
+            _ => Err(ParseError::Syntax(start)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use algos::drop_tree;
+    use test::{ParseError, TestNode, Shape, gen_tree, parse_tree};
+    use super::{from_newick, to_newick};
+
+    #[test]
+    fn round_trip() {
+        let tree = parse_tree::<String>("a(b(,d),c(e))").unwrap();
+        assert_eq!(to_newick(&tree), "((,d)b,(e)c)a;");
+        assert_eq!(from_newick::<String>("((,d)b,(e)c)a;").unwrap(), tree);
+
+        let tree = parse_tree::<String>("a b( , it's)").unwrap();
+        assert_eq!(to_newick(&tree), "(,'it''s')'a b';");
+        assert_eq!(from_newick::<String>(&to_newick(&tree)).unwrap(), tree);
+        let tree = TestNode::new(String::new());
+        assert_eq!(to_newick(&tree), "'';");
+        assert_eq!(from_newick::<String>("'';").unwrap(), tree);
+
+        for &shape in &[Shape::Random(7), Shape::PerfectlyBalanced] {
+            let tree = *gen_tree(shape, 1000).unwrap();
+            assert_eq!(from_newick(&to_newick(&tree)).unwrap(), tree);
+        }
+        let tree = gen_tree(Shape::RightSpine, 100000).unwrap();
+        let parsed = Box::new(from_newick(&to_newick(&*tree)).unwrap());
+        assert!(*parsed == *tree);
+        drop_tree::<TestNode<_>>(tree);
+        drop_tree::<TestNode<_>>(parsed);
+    }
+
+    #[test]
+    fn phylogenetic() {
+        let tree = from_newick::<String>(" ((A:0.1, B :2e-3)AB[comment] :0.5 ,C)'root node' ;\n").unwrap();
+        assert_eq!(tree, parse_tree("root node(AB(A,B),C)").unwrap());
+        assert_eq!(from_newick::<u32>("(1)2").unwrap(), parse_tree("2(1)").unwrap());
+    }
+
+    #[test]
+    fn errors() {
+        let parse = |text| from_newick::<u32>(text).map(|_| ());
+        assert_eq!(parse("(1,2,3)4;"), Err(ParseError::Syntax(6)));
+        assert_eq!(parse("(1,2;"), Err(ParseError::Syntax(4)));
+        assert_eq!(parse("(1:x)2"), Err(ParseError::Syntax(3)));
+        assert_eq!(parse("('1)2"), Err(ParseError::Syntax(5)));
+        assert_eq!(parse("1;2"), Err(ParseError::Syntax(2)));
+        assert_eq!(parse(";"), Err(ParseError::Syntax(1)));
+        assert!(matches!(parse("(a)1"), Err(ParseError::Value(_))));
+    }
+}
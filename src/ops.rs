@@ -0,0 +1,120 @@
+//! Operations on a `CountTree` as data.
+//!
+//! An `Op` describes a single call of a `CountTree` method, so that a
+//! sequence of them can be stored, printed in bug reports, (de)serialized
+//! with the `serde` feature, or generated by a fuzzer, and then replayed
+//! deterministically with `apply_ops`. Applying an operation never panics:
+//! indices are taken modulo the valid range (`len + 1` for `Insert` and
+//! `SplitAt`, `len` otherwise), and operations needing an index do nothing on
+//! an empty tree.
+
+use std::mem;
+
+use count::CountTree;
+
+/// A single operation on a `CountTree`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature="serde", derive(Serialize, Deserialize))]
+pub enum Op<T> {
+    Insert(usize, T),
+    Remove(usize),
+    Get(usize),
+    Set(usize, T),
+    PushFront(T),
+    PushBack(T),
+    PopFront,
+    PopBack,
+    Reverse,
+    Flush,
+    /// `SplitAt(at, index)` splits the tree at `at` (see `split_at`), then
+    /// inserts the first part into the second one at `index` (see
+    /// `insert_tree`), which is taken modulo the length of the second part
+    /// plus one.
+    SplitAt(usize, usize),
+    /// Removes (and drops) up to the given number of elements from the front.
+    PopFrontN(usize),
+    /// Removes (and drops) up to the given number of elements from the back.
+    PopBackN(usize),
+}
+
+/// Applies `op` to `tree`, returning the element removed (by `Remove`,
+/// `PopFront` or `PopBack`), read (by `Get`) or overwritten (by `Set`), if
+/// any.
+pub fn apply_op<T: Clone>(tree: &mut CountTree<T>, op: &Op<T>) -> Option<T> {
+    use self::Op::*;
+
+    let len = tree.len();
+    match *op {
+        Insert(index, ref value) => tree.insert(index % (len + 1), value.clone()),
+        Remove(_) | Get(_) | Set(_, _) if len == 0 => (),
+        Remove(index) => return Some(tree.remove(index % len)),
+        Get(index) => return tree.get(index % len).cloned(),
+        Set(index, ref value) => {
+            let slot = tree.get_mut(index % len).unwrap();
+            return Some(mem::replace(slot, value.clone()));
+        }
+        PushFront(ref value) => tree.push_front(value.clone()),
+        PushBack(ref value) => tree.push_back(value.clone()),
+        PopFront => return tree.pop_front(),
+        PopBack => return tree.pop_back(),
+        Reverse => tree.reverse(),
+        Flush => tree.flush(),
+        SplitAt(at, index) => {
+            let (front, mut rest) = mem::take(tree).split_at(at % (len + 1));
+            let index = index % (rest.len() + 1);
+            rest.insert_tree(index, front);
+            *tree = rest;
+        }
+        PopFrontN(n) => {
+            tree.pop_front_n(n);
+        }
+        PopBackN(n) => {
+            tree.pop_back_n(n);
+        }
+    }
+    None
+}
+
+/// Applies `ops` to `tree` in order, returning the results of `apply_op`.
+pub fn apply_ops<T: Clone>(tree: &mut CountTree<T>, ops: &[Op<T>]) -> Vec<Option<T>> {
+    ops.iter().map(|op| apply_op(tree, op)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use count::CountTree;
+    use super::apply_ops;
+    use super::Op::*;
+
+    #[test]
+    fn replay() {
+        let ops = [PushBack(1), PushFront(0), Insert(7, 2), Get(5), Set(4, 3), Remove(1), PopFront, Reverse,
+                   Flush, PopBack, PopBack, PopBack, Remove(0)];
+        let mut ct = CountTree::new();
+        assert_eq!(apply_ops(&mut ct, &ops),
+                   [None, None, None, Some(1), Some(2), Some(3), Some(0), None, None, Some(1), None, None, None]);
+        assert!(ct.is_empty());
+    }
+
+    #[test]
+    fn split() {
+        let mut ct: CountTree<_> = (0..10).collect();
+        let ops = [SplitAt(13, 2), PopFrontN(2), PopBackN(1), SplitAt(0, 100)];
+        assert_eq!(apply_ops(&mut ct, &ops), [None, None, None, None]);
+        assert_eq!(ct.to_vec(), [0, 1, 4, 5, 6, 7, 8]);
+        apply_ops(&mut ct, &[PopBackN(100), SplitAt(5, 5), PopFrontN(1)]);
+        assert!(ct.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature="json")]
+    fn serde() {
+        use serde_json;
+        use super::Op;
+
+        let ops = vec![Insert(3, 'a'), PopFront, Flush];
+        let json = serde_json::to_string(&ops).unwrap();
+        assert_eq!(json, r#"[{"Insert":[3,"a"]},"PopFront","Flush"]"#);
+        assert_eq!(serde_json::from_str::<Vec<Op<char>>>(&json).unwrap(), ops);
+    }
+}
@@ -0,0 +1,520 @@
+//! Counting tree storing its elements in chunks.
+//!
+//! A `PackedCountTree` is a list like `CountTree`, but each of its nodes holds
+//! a chunk of up to `chunk_capacity::<T>()` consecutive elements (at least 4,
+//! and about 512 bytes worth of them) instead of a single one, and caches the
+//! number of elements in its subtree as well as the number of nodes. The
+//! tree is balanced over the chunks, so there are far fewer nodes to allocate
+//! and follow, and neighbouring elements share a cache line. This makes it
+//! competitive with `Vec` for small elements, where `CountTree` spends most
+//! of its memory and time on the nodes.
+//!
+//! Inserting into a full chunk splits it in two, and removing elements merges
+//! a chunk which is less than a quarter full with the next (or else the
+//! previous) one, if they fit together.
+//!
+//! ```
+//! use binary_tree::packed::PackedCountTree;
+//!
+//! let mut pt: PackedCountTree<u8> = (0..200).collect();
+//! pt.insert(100, 255);
+//! assert_eq!(pt.remove(0), 0);
+//! assert_eq!(pt.get(99), Some(&255));
+//! assert_eq!(pt.len(), 200);
+//! ```
+
+use std::cmp;
+use std::fmt::{self, Debug};
+use std::iter::{Flatten, FromIterator};
+use std::mem;
+
+use BinaryTree;
+use Node;
+use NodeMut;
+use avl::{self, Balanced};
+use iter::{IntoIter, Iter};
+
+/// Returns the maximum number of elements in a chunk of a
+/// `PackedCountTree<T>`.
+pub fn chunk_capacity<T>() -> usize {
+    cmp::max(4, 512 / cmp::max(1, mem::size_of::<T>()))
+}
+
+/// Node of a `PackedCountTree`, holding a non-empty chunk of elements.
+pub struct PackedNode<T> {
+    chunk: Vec<T>,
+    left: Option<Box<PackedNode<T>>>,
+    right: Option<Box<PackedNode<T>>>,
+    // number of nodes in the subtree, used for balancing
+    nodes: u32,
+    // number of elements in the subtree
+    len: u32,
+    height: u16,
+}
+
+impl<T> PackedNode<T> {
+    fn new(chunk: Vec<T>) -> PackedNode<T> {
+        PackedNode {
+            len: chunk.len() as u32,
+            chunk,
+            left: None,
+            right: None,
+            nodes: 1,
+            height: 0,
+        }
+    }
+
+    // number of elements in the subtree
+    fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    fn llen(&self) -> usize {
+        self.left.as_ref().map_or(0, |node| node.len as usize)
+    }
+
+    fn update_stats(&mut self) {
+        let (nodes, height) = avl::stats(self.left.as_deref(), self.right.as_deref());
+        self.nodes = nodes;
+        self.height = height;
+        self.len = self.llen() as u32 + self.chunk.len() as u32 + self.right.as_ref().map_or(0, |node| node.len);
+    }
+}
+
+impl<T> Balanced for PackedNode<T> {
+    fn count(&self) -> u32 {
+        self.nodes
+    }
+
+    fn height(&self) -> u16 {
+        self.height
+    }
+}
+
+impl<T> Node for PackedNode<T> {
+    type Value = Vec<T>;
+
+    fn left(&self) -> Option<&Self> {
+        self.left.as_deref()
+    }
+
+    fn right(&self) -> Option<&Self> {
+        self.right.as_deref()
+    }
+
+    fn value(&self) -> &Vec<T> {
+        &self.chunk
+    }
+}
+
+impl<T> NodeMut for PackedNode<T> {
+    type NodePtr = Box<PackedNode<T>>;
+
+    fn detach_left(&mut self) -> Option<Self::NodePtr> {
+        let tree = self.left.take();
+        self.update_stats();
+        tree
+    }
+
+    fn detach_right(&mut self) -> Option<Self::NodePtr> {
+        let tree = self.right.take();
+        self.update_stats();
+        tree
+    }
+
+    fn insert_left(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.left, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn insert_right(&mut self, mut tree: Option<Self::NodePtr>) -> Option<Self::NodePtr> {
+        mem::swap(&mut self.right, &mut tree);
+        self.update_stats();
+        tree
+    }
+
+    fn value_mut(&mut self) -> &mut Vec<T> {
+        &mut self.chunk
+    }
+
+    fn into_parts(self) -> (Vec<T>, Option<Self::NodePtr>, Option<Self::NodePtr>) {
+        (self.chunk, self.left, self.right)
+    }
+
+    fn left_mut(&mut self) -> Option<&mut Self> {
+        self.left.as_deref_mut()
+    }
+
+    fn right_mut(&mut self) -> Option<&mut Self> {
+        self.right.as_deref_mut()
+    }
+}
+
+/// A balanced list of chunks of elements, see the module documentation.
+pub struct PackedCountTree<T> {
+    root: Option<Box<PackedNode<T>>>,
+}
+
+impl<T> PackedCountTree<T> {
+    /// Returns an empty `PackedCountTree`.
+    pub fn new() -> PackedCountTree<T> {
+        PackedCountTree { root: None }
+    }
+
+    /// Returns the number elements in the tree. Time complexity: O(1)
+    pub fn len(&self) -> usize {
+        self.root.as_ref().map_or(0, |node| node.len())
+    }
+
+    /// Returns `true` if the tree contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    /// Returns the element at the given index, or `None` if index is out of
+    /// bounds. Time complexity: O(log(n))
+    pub fn get(&self, mut index: usize) -> Option<&T> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut node = self.root.as_deref().unwrap();
+        loop {
+            let llen = node.llen();
+            if index < llen {
+                node = node.left.as_deref().unwrap();
+            } else if index < llen + node.chunk.len() {
+                return node.chunk.get(index - llen);
+            } else {
+                index -= llen + node.chunk.len();
+                node = node.right.as_deref().unwrap();
+            }
+        }
+    }
+
+    /// Returns a mutable reference to the element at the given index, or
+    /// `None` if out of bounds. Time complexity: O(log(n))
+    pub fn get_mut(&mut self, mut index: usize) -> Option<&mut T> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut node = self.root.as_deref_mut().unwrap();
+        loop {
+            let llen = node.llen();
+            if index < llen {
+                node = node.left.as_deref_mut().unwrap();
+            } else if index < llen + node.chunk.len() {
+                return node.chunk.get_mut(index - llen);
+            } else {
+                index -= llen + node.chunk.len();
+                node = node.right.as_deref_mut().unwrap();
+            }
+        }
+    }
+
+    /// Inserts an element at the given index. Time complexity:
+    /// O(log(n) + c) for chunks of c elements
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`
+    pub fn insert(&mut self, index: usize, value: T) {
+        assert!(index <= self.len(), "index out of bounds!");
+        if self.root.is_none() {
+            self.root = Some(Box::new(PackedNode::new(vec![value])));
+            return;
+        }
+        let capacity = chunk_capacity::<T>();
+        // the index of the node, and the upper half of its chunk if it was split
+        let mut split = (0, None);
+        self.walk_to(index,
+                     true,
+                     |node, offset, node_index| {
+                         if node.chunk.len() == capacity {
+                             let mut upper = node.chunk.split_off(capacity / 2);
+                             if offset > capacity / 2 {
+                                 upper.insert(offset - capacity / 2, value);
+                             } else {
+                                 node.chunk.insert(offset, value);
+                             }
+                             split = (node_index, Some(upper));
+                         } else {
+                             node.chunk.insert(offset, value);
+                         }
+                     });
+        if let (node_index, Some(upper)) = split {
+            avl::insert(&mut self.root, node_index + 1, Box::new(PackedNode::new(upper)));
+        }
+    }
+
+    /// Removes the element at the given index. Time complexity:
+    /// O(log(n) + c) for chunks of c elements
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "index out of bounds!");
+        let mut removed = None;
+        let mut chunk = (0, 0);
+        self.walk_to(index,
+                     false,
+                     |node, offset, node_index| {
+                         removed = Some(node.chunk.remove(offset));
+                         chunk = (node_index, node.chunk.len());
+                     });
+        let (node_index, chunk_len) = chunk;
+        if chunk_len == 0 {
+            avl::remove(&mut self.root, node_index);
+        } else if chunk_len < chunk_capacity::<T>() / 4 && !self.merge_next(node_index) && node_index > 0 {
+            self.merge_next(node_index - 1);
+        }
+        removed.unwrap()
+    }
+
+    /// Prepends an element. Time complexity: O(log(n) + c)
+    pub fn push_front(&mut self, value: T) {
+        self.insert(0, value);
+    }
+
+    /// Appends an element. Time complexity: O(log(n))
+    pub fn push_back(&mut self, value: T) {
+        let len = self.len();
+        self.insert(len, value);
+    }
+
+    /// Removes and returns the first element, or `None` if empty.
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.remove(0))
+        }
+    }
+
+    /// Removes and returns the last element, or `None` if empty.
+    pub fn pop_back(&mut self) -> Option<T> {
+        let len = self.len();
+        if len == 0 {
+            None
+        } else {
+            Some(self.remove(len - 1))
+        }
+    }
+
+    /// Returns an iterator over the elements.
+    pub fn iter(&self) -> Flatten<Iter<'_, PackedNode<T>>> {
+        Iter::new(self.root.as_deref()).flatten()
+    }
+
+    // Walks down to the chunk holding the element at `index` (or, if
+    // `at_end`, the one it can be appended to), calls `f` with the node, the
+    // offset of `index` in its chunk and the index of the node, and updates
+    // the stats on the way up.
+    fn walk_to<F>(&mut self, index: usize, at_end: bool, f: F)
+        where F: FnOnce(&mut PackedNode<T>, usize, usize)
+    {
+        use WalkAction::*;
+
+        // the index relative to the current subtree, and the number of nodes
+        // before it
+        let mut state = (index, 0);
+        self.root.as_mut().unwrap().walk_reshape_state(&mut state,
+                                                       |node, &mut (ref mut index, ref mut node_index)| {
+                                                           let llen = node.llen();
+                                                           let end = llen + node.chunk.len();
+                                                           if *index < llen {
+                                                               Left
+                                                           } else if *index < end || at_end && *index == end {
+                                                               Stop
+                                                           } else {
+                                                               *index -= end;
+                                                               *node_index += avl::lcount(node) as usize + 1;
+                                                               Right
+                                                           }
+                                                       },
+                                                       |node, &mut (index, node_index)| {
+                                                           let offset = index - node.llen();
+                                                           let node_index = node_index + avl::lcount(node) as usize;
+                                                           f(node, offset, node_index);
+                                                           node.update_stats();
+                                                       },
+                                                       |_, _, _| ());
+    }
+
+    // Moves the elements of the node after the one at `node_index` into it,
+    // if they fit in one chunk. Returns whether they did.
+    fn merge_next(&mut self, node_index: usize) -> bool {
+        let nodes = self.root.as_ref().map_or(0, |node| node.nodes as usize);
+        if node_index + 1 >= nodes {
+            return false;
+        }
+        let len = self.node_at(node_index).chunk.len() + self.node_at(node_index + 1).chunk.len();
+        if len > chunk_capacity::<T>() {
+            return false;
+        }
+        let next = avl::remove(&mut self.root, node_index + 1);
+        let mut index = node_index as u32;
+        self.root.as_mut().unwrap().walk_reshape(|node| avl::index_walker(&mut index, node),
+                                                 move |node| {
+                                                     node.chunk.extend(next.chunk);
+                                                     node.update_stats();
+                                                 },
+                                                 |_, _| ());
+        true
+    }
+
+    // Returns the node at `node_index`, in order.
+    fn node_at(&self, node_index: usize) -> &PackedNode<T> {
+        use WalkAction::*;
+
+        let mut node_index = node_index as u32;
+        let mut node = self.root.as_deref().unwrap();
+        loop {
+            node = match avl::index_walker(&mut node_index, node) {
+                Left => node.left.as_deref().unwrap(),
+                Right => node.right.as_deref().unwrap(),
+                Stop => return node,
+            };
+        }
+    }
+}
+
+impl<T> BinaryTree for PackedCountTree<T> {
+    type Node = PackedNode<T>;
+
+    fn root(&self) -> Option<&Self::Node> {
+        self.root.as_deref()
+    }
+}
+
+impl<T> Default for PackedCountTree<T> {
+    fn default() -> PackedCountTree<T> {
+        PackedCountTree::new()
+    }
+}
+
+impl<T: Debug> Debug for PackedCountTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> FromIterator<T> for PackedCountTree<T> {
+    /// Fills the chunks to capacity. Time complexity: O(n)
+    fn from_iter<I>(iterable: I) -> Self
+        where I: IntoIterator<Item = T>
+    {
+        let capacity = chunk_capacity::<T>();
+        let mut chunks = Vec::new();
+        let mut chunk = Vec::with_capacity(capacity);
+        for value in iterable {
+            chunk.push(value);
+            if chunk.len() == capacity {
+                chunks.push(mem::replace(&mut chunk, Vec::with_capacity(capacity)));
+            }
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        PackedCountTree { root: avl::build_balanced(chunks.len(), &mut chunks.into_iter(), &PackedNode::new) }
+    }
+}
+
+impl<T> IntoIterator for PackedCountTree<T> {
+    type Item = T;
+    type IntoIter = Flatten<IntoIter<PackedNode<T>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter::new(self.root).flatten()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a PackedCountTree<T> {
+    type Item = &'a T;
+    type IntoIter = Flatten<Iter<'a, PackedNode<T>>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem;
+
+    use BinaryTree;
+    use test::compute_level;
+    use super::{chunk_capacity, PackedCountTree, PackedNode};
+
+    // Verifies the cached stats, and that no chunk is empty or over capacity.
+    fn check<T>(node: &PackedNode<T>) -> (u32, u32, u16) {
+        let left = node.left.as_deref().map(check);
+        let right = node.right.as_deref().map(check);
+        assert!(!node.chunk.is_empty() && node.chunk.len() <= chunk_capacity::<T>());
+        let nodes = left.map_or(0, |s| s.0) + right.map_or(0, |s| s.0) + 1;
+        let len = left.map_or(0, |s| s.1) + right.map_or(0, |s| s.1) + node.chunk.len() as u32;
+        let height = match (left, right) {
+            (None, None) => 0,
+            _ => left.map_or(0, |s| s.2).max(right.map_or(0, |s| s.2)) + 1,
+        };
+        assert_eq!((node.nodes, node.len, node.height), (nodes, len, height));
+        (nodes, len, height)
+    }
+
+    #[test]
+    fn edits() {
+        assert_eq!(chunk_capacity::<u64>(), 64);
+        let mut pt = PackedCountTree::new();
+        let mut model = Vec::new();
+        let mut state = 54321u32;
+        for step in 0..5000 {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            let r = (state >> 8) as usize;
+            // grow first, then shrink
+            if model.is_empty() || (r % 5 < 3) == (step < 3000) {
+                let index = r % (model.len() + 1);
+                pt.insert(index, step as u64);
+                model.insert(index, step as u64);
+            } else {
+                let index = r % model.len();
+                assert_eq!(pt.remove(index), model.remove(index));
+            }
+            if step % 250 == 0 {
+                assert_eq!(pt.iter().cloned().collect::<Vec<_>>(), model);
+                if let Some(root) = pt.root() {
+                    check(root);
+                    assert!(compute_level(root, 1).is_balanced());
+                }
+            }
+        }
+        for (index, value) in model.iter().enumerate() {
+            assert_eq!(pt.get(index), Some(value));
+        }
+        assert_eq!(pt.get(model.len()), None);
+        *pt.get_mut(3).unwrap() = 7;
+        model[3] = 7;
+        assert_eq!(pt.into_iter().collect::<Vec<_>>(), model);
+    }
+
+    #[test]
+    fn packing() {
+        let mut pt: PackedCountTree<u8> = (0..=255).collect();
+        assert_eq!(chunk_capacity::<u8>(), 512);
+        assert_eq!(pt.root().unwrap().chunk.len(), 256);
+        assert!(mem::size_of::<PackedNode<u8>>() <= 56);
+        while pt.len() > 2 {
+            pt.pop_front();
+            pt.pop_back();
+        }
+        assert_eq!(format!("{:?}", pt), "[127, 128]");
+        let mut pt: PackedCountTree<_> = (0..1000u64).collect();
+        for _ in 0..980 {
+            pt.remove(10);
+        }
+        // merged into a single chunk
+        let root = pt.root().unwrap();
+        check(root);
+        assert_eq!((root.nodes, root.len()), (1, 20));
+    }
+}
@@ -0,0 +1,92 @@
+//! Parallel traversals (requires the `rayon` feature).
+//!
+//! The top of the tree is visited breadth-first until there are a few times
+//! as many disjoint subtrees as there are threads, and the subtrees are then
+//! traversed in parallel (each one iteratively). Thus the speedup depends on
+//! the shape of the tree: a balanced tree splits well, while a degenerate one
+//! does not split at all.
+
+use std::collections::VecDeque;
+
+use rayon::prelude::*;
+
+use Node;
+use iter::PreOrderIter;
+
+// Returns the nodes visited while splitting, and the subtrees split off.
+fn split<N: Node>(root: &N) -> (Vec<&N>, Vec<&N>) {
+    let parts = 4 * rayon::current_num_threads();
+    let mut top = Vec::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(root);
+    while queue.len() < parts {
+        match queue.pop_front() {
+            Some(node) => {
+                top.push(node);
+                queue.extend(node.left());
+                queue.extend(node.right());
+            }
+            None => break,
+        }
+    }
+    (top, queue.into_iter().collect())
+}
+
+/// Folds the values of the tree rooted at `root` in parallel. Each thread
+/// folds some of the values into an accumulator created by `identity`, and
+/// the accumulators are then combined with `reduce`. The values are visited
+/// in no particular order, so `fold` and `reduce` should be insensitive to
+/// it (like summing, or searching).
+pub fn par_fold<N, B, ID, F, R>(root: &N, identity: ID, fold: F, reduce: R) -> B
+    where N: Node + Sync,
+          B: Send,
+          ID: Fn() -> B + Sync,
+          F: Fn(B, &N::Value) -> B + Sync,
+          R: Fn(B, B) -> B + Sync
+{
+    let (top, subtrees) = split(root);
+    let acc = subtrees.into_par_iter()
+                      .map(|subtree| PreOrderIter::new(Some(subtree)).fold(identity(), &fold))
+                      .reduce(&identity, &reduce);
+    let top_acc = top.into_iter().map(N::value).fold(identity(), &fold);
+    reduce(top_acc, acc)
+}
+
+/// Calls `f` on each value of the tree rooted at `root`, in parallel, and in
+/// no particular order.
+pub fn par_for_each<N, F>(root: &N, f: F)
+    where N: Node + Sync,
+          F: Fn(&N::Value) + Sync
+{
+    let (top, subtrees) = split(root);
+    subtrees.into_par_iter().for_each(|subtree| PreOrderIter::new(Some(subtree)).for_each(&f));
+    top.into_iter().map(N::value).for_each(f);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use algos::drop_tree;
+    use test::{TestNode, Shape, gen_tree};
+    use super::{par_fold, par_for_each};
+
+    #[test]
+    fn fold_and_for_each() {
+        for &shape in &[Shape::PerfectlyBalanced, Shape::Random(11), Shape::RightSpine] {
+            let tree = gen_tree(shape, 10000).unwrap();
+            let sum = par_fold(&*tree, || 0, |acc, v| acc + v, |a, b| a + b);
+            assert_eq!(sum, 10000 * 9999 / 2);
+            let max = par_fold(&*tree, || None, |acc: Option<usize>, &v| acc.max(Some(v)), |a, b| a.max(b));
+            assert_eq!(max, Some(9999));
+
+            let count = AtomicUsize::new(0);
+            par_for_each(&*tree, |&v| {
+                count.fetch_add(v % 2, Ordering::Relaxed);
+            });
+            assert_eq!(count.into_inner(), 5000);
+            drop_tree::<TestNode<_>>(tree);
+        }
+        assert_eq!(par_fold(&TestNode::new(3), || 1, |acc, v| acc * v, |a, b| a * b), 3);
+    }
+}
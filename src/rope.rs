@@ -0,0 +1,328 @@
+//! Text indexed by chars, bytes and lines.
+//!
+//! A `Rope` is an `AugmentedCountTree` of chars, whose subtrees are
+//! summarized by their length in bytes (in UTF-8) and their number of line
+//! breaks (`'\n'`). The positions of the chars are tracked by the tree as
+//! usual, so converting between char indices, byte offsets and line numbers
+//! takes O(log(n)) time however the text is edited, which is the addressing a
+//! text editor needs.
+//!
+//! ```
+//! use binary_tree::rope::Rope;
+//!
+//! let mut rope = Rope::from("fn main() {\n}\n");
+//! rope.insert(12, "    println!(\"héllo\");\n");
+//! assert_eq!(rope.len_lines(), 4);
+//! assert_eq!(rope.line_to_char(2), 35);
+//! assert_eq!(rope.char_to_line(20), 1);
+//! assert_eq!(rope.line(1), "    println!(\"héllo\");\n");
+//! assert_eq!(rope.char_to_byte(35), 36);
+//! ```
+
+use std::fmt::{self, Debug, Display};
+use std::ops::Range;
+
+use BinaryTree;
+use Node;
+use augment::{AugNode, Augment, AugmentedCountTree};
+use avl::Balanced;
+
+/// Summary of a piece of text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextInfo {
+    /// Length in bytes, encoded in UTF-8.
+    pub bytes: usize,
+    /// Number of line breaks.
+    pub newlines: usize,
+}
+
+impl TextInfo {
+    fn of(c: char) -> TextInfo {
+        TextInfo {
+            bytes: c.len_utf8(),
+            newlines: (c == '\n') as usize,
+        }
+    }
+}
+
+/// Summarizes chars into `TextInfo`s.
+pub struct Text;
+
+impl Augment<char> for Text {
+    type Summary = TextInfo;
+
+    fn combine(left: Option<&TextInfo>, value: &char, right: Option<&TextInfo>) -> TextInfo {
+        let mut info = TextInfo::of(*value);
+        for side in left.iter().chain(right.iter()) {
+            info.bytes += side.bytes;
+            info.newlines += side.newlines;
+        }
+        info
+    }
+}
+
+/// Text as a balanced tree of chars, see the module documentation.
+#[derive(Default)]
+pub struct Rope {
+    chars: AugmentedCountTree<char, Text>,
+}
+
+impl Rope {
+    /// Returns an empty `Rope`.
+    pub fn new() -> Rope {
+        Rope { chars: AugmentedCountTree::new() }
+    }
+
+    /// Returns the length in chars. Time complexity: O(1)
+    pub fn len_chars(&self) -> usize {
+        self.chars.len()
+    }
+
+    /// Returns the length in bytes. Time complexity: O(1)
+    pub fn len_bytes(&self) -> usize {
+        self.info().bytes
+    }
+
+    /// Returns the number of lines, which is one more than the number of line
+    /// breaks (the last line may be empty). Time complexity: O(1)
+    pub fn len_lines(&self) -> usize {
+        self.info().newlines + 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    /// Returns the char at the given index, or `None` if out of bounds. Time
+    /// complexity: O(log(n))
+    pub fn char(&self, index: usize) -> Option<char> {
+        self.chars.get(index).cloned()
+    }
+
+    /// Inserts `text` at the given char index. Time complexity:
+    /// O(k log(n)) for k chars
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len_chars()`
+    pub fn insert(&mut self, index: usize, text: &str) {
+        for (i, c) in text.chars().enumerate() {
+            self.chars.insert(index + i, c);
+        }
+    }
+
+    /// Removes the chars in `range`. Time complexity: O(k log(n)) for k chars
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn remove(&mut self, range: Range<usize>) {
+        assert!(range.start <= range.end && range.end <= self.len_chars(),
+                "range {:?} out of bounds for length {}", range, self.len_chars());
+        for _ in range.clone() {
+            self.chars.remove(range.start);
+        }
+    }
+
+    /// Returns the line holding the char at the given index (or the last line
+    /// for the index `len_chars()`). Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len_chars()`
+    pub fn char_to_line(&self, index: usize) -> usize {
+        self.prefix(index).newlines
+    }
+
+    /// Returns the index of the first char of the given line. Time
+    /// complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if line is not less than `self.len_lines()`
+    pub fn line_to_char(&self, line: usize) -> usize {
+        assert!(line < self.len_lines(), "line out of bounds!");
+        if line == 0 {
+            0
+        } else {
+            // just after the line break ending the previous line
+            self.search(line - 1, |info| info.newlines) + 1
+        }
+    }
+
+    /// Returns the byte offset of the char at the given index. Time
+    /// complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len_chars()`
+    pub fn char_to_byte(&self, index: usize) -> usize {
+        self.prefix(index).bytes
+    }
+
+    /// Returns the index of the char containing the byte at the given offset
+    /// (or `len_chars()` for the offset `len_bytes()`). Time complexity:
+    /// O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if offset is greater than `self.len_bytes()`
+    pub fn byte_to_char(&self, offset: usize) -> usize {
+        assert!(offset <= self.len_bytes(), "offset out of bounds!");
+        self.search(offset, |info| info.bytes)
+    }
+
+    /// Returns the given line, including its line break (if any). Time
+    /// complexity: O(k + log(n)) for k chars
+    ///
+    /// ## Panics
+    ///
+    /// Panics if line is not less than `self.len_lines()`
+    pub fn line(&self, line: usize) -> String {
+        let start = self.line_to_char(line);
+        let end = if line + 1 < self.len_lines() {
+            self.line_to_char(line + 1)
+        } else {
+            self.len_chars()
+        };
+        self.slice(start..end)
+    }
+
+    /// Returns the chars in `range` as a `String`. Time complexity:
+    /// O(k + log(n)) for k chars
+    ///
+    /// ## Panics
+    ///
+    /// Panics if `range` is out of bounds.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        assert!(range.start <= range.end && range.end <= self.len_chars(),
+                "range {:?} out of bounds for length {}", range, self.len_chars());
+        let mut text = String::new();
+        if let Some(root) = self.chars.root() {
+            if range.start < range.end {
+                push_range(root, range.start, range.end, &mut text);
+            }
+        }
+        text
+    }
+
+    /// Returns an iterator over the chars.
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.chars.iter().cloned()
+    }
+
+    fn info(&self) -> TextInfo {
+        self.chars.summary().cloned().unwrap_or_default()
+    }
+
+    // summary of the chars before `index`
+    fn prefix(&self, index: usize) -> TextInfo {
+        self.chars.range_summary(0..index).unwrap_or_default()
+    }
+
+    // Returns the index of the first char at which `key` of the text up to
+    // and including it exceeds `target`, or the length if there is none.
+    fn search<F>(&self, mut target: usize, key: F) -> usize
+        where F: Fn(&TextInfo) -> usize
+    {
+        let mut index = 0;
+        let mut node = self.chars.root();
+        while let Some(n) = node {
+            let left = n.left().map_or(0, |left| key(left.summary()));
+            if target < left {
+                node = n.left();
+                continue;
+            }
+            target -= left;
+            index += n.left().map_or(0, |left| left.count() as usize);
+            let own = key(&TextInfo::of(*n.value()));
+            if target < own {
+                return index;
+            }
+            target -= own;
+            index += 1;
+            node = n.right();
+        }
+        index
+    }
+}
+
+// Appends the chars `start..end` (a non-empty range) of the subtree at `node`
+// to `text`. Recursion depth is O(height).
+fn push_range(node: &AugNode<char, Text>, start: usize, end: usize, text: &mut String) {
+    let lcount = node.left().map_or(0, |left| left.count() as usize);
+    if start < lcount {
+        push_range(node.left().unwrap(), start, end.min(lcount), text);
+    }
+    if start <= lcount && lcount < end {
+        text.push(*node.value());
+    }
+    if end > lcount + 1 {
+        push_range(node.right().unwrap(), start.saturating_sub(lcount + 1), end - lcount - 1, text);
+    }
+}
+
+impl<'a> From<&'a str> for Rope {
+    fn from(text: &'a str) -> Rope {
+        Rope { chars: text.chars().collect() }
+    }
+}
+
+impl Display for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        self.chars().try_for_each(|c| fmt::Write::write_char(f, c))
+    }
+}
+
+impl Debug for Rope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        Debug::fmt(&self.to_string(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rope;
+
+    const TEXT: &str = "ab\ncδe\n\nΦgh\nij";
+
+    #[test]
+    fn conversions() {
+        let rope = Rope::from(TEXT);
+        assert_eq!(rope.to_string(), TEXT);
+        assert_eq!((rope.len_chars(), rope.len_bytes(), rope.len_lines()), (14, 16, 5));
+        let chars: Vec<_> = TEXT.char_indices().collect();
+        for (index, &(offset, _)) in chars.iter().enumerate() {
+            assert_eq!(rope.char_to_byte(index), offset);
+            assert_eq!(rope.byte_to_char(offset), index);
+            assert_eq!(rope.char_to_line(index), TEXT[..offset].matches('\n').count());
+        }
+        assert_eq!(rope.char_to_byte(14), 16);
+        assert_eq!(rope.byte_to_char(16), 14);
+        assert_eq!(rope.byte_to_char(5), 4);
+        assert_eq!(rope.char_to_line(14), 4);
+        let starts: Vec<_> = (0..5).map(|line| rope.line_to_char(line)).collect();
+        assert_eq!(starts, [0, 3, 7, 8, 12]);
+        let lines: Vec<_> = (0..5).map(|line| rope.line(line)).collect();
+        assert_eq!(lines, ["ab\n", "cδe\n", "\n", "Φgh\n", "ij"]);
+
+        let empty = Rope::new();
+        assert_eq!((empty.len_lines(), empty.line_to_char(0), empty.line(0)), (1, 0, String::new()));
+    }
+
+    #[test]
+    fn edits() {
+        let mut rope = Rope::from(TEXT);
+        rope.insert(4, "x\ny");
+        rope.remove(0..3);
+        let mut text = TEXT.to_string();
+        text.insert_str(4, "x\ny");
+        text.replace_range(0..3, "");
+        assert_eq!(rope.to_string(), text);
+        assert_eq!(rope.len_lines(), 5);
+        assert_eq!(rope.line(0), "cx\n");
+        assert_eq!(rope.line(1), "yδe\n");
+        assert_eq!(rope.slice(2..6), "\nyδe");
+        assert_eq!(format!("{:?}", rope), format!("{:?}", text));
+    }
+}
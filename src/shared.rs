@@ -0,0 +1,194 @@
+//! Counting tree shared between concurrent readers and writers.
+//!
+//! A `SharedCountTree` publishes versions of an `ArcCountTree` through an
+//! atomic pointer, in the style of read-copy-update: a writer clones the
+//! current version (in O(1) time, since the nodes are shared), edits the clone
+//! (copying only the nodes along the modified paths) and swaps it in, while
+//! readers load a consistent snapshot of whichever version is current without
+//! ever taking a lock or waiting for a writer. Writers are serialized by a
+//! mutex, which readers never touch.
+//!
+//! A replaced version is freed by the first writer that sees no reader in the
+//! middle of a `load` (which only takes a few atomic operations), or when the
+//! `SharedCountTree` is dropped. Snapshots keep their version alive as long
+//! as they are held.
+//!
+//! ```
+//! use std::sync::Arc;
+//! use std::thread;
+//! use binary_tree::shared::SharedCountTree;
+//!
+//! let list = Arc::new(SharedCountTree::new((0..100).collect()));
+//! let reader = {
+//!     let list = list.clone();
+//!     thread::spawn(move || {
+//!         let snapshot = list.load();
+//!         assert!(snapshot.len() == 100 || snapshot.len() == 101);
+//!     })
+//! };
+//! list.update(|tree| tree.push_front(-1));
+//! reader.join().unwrap();
+//! assert_eq!(list.load().get(0), Some(&-1));
+//! ```
+
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use count::ArcCountTree;
+use cow;
+
+/// An `ArcCountTree` with wait-free snapshots, see the module documentation.
+pub struct SharedCountTree<T: Clone> {
+    // the current version, owning one reference count of its `Arc`
+    current: AtomicPtr<ArcCountTree<T>>,
+    // number of readers between loading `current` and taking a reference
+    readers: AtomicUsize,
+    // the replaced versions which readers may still be taking a reference to
+    retired: Mutex<Vec<Arc<ArcCountTree<T>>>>,
+}
+
+impl<T: Clone> SharedCountTree<T> {
+    /// Returns a `SharedCountTree` whose current version is `tree`.
+    pub fn new(mut tree: ArcCountTree<T>) -> SharedCountTree<T> {
+        // `tree` may share nodes with other trees
+        cow::allow_clones(|| tree.flush());
+        SharedCountTree {
+            current: AtomicPtr::new(Arc::into_raw(Arc::new(tree)) as *mut _),
+            readers: AtomicUsize::new(0),
+            retired: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns a snapshot of the current version, which is not affected by
+    /// later writes. Wait-free, time complexity: O(1)
+    pub fn load(&self) -> Arc<ArcCountTree<T>> {
+        self.readers.fetch_add(1, Ordering::SeqCst);
+        let ptr = self.current.load(Ordering::SeqCst);
+        // `ptr` cannot be freed while `readers` counts this thread
+        let snapshot = unsafe {
+            Arc::increment_strong_count(ptr);
+            Arc::from_raw(ptr)
+        };
+        self.readers.fetch_sub(1, Ordering::SeqCst);
+        snapshot
+    }
+
+    /// Replaces the current version with `tree`.
+    pub fn store(&self, mut tree: ArcCountTree<T>) {
+        cow::allow_clones(|| tree.flush());
+        let mut retired = self.lock();
+        self.publish(&mut retired, tree);
+    }
+
+    /// Calls `f` on a copy of the current version, publishes the result as
+    /// the new version and returns what `f` returned. Writers are serialized,
+    /// so no update is lost. If `f` panics, nothing is published. Time
+    /// complexity: O(1), plus that of the edits done by `f`
+    pub fn update<R, F>(&self, f: F) -> R
+        where F: FnOnce(&mut ArcCountTree<T>) -> R
+    {
+        let mut retired = self.lock();
+        // only writers replace `current`, so it stays alive while locked
+        let mut tree = unsafe { (*self.current.load(Ordering::SeqCst)).clone() };
+        // copying the nodes shared with the current version is intended
+        let ret = cow::allow_clones(|| {
+            let ret = f(&mut tree);
+            tree.flush();
+            ret
+        });
+        self.publish(&mut retired, tree);
+        ret
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Vec<Arc<ArcCountTree<T>>>> {
+        // the list of retired versions is valid even if a writer panicked
+        self.retired.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn publish(&self, retired: &mut Vec<Arc<ArcCountTree<T>>>, tree: ArcCountTree<T>) {
+        let new = Arc::into_raw(Arc::new(tree)) as *mut _;
+        let old = self.current.swap(new, Ordering::SeqCst);
+        retired.push(unsafe { Arc::from_raw(old) });
+        // a reader which loaded a retired pointer has been counted since
+        // before it was replaced, until it took its reference
+        if self.readers.load(Ordering::SeqCst) == 0 {
+            retired.clear();
+        }
+    }
+}
+
+impl<T: Clone> Default for SharedCountTree<T> {
+    fn default() -> SharedCountTree<T> {
+        SharedCountTree::new(ArcCountTree::default())
+    }
+}
+
+impl<T: Clone + Debug> Debug for SharedCountTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(&*self.load()).finish()
+    }
+}
+
+impl<T: Clone> Drop for SharedCountTree<T> {
+    fn drop(&mut self) {
+        drop(unsafe { Arc::from_raw(*self.current.get_mut()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use count::ArcCountTree;
+    use super::SharedCountTree;
+
+    #[test]
+    fn versions() {
+        let shared = SharedCountTree::new((0..10).collect());
+        let snapshot = shared.load();
+        assert_eq!(shared.update(|tree| tree.remove(0)), 0);
+        shared.update(|tree| tree.push_back(10));
+        assert_eq!(snapshot.to_vec(), (0..10).collect::<Vec<_>>());
+        assert_eq!(shared.load().to_vec(), (1..11).collect::<Vec<_>>());
+
+        // replaced versions are freed once no reader could be loading them
+        let old = Arc::downgrade(&shared.load());
+        shared.store(ArcCountTree::default());
+        assert!(old.upgrade().is_none());
+        assert_eq!(format!("{:?}", shared), "[]");
+        drop(shared);
+        assert_eq!(snapshot.len(), 10);
+    }
+
+    #[test]
+    fn concurrent() {
+        // every version holds `len` copies of `len`
+        let shared = Arc::new(SharedCountTree::new(ArcCountTree::default()));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let mut last = 0;
+                    while last < 200 {
+                        let snapshot = shared.load();
+                        let len = snapshot.len();
+                        assert!(len >= last);
+                        assert!(snapshot.fold(true, |ok, &x| ok && x == len));
+                        last = len;
+                    }
+                })
+            })
+            .collect();
+        for len in 1..201 {
+            shared.update(|tree| {
+                tree.for_each_mut(|x| *x = len);
+                tree.push_back(len);
+            });
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,408 @@
+//! Succinct static trees.
+//!
+//! A `SuccinctTree` is a frozen, read-only copy of a binary tree which encodes
+//! the shape using only two bits per node ([LOUDS][louds] representation), and
+//! stores the values in a flat array in level-order. Navigating from a node to
+//! its children takes O(1) time, and to its parent O(log(n)) time.
+//!
+//! [louds]: https://en.wikipedia.org/wiki/Succinct_data_structure
+
+use std::collections::VecDeque;
+use std::ops::DerefMut;
+
+use Node;
+use NodeMut;
+
+const WORD_BITS: usize = 64;
+
+/// Bit vector supporting rank and select queries.
+struct BitVec {
+    words: Vec<u64>,
+    // number of set bits in all the words preceding each word
+    ranks: Vec<u32>,
+    len: usize,
+}
+
+impl BitVec {
+    fn new() -> BitVec {
+        BitVec {
+            words: Vec::new(),
+            ranks: Vec::new(),
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, bit: bool) {
+        if self.len.is_multiple_of(WORD_BITS) {
+            self.words.push(0);
+        }
+        if bit {
+            *self.words.last_mut().unwrap() |= 1 << (self.len % WORD_BITS);
+        }
+        self.len += 1;
+    }
+
+    // must be called after the last `push`
+    fn build_ranks(&mut self) {
+        let mut rank = 0;
+        self.ranks = self.words
+                         .iter()
+                         .map(|word| {
+                             let r = rank;
+                             rank += word.count_ones();
+                             r
+                         })
+                         .collect();
+    }
+
+    fn get(&self, pos: usize) -> bool {
+        debug_assert!(pos < self.len);
+        self.words[pos / WORD_BITS] & (1 << (pos % WORD_BITS)) != 0
+    }
+
+    /// Number of set bits in `[0, pos]`. Time complexity: O(1)
+    fn rank1(&self, pos: usize) -> usize {
+        let word = pos / WORD_BITS;
+        let bit = pos % WORD_BITS;
+        let mask = if bit == WORD_BITS - 1 {
+            !0
+        } else {
+            (1 << (bit + 1)) - 1
+        };
+        self.ranks[word] as usize + (self.words[word] & mask).count_ones() as usize
+    }
+
+    /// Position of the `k`-th set bit (1-based). Time complexity: O(log(n))
+    fn select1(&self, k: usize) -> usize {
+        debug_assert!(k > 0);
+        // the last word whose preceding set bits are less than k
+        let word = self.ranks.partition_point(|&r| (r as usize) < k) - 1;
+        let mut remaining = k - self.ranks[word] as usize;
+        let mut bits = self.words[word];
+        let mut pos = 0;
+        loop {
+            if bits & 1 == 1 {
+                remaining -= 1;
+                if remaining == 0 {
+                    return word * WORD_BITS + pos;
+                }
+            }
+            bits >>= 1;
+            pos += 1;
+        }
+    }
+}
+
+/// A compact, immutable binary tree.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate binary_tree;
+/// # use binary_tree::NodeMut;
+/// # use binary_tree::succinct::SuccinctTree;
+/// # use binary_tree::test::TestNode;
+/// # fn main() {
+/// let mut tn = TestNode::new(2);
+/// tn.insert_left(Some(Box::new(TestNode::new(1))));
+/// tn.insert_right(Some(Box::new(TestNode::new(3))));
+///
+/// let st = SuccinctTree::from_node(Some(&tn));
+/// let root = st.root().unwrap();
+/// assert_eq!(root.value(), &2);
+/// assert_eq!(root.left().unwrap().value(), &1);
+/// assert_eq!(root.right().unwrap().parent().unwrap().value(), &2);
+/// # }
+/// ```
+pub struct SuccinctTree<T> {
+    // two bits per node in level-order: has-left and has-right
+    shape: BitVec,
+    // values in level-order
+    values: Vec<T>,
+}
+
+impl<T> SuccinctTree<T> {
+    /// Creates a succinct copy of the tree rooted at `root`. Time complexity:
+    /// O(n)
+    pub fn from_node<N>(root: Option<&N>) -> SuccinctTree<T>
+        where N: Node<Value = T>,
+              T: Clone
+    {
+        let mut shape = BitVec::new();
+        let mut values = Vec::new();
+        let mut queue: VecDeque<&N> = root.into_iter().collect();
+        while let Some(node) = queue.pop_front() {
+            values.push(node.value().clone());
+            shape.push(node.left().is_some());
+            shape.push(node.right().is_some());
+            queue.extend(node.left());
+            queue.extend(node.right());
+        }
+        shape.build_ranks();
+        SuccinctTree {
+            shape,
+            values,
+        }
+    }
+
+    /// Returns the number of nodes in the tree.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if the tree has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Returns the root node, or `None` if the tree is empty.
+    pub fn root(&self) -> Option<SuccinctNode<'_, T>> {
+        self.node(0)
+    }
+
+    /// Returns the node at the given level-order index.
+    pub fn node(&self, index: usize) -> Option<SuccinctNode<'_, T>> {
+        if index < self.len() {
+            Some(SuccinctNode {
+                tree: self,
+                index,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the values of all nodes in level-order.
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    fn child(&self, index: usize, pos: usize) -> Option<usize> {
+        let pos = 2 * index + pos;
+        if self.shape.get(pos) {
+            Some(self.shape.rank1(pos))
+        } else {
+            None
+        }
+    }
+}
+
+/// A reference to a node of a `SuccinctTree`.
+pub struct SuccinctNode<'a, T: 'a> {
+    tree: &'a SuccinctTree<T>,
+    index: usize,
+}
+
+impl<'a, T> Clone for SuccinctNode<'a, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, T> Copy for SuccinctNode<'a, T> {}
+
+impl<'a, T> SuccinctNode<'a, T> {
+    /// Returns the level-order index of this node.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the value of this node.
+    pub fn value(&self) -> &'a T {
+        &self.tree.values[self.index]
+    }
+
+    /// Returns the left child. Time complexity: O(1)
+    pub fn left(&self) -> Option<SuccinctNode<'a, T>> {
+        self.tree.child(self.index, 0).map(|index| SuccinctNode { tree: self.tree, index })
+    }
+
+    /// Returns the right child. Time complexity: O(1)
+    pub fn right(&self) -> Option<SuccinctNode<'a, T>> {
+        self.tree.child(self.index, 1).map(|index| SuccinctNode { tree: self.tree, index })
+    }
+
+    /// Returns the parent, or `None` for the root. Time complexity: O(log(n))
+    pub fn parent(&self) -> Option<SuccinctNode<'a, T>> {
+        if self.index == 0 {
+            None
+        } else {
+            let pos = self.tree.shape.select1(self.index);
+            Some(SuccinctNode {
+                tree: self.tree,
+                index: pos / 2,
+            })
+        }
+    }
+
+    /// Returns `true` if this node is the left child of its parent.
+    pub fn is_left_child(&self) -> bool {
+        self.index > 0 && self.tree.shape.select1(self.index).is_multiple_of(2)
+    }
+}
+
+/// Encodes the shape of the tree rooted at `root` as balanced parentheses
+/// (`true` for an opening one), along with its values in-order. A tree is
+/// written as `( left ) right`, so the i-th closing parenthesis stands for the
+/// i-th node in-order, and each node takes two bits. Time complexity: O(n)
+pub fn encode_shape<N>(root: Option<&N>) -> (Vec<bool>, Vec<N::Value>)
+    where N: Node,
+          N::Value: Clone
+{
+    let mut bits = Vec::new();
+    let mut values = Vec::new();
+    let mut stack = Vec::new();
+    let mut subtree = root;
+    loop {
+        while let Some(node) = subtree {
+            bits.push(true);
+            stack.push(node);
+            subtree = node.left();
+        }
+        match stack.pop() {
+            Some(node) => {
+                bits.push(false);
+                values.push(node.value().clone());
+                subtree = node.right();
+            }
+            None => break,
+        }
+    }
+    (bits, values)
+}
+
+/// Error returned by `decode_shape`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShapeError {
+    /// The parentheses are not balanced.
+    Unbalanced,
+    /// The number of values differs from the number of nodes.
+    ValueCount,
+}
+
+/// Builds a tree from balanced parentheses and in-order values, as returned
+/// by `encode_shape`. `make` creates a (childless) node from a value. Time
+/// complexity: O(n)
+pub fn decode_shape<V, P, M>(bits: &[bool], values: Vec<V>, mut make: M) -> Result<Option<P>, ShapeError>
+    where P: DerefMut,
+          P::Target: NodeMut<NodePtr = P>,
+          M: FnMut(V) -> P
+{
+    if bits.len() != 2 * values.len() {
+        return Err(if bits.len().is_multiple_of(2) { ShapeError::ValueCount } else { ShapeError::Unbalanced });
+    }
+    let mut values = values.into_iter();
+    // `None` for an opened node, or a node waiting for its right subtree
+    let mut stack: Vec<Option<P>> = Vec::new();
+    // the subtree completed most recently
+    let mut done: Option<P> = None;
+    for &bit in bits {
+        if bit {
+            stack.push(None);
+            continue;
+        }
+        while let Some(&Some(_)) = stack.last() {
+            let mut node = stack.pop().unwrap().unwrap();
+            node.insert_right(done);
+            done = Some(node);
+        }
+        if stack.pop().is_none() {
+            return Err(ShapeError::Unbalanced);
+        }
+        let mut node = make(values.next().unwrap());
+        node.insert_left(done);
+        stack.push(Some(node));
+        done = None;
+    }
+    while let Some(node) = stack.pop() {
+        let mut node = node.ok_or(ShapeError::Unbalanced)?;
+        node.insert_right(done);
+        done = Some(node);
+    }
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use NodeMut;
+    use test::TestNode;
+    use super::BitVec;
+    use super::SuccinctTree;
+    use super::{ShapeError, encode_shape, decode_shape};
+
+    #[test]
+    fn bitvec() {
+        let mut bv = BitVec::new();
+        for i in 0..200 {
+            bv.push(i % 3 == 0);
+        }
+        bv.build_ranks();
+        for i in 0..200 {
+            assert_eq!(bv.get(i), i % 3 == 0);
+            assert_eq!(bv.rank1(i), i / 3 + 1);
+        }
+        for k in 1..68 {
+            assert_eq!(bv.select1(k), 3 * (k - 1));
+        }
+    }
+
+    #[test]
+    fn navigate() {
+        // a long left spine with a right leaf hanging off every node
+        let mut tn = Box::new(TestNode::new(0));
+        for i in 1..100 {
+            let mut parent = Box::new(TestNode::new(2 * i));
+            parent.insert_right(Some(Box::new(TestNode::new(2 * i + 1))));
+            parent.insert_left(Some(tn));
+            tn = parent;
+        }
+        let st = SuccinctTree::from_node(Some(&*tn));
+        assert_eq!(st.len(), 199);
+
+        let mut node = st.root().unwrap();
+        assert!(node.parent().is_none());
+        for i in (1..100).rev() {
+            assert_eq!(*node.value(), 2 * i);
+            let right = node.right().unwrap();
+            assert_eq!(*right.value(), 2 * i + 1);
+            assert!(right.left().is_none() && right.right().is_none());
+            assert_eq!(right.parent().unwrap().index(), node.index());
+            assert!(!right.is_left_child());
+            let left = node.left().unwrap();
+            assert!(left.is_left_child());
+            assert_eq!(left.parent().unwrap().index(), node.index());
+            node = left;
+        }
+        assert_eq!(*node.value(), 0);
+        assert!(node.left().is_none());
+
+        let empty = SuccinctTree::<u32>::from_node::<TestNode<u32>>(None);
+        assert!(empty.is_empty() && empty.root().is_none());
+    }
+
+    #[test]
+    fn shape_codec() {
+        use algos::tree_eq;
+        use test::{Shape, gen_tree};
+
+        let decode = |bits: &[bool], values| decode_shape(bits, values, |v| Box::new(TestNode::new(v)));
+        let tree = gen_tree(Shape::PerfectlyBalanced, 3).unwrap();
+        let (bits, values) = encode_shape(Some(&*tree));
+        assert_eq!(bits, [true, true, false, false, true, false]);
+        assert_eq!(values, [0, 1, 2]);
+        let decoded: Box<TestNode<_>> = decode(&bits, values).unwrap().unwrap();
+        assert!(tree_eq(&*tree, &*decoded));
+
+        for &shape in &[Shape::LeftSpine, Shape::RightSpine, Shape::Random(5)] {
+            let tree = gen_tree(shape, 200).unwrap();
+            let (bits, values) = encode_shape(Some(&*tree));
+            assert_eq!(bits.len(), 400);
+            assert!(tree_eq(&*tree, &*decode(&bits, values).unwrap().unwrap()));
+        }
+
+        assert!(decode(&[], vec![]).unwrap().is_none());
+        assert_eq!(decode(&[false, true], vec![0]).err(), Some(ShapeError::Unbalanced));
+        assert_eq!(decode(&[true, true], vec![0]).err(), Some(ShapeError::Unbalanced));
+        assert_eq!(decode(&[true, false], vec![]).err(), Some(ShapeError::ValueCount));
+    }
+}
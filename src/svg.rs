@@ -0,0 +1,261 @@
+//! Tidy drawing of trees, and rendering of the drawing as SVG.
+//!
+//! `layout` places the nodes on a grid following Reingold and Tilford: each
+//! node is centered above its children, nodes of the same depth are at least
+//! two units apart, and mirror-image subtrees are drawn as mirror images. An
+//! only child is placed one unit to its side. `SvgTree` renders such a
+//! layout, for embedding in documentation or web pages.
+//!
+//! Everything here is iterative, and takes O(n) time for any shape of tree.
+
+use std::fmt;
+
+use Node;
+
+/// Position of a node in a layout returned by `layout`.
+#[derive(Debug)]
+pub struct Placement<'a, N: 'a> {
+    pub node: &'a N,
+    /// Horizontal position, in units where the closest nodes on a level are
+    /// two apart. The leftmost node is at zero.
+    pub x: usize,
+    /// Depth of the node, i.e. zero for the root.
+    pub y: usize,
+    /// Index of the placement of the parent, if any.
+    pub parent: Option<usize>,
+}
+
+// Outline of a laid out subtree, one entry per level with the deepest level
+// first (so that a parent is added with a push). An entry plus `shift` is the
+// position relative to the root of the subtree.
+struct Contour {
+    left: Vec<isize>,
+    right: Vec<isize>,
+    shift: isize,
+}
+
+/// Lays out the tree rooted at `root`, returning the placements of its nodes
+/// in pre-order. Time complexity: O(n)
+pub fn layout<N: Node>(root: &N) -> Vec<Placement<'_, N>> {
+    // pre-order, along with the indices of the children
+    let mut nodes = Vec::new();
+    let mut children = Vec::new();
+    let mut stack = vec![(root, None, 0, false)];
+    while let Some((node, parent, y, is_right)) = stack.pop() {
+        let index = nodes.len();
+        if let Some(p) = parent {
+            let (ref mut left, ref mut right): (Option<usize>, Option<usize>) = children[p];
+            if is_right {
+                *right = Some(index);
+            } else {
+                *left = Some(index);
+            }
+        }
+        nodes.push(Placement { node, x: 0, y, parent });
+        children.push((None, None));
+        stack.extend(node.right().map(|n| (n, Some(index), y + 1, true)));
+        stack.extend(node.left().map(|n| (n, Some(index), y + 1, false)));
+    }
+
+    // offsets of the nodes from their parents, computed bottom-up
+    let mut offsets = vec![0isize; nodes.len()];
+    let mut contours: Vec<Option<Contour>> = (0..nodes.len()).map(|_| None).collect();
+    for index in (0..nodes.len()).rev() {
+        let (left, right) = children[index];
+        let mut contour = match (left, right) {
+            (None, None) => {
+                Contour {
+                    left: vec![],
+                    right: vec![],
+                    shift: 0,
+                }
+            }
+            (Some(l), None) => {
+                offsets[l] = -1;
+                let mut contour = contours[l].take().unwrap();
+                contour.shift -= 1;
+                contour
+            }
+            (None, Some(r)) => {
+                offsets[r] = 1;
+                let mut contour = contours[r].take().unwrap();
+                contour.shift += 1;
+                contour
+            }
+            (Some(l), Some(r)) => {
+                let lc = contours[l].take().unwrap();
+                let rc = contours[r].take().unwrap();
+                let mut dist = 2;
+                for (&lx, &rx) in lc.right.iter().rev().zip(rc.left.iter().rev()) {
+                    dist = dist.max(2 + lx + lc.shift - rx - rc.shift);
+                }
+                // keep the parent on the grid
+                dist += dist % 2;
+                offsets[l] = -dist / 2;
+                offsets[r] = dist / 2;
+                merge(lc, rc, dist / 2)
+            }
+        };
+        contour.left.push(-contour.shift);
+        contour.right.push(-contour.shift);
+        contours[index] = Some(contour);
+    }
+
+    let mut xs = vec![0isize; nodes.len()];
+    for index in 1..nodes.len() {
+        xs[index] = xs[nodes[index].parent.unwrap()] + offsets[index];
+    }
+    let min_x = xs.iter().cloned().min().unwrap();
+    for (placement, x) in nodes.iter_mut().zip(xs) {
+        placement.x = (x - min_x) as usize;
+    }
+    nodes
+}
+
+// Merges the contours of the left and the right subtrees of a node, which are
+// placed `half` units to its left and right. The longer contour is reused, so
+// this takes time proportional to the height of the shorter one.
+fn merge(mut lc: Contour, mut rc: Contour, half: isize) -> Contour {
+    lc.shift -= half;
+    rc.shift += half;
+    let (mut base, top, from_left) = if lc.left.len() >= rc.left.len() {
+        (lc, rc, false)
+    } else {
+        (rc, lc, true)
+    };
+    let (dst, src) = if from_left {
+        (&mut base.left, &top.left)
+    } else {
+        (&mut base.right, &top.right)
+    };
+    let delta = top.shift - base.shift;
+    for (d, &s) in dst.iter_mut().rev().zip(src.iter().rev()) {
+        *d = s + delta;
+    }
+    base
+}
+
+/// Wrapper which renders a tree as a standalone SVG image, using `layout`.
+/// The nodes are drawn as labelled circles in pre-order, after the edges.
+///
+/// ```
+/// use binary_tree::svg::SvgTree;
+/// use binary_tree::test::parse_tree;
+///
+/// let tree = parse_tree::<u32>("20(10,30(25))").unwrap();
+/// let svg = SvgTree(&tree).to_string();
+/// assert!(svg.starts_with("<svg "));
+/// ```
+pub struct SvgTree<'a, N: 'a>(pub &'a N);
+
+const UNIT: usize = 20;
+const LEVEL: usize = 50;
+const RADIUS: usize = 16;
+const MARGIN: usize = 20;
+
+impl<'a, N> fmt::Display for SvgTree<'a, N>
+    where N: Node,
+          N::Value: fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        let nodes = layout(self.0);
+        let cx = |p: &Placement<N>| MARGIN + p.x * UNIT;
+        let cy = |p: &Placement<N>| MARGIN + p.y * LEVEL;
+        let width = nodes.iter().map(&cx).max().unwrap() + MARGIN;
+        let height = nodes.iter().map(&cy).max().unwrap() + MARGIN;
+        writeln!(f,
+                 r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+                 width,
+                 height)?;
+        writeln!(f, r#"<g stroke="black" fill="none">"#)?;
+        for p in &nodes {
+            if let Some(parent) = p.parent {
+                let q = &nodes[parent];
+                writeln!(f, r#"<line x1="{}" y1="{}" x2="{}" y2="{}"/>"#, cx(q), cy(q), cx(p), cy(p))?;
+            }
+        }
+        writeln!(f, "</g>")?;
+        writeln!(f, r#"<g text-anchor="middle" dominant-baseline="central" font-family="sans-serif" font-size="12">"#)?;
+        for p in &nodes {
+            writeln!(f,
+                     r#"<circle cx="{0}" cy="{1}" r="{2}" fill="white" stroke="black"/><text x="{0}" y="{1}">{3}</text>"#,
+                     cx(p),
+                     cy(p),
+                     RADIUS,
+                     Escaped(&p.node.value().to_string()))?;
+        }
+        writeln!(f, "</g>")?;
+        f.write_str("</svg>")
+    }
+}
+
+// Escapes the XML special characters of a text.
+struct Escaped<'a>(&'a str);
+
+impl<'a> fmt::Display for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        for c in self.0.chars() {
+            match c {
+                '&' => f.write_str("&amp;")?,
+                '<' => f.write_str("&lt;")?,
+                '>' => f.write_str("&gt;")?,
+                '"' => f.write_str("&quot;")?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use algos::drop_tree;
+    use test::{TestNode, Shape, gen_tree, parse_tree};
+    use super::{layout, SvgTree};
+
+    fn coords(text: &str) -> Vec<(u32, usize, usize)> {
+        let tree = parse_tree::<u32>(text).unwrap();
+        layout(&tree).iter().map(|p| (p.node.val, p.x, p.y)).collect()
+    }
+
+    #[test]
+    fn tidy_layout() {
+        assert_eq!(coords("1"), [(1, 0, 0)]);
+        assert_eq!(coords("1(2,3)"), [(1, 1, 0), (2, 0, 1), (3, 2, 1)]);
+        assert_eq!(coords("1(2)"), [(1, 1, 0), (2, 0, 1)]);
+        // the inner grandchildren would collide without spreading the
+        // children apart
+        assert_eq!(coords("1(2(4,5),3(6,7))"),
+                   [(1, 3, 0), (2, 1, 1), (4, 0, 2), (5, 2, 2), (3, 5, 1), (6, 4, 2), (7, 6, 2)]);
+        // only the levels that both subtrees have are compared
+        assert_eq!(coords("1(2(,4(,5)),3)"),
+                   [(1, 1, 0), (2, 0, 1), (4, 1, 2), (5, 2, 3), (3, 2, 1)]);
+        assert_eq!(coords("1(2(,4),3(5))"), [(1, 2, 0), (2, 0, 1), (4, 1, 2), (3, 4, 1), (5, 3, 2)]);
+
+        let tree = gen_tree(Shape::PerfectlyBalanced, 1023).unwrap();
+        let nodes = layout(&*tree);
+        let mut levels = vec![vec![]; 10];
+        for p in &nodes {
+            levels[p.y].push(p.x);
+        }
+        for level in &levels {
+            assert!(level.windows(2).all(|w| w[1] >= w[0] + 2));
+        }
+        assert_eq!(nodes[0].x, 511);
+
+        let tree = gen_tree(Shape::LeftSpine, 100000).unwrap();
+        assert_eq!(layout(&*tree).last().map(|p| (p.x, p.y)), Some((0, 99999)));
+        drop_tree::<TestNode<_>>(tree);
+    }
+
+    #[test]
+    fn svg() {
+        let tree = parse_tree::<String>("a<b(c&d)").unwrap();
+        let svg = SvgTree(&tree).to_string();
+        assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="60" height="90""#));
+        assert_eq!(svg.matches("<line ").count(), 1);
+        assert_eq!(svg.matches("<circle ").count(), 2);
+        assert!(svg.contains(">a&lt;b</text>") && svg.contains(">c&amp;d</text>"));
+        assert!(svg.ends_with("</svg>"));
+    }
+}
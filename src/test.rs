@@ -2,9 +2,17 @@
 
 use std::mem;
 use std::cmp;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::str::FromStr;
+
+#[cfg(feature="quickcheck")]
+use quickcheck::{Arbitrary, Gen};
 
 use Node;
 use NodeMut;
+use WalkAction;
+use algos;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Level {
@@ -35,6 +43,8 @@ impl Level {
 /// `level = height + 1`. Recursive, hence risk of stack blow up depending on
 /// the height of the tree! The node is considered balanced if, at every node,
 /// the difference in levels of the child nodes is not greater than `tolerance`.
+/// See [`algos::height`](../algos/fn.height.html) for an iterative alternative
+/// if balance is irrelevant.
 pub fn compute_level<N: Node>(node: &N, tolerance: u32) -> Level {
     use test::Level::*;
 
@@ -54,14 +64,20 @@ pub fn compute_level<N: Node>(node: &N, tolerance: u32) -> Level {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature="serde", derive(Serialize, Deserialize))]
 /// A minimal `Node` implementation.
 ///
 /// ## When should you use `TestNode`?
 ///
 /// You should not use `TestNode` for anything, except may be to get to know what
 /// a binary tree is!
+///
+/// With the `serde` feature, a `TestNode` is (de)serialized as nested maps
+/// with the keys `value`, `left` and `right`, so that its exact shape is
+/// preserved. Note that this recurses once per level of the tree.
 pub struct TestNode<T> {
+    #[cfg_attr(feature="serde", serde(rename="value"))]
     pub val: T,
     pub left: Option<Box<TestNode<T>>>,
     pub right: Option<Box<TestNode<T>>>,
@@ -93,6 +109,30 @@ impl<T> Node for TestNode<T> {
     }
 }
 
+/// Trees are equal if they have the same shape and equal values at the
+/// corresponding nodes. The comparison is iterative.
+impl<T: PartialEq> PartialEq for TestNode<T> {
+    fn eq(&self, other: &TestNode<T>) -> bool {
+        algos::tree_eq(self, other)
+    }
+}
+
+impl<T: Eq> Eq for TestNode<T> {}
+
+/// Hashes the shape along with the values, iteratively, consistent with
+/// `PartialEq`.
+impl<T: Hash> Hash for TestNode<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            node.val.hash(state);
+            state.write_u8((node.left.is_some() as u8) | (node.right.is_some() as u8) << 1);
+            stack.extend(node.right());
+            stack.extend(node.left());
+        }
+    }
+}
+
 impl<T> NodeMut for TestNode<T> {
     type NodePtr = Box<TestNode<T>>;
 
@@ -131,9 +171,434 @@ impl<T> NodeMut for TestNode<T> {
     }
 }
 
+/// One side of a `Diff`: a node, summarized by its value and the presence of
+/// its children.
+#[derive(Debug, PartialEq)]
+pub struct DiffNode<'a, V: 'a> {
+    pub value: &'a V,
+    pub has_left: bool,
+    pub has_right: bool,
+}
+
+impl<'a, V: 'a> DiffNode<'a, V> {
+    fn new<N: Node<Value = V>>(node: &'a N) -> DiffNode<'a, V> {
+        DiffNode {
+            value: node.value(),
+            has_left: node.left().is_some(),
+            has_right: node.right().is_some(),
+        }
+    }
+}
+
+/// The first difference between two trees, as found by `diff`.
+#[derive(Debug, PartialEq)]
+pub struct Diff<'a, 'b, U: 'a, V: 'b> {
+    /// The path from the roots to the differing position.
+    pub path: Vec<WalkAction>,
+    /// The node of the first tree at that position, if any.
+    pub a: Option<DiffNode<'a, U>>,
+    /// The node of the second tree at that position, if any.
+    pub b: Option<DiffNode<'b, V>>,
+}
+
+/// Compares two trees in pre-order, and returns the first position where they
+/// differ, either in value or because only one of them has a node there.
+/// Meant to be used as `assert_eq!(diff(a, b), None)`, which prints a much
+/// shorter message than comparing the trees themselves. Time complexity: O(n)
+pub fn diff<'a, 'b, A, B>(a: &'a A, b: &'b B) -> Option<Diff<'a, 'b, A::Value, B::Value>>
+    where A: Node,
+          B: Node,
+          A::Value: PartialEq<B::Value>
+{
+    let mut path = Vec::new();
+    // (node of a, node of b, length of the path to their parents, last step)
+    let mut stack = vec![(Some(a), Some(b), 0, None)];
+    while let Some((a, b, depth, action)) = stack.pop() {
+        path.truncate(depth);
+        path.extend(action);
+        let differs = match (a, b) {
+            (Some(a), Some(b)) => a.value() != b.value(),
+            _ => true,
+        };
+        if differs {
+            return Some(Diff {
+                path,
+                a: a.map(DiffNode::new),
+                b: b.map(DiffNode::new),
+            });
+        }
+        let (a, b) = (a.unwrap(), b.unwrap());
+        let depth = path.len();
+        if a.right().is_some() || b.right().is_some() {
+            stack.push((a.right(), b.right(), depth, Some(WalkAction::Right)));
+        }
+        if a.left().is_some() || b.left().is_some() {
+            stack.push((a.left(), b.left(), depth, Some(WalkAction::Left)));
+        }
+    }
+    None
+}
+
+/// Shape statistics of a tree, as computed by `stats`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stats {
+    /// The number of nodes.
+    pub count: usize,
+    /// The height of the root.
+    pub height: usize,
+    /// The number of nodes without children.
+    pub leaves: usize,
+    /// The average depth of the nodes, the root being at depth 0.
+    pub avg_depth: f64,
+    /// The number of nodes at each depth, starting from the root.
+    pub depth_histogram: Vec<usize>,
+}
+
+/// Computes the shape statistics of the tree rooted at `root`. Iterative, so
+/// it works on degenerate trees too. Time complexity: O(n)
+pub fn stats<N: Node>(root: &N) -> Stats {
+    let mut leaves = 0;
+    let mut depth_sum = 0;
+    let mut histogram: Vec<usize> = Vec::new();
+    let mut stack = vec![(root, 0)];
+    while let Some((node, depth)) = stack.pop() {
+        if depth == histogram.len() {
+            histogram.push(0);
+        }
+        histogram[depth] += 1;
+        depth_sum += depth;
+        if node.left().is_none() && node.right().is_none() {
+            leaves += 1;
+        }
+        stack.extend(node.left().map(|n| (n, depth + 1)));
+        stack.extend(node.right().map(|n| (n, depth + 1)));
+    }
+    let count = histogram.iter().sum();
+    Stats {
+        count,
+        height: histogram.len() - 1,
+        leaves,
+        avg_depth: depth_sum as f64 / count as f64,
+        depth_histogram: histogram,
+    }
+}
+
+/// Wrapper which displays a tree top-down, with box-drawing connectors like
+/// the output of the `tree` command. The left child is shown above the right
+/// one, and a missing child is shown as `∅` if its sibling exists.
+///
+/// ```text
+/// 20
+/// ├── 10
+/// └── 30
+///     ├── 25
+///     └── ∅
+/// ```
+pub struct DisplayTree<'a, N: 'a>(pub &'a N);
+
+impl<'a, N> fmt::Display for DisplayTree<'a, N>
+    where N: Node,
+          N::Value: fmt::Display
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        // (node, prefix of its line, prefix of the lines of its children)
+        let mut stack = vec![(Some(self.0), String::new(), String::new())];
+        let mut first = true;
+        while let Some((node, prefix, child_prefix)) = stack.pop() {
+            if !first {
+                f.write_str("\n")?;
+            }
+            first = false;
+            f.write_str(&prefix)?;
+            let node = match node {
+                Some(node) => node,
+                None => {
+                    f.write_str("∅")?;
+                    continue;
+                }
+            };
+            write!(f, "{}", node.value())?;
+            if node.left().is_some() || node.right().is_some() {
+                stack.push((node.right(), format!("{}└── ", child_prefix), format!("{}    ", child_prefix)));
+                stack.push((node.left(), format!("{}├── ", child_prefix), format!("{}│   ", child_prefix)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Error returned by `parse_tree`.
+#[derive(Debug, PartialEq)]
+pub enum ParseError<E> {
+    /// Unexpected character (or end of input) at the given byte offset.
+    Syntax(usize),
+    /// A value could not be parsed.
+    Value(E),
+}
+
+/// Parses a tree written in bracket notation, where a node is its value
+/// optionally followed by its left and right subtrees in parentheses,
+/// separated by a comma. Either subtree can be left out, so `a(b(,d),c)` is
+/// the tree with root `a`, whose left child `b` has a right child `d`, and
+/// `a(b)` has only a left child. Whitespace around the values is ignored, and
+/// values cannot be empty.
+///
+/// The tree can also be written as an s-expression `(value left right)`,
+/// where a missing subtree is `()`, trailing missing subtrees can be left
+/// out, and a leaf can be written as its bare value. The tree above is then
+/// `(a (b () d) c)`. Values cannot contain whitespace or parentheses in this
+/// form. Input starting with `(` is read as an s-expression, as it cannot be
+/// valid bracket notation. The parsing is iterative in both cases.
+pub fn parse_tree<T: FromStr>(text: &str) -> Result<TestNode<T>, ParseError<T::Err>> {
+    let bytes = text.as_bytes();
+    let mut pos = 0;
+    let skip_space = |pos: &mut usize| {
+        while *pos < bytes.len() && (bytes[*pos] as char).is_whitespace() {
+            *pos += 1;
+        }
+    };
+    skip_space(&mut pos);
+    if bytes.get(pos) == Some(&b'(') {
+        return parse_sexpr(text, pos);
+    }
+    // nodes whose subtrees are being parsed; the flag is set when in the
+    // right one
+    let mut stack: Vec<(Box<TestNode<T>>, bool)> = Vec::new();
+    let mut done: Option<Box<TestNode<T>>>;
+    'subtree: loop {
+        let start = pos;
+        while pos < bytes.len() && !b"(,)".contains(&bytes[pos]) {
+            pos += 1;
+        }
+        let value = text[start..pos].trim();
+        done = None;
+        if !value.is_empty() {
+            let node = Box::new(TestNode::new(value.parse().map_err(ParseError::Value)?));
+            if pos < bytes.len() && bytes[pos] == b'(' {
+                pos += 1;
+                stack.push((node, false));
+                continue;
+            }
+            done = Some(node);
+        }
+        // attach the completed subtree to its parent, and so on upwards
+        loop {
+            skip_space(&mut pos);
+            match stack.pop() {
+                Some((mut node, false)) => {
+                    node.left = done;
+                    match bytes.get(pos) {
+                        Some(&b',') => {
+                            pos += 1;
+                            stack.push((node, true));
+                            continue 'subtree;
+                        }
+                        Some(&b')') => pos += 1,
+                        _ => return Err(ParseError::Syntax(pos)),
+                    }
+                    done = Some(node);
+                }
+                Some((mut node, true)) => {
+                    node.right = done;
+                    if bytes.get(pos) != Some(&b')') {
+                        return Err(ParseError::Syntax(pos));
+                    }
+                    pos += 1;
+                    done = Some(node);
+                }
+                None => break 'subtree,
+            }
+        }
+    }
+    match done {
+        Some(root) if pos == bytes.len() => Ok(*root),
+        _ => Err(ParseError::Syntax(pos)),
+    }
+}
+
+// Parses the s-expression starting at byte offset `pos` of `text`.
+fn parse_sexpr<T: FromStr>(text: &str, mut pos: usize) -> Result<TestNode<T>, ParseError<T::Err>> {
+    let bytes = text.as_bytes();
+    let skip_space = |pos: &mut usize| {
+        while *pos < bytes.len() && (bytes[*pos] as char).is_whitespace() {
+            *pos += 1;
+        }
+    };
+    let atom = |pos: &mut usize| {
+        let start = *pos;
+        while *pos < bytes.len() && !b"()".contains(&bytes[*pos]) &&
+              !(bytes[*pos] as char).is_whitespace() {
+            *pos += 1;
+        }
+        if start == *pos {
+            return Err(ParseError::Syntax(start));
+        }
+        text[start..*pos].parse().map(TestNode::new).map_err(ParseError::Value)
+    };
+    // lists being parsed, with the number of subtrees read so far
+    let mut stack: Vec<(Box<TestNode<T>>, u8)> = Vec::new();
+    loop {
+        skip_space(&mut pos);
+        let start = pos;
+        let done = match bytes.get(pos) {
+            Some(&b'(') => {
+                pos += 1;
+                skip_space(&mut pos);
+                if bytes.get(pos) == Some(&b')') {
+                    pos += 1;
+                    None
+                } else {
+                    let node = atom(&mut pos)?;
+                    stack.push((Box::new(node), 0));
+                    continue;
+                }
+            }
+            Some(&b')') if !stack.is_empty() => {
+                pos += 1;
+                stack.pop().map(|(node, _)| node)
+            }
+            _ => Some(Box::new(atom(&mut pos)?)),
+        };
+        match stack.last_mut() {
+            Some(&mut (ref mut node, ref mut read)) => {
+                match *read {
+                    0 => node.left = done,
+                    1 => node.right = done,
+                    _ => return Err(ParseError::Syntax(start)),
+                }
+                *read += 1;
+            }
+            None => {
+                skip_space(&mut pos);
+                return match done {
+                    Some(root) if pos == bytes.len() => Ok(*root),
+                    _ => Err(ParseError::Syntax(pos)),
+                };
+            }
+        }
+    }
+}
+
+/// Shapes of the trees built by `gen_tree`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    /// Every node but the last has only a left child.
+    LeftSpine,
+    /// Every node but the last has only a right child.
+    RightSpine,
+    /// The shape of a search tree after inserting the values in a random
+    /// order, generated from the given seed.
+    Random(u64),
+    /// A complete binary tree of minimum height.
+    PerfectlyBalanced,
+}
+
+/// Builds a tree of the given shape holding the values `0..n` in-order, or
+/// `None` if `n` is 0. The construction is iterative, so even degenerate
+/// trees can be large (use `algos::drop_tree` to drop those). Time complexity:
+/// O(n), except O(n log(n)) expected for `Random`.
+pub fn gen_tree(shape: Shape, n: usize) -> Option<Box<TestNode<usize>>> {
+    use self::Shape::*;
+
+    let mut tree: Option<Box<TestNode<usize>>> = None;
+    match shape {
+        LeftSpine => {
+            for val in 0..n {
+                let mut node = Box::new(TestNode::new(val));
+                node.left = tree;
+                tree = Some(node);
+            }
+        }
+        RightSpine | PerfectlyBalanced => {
+            for val in (0..n).rev() {
+                let mut node = Box::new(TestNode::new(val));
+                node.right = tree;
+                tree = Some(node);
+            }
+            if shape == PerfectlyBalanced {
+                if let Some(ref mut root) = tree {
+                    algos::vine_to_tree(&mut **root, n);
+                }
+            }
+        }
+        Random(seed) => {
+            let mut vals: Vec<_> = (0..n).collect();
+            // xorshift64*, the state must not be zero
+            let mut state = seed | 1;
+            for i in (1..n).rev() {
+                state ^= state >> 12;
+                state ^= state << 25;
+                state ^= state >> 27;
+                let r = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+                vals.swap(i, (r % (i as u64 + 1)) as usize);
+            }
+            for val in vals {
+                let mut slot = &mut tree;
+                while let Some(ref mut node) = *slot {
+                    slot = if val < node.val { &mut node.left } else { &mut node.right };
+                }
+                *slot = Some(Box::new(TestNode::new(val)));
+            }
+        }
+    }
+    tree
+}
+
+#[cfg(feature="quickcheck")]
+impl<T: Arbitrary> Arbitrary for TestNode<T> {
+    /// Generates trees of arbitrary shapes, including degenerate ones where
+    /// every node has at most one child.
+    fn arbitrary<G: Gen>(g: &mut G) -> TestNode<T> {
+        let size = { let s = g.size(); g.gen_range(0, s) };
+        // chance (in percent) of turning left when looking for a free slot
+        let bias = *g.choose(&[0, 50, 100]).unwrap();
+        let mut root = TestNode::new(T::arbitrary(g));
+        for _ in 0..size {
+            let new_node = Box::new(TestNode::new(T::arbitrary(g)));
+            let mut node = &mut root;
+            loop {
+                let slot = if g.gen_range(0, 100) < bias {
+                    &mut node.left
+                } else {
+                    &mut node.right
+                };
+                match *slot {
+                    Some(ref mut st) => node = st,
+                    None => {
+                        *slot = Some(new_node);
+                        break;
+                    }
+                }
+            }
+        }
+        root
+    }
+
+    fn shrink(&self) -> Box<Iterator<Item=TestNode<T>>> {
+        let mut shrunk = vec![];
+        shrunk.extend(self.left.as_ref().map(|st| (**st).clone()));
+        shrunk.extend(self.right.as_ref().map(|st| (**st).clone()));
+        if self.left.is_some() {
+            let mut tn = self.clone();
+            tn.left = None;
+            shrunk.push(tn);
+        }
+        if self.right.is_some() {
+            let mut tn = self.clone();
+            tn.right = None;
+            shrunk.push(tn);
+        }
+        Box::new(shrunk.into_iter())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::TestNode;
+    use super::{TestNode, Shape, gen_tree};
+    use super::{Diff, DiffNode, diff};
+    use super::{Stats, stats};
+    use super::DisplayTree;
+    use super::{ParseError, parse_tree};
     use Node;
     use NodeMut;
 
@@ -194,6 +659,142 @@ mod tests {
         assert_eq!(steps.len(), 0);
     }
 
+    #[test]
+    fn walk_mut() {
+        use WalkAction::*;
+
+        let mut tt = test_tree();
+        let mut steps = vec![Right, Left, Right].into_iter();
+        tt.walk_mut(|_| steps.next().unwrap(), |st| st.val += 1);
+        assert_eq!(steps.len(), 0);
+        assert_eq!(tt.right().unwrap().left().unwrap().val, 26);
+
+        tt.walk_mut(|_| Stop, |st| st.val += 1);
+        assert_eq!(tt.val, 21);
+        assert_eq!(tt.left().unwrap().val, 10);
+    }
+
+    #[test]
+    fn walk_path() {
+        use WalkAction::*;
+
+        let tt = test_tree();
+        let mut steps = vec![Right, Left, Right].into_iter();
+        let path = tt.walk_path(|_| steps.next().unwrap());
+        assert_eq!(path, [Right, Left]);
+        assert_eq!(steps.len(), 0);
+        assert_eq!(tt.walk_path(|node| if node.val > 20 { Left } else { Stop }), []);
+    }
+
+    #[test]
+    fn walk_full() {
+        use Descend;
+
+        // values in [15, 27] from a search tree
+        let tt = test_tree();
+        let mut found = vec![];
+        let mut visited = 0;
+        tt.walk_full(|node| {
+            visited += 1;
+            if node.val >= 15 && node.val <= 27 {
+                found.push(node.val);
+            }
+            match (node.val > 15, node.val < 27) {
+                (true, true) => Descend::Both,
+                (true, false) => Descend::Left,
+                (false, true) => Descend::Right,
+                (false, false) => Descend::Skip,
+            }
+        });
+        assert_eq!(found, [20, 25]);
+        assert_eq!(visited, 4);
+    }
+
+    #[test]
+    fn try_walk() {
+        use WalkAction::*;
+
+        let mut tt = test_tree();
+        let mut visited = vec![];
+        let res = tt.try_walk(|node| {
+            visited.push(node.val);
+            if node.val == 30 { Err("thirty") } else { Ok(Right) }
+        });
+        assert_eq!(res, Err("thirty"));
+        assert_eq!(visited, [20, 30]);
+        assert_eq!(tt.try_walk(|_| Ok::<_, ()>(Left)), Ok(()));
+
+        let mut stopped = false;
+        let mut stepped_out = 0;
+        let res = tt.try_walk_reshape(|node| if node.val == 25 { Err(25) } else { Ok(if node.val < 25 { Right } else { Left }) },
+                                      |_| stopped = true,
+                                      |_, _| stepped_out += 1);
+        assert_eq!(res, Err(25));
+        assert!(!stopped);
+        assert_eq!(stepped_out, 0);
+        // the tree is intact
+        assert_eq!(tt.right.as_ref().unwrap().left.as_ref().unwrap().val, 25);
+
+        let res = tt.try_walk_reshape(|node| Ok::<_, ()>(if node.val < 30 { Right } else { Stop }),
+                                      |node| node.val = 35,
+                                      |_, _| stepped_out += 1);
+        assert_eq!(res, Ok(()));
+        assert_eq!(tt.right.as_ref().unwrap().val, 35);
+        assert_eq!(stepped_out, 1);
+    }
+
+    #[test]
+    fn walk_reshape_state() {
+        use std::cmp::Ordering;
+        use WalkAction::*;
+
+        let mut tt = test_tree();
+        // (path taken, value at the end of it)
+        let mut state = (vec![], 0);
+        tt.walk_reshape_state(&mut state,
+                              |node, state| {
+                                  let action = match node.val.cmp(&25) {
+                                      Ordering::Less => Right,
+                                      Ordering::Equal => return Stop,
+                                      Ordering::Greater => Left,
+                                  };
+                                  state.0.push(action);
+                                  action
+                              },
+                              |node, state| state.1 = node.val,
+                              |_, action, state| assert_eq!(state.0.pop(), Some(action)));
+        assert_eq!(state, (vec![], 25));
+    }
+
+    #[test]
+    fn graft() {
+        let mut tt = test_tree();
+        let (left, right) = tt.detach_children();
+        assert!(tt.left.is_none() && tt.right.is_none());
+        let (old_left, old_right) = tt.graft(right, left);
+        assert!(old_left.is_none() && old_right.is_none());
+        assert_eq!(tt.left.as_ref().unwrap().val, 30);
+        assert_eq!(tt.right.as_ref().unwrap().val, 10);
+    }
+
+    #[test]
+    fn fold() {
+        use std::cmp::max;
+
+        let tt = test_tree();
+        assert_eq!(tt.fold(vec![], |mut v, &x| {
+            v.push(x);
+            v
+        }), [10, 20, 25, 30]);
+        assert_eq!(tt.fold(0, |acc, x| acc + x), 85);
+        let height = tt.fold_tree(|l, _, r| max(l.map_or(0, |h| h + 1), r.map_or(0, |h| h + 1)));
+        assert_eq!(height, 2);
+        let rendered = tt.fold_tree(|l: Option<String>, v, r: Option<String>| {
+            format!("({}{}{})", l.unwrap_or_default(), v, r.unwrap_or_default())
+        });
+        assert_eq!(rendered, "((10)20((25)30))");
+    }
+
     #[test]
     fn remove() {
         let mut tt = test_tree();
@@ -211,6 +812,153 @@ mod tests {
     }
 
     #[test]
+    fn diff_trees() {
+        use WalkAction::*;
+
+        let tt = test_tree();
+        assert_eq!(diff(&tt, &test_tree()), None);
+        let mut tt2 = test_tree();
+        tt2.right.as_mut().unwrap().left.as_mut().unwrap().val = 26;
+        assert_eq!(diff(&tt, &tt2), Some(Diff {
+            path: vec![Right, Left],
+            a: Some(DiffNode { value: &25, has_left: false, has_right: false }),
+            b: Some(DiffNode { value: &26, has_left: false, has_right: false }),
+        }));
+        tt2.left.as_mut().unwrap().right = Some(new_node(15));
+        let d = diff(&tt, &tt2).unwrap();
+        assert_eq!((d.path, d.a, d.b.map(|b| *b.value)), (vec![Left, Right], None, Some(15)));
+    }
+
+    #[test]
+    fn shape_stats() {
+        assert_eq!(stats(&test_tree()), Stats {
+            count: 4,
+            height: 2,
+            leaves: 2,
+            avg_depth: 1.0,
+            depth_histogram: vec![1, 2, 1],
+        });
+        let spine = gen_tree(Shape::LeftSpine, 100000).unwrap();
+        let st = stats(&*spine);
+        assert_eq!((st.height, st.leaves), (99999, 1));
+        ::algos::drop_tree::<TestNode<_>>(spine);
+        let full = stats(&*gen_tree(Shape::PerfectlyBalanced, 15).unwrap());
+        assert_eq!(full.depth_histogram, [1, 2, 4, 8]);
+        assert_eq!(full.leaves, 8);
+    }
+
+    #[test]
+    fn display_tree() {
+        let mut tt = test_tree();
+        assert_eq!(DisplayTree(&tt).to_string(),
+                   "20\n├── 10\n└── 30\n    ├── 25\n    └── ∅");
+        tt.left.as_mut().unwrap().right = Some(new_node(15));
+        assert_eq!(DisplayTree(&*tt.left.unwrap()).to_string(), "10\n├── ∅\n└── 15");
+        assert_eq!(DisplayTree(&*new_node(1)).to_string(), "1");
+    }
+
+    #[test]
+    #[cfg(feature="serde")]
+    fn serde() {
+        extern crate serde_json;
+        use algos::tree_eq;
+
+        let tt = test_tree();
+        let json = serde_json::to_string(&tt).unwrap();
+        assert_eq!(json, concat!(r#"{"value":20,"left":{"value":10,"left":null,"right":null},"#,
+                                 r#""right":{"value":30,"left":{"value":25,"left":null,"right":null},"right":null}}"#));
+        let tt2: TestNode<u32> = serde_json::from_str(&json).unwrap();
+        assert!(tree_eq(&tt, &tt2));
+        let spine = gen_tree(Shape::LeftSpine, 20).unwrap();
+        let spine2: TestNode<usize> = serde_json::from_value(serde_json::to_value(&spine).unwrap()).unwrap();
+        assert!(tree_eq(&*spine, &spine2));
+    }
+
+    #[test]
+    fn parse() {
+        use algos::tree_eq;
+
+        let tt: TestNode<u32> = parse_tree("20(10, 30(25))").unwrap();
+        assert!(tree_eq(&tt, &test_tree()));
+        let tt: TestNode<String> = parse_tree("a(b(,d),c)").unwrap();
+        assert_eq!(DisplayTree(&tt).to_string(), "a\n├── b\n│   ├── ∅\n│   └── d\n└── c");
+        let tt: TestNode<char> = parse_tree(" x() ").unwrap();
+        assert!(tt.left.is_none() && tt.right.is_none());
+
+        assert_eq!(parse_tree::<u32>("1(2,3").err(), Some(ParseError::Syntax(5)));
+        assert_eq!(parse_tree::<u32>("1(2,3,4)").err(), Some(ParseError::Syntax(5)));
+        assert_eq!(parse_tree::<u32>("1)").err(), Some(ParseError::Syntax(1)));
+        assert_eq!(parse_tree::<u32>("").err(), Some(ParseError::Syntax(0)));
+        assert!(matches!(parse_tree::<u32>("1(x)"), Err(ParseError::Value(_))));
+
+        let tt: TestNode<u32> = parse_tree(" (20 10 (30 25)) ").unwrap();
+        assert!(tree_eq(&tt, &test_tree()));
+        let tt: TestNode<String> = parse_tree("(a (b () d) (c))").unwrap();
+        assert!(tree_eq(&tt, &parse_tree::<String>("a(b(,d),c)").unwrap()));
+        let tt: TestNode<char> = parse_tree("(x () ())").unwrap();
+        assert!(tt.left.is_none() && tt.right.is_none());
+
+        assert_eq!(parse_tree::<u32>("(1 2").err(), Some(ParseError::Syntax(4)));
+        assert_eq!(parse_tree::<u32>("(1 2 3 4)").err(), Some(ParseError::Syntax(7)));
+        assert_eq!(parse_tree::<u32>("(1))").err(), Some(ParseError::Syntax(3)));
+        assert_eq!(parse_tree::<u32>("(()").err(), Some(ParseError::Syntax(1)));
+        assert_eq!(parse_tree::<u32>("()").err(), Some(ParseError::Syntax(2)));
+        assert!(matches!(parse_tree::<u32>("(1 x)"), Err(ParseError::Value(_))));
+    }
+
+    #[test]
+    fn eq_and_hash() {
+        use std::collections::HashSet;
+
+        let tt = test_tree();
+        assert_eq!(tt, test_tree());
+        let mut rotated = test_tree();
+        rotated.rotate_left().unwrap();
+        // same values in-order, but a different shape
+        assert!(tt != rotated);
+        let mut set = HashSet::new();
+        set.insert(test_tree());
+        set.insert(rotated);
+        set.insert(test_tree());
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&tt));
+
+        // deep trees are fine too
+        let (a, b) = (gen_tree(Shape::LeftSpine, 100000).unwrap(), gen_tree(Shape::LeftSpine, 100000).unwrap());
+        assert_eq!(a, b);
+        let mut hashes = HashSet::new();
+        hashes.insert(&*a);
+        assert!(hashes.contains(&*b));
+        drop(hashes);
+        ::algos::drop_tree::<TestNode<_>>(a);
+        ::algos::drop_tree::<TestNode<_>>(b);
+    }
+
+    #[test]
+    fn gen_shapes() {
+        use algos::{self, height};
+        use iter::Iter;
+
+        for &shape in &[Shape::LeftSpine, Shape::RightSpine, Shape::PerfectlyBalanced, Shape::Random(42)] {
+            let tree = gen_tree(shape, 10).unwrap();
+            assert_eq!(Iter::new(Some(&*tree)).cloned().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        }
+        let tree = gen_tree(Shape::LeftSpine, 10).unwrap();
+        assert!(tree.right.is_none());
+        assert_eq!(height(&*tree), 9);
+        let tree = gen_tree(Shape::RightSpine, 10).unwrap();
+        assert!(tree.left.is_none());
+        assert_eq!(height(&*tree), 9);
+        assert_eq!(height(&*gen_tree(Shape::PerfectlyBalanced, 10).unwrap()), 3);
+        assert_eq!(height(&*gen_tree(Shape::PerfectlyBalanced, 15).unwrap()), 3);
+        // same seed, same shape
+        assert!(algos::same_shape(&*gen_tree(Shape::Random(7), 50).unwrap(),
+                                  &*gen_tree(Shape::Random(7), 50).unwrap()));
+        assert!(gen_tree(Shape::Random(7), 0).is_none());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
     fn stack_blow() {
         use iter::IntoIter;
         let mut pt = new_node(20);
@@ -222,4 +970,27 @@ mod tests {
         // comment out the line below to observe a stack overflow
         let _: IntoIter<TestNode<_>> = IntoIter::new(Some(pt));
     }
+
+    #[test]
+    #[cfg(feature="quickcheck")]
+    fn arbitrary() {
+        use quickcheck::{Arbitrary, StdGen};
+        use rand::{SeedableRng, XorShiftRng};
+        use algos::{count_nodes, height};
+
+        let mut g = StdGen::new(XorShiftRng::from_seed([1, 2, 3, 4]), 50);
+        let mut degenerate = 0;
+        for _ in 0..100 {
+            let tn: TestNode<u8> = Arbitrary::arbitrary(&mut g);
+            let count = count_nodes(&tn);
+            assert!(count <= 50);
+            if count > 2 && height(&tn) == count - 1 {
+                degenerate += 1;
+            }
+            for shrunk in tn.shrink() {
+                assert!(count_nodes(&shrunk) < count);
+            }
+        }
+        assert!(degenerate > 0);
+    }
 }
@@ -1,9 +1,16 @@
 //! Pointer unboxing.
+//!
+//! Also provides `TreePtr`, which abstracts over the owning pointer types
+//! that can link the nodes of a tree.
 
-use std::ptr;
+#[cfg(feature="allocator_api")]
+use std::alloc::Allocator;
+use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 use std::sync::Arc;
 
+use cow::{ArcCow, RcCow};
+
 /// Trait specifying unboxing capability of a pointer type.
 pub trait Unbox {
     type Target;
@@ -11,13 +18,25 @@ pub trait Unbox {
     fn unbox(self) -> Self::Target;
 }
 
+#[cfg(not(feature="allocator_api"))]
 impl<T> Unbox for Box<T>
     where T: Sized
 {
     type Target = T;
 
     fn unbox(self) -> T {
-        unsafe { ptr::read(Box::into_raw(self)) }
+        *self
+    }
+}
+
+#[cfg(feature="allocator_api")]
+impl<T, A: Allocator> Unbox for Box<T, A>
+    where T: Sized
+{
+    type Target = T;
+
+    fn unbox(self) -> T {
+        *self
     }
 }
 
@@ -48,3 +67,163 @@ impl<T> Unbox for Arc<T>
         }
     }
 }
+
+impl<T> Unbox for RcCow<T>
+    where T: Clone
+{
+    type Target = T;
+
+    fn unbox(self) -> T {
+        self.0.unbox()
+    }
+}
+
+impl<T> Unbox for ArcCow<T>
+    where T: Clone
+{
+    type Target = T;
+
+    fn unbox(self) -> T {
+        self.0.unbox()
+    }
+}
+
+/// Fallible unboxing, which never clones. Unlike `Unbox`, shared
+/// pointers are given back as an error, so that the caller can detect
+/// unexpected sharing instead of paying for a hidden deep clone.
+pub trait TryUnbox: Sized {
+    type Target;
+
+    fn try_unbox(self) -> Result<Self::Target, Self>;
+}
+
+#[cfg(not(feature="allocator_api"))]
+impl<T> TryUnbox for Box<T> {
+    type Target = T;
+
+    fn try_unbox(self) -> Result<T, Box<T>> {
+        Ok(*self)
+    }
+}
+
+#[cfg(feature="allocator_api")]
+impl<T, A: Allocator> TryUnbox for Box<T, A> {
+    type Target = T;
+
+    fn try_unbox(self) -> Result<T, Box<T, A>> {
+        Ok(*self)
+    }
+}
+
+impl<T> TryUnbox for Rc<T> {
+    type Target = T;
+
+    fn try_unbox(self) -> Result<T, Rc<T>> {
+        Rc::try_unwrap(self)
+    }
+}
+
+impl<T> TryUnbox for Arc<T> {
+    type Target = T;
+
+    fn try_unbox(self) -> Result<T, Arc<T>> {
+        Arc::try_unwrap(self)
+    }
+}
+
+impl<T> TryUnbox for RcCow<T> {
+    type Target = T;
+
+    fn try_unbox(self) -> Result<T, RcCow<T>> {
+        RcCow::try_unwrap(self)
+    }
+}
+
+impl<T> TryUnbox for ArcCow<T> {
+    type Target = T;
+
+    fn try_unbox(self) -> Result<T, ArcCow<T>> {
+        ArcCow::try_unwrap(self)
+    }
+}
+
+/// Owning pointer to a node, which can be mutated and unboxed. This is
+/// implemented by `Box`, and the copy-on-write pointers `RcCow` and `ArcCow`,
+/// so that code generic over `TreePtr` works with both uniquely owned and
+/// cheaply cloneable (shared) trees.
+pub trait TreePtr: DerefMut + Unbox<Target = <Self as Deref>::Target>
+    where <Self as Deref>::Target: Sized
+{
+    /// Allocates `value` and returns a pointer to it.
+    fn new_ptr(value: <Self as Deref>::Target) -> Self;
+}
+
+#[cfg(not(feature="allocator_api"))]
+impl<T> TreePtr for Box<T> {
+    fn new_ptr(value: T) -> Box<T> {
+        Box::new(value)
+    }
+}
+
+/// With the `allocator_api` feature, boxes in any default-constructible
+/// allocator (such as `Global`) are tree pointers.
+#[cfg(feature="allocator_api")]
+impl<T, A: Allocator + Default> TreePtr for Box<T, A> {
+    fn new_ptr(value: T) -> Box<T, A> {
+        Box::new_in(value, A::default())
+    }
+}
+
+impl<T: Clone> TreePtr for RcCow<T> {
+    fn new_ptr(value: T) -> RcCow<T> {
+        RcCow::new(value)
+    }
+}
+
+impl<T: Clone> TreePtr for ArcCow<T> {
+    fn new_ptr(value: T) -> ArcCow<T> {
+        ArcCow::new(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Deref;
+    use std::rc::Rc;
+    use cow::{ArcCow, RcCow, allow_clones};
+    use super::{TreePtr, TryUnbox, Unbox};
+
+    fn modify<P>(value: u32) -> (u32, u32)
+        where P: TreePtr + Clone + Deref<Target = u32>
+    {
+        let mut a = P::new_ptr(value);
+        let b = a.clone();
+        allow_clones(|| *a += 1);
+        (a.unbox(), b.unbox())
+    }
+
+    #[test]
+    fn tree_ptr() {
+        assert_eq!(modify::<RcCow<_>>(1), (2, 1));
+        assert_eq!(modify::<ArcCow<_>>(1), (2, 1));
+        let mut b = <Box<_> as TreePtr>::new_ptr(1);
+        *b += 1;
+        assert_eq!(b.unbox(), 2);
+    }
+
+    #[test]
+    fn try_unbox() {
+        let a = Rc::new(1);
+        let b = a.clone();
+        let a = a.try_unbox().unwrap_err();
+        drop(b);
+        assert_eq!(a.try_unbox(), Ok(1));
+
+        let a = ArcCow::new(vec![1]);
+        let b = a.clone();
+        assert!(a.try_unbox().is_err());
+        // the pointer given back was dropped
+        assert_eq!(b.try_unbox().ok(), Some(vec![1]));
+        assert_eq!(Box::new(1).try_unbox(), Ok(1));
+    }
+}
@@ -0,0 +1,184 @@
+//! Counting trees with a history of versions.
+//!
+//! A `VersionedCountTree` is an `ArcCountTree` (the working copy, which it
+//! dereferences to) along with a linear history of committed versions. Since
+//! the nodes are shared between the versions and copied only on write, a
+//! version is just a clone of the root pointer: `commit`, `undo`, `redo` and
+//! `checkout` take O(1) time, and the versions only cost the memory of the
+//! nodes in which they differ. This is the undo history of a text editor.
+//!
+//! Editing the working copy copies the nodes it shares with the committed
+//! versions, so with the `cow-strict` feature, edits must be made within
+//! `cow::allow_clones`.
+//!
+//! ```
+//! use binary_tree::versioned::VersionedCountTree;
+//!
+//! let mut doc = VersionedCountTree::new();
+//! doc.push_back('a');
+//! doc.commit();
+//! doc.push_back('b');
+//! doc.commit();
+//! assert!(doc.undo());
+//! assert_eq!(doc.to_vec(), ['a']);
+//! assert!(doc.redo());
+//! assert_eq!(doc.to_vec(), ['a', 'b']);
+//! ```
+
+use std::ops::{Deref, DerefMut};
+
+use count::ArcCountTree;
+use cow;
+
+/// An `ArcCountTree` with undo and redo, see the module documentation.
+pub struct VersionedCountTree<T: Clone> {
+    // the working copy
+    tree: ArcCountTree<T>,
+    // the committed versions, starting with the empty tree
+    versions: Vec<ArcCountTree<T>>,
+    // the version the working copy was last reset to, or committed as
+    head: usize,
+}
+
+impl<T: Clone> VersionedCountTree<T> {
+    /// Returns an empty tree, whose history holds only the empty version 0.
+    pub fn new() -> VersionedCountTree<T> {
+        VersionedCountTree::from_tree(ArcCountTree::default())
+    }
+
+    /// Returns a tree whose history holds only `tree`, as version 0.
+    pub fn from_tree(mut tree: ArcCountTree<T>) -> VersionedCountTree<T> {
+        cow::allow_clones(|| tree.flush());
+        VersionedCountTree {
+            versions: vec![tree.clone()],
+            tree,
+            head: 0,
+        }
+    }
+
+    /// Records the working copy as a new version, and returns its number.
+    /// The versions after the current one (which could have been restored by
+    /// `redo`) are discarded. Time complexity: O(1), after merging the
+    /// buffered elements into the tree (see `CountTree::flush`)
+    pub fn commit(&mut self) -> usize {
+        // the buffered elements are merged into nodes shared with the
+        // previous versions
+        cow::allow_clones(|| self.tree.flush());
+        self.versions.truncate(self.head + 1);
+        self.versions.push(self.tree.clone());
+        self.head += 1;
+        self.head
+    }
+
+    /// Resets the working copy to the version before the current one, and
+    /// returns `true`, or returns `false` if at version 0. Uncommitted
+    /// changes are lost. Time complexity: O(1)
+    pub fn undo(&mut self) -> bool {
+        if self.head == 0 {
+            return false;
+        }
+        self.checkout(self.head - 1);
+        true
+    }
+
+    /// Resets the working copy to the version after the current one, and
+    /// returns `true`, or returns `false` if at the latest version.
+    /// Uncommitted changes are lost. Time complexity: O(1)
+    pub fn redo(&mut self) -> bool {
+        if self.head + 1 == self.versions.len() {
+            return false;
+        }
+        self.checkout(self.head + 1);
+        true
+    }
+
+    /// Resets the working copy to the given version, which becomes the
+    /// current one. Uncommitted changes are lost. Time complexity: O(1)
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the version does not exist.
+    pub fn checkout(&mut self, version: usize) {
+        assert!(version < self.versions.len(), "no version {}", version);
+        self.tree = self.versions[version].clone();
+        self.head = version;
+    }
+
+    /// Returns the number of the current version.
+    pub fn version(&self) -> usize {
+        self.head
+    }
+
+    /// Returns the number of versions in the history, including those which
+    /// can be restored by `redo`.
+    pub fn versions(&self) -> usize {
+        self.versions.len()
+    }
+
+    /// Returns the given version, or `None` if it does not exist.
+    pub fn get_version(&self, version: usize) -> Option<&ArcCountTree<T>> {
+        self.versions.get(version)
+    }
+}
+
+impl<T: Clone> Default for VersionedCountTree<T> {
+    fn default() -> VersionedCountTree<T> {
+        VersionedCountTree::new()
+    }
+}
+
+impl<T: Clone> Deref for VersionedCountTree<T> {
+    type Target = ArcCountTree<T>;
+
+    fn deref(&self) -> &ArcCountTree<T> {
+        &self.tree
+    }
+}
+
+impl<T: Clone> DerefMut for VersionedCountTree<T> {
+    fn deref_mut(&mut self) -> &mut ArcCountTree<T> {
+        &mut self.tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cow::allow_clones;
+    use super::VersionedCountTree;
+
+    #[test]
+    fn history() {
+        let mut vt = VersionedCountTree::from_tree((0..100).collect());
+        allow_clones(|| vt.insert(50, -1));
+        assert_eq!(vt.commit(), 1);
+        allow_clones(|| {
+            vt.remove(0);
+            vt.push_front(-2);
+        });
+        assert_eq!(vt.commit(), 2);
+        allow_clones(|| *vt.get_mut(99).unwrap() = -3);
+        assert_eq!(vt.get_version(0).unwrap().len(), 100);
+        assert_eq!(vt.get_version(1).unwrap().get(50), Some(&-1));
+        assert_eq!(vt.get_version(2).unwrap().get(0), Some(&-2));
+        assert_eq!(vt.get_version(2).unwrap().get(99), Some(&98));
+
+        // the uncommitted change is lost
+        assert!(vt.undo());
+        assert_eq!((vt.version(), vt.get(0), vt.get(99)), (1, Some(&0), Some(&98)));
+        assert!(vt.undo());
+        assert!(!vt.undo());
+        assert_eq!(vt.to_vec(), (0..100).collect::<Vec<_>>());
+        assert!(vt.redo());
+        assert!(vt.redo());
+        assert!(!vt.redo());
+        assert_eq!(vt.get(0), Some(&-2));
+
+        // committing after an undo discards the versions after it
+        vt.checkout(0);
+        allow_clones(|| vt.pop_back());
+        assert_eq!(vt.commit(), 1);
+        assert_eq!(vt.versions(), 2);
+        assert_eq!(vt.len(), 99);
+        assert!(!vt.redo());
+    }
+}
@@ -0,0 +1,212 @@
+//! Resumable walks which can move back up the tree.
+//!
+//! `Node::walk` only ever moves down. A `Walker` instead keeps the path from
+//! the root to the current node on an explicit stack, so that it can also move
+//! up to the parent. This is needed by algorithms which backtrack, like
+//! finding the in-order successor of a node, range scans or nearest-neighbour
+//! searches.
+
+use Node;
+use WalkAction;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// List of moves during a `Walker::walk`.
+pub enum Move {
+    /// Enter the left child
+    Left,
+    /// Enter the right child
+    Right,
+    /// Return to the parent
+    Up,
+    /// Stop walking
+    Stop,
+}
+
+/// A cursor on a tree which remembers the path from the root to the current
+/// node.
+///
+/// # Examples
+///
+/// ```rust
+/// # extern crate binary_tree;
+/// # use binary_tree::Node;
+/// # use binary_tree::count::CountTree;
+/// # use binary_tree::walker::{Move, Walker};
+/// # fn main() {
+/// let mut ct: CountTree<_> = (0..7).collect();
+/// let mut walker = Walker::new(ct.root().unwrap());
+/// // walk down to the leftmost node, and then back up to its grandparent
+/// let mut ups = 0;
+/// walker.walk(|node| {
+///     if node.left().is_some() && ups == 0 {
+///         Move::Left
+///     } else if ups < 2 {
+///         ups += 1;
+///         Move::Up
+///     } else {
+///         Move::Stop
+///     }
+/// });
+/// assert_eq!(walker.current().value(), &3);
+/// assert_eq!(walker.depth(), 0);
+/// # }
+/// ```
+pub struct Walker<'a, N: 'a> {
+    root: &'a N,
+    // the ancestors of the current node, along with the step taken from each
+    // of them
+    stack: Vec<(&'a N, WalkAction)>,
+    current: &'a N,
+}
+
+impl<'a, N> Clone for Walker<'a, N> {
+    fn clone(&self) -> Self {
+        Walker {
+            root: self.root,
+            stack: self.stack.clone(),
+            current: self.current,
+        }
+    }
+}
+
+impl<'a, N: Node> Walker<'a, N> {
+    /// Creates a walker positioned at `root`.
+    pub fn new(root: &'a N) -> Walker<'a, N> {
+        Walker {
+            root,
+            stack: Vec::new(),
+            current: root,
+        }
+    }
+
+    /// Returns the node the walker is positioned at.
+    pub fn current(&self) -> &'a N {
+        self.current
+    }
+
+    /// Returns the depth of the current node. The root is at depth zero.
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Returns the step which led from the parent to the current node, or
+    /// `None` at the root.
+    pub fn last_step(&self) -> Option<WalkAction> {
+        self.stack.last().map(|&(_, action)| action)
+    }
+
+    /// Returns the path from the root to the current node.
+    pub fn path(&self) -> Vec<WalkAction> {
+        self.stack.iter().map(|&(_, action)| action).collect()
+    }
+
+    /// Moves the walker back to the root.
+    pub fn reset(&mut self) {
+        self.stack.clear();
+        self.current = self.root;
+    }
+
+    /// Makes a single move, and returns `false` (without moving) if it leads
+    /// out of the tree. `Stop` never moves, and always succeeds.
+    pub fn step(&mut self, mv: Move) -> bool {
+        let next = match mv {
+            Move::Left => self.current.left().map(|n| (n, WalkAction::Left)),
+            Move::Right => self.current.right().map(|n| (n, WalkAction::Right)),
+            Move::Up => {
+                return match self.stack.pop() {
+                    Some((parent, _)) => {
+                        self.current = parent;
+                        true
+                    }
+                    None => false,
+                };
+            }
+            Move::Stop => return true,
+        };
+        match next {
+            Some((node, action)) => {
+                self.stack.push((self.current, action));
+                self.current = node;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Walks the tree starting from the current node. `step_in` is called
+    /// with the current node after every move, and decides the next one. The
+    /// walk ends when `step_in` returns `Stop`, or when a move leads out of
+    /// the tree, in which case `false` is returned. The walker stays at the
+    /// node where the walk ended, and can be resumed from there.
+    pub fn walk<F>(&mut self, mut step_in: F) -> bool
+        where F: FnMut(&'a N) -> Move
+    {
+        loop {
+            match step_in(self.current) {
+                Move::Stop => return true,
+                mv => {
+                    if !self.step(mv) {
+                        return false;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use Node;
+    use WalkAction;
+    use count::CountTree;
+    use super::{Move, Walker};
+
+    // Moves to the in-order successor of the current node.
+    fn successor<N: Node>(walker: &mut Walker<'_, N>) -> bool {
+        if walker.step(Move::Right) {
+            while walker.step(Move::Left) {}
+            return true;
+        }
+        let saved = walker.clone();
+        while walker.last_step() == Some(WalkAction::Right) {
+            walker.step(Move::Up);
+        }
+        if walker.step(Move::Up) {
+            true
+        } else {
+            // the current node is the last one
+            *walker = saved;
+            false
+        }
+    }
+
+    #[test]
+    fn range_scan() {
+        let mut ct: CountTree<_> = (0..20).collect();
+        let mut walker = Walker::new(ct.root().unwrap());
+        // find 5, and scan forward from there
+        assert!(walker.walk(|node| {
+            if *node.value() < 5 {
+                Move::Right
+            } else if *node.value() > 5 {
+                Move::Left
+            } else {
+                Move::Stop
+            }
+        }));
+        let mut vals = vec![*walker.current().value()];
+        while *walker.current().value() < 12 && successor(&mut walker) {
+            vals.push(*walker.current().value());
+        }
+        assert_eq!(vals, (5..13).collect::<Vec<_>>());
+
+        while successor(&mut walker) {}
+        assert_eq!(*walker.current().value(), 19);
+        walker.reset();
+        assert_eq!(walker.depth(), 0);
+        assert!(!walker.walk(|_| Move::Up));
+        assert!(!walker.walk(|_| Move::Right));
+        assert_eq!(walker.last_step(), Some(WalkAction::Right));
+        assert_eq!(*walker.current().value(), 19);
+    }
+}
@@ -0,0 +1,283 @@
+//! List of weighted elements, for random sampling.
+//!
+//! A `WeightedTree` is a list in which every element has a non-negative
+//! weight, and whose nodes cache the total weight of their subtrees (it is an
+//! `AugmentedCountTree` underneath). Picking an element with probability
+//! proportional to its weight, changing a weight, and finding the element at
+//! which the running total of the weights reaches some value all take
+//! O(log(n)) time, as do the usual edits. Elements of weight zero are never
+//! picked.
+//!
+//! `sample` takes a `rand::Rng`, and requires the `rand` feature; otherwise
+//! `sample_with` takes a number drawn uniformly from `[0, 1)`.
+//!
+//! ```
+//! use binary_tree::weighted::WeightedTree;
+//!
+//! let mut tasks = WeightedTree::new();
+//! tasks.push_back("render", 3.0);
+//! tasks.push_back("idle", 0.0);
+//! tasks.push_back("io", 1.0);
+//! assert_eq!(tasks.total_weight(), 4.0);
+//! assert_eq!(tasks.sample_with(0.5), Some(0));
+//! assert_eq!(tasks.sample_with(0.8), Some(2));
+//! tasks.update_weight(2, 5.0);
+//! assert_eq!(tasks.search(3.5), Some(2));
+//! ```
+
+use std::fmt::{self, Debug};
+use std::mem;
+
+#[cfg(feature="rand")]
+use rand::Rng;
+
+use BinaryTree;
+use Node;
+use augment::{Augment, AugmentedCountTree};
+use avl::Balanced;
+
+// Summarizes weighted elements into their total weight.
+struct Weight;
+
+impl<T> Augment<(T, f64)> for Weight {
+    type Summary = f64;
+
+    fn combine(left: Option<&f64>, value: &(T, f64), right: Option<&f64>) -> f64 {
+        left.cloned().unwrap_or(0.0) + value.1 + right.cloned().unwrap_or(0.0)
+    }
+}
+
+fn check_weight(weight: f64) {
+    assert!(weight >= 0.0 && weight.is_finite(), "invalid weight {}", weight);
+}
+
+/// A list of weighted elements, see the module documentation.
+pub struct WeightedTree<T> {
+    items: AugmentedCountTree<(T, f64), Weight>,
+}
+
+impl<T> WeightedTree<T> {
+    /// Returns an empty `WeightedTree`.
+    pub fn new() -> WeightedTree<T> {
+        WeightedTree { items: AugmentedCountTree::new() }
+    }
+
+    /// Returns the number of elements. Time complexity: O(1)
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the tree contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the sum of the weights. Time complexity: O(1)
+    pub fn total_weight(&self) -> f64 {
+        self.items.summary().cloned().unwrap_or(0.0)
+    }
+
+    /// Returns the element at the given index, or `None` if out of bounds.
+    /// Time complexity: O(log(n))
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.items.get(index).map(|item| &item.0)
+    }
+
+    /// Returns the weight of the element at the given index, or `None` if out
+    /// of bounds. Time complexity: O(log(n))
+    pub fn weight(&self, index: usize) -> Option<f64> {
+        self.items.get(index).map(|item| item.1)
+    }
+
+    /// Returns the sum of the weights of the elements before the given index.
+    /// Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`
+    pub fn prefix_weight(&self, index: usize) -> f64 {
+        self.items.range_summary(0..index).unwrap_or(0.0)
+    }
+
+    /// Inserts an element with the given weight at the given index. Time
+    /// complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is greater than `self.len()`, or if the weight is
+    /// negative, infinite or NaN.
+    pub fn insert(&mut self, index: usize, value: T, weight: f64) {
+        check_weight(weight);
+        self.items.insert(index, (value, weight));
+    }
+
+    /// Appends an element with the given weight. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if the weight is negative, infinite or NaN.
+    pub fn push_back(&mut self, value: T, weight: f64) {
+        let len = self.len();
+        self.insert(len, value, weight);
+    }
+
+    /// Removes the element at the given index, and returns it with its
+    /// weight. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is out of bounds.
+    pub fn remove(&mut self, index: usize) -> (T, f64) {
+        self.items.remove(index)
+    }
+
+    /// Sets the weight of the element at the given index, and returns the old
+    /// one. Time complexity: O(log(n))
+    ///
+    /// ## Panics
+    ///
+    /// Panics if index is out of bounds, or if the weight is negative,
+    /// infinite or NaN.
+    pub fn update_weight(&mut self, index: usize, weight: f64) -> f64 {
+        check_weight(weight);
+        self.items.modify(index, |item| mem::replace(&mut item.1, weight))
+    }
+
+    /// Returns the index of the element at which the running total of the
+    /// weights exceeds `target`, that is the `i` for which
+    /// `prefix_weight(i) <= target < prefix_weight(i + 1)`, or `None` if
+    /// `target` is negative or not less than the total weight. Time
+    /// complexity: O(log(n))
+    pub fn search(&self, mut target: f64) -> Option<usize> {
+        if !(target >= 0.0 && target < self.total_weight()) {
+            return None;
+        }
+        let mut index = 0;
+        // the last element of positive weight passed on the way, in case
+        // rounding errors make `target` overshoot the last subtree
+        let mut passed = None;
+        let mut node = self.items.root();
+        while let Some(n) = node {
+            let left = n.left().map_or(0.0, |left| *left.summary());
+            if target < left {
+                node = n.left();
+                continue;
+            }
+            target -= left;
+            index += n.left().map_or(0, |left| left.count() as usize);
+            let own = n.value().1;
+            if target < own {
+                return Some(index);
+            }
+            target -= own;
+            if own > 0.0 {
+                passed = Some(index);
+            }
+            index += 1;
+            node = n.right();
+        }
+        passed
+    }
+
+    /// Returns the index of an element picked with probability proportional
+    /// to its weight, given `u` drawn uniformly from `[0, 1)`, or `None` if
+    /// the total weight is zero. Time complexity: O(log(n))
+    pub fn sample_with(&self, u: f64) -> Option<usize> {
+        self.search(u * self.total_weight())
+    }
+
+    /// Returns the index of an element picked at random, with probability
+    /// proportional to its weight, or `None` if the total weight is zero.
+    /// Requires the `rand` feature. Time complexity: O(log(n))
+    #[cfg(feature="rand")]
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<usize> {
+        self.sample_with(rng.next_f64())
+    }
+
+    /// Returns an iterator over the elements and their weights.
+    pub fn iter(&self) -> impl Iterator<Item = (&T, f64)> + '_ {
+        self.items.iter().map(|item| (&item.0, item.1))
+    }
+}
+
+impl<T> Default for WeightedTree<T> {
+    fn default() -> WeightedTree<T> {
+        WeightedTree::new()
+    }
+}
+
+impl<T: Debug> Debug for WeightedTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WeightedTree;
+
+    #[test]
+    fn search() {
+        let mut wt = WeightedTree::new();
+        for i in 0..100 {
+            wt.push_back(i, (i % 4) as f64);
+        }
+        assert_eq!(wt.total_weight(), 150.0);
+        let mut total = 0.0;
+        for i in 0..100 {
+            assert_eq!(wt.prefix_weight(i), total);
+            if i % 4 != 0 {
+                assert_eq!(wt.search(total), Some(i));
+                assert_eq!(wt.search(total + 0.5), Some(i));
+            }
+            total += (i % 4) as f64;
+        }
+        assert_eq!(wt.search(150.0), None);
+        assert_eq!(wt.search(-1.0), None);
+        assert_eq!(wt.sample_with(0.0), Some(1));
+        assert_eq!(wt.sample_with(0.999999), Some(99));
+
+        assert_eq!(wt.update_weight(1, 0.0), 1.0);
+        assert_eq!(wt.remove(2), (2, 2.0));
+        wt.insert(0, -1, 7.0);
+        assert_eq!(wt.total_weight(), 154.0);
+        assert_eq!((wt.search(6.9), wt.search(7.0)), (Some(0), Some(3)));
+        assert_eq!((wt.get(3), wt.weight(3), wt.weight(2)), (Some(&3), Some(3.0), Some(0.0)));
+    }
+
+    #[test]
+    fn frequencies() {
+        let mut wt = WeightedTree::new();
+        for (value, weight) in [('a', 1.0), ('b', 0.0), ('c', 2.0), ('d', 5.0)] {
+            wt.push_back(value, weight);
+        }
+        let mut counts = [0; 4];
+        for k in 0..800 {
+            counts[wt.sample_with((k as f64 + 0.5) / 800.0).unwrap()] += 1;
+        }
+        assert_eq!(counts, [100, 0, 200, 500]);
+        assert_eq!(format!("{:?}", wt), "[('a', 1.0), ('b', 0.0), ('c', 2.0), ('d', 5.0)]");
+        assert_eq!(WeightedTree::<()>::new().sample_with(0.5), None);
+    }
+
+    #[test]
+    #[cfg(feature="rand")]
+    fn sample() {
+        use rand::{SeedableRng, StdRng};
+
+        let mut wt = WeightedTree::new();
+        wt.push_back('a', 0.0);
+        wt.push_back('b', 1.0);
+        wt.push_back('c', 0.0);
+        let mut rng = StdRng::from_seed(&[7usize][..]);
+        for _ in 0..100 {
+            assert_eq!(wt.sample(&mut rng), Some(1));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_weight() {
+        WeightedTree::new().push_back((), -1.0);
+    }
+}
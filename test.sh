@@ -2,8 +2,10 @@
 
 cargo build --verbose
 cargo test --verbose
+cargo test --verbose --features cow-metrics,cow-strict
 
 if [ "$TRAVIS_RUST_VERSION" = "nightly" ]; then
     cargo build --verbose --features quickcheck
     cargo test --verbose --features qc_tests
+    cargo miri test --verbose
 fi
@@ -6,7 +6,6 @@
 extern crate quickcheck;
 extern crate binary_tree;
 
-use binary_tree::BinaryTree;
 use binary_tree::count::CountTree;
 use binary_tree::test::compute_level;
 use quickcheck::TestResult;
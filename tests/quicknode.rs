@@ -0,0 +1,50 @@
+#![cfg(feature="qc_tests")]
+
+#![feature(plugin)]
+#![plugin(quickcheck_macros)]
+
+extern crate quickcheck;
+extern crate binary_tree;
+
+use binary_tree::Node;
+use binary_tree::NodeMut;
+use binary_tree::iter::{Iter, IntoIter};
+use binary_tree::test::TestNode;
+use quickcheck::TestResult;
+
+macro_rules! qc_assert {
+    ($cond:expr) => {
+        if !($cond) {
+            return TestResult::failed();
+        }
+    }
+}
+
+fn in_order(tn: &TestNode<u8>) -> Vec<u8> {
+    Iter::new(Some(tn)).cloned().collect()
+}
+
+#[quickcheck]
+fn qc_rotate(mut tn: TestNode<u8>) -> TestResult {
+    let vals = in_order(&tn);
+
+    if tn.rotate_left().is_ok() {
+        qc_assert!(in_order(&tn) == vals);
+        qc_assert!(tn.rotate_right().is_ok());
+    }
+    qc_assert!(in_order(&tn) == vals);
+    if tn.rotate_right().is_ok() {
+        qc_assert!(in_order(&tn) == vals);
+    }
+
+    TestResult::passed()
+}
+
+#[quickcheck]
+fn qc_into_iter(tn: TestNode<u8>) -> TestResult {
+    let vals = in_order(&tn);
+    let iter: IntoIter<TestNode<_>> = IntoIter::new(Some(Box::new(tn)));
+    qc_assert!(iter.rev().collect::<Vec<_>>() == vals.into_iter().rev().collect::<Vec<_>>());
+
+    TestResult::passed()
+}